@@ -160,51 +160,51 @@ fn create_bindings(cpal_asio_dir: &PathBuf) {
         .clang_arg(format!("-I{}/{}", cpal_asio_dir.display(), "host/pc"))
         .clang_arg(format!("-I{}/{}", cpal_asio_dir.display(), "host"))
         .clang_arg(format!("-I{}/{}", cpal_asio_dir.display(), "common"))
-        // Need to whitelist to avoid binding tp c++ std::*
-        .whitelist_type("AsioDrivers")
-        .whitelist_type("AsioDriver")
-        .whitelist_type("ASIOTime")
-        .whitelist_type("ASIOTimeInfo")
-        .whitelist_type("ASIODriverInfo")
-        .whitelist_type("ASIOBufferInfo")
-        .whitelist_type("ASIOCallbacks")
-        .whitelist_type("ASIOSamples")
-        .whitelist_type("ASIOSampleType")
-        .whitelist_type("ASIOSampleRate")
-        .whitelist_type("ASIOChannelInfo")
-        .whitelist_type("AsioTimeInfoFlags")
-        .whitelist_type("ASIOTimeCodeFlags")
-        .whitelist_var("kAsioSelectorSupported")
-        .whitelist_var("kAsioEngineVersion")
-        .whitelist_var("kAsioResetRequest")
-        .whitelist_var("kAsioBufferSizeChange")
-        .whitelist_var("kAsioResyncRequest")
-        .whitelist_var("kAsioLatenciesChanged")
-        .whitelist_var("kAsioSupportsTimeInfo")
-        .whitelist_var("kAsioSupportsTimeCode")
-        .whitelist_var("kAsioMMCCommand")
-        .whitelist_var("kAsioSupportsInputMonitor")
-        .whitelist_var("kAsioSupportsInputGain")
-        .whitelist_var("kAsioSupportsInputMeter")
-        .whitelist_var("kAsioSupportsOutputGain")
-        .whitelist_var("kAsioSupportsOutputMeter")
-        .whitelist_var("kAsioOverload")
-        .whitelist_function("ASIOGetChannels")
-        .whitelist_function("ASIOGetChannelInfo")
-        .whitelist_function("ASIOGetBufferSize")
-        .whitelist_function("ASIOGetSamplePosition")
-        .whitelist_function("get_sample_rate")
-        .whitelist_function("set_sample_rate")
-        .whitelist_function("can_sample_rate")
-        .whitelist_function("ASIOInit")
-        .whitelist_function("ASIOCreateBuffers")
-        .whitelist_function("ASIOStart")
-        .whitelist_function("ASIOStop")
-        .whitelist_function("ASIODisposeBuffers")
-        .whitelist_function("ASIOExit")
-        .whitelist_function("load_asio_driver")
-        .whitelist_function("remove_current_driver")
-        .whitelist_function("get_driver_names")
+        // Need to allowlist to avoid binding to c++ std::*
+        .allowlist_type("AsioDrivers")
+        .allowlist_type("AsioDriver")
+        .allowlist_type("ASIOTime")
+        .allowlist_type("ASIOTimeInfo")
+        .allowlist_type("ASIODriverInfo")
+        .allowlist_type("ASIOBufferInfo")
+        .allowlist_type("ASIOCallbacks")
+        .allowlist_type("ASIOSamples")
+        .allowlist_type("ASIOSampleType")
+        .allowlist_type("ASIOSampleRate")
+        .allowlist_type("ASIOChannelInfo")
+        .allowlist_type("AsioTimeInfoFlags")
+        .allowlist_type("ASIOTimeCodeFlags")
+        .allowlist_var("kAsioSelectorSupported")
+        .allowlist_var("kAsioEngineVersion")
+        .allowlist_var("kAsioResetRequest")
+        .allowlist_var("kAsioBufferSizeChange")
+        .allowlist_var("kAsioResyncRequest")
+        .allowlist_var("kAsioLatenciesChanged")
+        .allowlist_var("kAsioSupportsTimeInfo")
+        .allowlist_var("kAsioSupportsTimeCode")
+        .allowlist_var("kAsioMMCCommand")
+        .allowlist_var("kAsioSupportsInputMonitor")
+        .allowlist_var("kAsioSupportsInputGain")
+        .allowlist_var("kAsioSupportsInputMeter")
+        .allowlist_var("kAsioSupportsOutputGain")
+        .allowlist_var("kAsioSupportsOutputMeter")
+        .allowlist_var("kAsioOverload")
+        .allowlist_function("ASIOGetChannels")
+        .allowlist_function("ASIOGetChannelInfo")
+        .allowlist_function("ASIOGetBufferSize")
+        .allowlist_function("ASIOGetSamplePosition")
+        .allowlist_function("get_sample_rate")
+        .allowlist_function("set_sample_rate")
+        .allowlist_function("can_sample_rate")
+        .allowlist_function("ASIOInit")
+        .allowlist_function("ASIOCreateBuffers")
+        .allowlist_function("ASIOStart")
+        .allowlist_function("ASIOStop")
+        .allowlist_function("ASIODisposeBuffers")
+        .allowlist_function("ASIOExit")
+        .allowlist_function("load_asio_driver")
+        .allowlist_function("remove_current_driver")
+        .allowlist_function("get_driver_names")
         .bitfield_enum("AsioTimeInfoFlags")
         .bitfield_enum("ASIOTimeCodeFlags")
         // Finish the builder and generate the bindings.
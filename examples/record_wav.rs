@@ -126,6 +126,32 @@ fn main() -> Result<(), anyhow::Error> {
             move |data, _: &_| write_input_data::<u16, i16>(data, &writer_2),
             err_fn,
         )?,
+        cpal::SampleFormat::I8 => device.build_input_stream(
+            &config.into(),
+            move |data, _: &_| write_input_data::<i8, i8>(data, &writer_2),
+            err_fn,
+        )?,
+        cpal::SampleFormat::U8 => device.build_input_stream(
+            &config.into(),
+            move |data, _: &_| write_input_data::<u8, i8>(data, &writer_2),
+            err_fn,
+        )?,
+        cpal::SampleFormat::I32 => device.build_input_stream(
+            &config.into(),
+            move |data, _: &_| write_input_data::<i32, i32>(data, &writer_2),
+            err_fn,
+        )?,
+        cpal::SampleFormat::U32 => device.build_input_stream(
+            &config.into(),
+            move |data, _: &_| write_input_data::<u32, i32>(data, &writer_2),
+            err_fn,
+        )?,
+        sample_format => {
+            return Err(anyhow::Error::msg(format!(
+                "Unsupported sample format '{:?}'",
+                sample_format
+            )))
+        }
     };
 
     stream.play()?;
@@ -140,9 +166,8 @@ fn main() -> Result<(), anyhow::Error> {
 
 fn sample_format(format: cpal::SampleFormat) -> hound::SampleFormat {
     match format {
-        cpal::SampleFormat::U16 => hound::SampleFormat::Int,
-        cpal::SampleFormat::I16 => hound::SampleFormat::Int,
-        cpal::SampleFormat::F32 => hound::SampleFormat::Float,
+        cpal::SampleFormat::F32 | cpal::SampleFormat::F64 => hound::SampleFormat::Float,
+        _ => hound::SampleFormat::Int,
     }
 }
 
@@ -0,0 +1,571 @@
+//! Streams that are read from and written to by the calling thread rather than via a callback.
+
+use crate::samples_formats::fill_with_silence;
+use crate::{
+    BackendSpecificError, BuildStreamError, ChannelCount, Data, PlayStreamError, Sample,
+    SampleFormat, StreamConfig, StreamError,
+};
+use std::cell::UnsafeCell;
+use std::collections::VecDeque;
+use std::convert::TryInto;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread::{self, JoinHandle, Thread};
+use std::time::Duration;
+use std::{cmp, mem, ptr, slice};
+use traits::{DeviceTrait, StreamTrait};
+
+/// The duration of audio the ring buffer of a callback based blocking stream can hold, unless the
+/// requested buffer size calls for more.
+const RING_BUFFER_DURATION_MS: usize = 200;
+
+/// How long a blocked caller sleeps before checking whether it can make progress again, in case
+/// the callback did not get to wake it up.
+const WAIT_TIMEOUT: Duration = Duration::from_millis(10);
+
+/// An input stream whose captured audio is read by the calling thread, created via
+/// `DeviceTrait::build_input_stream_blocking`.
+///
+/// The stream runs from the moment it is built until it is dropped. The reader may be sent to and
+/// read from another thread.
+pub struct InputStreamReader {
+    inner: Box<dyn BlockingRead + Send>,
+    channels: ChannelCount,
+    sample_format: SampleFormat,
+}
+
+/// An output stream that plays the audio written to it by the calling thread, created via
+/// `DeviceTrait::build_output_stream_blocking`.
+///
+/// The stream runs from the moment it is built until it is dropped. Silence is played whenever no
+/// audio has been written in time. The writer may be sent to and written from another thread.
+pub struct OutputStreamWriter {
+    inner: Box<dyn BlockingWrite + Send>,
+    channels: ChannelCount,
+    sample_format: SampleFormat,
+}
+
+// The host specific part of an `InputStreamReader`.
+pub(crate) trait BlockingRead {
+    // Fills `bytes`, which holds whole frames, blocking until enough audio has been captured.
+    //
+    // Errors that do not stop the stream must be returned before any audio is read.
+    fn read(&mut self, bytes: &mut [u8]) -> Result<(), StreamError>;
+}
+
+// The host specific part of an `OutputStreamWriter`.
+pub(crate) trait BlockingWrite {
+    // Writes `bytes`, which holds whole frames, blocking until all of it has been written.
+    //
+    // Errors that do not stop the stream must be returned before any audio is written.
+    fn write(&mut self, bytes: &[u8]) -> Result<(), StreamError>;
+}
+
+impl InputStreamReader {
+    pub(crate) fn new<R>(inner: R, config: &StreamConfig, sample_format: SampleFormat) -> Self
+    where
+        R: BlockingRead + Send + 'static,
+    {
+        InputStreamReader {
+            inner: Box::new(inner),
+            channels: config.channels,
+            sample_format,
+        }
+    }
+
+    /// The number of channels of each frame read from the stream.
+    pub fn channels(&self) -> ChannelCount {
+        self.channels
+    }
+
+    /// The sample format of the stream.
+    pub fn sample_format(&self) -> SampleFormat {
+        self.sample_format
+    }
+
+    /// Fills `samples` with interleaved frames of captured audio, blocking until enough audio has
+    /// been captured.
+    ///
+    /// Returns the number of samples read, which is only less than `samples.len()` if its length
+    /// is not a multiple of the channel count.
+    ///
+    /// Returns `StreamError::BufferOverrun` if captured audio was dropped since the previous call
+    /// because it was not read in time. Nothing is read in this case, so the call may simply be
+    /// repeated.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `T` does not match the sample format of the stream.
+    pub fn read<T>(&mut self, samples: &mut [T]) -> Result<usize, StreamError>
+    where
+        T: Sample,
+    {
+        assert_eq!(
+            T::FORMAT,
+            self.sample_format,
+            "sample type does not match the sample format of the stream"
+        );
+        let len = samples.len() - samples.len() % self.channels as usize;
+        let samples = &mut samples[..len];
+        let bytes = unsafe {
+            slice::from_raw_parts_mut(samples.as_mut_ptr() as *mut u8, mem::size_of_val(samples))
+        };
+        self.inner.read(bytes)?;
+        Ok(len)
+    }
+}
+
+impl OutputStreamWriter {
+    pub(crate) fn new<W>(inner: W, config: &StreamConfig, sample_format: SampleFormat) -> Self
+    where
+        W: BlockingWrite + Send + 'static,
+    {
+        OutputStreamWriter {
+            inner: Box::new(inner),
+            channels: config.channels,
+            sample_format,
+        }
+    }
+
+    /// The number of channels of each frame written to the stream.
+    pub fn channels(&self) -> ChannelCount {
+        self.channels
+    }
+
+    /// The sample format of the stream.
+    pub fn sample_format(&self) -> SampleFormat {
+        self.sample_format
+    }
+
+    /// Writes the interleaved frames in `samples` to the stream, blocking until all of them have
+    /// been written.
+    ///
+    /// Returns the number of samples written, which is only less than `samples.len()` if its
+    /// length is not a multiple of the channel count. The incomplete frame at the end is not
+    /// written.
+    ///
+    /// Returns `StreamError::BufferUnderrun` if the stream ran out of audio since the previous
+    /// call because it was not written in time. Nothing is written in this case, so the call may
+    /// simply be repeated.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `T` does not match the sample format of the stream.
+    pub fn write<T>(&mut self, samples: &[T]) -> Result<usize, StreamError>
+    where
+        T: Sample,
+    {
+        assert_eq!(
+            T::FORMAT,
+            self.sample_format,
+            "sample type does not match the sample format of the stream"
+        );
+        let len = samples.len() - samples.len() % self.channels as usize;
+        let samples = &samples[..len];
+        let bytes = unsafe {
+            slice::from_raw_parts(samples.as_ptr() as *const u8, mem::size_of_val(samples))
+        };
+        self.inner.write(bytes)?;
+        Ok(len)
+    }
+}
+
+/// Creates an `InputStreamReader` on top of `build_input_stream_raw`, passing the captured audio
+/// from the callback to the reader through a ring buffer.
+pub(crate) fn build_input_stream_blocking<D>(
+    device: &D,
+    config: &StreamConfig,
+    sample_format: SampleFormat,
+) -> Result<InputStreamReader, BuildStreamError>
+where
+    D: DeviceTrait + Sync + ?Sized + 'static,
+{
+    let frame_size = config.channels as usize * sample_format.sample_size();
+    let shared = Arc::new(Shared::new(ring_buffer_frames(config), frame_size));
+    let stream = {
+        let (data_shared, error_shared) = (shared.clone(), shared.clone());
+        let config = config.clone();
+        StreamThread::spawn(device, move |device: &D| {
+            device.build_input_stream_raw(
+                &config,
+                sample_format,
+                move |data: &Data, _: &_| data_shared.push_input(data.bytes()),
+                move |err| error_shared.report(err),
+            )
+        })?
+    };
+    let reader = RingBufferReader {
+        shared,
+        _stream: stream,
+    };
+    Ok(InputStreamReader::new(reader, config, sample_format))
+}
+
+/// Creates an `OutputStreamWriter` on top of `build_output_stream_raw`, passing the written audio
+/// from the writer to the callback through a ring buffer.
+pub(crate) fn build_output_stream_blocking<D>(
+    device: &D,
+    config: &StreamConfig,
+    sample_format: SampleFormat,
+) -> Result<OutputStreamWriter, BuildStreamError>
+where
+    D: DeviceTrait + Sync + ?Sized + 'static,
+{
+    let frame_size = config.channels as usize * sample_format.sample_size();
+    let shared = Arc::new(Shared::new(ring_buffer_frames(config), frame_size));
+    let stream = {
+        let (data_shared, error_shared) = (shared.clone(), shared.clone());
+        let config = config.clone();
+        StreamThread::spawn(device, move |device: &D| {
+            // Whether the previous callback was filled entirely, so that running out of audio now
+            // is an underrun rather than the writer not having started yet.
+            let mut primed = false;
+            device.build_output_stream_raw(
+                &config,
+                sample_format,
+                move |data: &mut Data, _: &_| {
+                    primed = data_shared.pop_output(data.bytes_mut(), sample_format, primed);
+                },
+                move |err| error_shared.report(err),
+            )
+        })?
+    };
+    let writer = RingBufferWriter {
+        shared,
+        _stream: stream,
+    };
+    Ok(OutputStreamWriter::new(writer, config, sample_format))
+}
+
+// Builds, plays and eventually drops a stream on a thread of its own.
+//
+// Streams are not `Send` on every host, while readers and writers are, so the stream of a ring
+// buffer based reader or writer stays on this thread until the `StreamThread` is dropped.
+struct StreamThread {
+    stop: mpsc::Sender<()>,
+    thread: Option<JoinHandle<()>>,
+}
+
+// A device lent to a `StreamThread` for building its stream.
+struct DeviceRef<D: ?Sized>(*const D);
+
+// The device is `Sync`, and `StreamThread::spawn` does not return before the thread is done using
+// it.
+unsafe impl<D: Sync + ?Sized> Send for DeviceRef<D> {}
+
+impl StreamThread {
+    fn spawn<D, B>(device: &D, build: B) -> Result<Self, BuildStreamError>
+    where
+        D: DeviceTrait + Sync + ?Sized + 'static,
+        B: FnOnce(&D) -> Result<D::Stream, BuildStreamError> + Send + 'static,
+    {
+        let device = DeviceRef(device as *const D);
+        let (ready_tx, ready_rx) = mpsc::channel();
+        let (stop_tx, stop_rx) = mpsc::channel::<()>();
+        let thread = thread::Builder::new()
+            .name("cpal_blocking_stream".to_owned())
+            .spawn(move || {
+                let device = unsafe { &*device.0 };
+                let result = build(device).and_then(|stream| {
+                    stream.play().map_err(build_stream_error)?;
+                    Ok(stream)
+                });
+                let _stream = match result {
+                    Ok(stream) => stream,
+                    Err(err) => {
+                        let _ = ready_tx.send(Err(err));
+                        return;
+                    }
+                };
+                let _ = ready_tx.send(Ok(()));
+                // Returns once the `StreamThread` is dropped.
+                let _ = stop_rx.recv();
+            })
+            .map_err(|err| BackendSpecificError {
+                description: err.to_string(),
+            })?;
+        let stream_thread = StreamThread {
+            stop: stop_tx,
+            thread: Some(thread),
+        };
+        match ready_rx.recv() {
+            Ok(result) => result.map(|()| stream_thread),
+            Err(_) => {
+                let description = String::from("the stream thread panicked");
+                Err(BackendSpecificError { description }.into())
+            }
+        }
+    }
+}
+
+impl Drop for StreamThread {
+    fn drop(&mut self) {
+        let _ = self.stop.send(());
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn build_stream_error(err: PlayStreamError) -> BuildStreamError {
+    match err {
+        PlayStreamError::DeviceNotAvailable => BuildStreamError::DeviceNotAvailable,
+        PlayStreamError::BackendSpecific { err } => err.into(),
+    }
+}
+
+fn ring_buffer_frames(config: &StreamConfig) -> usize {
+    let frames = config.sample_rate.0 as usize * RING_BUFFER_DURATION_MS / 1000;
+    match config.buffer_size.buffer_frames(config.sample_rate) {
+        Some(buffer_frames) => cmp::max(frames, buffer_frames as usize * 2),
+        None => frames,
+    }
+}
+
+struct RingBufferReader {
+    shared: Arc<Shared>,
+    _stream: StreamThread,
+}
+
+struct RingBufferWriter {
+    shared: Arc<Shared>,
+    _stream: StreamThread,
+}
+
+impl BlockingRead for RingBufferReader {
+    fn read(&mut self, bytes: &mut [u8]) -> Result<(), StreamError> {
+        if let Some(err) = self.shared.take_error() {
+            return Err(err);
+        }
+        if self.shared.xrun.swap(false, Ordering::AcqRel) {
+            let frames_lost = self.shared.frames_lost.swap(0, Ordering::AcqRel);
+            return Err(StreamError::BufferOverrun {
+                frames_lost: frames_lost.try_into().ok(),
+            });
+        }
+        let mut read = 0;
+        while read < bytes.len() {
+            let len = self.shared.ring.pop(&mut bytes[read..]);
+            if len == 0 {
+                self.shared.wait()?;
+            }
+            read += len;
+        }
+        Ok(())
+    }
+}
+
+impl BlockingWrite for RingBufferWriter {
+    fn write(&mut self, bytes: &[u8]) -> Result<(), StreamError> {
+        if let Some(err) = self.shared.take_error() {
+            return Err(err);
+        }
+        if self.shared.xrun.swap(false, Ordering::AcqRel) {
+            return Err(StreamError::BufferUnderrun { frames_lost: None });
+        }
+        let mut written = 0;
+        while written < bytes.len() {
+            let len = self.shared.ring.push(&bytes[written..]);
+            if len == 0 {
+                self.shared.wait()?;
+            }
+            written += len;
+        }
+        Ok(())
+    }
+}
+
+// The state shared between the callbacks of a stream and its blocking reader or writer.
+//
+// The data callback never blocks on the reader or writer: the audio passes through a lock-free ring
+// buffer and waking up the blocked thread is skipped if it would require waiting for a lock.
+struct Shared {
+    ring: RingBuffer,
+    // The thread blocked waiting on the ring buffer, if any.
+    waiter: Mutex<Option<Thread>>,
+    // Errors reported by the stream that have not been returned to the reader or writer yet.
+    errors: Mutex<VecDeque<StreamError>>,
+    // Whether the stream has reported that the device is no longer available.
+    disconnected: AtomicBool,
+    // Whether the ring buffer ran full or empty since the last time an xrun was returned.
+    xrun: AtomicBool,
+    // The number of captured frames dropped since the last time an overrun was returned.
+    frames_lost: AtomicUsize,
+}
+
+impl Shared {
+    fn new(frames: usize, frame_size: usize) -> Self {
+        Shared {
+            ring: RingBuffer::new(frames, frame_size),
+            waiter: Mutex::new(None),
+            errors: Mutex::new(VecDeque::new()),
+            disconnected: AtomicBool::new(false),
+            xrun: AtomicBool::new(false),
+            frames_lost: AtomicUsize::new(0),
+        }
+    }
+
+    // Called by the data callback of an input stream.
+    fn push_input(&self, bytes: &[u8]) {
+        let len = self.ring.push(bytes);
+        if len < bytes.len() {
+            let frames_lost = (bytes.len() - len) / self.ring.frame_size;
+            self.frames_lost.fetch_add(frames_lost, Ordering::AcqRel);
+            self.xrun.store(true, Ordering::Release);
+        }
+        self.wake();
+    }
+
+    // Called by the data callback of an output stream, filling whatever the ring buffer cannot
+    // provide with silence. Returns whether all of `bytes` came from the ring buffer.
+    fn pop_output(&self, bytes: &mut [u8], sample_format: SampleFormat, primed: bool) -> bool {
+        let len = self.ring.pop(bytes);
+        let filled = len == bytes.len();
+        if !filled {
+            fill_with_silence(&mut bytes[len..], sample_format);
+            if primed {
+                self.xrun.store(true, Ordering::Release);
+            }
+        }
+        self.wake();
+        filled
+    }
+
+    // Called by the error callback.
+    fn report(&self, err: StreamError) {
+        if let StreamError::DeviceNotAvailable = err {
+            self.disconnected.store(true, Ordering::Release);
+        }
+        self.errors.lock().unwrap().push_back(err);
+        self.wake();
+    }
+
+    fn take_error(&self) -> Option<StreamError> {
+        let err = self.errors.lock().unwrap().pop_front();
+        if err.is_none() && self.disconnected.load(Ordering::Acquire) {
+            return Some(StreamError::DeviceNotAvailable);
+        }
+        err
+    }
+
+    // Blocks the calling thread until the callback has made progress, or until the stream can no
+    // longer make any.
+    fn wait(&self) -> Result<(), StreamError> {
+        if self.disconnected.load(Ordering::Acquire) {
+            return Err(StreamError::DeviceNotAvailable);
+        }
+        *self.waiter.lock().unwrap() = Some(thread::current());
+        thread::park_timeout(WAIT_TIMEOUT);
+        Ok(())
+    }
+
+    fn wake(&self) {
+        if let Ok(waiter) = self.waiter.try_lock() {
+            if let Some(thread) = waiter.as_ref() {
+                thread.unpark();
+            }
+        }
+    }
+}
+
+// A single producer, single consumer ring buffer of bytes that only ever moves whole frames.
+struct RingBuffer {
+    buffer: Box<[UnsafeCell<u8>]>,
+    frame_size: usize,
+    // The positions of the producer and the consumer, in bytes. They run from `0` to twice the
+    // capacity so that a full buffer can be told apart from an empty one.
+    write: AtomicUsize,
+    read: AtomicUsize,
+}
+
+// The producer only writes to the free part of the buffer and the consumer only reads from the
+// filled part. Each side publishes its changes by storing its position.
+unsafe impl Sync for RingBuffer {}
+
+impl RingBuffer {
+    fn new(frames: usize, frame_size: usize) -> Self {
+        let capacity = cmp::max(frames, 1) * frame_size;
+        RingBuffer {
+            buffer: (0..capacity).map(|_| UnsafeCell::new(0)).collect(),
+            frame_size,
+            write: AtomicUsize::new(0),
+            read: AtomicUsize::new(0),
+        }
+    }
+
+    fn capacity(&self) -> usize {
+        self.buffer.len()
+    }
+
+    fn filled(&self, write: usize, read: usize) -> usize {
+        (write + 2 * self.capacity() - read) % (2 * self.capacity())
+    }
+
+    // Copies as many whole frames of `bytes` as fit into the buffer. Returns the number of bytes
+    // copied. Must only be called by the producer.
+    fn push(&self, bytes: &[u8]) -> usize {
+        let capacity = self.capacity();
+        let write = self.write.load(Ordering::Relaxed);
+        let read = self.read.load(Ordering::Acquire);
+        let free = capacity - self.filled(write, read);
+        let len = cmp::min(free, bytes.len() - bytes.len() % self.frame_size);
+        let start = write % capacity;
+        let first = cmp::min(len, capacity - start);
+        unsafe {
+            let buffer = UnsafeCell::raw_get(self.buffer.as_ptr());
+            ptr::copy_nonoverlapping(bytes.as_ptr(), buffer.add(start), first);
+            ptr::copy_nonoverlapping(bytes.as_ptr().add(first), buffer, len - first);
+        }
+        self.write
+            .store((write + len) % (2 * capacity), Ordering::Release);
+        len
+    }
+
+    // Copies as many whole frames as are available and fit into `bytes`. Returns the number of
+    // bytes copied. Must only be called by the consumer.
+    fn pop(&self, bytes: &mut [u8]) -> usize {
+        let capacity = self.capacity();
+        let read = self.read.load(Ordering::Relaxed);
+        let write = self.write.load(Ordering::Acquire);
+        let filled = self.filled(write, read);
+        let len = cmp::min(filled, bytes.len() - bytes.len() % self.frame_size);
+        let start = read % capacity;
+        let first = cmp::min(len, capacity - start);
+        unsafe {
+            let buffer = UnsafeCell::raw_get(self.buffer.as_ptr()) as *const u8;
+            ptr::copy_nonoverlapping(buffer.add(start), bytes.as_mut_ptr(), first);
+            ptr::copy_nonoverlapping(buffer, bytes.as_mut_ptr().add(first), len - first);
+        }
+        self.read
+            .store((read + len) % (2 * capacity), Ordering::Release);
+        len
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RingBuffer;
+
+    #[test]
+    fn test_ring_buffer_wraps_around() {
+        let ring = RingBuffer::new(4, 2);
+        let mut out = [0u8; 8];
+        assert_eq!(ring.push(&[1, 2, 3, 4, 5, 6]), 6);
+        assert_eq!(ring.pop(&mut out[..4]), 4);
+        assert_eq!(&out[..4], &[1, 2, 3, 4]);
+        assert_eq!(ring.push(&[7, 8, 9, 10, 11, 12, 13, 14]), 6);
+        assert_eq!(ring.pop(&mut out), 8);
+        assert_eq!(out, [5, 6, 7, 8, 9, 10, 11, 12]);
+        assert_eq!(ring.pop(&mut out), 0);
+    }
+
+    #[test]
+    fn test_ring_buffer_moves_whole_frames() {
+        let ring = RingBuffer::new(2, 3);
+        assert_eq!(ring.push(&[1, 2, 3, 4, 5]), 3);
+        let mut out = [0u8; 5];
+        assert_eq!(ring.pop(&mut out[..2]), 0);
+        assert_eq!(ring.pop(&mut out), 3);
+        assert_eq!(&out[..3], &[1, 2, 3]);
+    }
+}
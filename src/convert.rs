@@ -0,0 +1,447 @@
+//! Streams whose data callbacks take samples of a type the device may not support, opened in a
+//! sample format the device does support and converted with the `Sample` conversions.
+
+use crate::{
+    BuildStreamError, Data, InputCallbackInfo, OutputCallbackInfo, Sample, SampleFormat,
+    StreamConfig, StreamError, SupportedBufferSize, SupportedStreamConfigRange,
+    SupportedStreamConfigsError,
+};
+use std::sync::{Arc, Mutex};
+use traits::DeviceTrait;
+
+/// The number of frames the scratch buffers of converted, resampled and remixed streams are
+/// allocated for when neither the config nor the device gives a buffer size. The buffers only grow
+/// if the host passes larger buffers.
+const DEFAULT_SCRATCH_FRAMES: usize = 4096;
+
+/// The largest number of frames scratch buffers are allocated for up front from the buffer sizes
+/// a device supports, as some hosts report ranges of up to `u32::MAX` frames.
+const MAX_SCRATCH_FRAMES: usize = 1 << 16;
+
+/// The error callback of a stream, shared between the host and the data callback so that the
+/// latter can report data it cannot handle.
+pub(crate) struct ErrorCallback<E>(Arc<Mutex<E>>);
+
+impl<E> ErrorCallback<E>
+where
+    E: FnMut(StreamError),
+{
+    pub(crate) fn new(error_callback: E) -> Self {
+        ErrorCallback(Arc::new(Mutex::new(error_callback)))
+    }
+
+    pub(crate) fn report(&self, err: StreamError) {
+        if let Ok(mut error_callback) = self.0.lock() {
+            error_callback(err);
+        }
+    }
+}
+
+impl<E> Clone for ErrorCallback<E> {
+    fn clone(&self) -> Self {
+        ErrorCallback(self.0.clone())
+    }
+}
+
+/// Report data supplied by the host in a sample format other than the `expected` one, once per
+/// stream as tracked by `reported`.
+pub(crate) fn report_incorrect_sample_format<E>(
+    error_callback: &ErrorCallback<E>,
+    reported: &mut bool,
+    expected: SampleFormat,
+    data: &Data,
+) where
+    E: FnMut(StreamError),
+{
+    if !*reported {
+        *reported = true;
+        error_callback.report(StreamError::IncorrectSampleFormat {
+            expected,
+            actual: data.sample_format(),
+        });
+    }
+}
+
+/// The error to fail building a stream with when the configs of the device cannot be queried.
+pub(crate) fn build_stream_error(err: SupportedStreamConfigsError) -> BuildStreamError {
+    match err {
+        SupportedStreamConfigsError::DeviceNotAvailable => BuildStreamError::DeviceNotAvailable,
+        SupportedStreamConfigsError::InvalidArgument => BuildStreamError::InvalidArgument,
+        SupportedStreamConfigsError::BackendSpecific { err } => err.into(),
+    }
+}
+
+// The sample format to open a stream of `T` samples with `config` in: `T::FORMAT` if the device
+// supports it, and otherwise the format the device prefers by `cmp_default_heuristics`.
+fn native_format<T, I>(
+    supported: Result<I, SupportedStreamConfigsError>,
+    config: &StreamConfig,
+) -> Result<SampleFormat, BuildStreamError>
+where
+    T: Sample,
+    I: Iterator<Item = SupportedStreamConfigRange>,
+{
+    let supported = supported.map_err(build_stream_error)?;
+    let candidates: Vec<_> = supported
+        .filter(|range| {
+            range.channels == config.channels
+                && range.min_sample_rate <= config.sample_rate
+                && config.sample_rate <= range.max_sample_rate
+        })
+        .collect();
+    if candidates
+        .iter()
+        .any(|range| range.sample_format == T::FORMAT)
+    {
+        return Ok(T::FORMAT);
+    }
+    candidates
+        .into_iter()
+        .max_by(|a, b| a.cmp_default_heuristics(b))
+        .map(|range| range.sample_format)
+        .ok_or(BuildStreamError::StreamConfigNotSupported)
+}
+
+/// The number of frames of the largest buffer the host may pass to the data callback of a stream
+/// opened with `config`: the requested buffer size, and otherwise the largest buffer size the
+/// `supported` configs matching `config` allow up to `MAX_SCRATCH_FRAMES`, so that scratch buffers
+/// of that size rarely have to grow on the audio thread.
+pub(crate) fn max_buffer_frames<I>(
+    supported: Result<I, SupportedStreamConfigsError>,
+    config: &StreamConfig,
+) -> usize
+where
+    I: Iterator<Item = SupportedStreamConfigRange>,
+{
+    if let Some(frames) = config.buffer_size.buffer_frames(config.sample_rate) {
+        return frames as usize;
+    }
+    supported
+        .into_iter()
+        .flatten()
+        .filter(|range| {
+            range.channels == config.channels
+                && range.min_sample_rate <= config.sample_rate
+                && config.sample_rate <= range.max_sample_rate
+        })
+        .filter_map(|range| match range.buffer_size {
+            SupportedBufferSize::Range { max, .. } => Some((max as usize).min(MAX_SCRATCH_FRAMES)),
+            SupportedBufferSize::Unknown => None,
+        })
+        .max()
+        .unwrap_or(DEFAULT_SCRATCH_FRAMES)
+}
+
+// Convert the samples of `data`, of any format, into `scratch`. Returns `false` if `data` is
+// planar.
+fn convert_from<T>(data: &Data, scratch: &mut Vec<T>) -> bool
+where
+    T: Sample,
+{
+    fn convert<S, T>(data: &Data, scratch: &mut [T]) -> bool
+    where
+        S: Sample,
+        T: Sample,
+    {
+        match data.as_slice::<S>() {
+            Some(samples) => {
+                for (out, sample) in scratch.iter_mut().zip(samples) {
+                    *out = T::from(sample);
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    scratch.resize(data.len(), T::from(&0.0f32));
+    match data.sample_format() {
+        SampleFormat::I8 => convert::<i8, T>(data, scratch),
+        SampleFormat::I16 => convert::<i16, T>(data, scratch),
+        SampleFormat::I24 => convert::<crate::I24, T>(data, scratch),
+        SampleFormat::I24Packed => convert::<crate::I24Packed, T>(data, scratch),
+        SampleFormat::I32 => convert::<i32, T>(data, scratch),
+        SampleFormat::U8 => convert::<u8, T>(data, scratch),
+        SampleFormat::U16 => convert::<u16, T>(data, scratch),
+        SampleFormat::U32 => convert::<u32, T>(data, scratch),
+        SampleFormat::F32 => convert::<f32, T>(data, scratch),
+        SampleFormat::F64 => convert::<f64, T>(data, scratch),
+    }
+}
+
+// Convert the samples of `scratch` into `data`, of any format. Returns `false` if `data` is
+// planar.
+fn convert_into<T>(scratch: &[T], data: &mut Data) -> bool
+where
+    T: Sample,
+{
+    fn convert<S, T>(scratch: &[T], data: &mut Data) -> bool
+    where
+        S: Sample,
+        T: Sample,
+    {
+        match data.as_slice_mut::<S>() {
+            Some(samples) => {
+                for (out, sample) in samples.iter_mut().zip(scratch) {
+                    *out = S::from(sample);
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    match data.sample_format() {
+        SampleFormat::I8 => convert::<i8, T>(scratch, data),
+        SampleFormat::I16 => convert::<i16, T>(scratch, data),
+        SampleFormat::I24 => convert::<crate::I24, T>(scratch, data),
+        SampleFormat::I24Packed => convert::<crate::I24Packed, T>(scratch, data),
+        SampleFormat::I32 => convert::<i32, T>(scratch, data),
+        SampleFormat::U8 => convert::<u8, T>(scratch, data),
+        SampleFormat::U16 => convert::<u16, T>(scratch, data),
+        SampleFormat::U32 => convert::<u32, T>(scratch, data),
+        SampleFormat::F32 => convert::<f32, T>(scratch, data),
+        SampleFormat::F64 => convert::<f64, T>(scratch, data),
+    }
+}
+
+/// The default implementation of `DeviceTrait::build_input_stream_converted`.
+pub(crate) fn build_input_stream_converted<Dev, T, D, E>(
+    device: &Dev,
+    config: &StreamConfig,
+    mut data_callback: D,
+    error_callback: E,
+) -> Result<Dev::Stream, BuildStreamError>
+where
+    Dev: DeviceTrait + ?Sized,
+    T: Sample + Send + 'static,
+    D: FnMut(&[T], &InputCallbackInfo) + Send + 'static,
+    E: FnMut(StreamError) + Send + 'static,
+{
+    let sample_format = native_format::<T, _>(device.supported_input_configs(), config)?;
+    if sample_format == T::FORMAT {
+        return device.build_input_stream(config, data_callback, error_callback);
+    }
+    let frames = max_buffer_frames(device.supported_input_configs(), config);
+    let mut scratch: Vec<T> = Vec::with_capacity(frames * config.channels as usize);
+    let error_callback = ErrorCallback::new(error_callback);
+    let data_error_callback = error_callback.clone();
+    let mut reported = false;
+    device.build_input_stream_raw(
+        config,
+        sample_format,
+        move |data, info| {
+            if convert_from(data, &mut scratch) {
+                data_callback(&scratch, info);
+            } else {
+                report_incorrect_sample_format(
+                    &data_error_callback,
+                    &mut reported,
+                    sample_format,
+                    data,
+                );
+            }
+        },
+        move |err| error_callback.report(err),
+    )
+}
+
+/// The default implementation of `DeviceTrait::build_output_stream_converted`.
+pub(crate) fn build_output_stream_converted<Dev, T, D, E>(
+    device: &Dev,
+    config: &StreamConfig,
+    mut data_callback: D,
+    error_callback: E,
+) -> Result<Dev::Stream, BuildStreamError>
+where
+    Dev: DeviceTrait + ?Sized,
+    T: Sample + Send + 'static,
+    D: FnMut(&mut [T], &OutputCallbackInfo) + Send + 'static,
+    E: FnMut(StreamError) + Send + 'static,
+{
+    let sample_format = native_format::<T, _>(device.supported_output_configs(), config)?;
+    if sample_format == T::FORMAT {
+        return device.build_output_stream(config, data_callback, error_callback);
+    }
+    let frames = max_buffer_frames(device.supported_output_configs(), config);
+    let mut scratch: Vec<T> = Vec::with_capacity(frames * config.channels as usize);
+    let error_callback = ErrorCallback::new(error_callback);
+    let data_error_callback = error_callback.clone();
+    let mut reported = false;
+    device.build_output_stream_raw(
+        config,
+        sample_format,
+        move |data, info| {
+            let equilibrium = T::from(&0.0f32);
+            scratch.clear();
+            scratch.resize(data.len(), equilibrium);
+            data_callback(&mut scratch, info);
+            if !convert_into(&scratch, data) {
+                report_incorrect_sample_format(
+                    &data_error_callback,
+                    &mut reported,
+                    sample_format,
+                    data,
+                );
+            }
+        },
+        move |err| error_callback.report(err),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        convert_from, convert_into, max_buffer_frames, native_format, DEFAULT_SCRATCH_FRAMES,
+        MAX_SCRATCH_FRAMES,
+    };
+    use {
+        BufferSize, BuildStreamError, Data, SampleFormat, SampleRate, StreamConfig,
+        SupportedBufferSize, SupportedStreamConfigRange,
+    };
+
+    fn range(channels: u16, sample_format: SampleFormat) -> SupportedStreamConfigRange {
+        SupportedStreamConfigRange::new(
+            channels,
+            SampleRate(8_000),
+            SampleRate(96_000),
+            SupportedBufferSize::Unknown,
+            sample_format,
+        )
+    }
+
+    #[test]
+    fn test_native_format() {
+        let config = StreamConfig {
+            channels: 2,
+            sample_rate: SampleRate(48_000),
+            buffer_size: BufferSize::Default,
+            channel_map: None,
+        };
+        let supported = || {
+            Ok(vec![
+                range(2, SampleFormat::I32),
+                range(2, SampleFormat::I16),
+                range(1, SampleFormat::F32),
+            ]
+            .into_iter())
+        };
+        assert_eq!(
+            native_format::<i32, _>(supported(), &config).unwrap(),
+            SampleFormat::I32
+        );
+        assert_eq!(
+            native_format::<f32, _>(supported(), &config).unwrap(),
+            SampleFormat::I16
+        );
+        let surround = StreamConfig {
+            channels: 6,
+            ..config
+        };
+        match native_format::<f32, _>(supported(), &surround) {
+            Err(BuildStreamError::StreamConfigNotSupported) => (),
+            result => panic!("unexpected result {:?}", result.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_max_buffer_frames() {
+        let config = StreamConfig {
+            channels: 2,
+            sample_rate: SampleRate(48_000),
+            buffer_size: BufferSize::Default,
+            channel_map: None,
+        };
+        let with_buffer_size = |channels, max| SupportedStreamConfigRange {
+            buffer_size: SupportedBufferSize::Range { min: 64, max },
+            ..range(channels, SampleFormat::F32)
+        };
+        let supported = || {
+            Ok(vec![
+                with_buffer_size(2, 8192),
+                with_buffer_size(2, 2048),
+                with_buffer_size(1, 16384),
+                range(2, SampleFormat::I16),
+            ]
+            .into_iter())
+        };
+        assert_eq!(max_buffer_frames(supported(), &config), 8192);
+        let fixed = StreamConfig {
+            buffer_size: BufferSize::Fixed(512),
+            ..config.clone()
+        };
+        assert_eq!(max_buffer_frames(supported(), &fixed), 512);
+        let unknown = Ok(vec![range(2, SampleFormat::F32)].into_iter());
+        assert_eq!(max_buffer_frames(unknown, &config), DEFAULT_SCRATCH_FRAMES);
+        let unbounded = Ok(vec![with_buffer_size(2, u32::MAX)].into_iter());
+        assert_eq!(max_buffer_frames(unbounded, &config), MAX_SCRATCH_FRAMES);
+    }
+
+    #[test]
+    fn test_convert_round_trip() {
+        let mut device_samples = [i16::MIN, 0, i16::MAX];
+        let mut data = unsafe {
+            Data::from_parts(
+                device_samples.as_mut_ptr() as *mut (),
+                device_samples.len(),
+                SampleFormat::I16,
+            )
+        };
+        let mut scratch: Vec<f32> = Vec::new();
+        assert!(convert_from(&data, &mut scratch));
+        assert_eq!(scratch, vec![-1.0, 0.0, 1.0]);
+        scratch[0] = 0.5;
+        assert!(convert_into(&scratch, &mut data));
+        assert_eq!(device_samples, [i16::MAX / 2, 0, i16::MAX]);
+    }
+
+    #[test]
+    #[cfg(feature = "test-host")]
+    fn test_converted_streams() {
+        use host::null::single_config;
+        use host::test::Device;
+        use std::sync::mpsc;
+        use traits::{DeviceTrait, StreamTrait};
+        use Sample;
+
+        let device = Device::new(
+            "converter",
+            vec![single_config(2, SampleRate(48_000), SampleFormat::U16)],
+            vec![single_config(2, SampleRate(48_000), SampleFormat::I16)],
+        );
+        let config = StreamConfig {
+            channels: 2,
+            sample_rate: SampleRate(48_000),
+            buffer_size: BufferSize::Fixed(64),
+            channel_map: None,
+        };
+
+        let captured = [0u16, 0x2000, 0x8000, 0xc000, 0xffff, 0x1234];
+        let (tx, rx) = mpsc::channel();
+        let input = device
+            .build_input_stream_converted(
+                &config,
+                move |data: &[f32], _: &_| tx.send(data.to_vec()).unwrap(),
+                |_| panic!(),
+            )
+            .unwrap();
+        input.play().unwrap();
+        input.handle().push_input(&captured);
+        assert!(input.handle().advance(3));
+        let expected: Vec<f32> = captured.iter().map(Sample::to_f32).collect();
+        assert_eq!(rx.try_recv().unwrap(), expected);
+
+        let played = [0.5f32, -0.5, 0.25, -1.0, 1.0, 0.0];
+        let output = device
+            .build_output_stream_converted(
+                &config,
+                move |data: &mut [f32], _: &_| data.copy_from_slice(&played),
+                |_| panic!(),
+            )
+            .unwrap();
+        output.play().unwrap();
+        assert!(output.handle().advance(3));
+        let expected: Vec<i16> = played.iter().map(Sample::to_i16).collect();
+        assert_eq!(output.handle().take_output::<i16>(), expected);
+    }
+}
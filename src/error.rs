@@ -1,10 +1,29 @@
 use thiserror::Error;
+use {ChannelCount, FrameCount, SampleFormat, SampleRate};
 
 /// The requested host, although supported on this platform, is unavailable.
 #[derive(Clone, Debug, Error)]
 #[error("the requested host is unavailable")]
 pub struct HostUnavailable;
 
+/// May occur when resolving a `DeviceDescriptor` into a `Device`.
+#[derive(Debug, Error)]
+pub enum ResolveDeviceError {
+    /// The host of the device is unavailable on this system.
+    #[error("the requested host is unavailable")]
+    HostUnavailable,
+    /// The host has no device with the identifier of the descriptor. This can happen if the
+    /// device has been unplugged.
+    #[error("The requested device was not found. For example, it has been unplugged.")]
+    DeviceNotFound,
+}
+
+impl From<HostUnavailable> for ResolveDeviceError {
+    fn from(_: HostUnavailable) -> Self {
+        ResolveDeviceError::HostUnavailable
+    }
+}
+
 /// Some error has occurred that is specific to the backend from which it was produced.
 ///
 /// This error is often used as a catch-all in cases where:
@@ -45,6 +64,20 @@ pub enum DeviceNameError {
     },
 }
 
+/// An error that may occur while attempting to subscribe to device events.
+#[derive(Debug, Error)]
+pub enum WatchDevicesError {
+    /// The host does not support device event notifications.
+    #[error("The host does not support device event notifications.")]
+    NotSupported,
+    /// See the `BackendSpecificError` docs for more information about this error variant.
+    #[error("{err}")]
+    BackendSpecific {
+        #[from]
+        err: BackendSpecificError,
+    },
+}
+
 /// Error that can happen when enumerating the list of supported formats.
 #[derive(Debug, Error)]
 pub enum SupportedStreamConfigsError {
@@ -83,6 +116,51 @@ pub enum DefaultStreamConfigError {
     },
 }
 
+/// May occur when matching a `ConfigRequest` against the configs a device supports, stating why
+/// no config matched.
+#[derive(Debug, Error)]
+pub enum MatchConfigError {
+    /// The device no longer exists. This can happen if the device is disconnected while the
+    /// program is running.
+    #[error("The requested device is no longer available. For example, it has been unplugged.")]
+    DeviceNotAvailable,
+    /// The device does not support any config in the requested direction.
+    #[error("The device does not support any stream configuration.")]
+    NoSupportedConfigs,
+    /// No supported config has as many channels as requested.
+    #[error("No supported configuration has {min_channels} channels, the most is {max_channels}.")]
+    TooFewChannels {
+        min_channels: ChannelCount,
+        max_channels: ChannelCount,
+    },
+    /// No supported config has any of the requested sample formats.
+    #[error("No supported configuration has an acceptable sample format, only {supported:?}.")]
+    NoAcceptableFormat { supported: Vec<SampleFormat> },
+    /// Some supported configs have enough channels and others an acceptable sample format, but
+    /// none has both.
+    #[error(
+        "No supported configuration has both enough channels and an acceptable sample format."
+    )]
+    NoMatchingConfig,
+    /// See the `BackendSpecificError` docs for more information about this error variant.
+    #[error("{err}")]
+    BackendSpecific {
+        #[from]
+        err: BackendSpecificError,
+    },
+}
+
+impl From<SupportedStreamConfigsError> for MatchConfigError {
+    fn from(err: SupportedStreamConfigsError) -> Self {
+        match err {
+            SupportedStreamConfigsError::DeviceNotAvailable => MatchConfigError::DeviceNotAvailable,
+            // Devices report an invalid argument when queried in a direction they do not support.
+            SupportedStreamConfigsError::InvalidArgument => MatchConfigError::NoSupportedConfigs,
+            SupportedStreamConfigsError::BackendSpecific { err } => err.into(),
+        }
+    }
+}
+
 /// Error that can happen when creating a `Stream`.
 #[derive(Debug, Error)]
 pub enum BuildStreamError {
@@ -146,6 +224,23 @@ pub enum PauseStreamError {
     },
 }
 
+/// Errors that might occur when querying or changing the volume of a stream.
+#[derive(Debug, Error)]
+pub enum VolumeError {
+    /// The volume of the stream cannot be controlled.
+    #[error("The volume of the stream cannot be controlled.")]
+    NotSupported,
+    /// The device associated with the stream is no longer available.
+    #[error("the device associated with the stream is no longer available")]
+    DeviceNotAvailable,
+    /// See the `BackendSpecificError` docs for more information about this error variant.
+    #[error("{err}")]
+    BackendSpecific {
+        #[from]
+        err: BackendSpecificError,
+    },
+}
+
 /// Errors that might occur while a stream is running.
 #[derive(Debug, Error)]
 pub enum StreamError {
@@ -153,6 +248,34 @@ pub enum StreamError {
     /// program is running.
     #[error("The requested device is no longer available. For example, it has been unplugged.")]
     DeviceNotAvailable,
+    /// The output buffer ran empty before new data was provided, causing a gap in playback.
+    ///
+    /// `frames_lost` is the number of frames that could not be played, if known.
+    #[error("An output buffer underrun occurred.")]
+    BufferUnderrun { frames_lost: Option<FrameCount> },
+    /// The input buffer filled up before its data was consumed, causing captured data to be
+    /// dropped.
+    ///
+    /// `frames_lost` is the number of frames that were dropped, if known.
+    #[error("An input buffer overrun occurred.")]
+    BufferOverrun { frames_lost: Option<FrameCount> },
+    /// The sample rate or buffer size of the device was changed from outside of the application,
+    /// for example by the user reconfiguring a sound server.
+    ///
+    /// The stream keeps running with the new configuration: the data callback receives buffers of
+    /// `buffer_size` frames at `sample_rate` from now on.
+    #[error("The stream configuration changed to {} Hz with {buffer_size} frame buffers.", sample_rate.0)]
+    ConfigChanged {
+        sample_rate: SampleRate,
+        buffer_size: FrameCount,
+    },
+    /// The host supplied data in another sample format than the one the stream was built with, so
+    /// that the data callback could not be called with it. Reported once per stream.
+    #[error("The host supplied {actual:?} samples to a stream of {expected:?} samples.")]
+    IncorrectSampleFormat {
+        expected: SampleFormat,
+        actual: SampleFormat,
+    },
     /// See the `BackendSpecificError` docs for more information about this error variant.
     #[error("{err}")]
     BackendSpecific {
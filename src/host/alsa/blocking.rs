@@ -0,0 +1,87 @@
+use super::alsa;
+use super::{xrun_frames_lost, StreamInner};
+use blocking::{BlockingRead, BlockingWrite};
+use StreamError;
+
+/// A stream that is read from or written to directly from the calling thread, without a worker
+/// thread.
+///
+/// The handle is non-blocking like those of callback based streams, so the calling thread waits
+/// on the handle whenever ALSA cannot take or provide more frames right away.
+pub struct BlockingStream {
+    inner: StreamInner,
+    // An xrun that occurred in the middle of a read or write, to be returned by the next one.
+    xrun: Option<StreamError>,
+}
+
+impl BlockingStream {
+    pub fn new(inner: StreamInner) -> Self {
+        BlockingStream { inner, xrun: None }
+    }
+
+    fn frame_size(&self) -> usize {
+        self.inner.conf.channels as usize * self.inner.sample_format.sample_size()
+    }
+
+    // Recovers from an error of `readi` or `writei` so that the transfer can continue, or returns
+    // the error if it cannot.
+    fn recover(&mut self, err: alsa::Error) -> Result<(), StreamError> {
+        match err.errno() {
+            Some(nix::errno::Errno::EAGAIN) => match self.inner.channel.wait(None) {
+                Ok(_) => Ok(()),
+                Err(err) => self.recover(err),
+            },
+            Some(nix::errno::Errno::EPIPE) => {
+                let frames_lost = xrun_frames_lost(&self.inner);
+                self.xrun = Some(match self.inner.stream_type {
+                    alsa::Direction::Capture => StreamError::BufferOverrun { frames_lost },
+                    alsa::Direction::Playback => StreamError::BufferUnderrun { frames_lost },
+                });
+                self.inner.channel.prepare()?;
+                Ok(())
+            }
+            Some(nix::errno::Errno::ESTRPIPE) => {
+                self.inner.channel.try_recover(err, true)?;
+                Ok(())
+            }
+            Some(nix::errno::Errno::ENODEV) => Err(StreamError::DeviceNotAvailable),
+            _ => Err(err.into()),
+        }
+    }
+}
+
+impl BlockingRead for BlockingStream {
+    fn read(&mut self, bytes: &mut [u8]) -> Result<(), StreamError> {
+        if let Some(err) = self.xrun.take() {
+            return Err(err);
+        }
+        let frame_size = self.frame_size();
+        let mut read = 0;
+        while read < bytes.len() {
+            let result = self.inner.channel.io_bytes().readi(&mut bytes[read..]);
+            match result {
+                Ok(frames) => read += frames * frame_size,
+                Err(err) => self.recover(err)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl BlockingWrite for BlockingStream {
+    fn write(&mut self, bytes: &[u8]) -> Result<(), StreamError> {
+        if let Some(err) = self.xrun.take() {
+            return Err(err);
+        }
+        let frame_size = self.frame_size();
+        let mut written = 0;
+        while written < bytes.len() {
+            let result = self.inner.channel.io_bytes().writei(&bytes[written..]);
+            match result {
+                Ok(frames) => written += frames * frame_size,
+                Err(err) => self.recover(err)?,
+            }
+        }
+        Ok(())
+    }
+}
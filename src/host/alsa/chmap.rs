@@ -0,0 +1,190 @@
+use super::alsa;
+use super::alsa::pcm::{Chmap, ChmapType};
+use super::libc::c_uint;
+use super::sys;
+use crate::{BuildStreamError, ChannelCount, ChannelPosition};
+use std::fmt::Write;
+
+// The positions of a channel map.
+//
+// The map is parsed from its printed form, e.g. `FL FR FC LFE RL RR`, as `ChmapPosition` lacks the
+// front center, LFE, rear right and side left positions and converting maps containing them
+// panics.
+fn positions(chmap: &Chmap) -> Vec<ChannelPosition> {
+    let mut printed = String::new();
+    if write!(printed, "{}", chmap).is_err() {
+        return Vec::new();
+    }
+    parse_positions(&printed)
+}
+
+fn parse_positions(printed: &str) -> Vec<ChannelPosition> {
+    printed
+        .split_whitespace()
+        .map(|name| match name.trim_end_matches("[INV]") {
+            "MONO" => ChannelPosition::Mono,
+            "FL" => ChannelPosition::FrontLeft,
+            "FR" => ChannelPosition::FrontRight,
+            "FC" => ChannelPosition::FrontCenter,
+            "LFE" => ChannelPosition::LowFrequency,
+            "RL" => ChannelPosition::RearLeft,
+            "RR" => ChannelPosition::RearRight,
+            "RC" => ChannelPosition::RearCenter,
+            "SL" => ChannelPosition::SideLeft,
+            "SR" => ChannelPosition::SideRight,
+            "FLC" => ChannelPosition::FrontLeftOfCenter,
+            "FRC" => ChannelPosition::FrontRightOfCenter,
+            "TC" => ChannelPosition::TopCenter,
+            "TFL" => ChannelPosition::TopFrontLeft,
+            "TFC" => ChannelPosition::TopFrontCenter,
+            "TFR" => ChannelPosition::TopFrontRight,
+            "TRL" => ChannelPosition::TopRearLeft,
+            "TRC" => ChannelPosition::TopRearCenter,
+            "TRR" => ChannelPosition::TopRearRight,
+            _ => ChannelPosition::Unknown,
+        })
+        .collect()
+}
+
+// The `SND_CHMAP_*` value of `position`. Those values are used rather than `ChmapPosition`, which
+// lacks the front center, LFE, rear right and side left positions.
+fn alsa_position(position: ChannelPosition) -> Option<c_uint> {
+    let position = match position {
+        ChannelPosition::Mono => sys::SND_CHMAP_MONO,
+        ChannelPosition::FrontLeft => sys::SND_CHMAP_FL,
+        ChannelPosition::FrontRight => sys::SND_CHMAP_FR,
+        ChannelPosition::FrontCenter => sys::SND_CHMAP_FC,
+        ChannelPosition::LowFrequency => sys::SND_CHMAP_LFE,
+        ChannelPosition::RearLeft => sys::SND_CHMAP_RL,
+        ChannelPosition::RearRight => sys::SND_CHMAP_RR,
+        ChannelPosition::RearCenter => sys::SND_CHMAP_RC,
+        ChannelPosition::SideLeft => sys::SND_CHMAP_SL,
+        ChannelPosition::SideRight => sys::SND_CHMAP_SR,
+        ChannelPosition::FrontLeftOfCenter => sys::SND_CHMAP_FLC,
+        ChannelPosition::FrontRightOfCenter => sys::SND_CHMAP_FRC,
+        ChannelPosition::TopCenter => sys::SND_CHMAP_TC,
+        ChannelPosition::TopFrontLeft => sys::SND_CHMAP_TFL,
+        ChannelPosition::TopFrontCenter => sys::SND_CHMAP_TFC,
+        ChannelPosition::TopFrontRight => sys::SND_CHMAP_TFR,
+        ChannelPosition::TopRearLeft => sys::SND_CHMAP_TRL,
+        ChannelPosition::TopRearCenter => sys::SND_CHMAP_TRC,
+        ChannelPosition::TopRearRight => sys::SND_CHMAP_TRR,
+        ChannelPosition::Unknown => return None,
+    };
+    Some(position)
+}
+
+// A `snd_pcm_chmap_t` built from `ChannelPosition`s: the number of channels followed by the
+// position of each.
+struct RawChmap(Vec<c_uint>);
+
+impl RawChmap {
+    // The map of `map`, unless it holds an unknown position.
+    fn new(map: &[ChannelPosition]) -> Option<Self> {
+        let mut raw = Vec::with_capacity(map.len() + 1);
+        raw.push(map.len() as c_uint);
+        for &position in map {
+            raw.push(alsa_position(position)?);
+        }
+        Some(RawChmap(raw))
+    }
+
+    fn as_ptr(&self) -> *const sys::snd_pcm_chmap_t {
+        self.0.as_ptr() as *const sys::snd_pcm_chmap_t
+    }
+}
+
+// Whether `a` holds the same positions as `b`, in any order.
+fn is_permutation(a: &[ChannelPosition], b: &[ChannelPosition]) -> bool {
+    let count = |map: &[ChannelPosition], position| map.iter().filter(|&&p| p == position).count();
+    a.len() == b.len()
+        && a.iter()
+            .all(|&position| count(a, position) == count(b, position))
+}
+
+/// The first channel map the device offers for `channels` channels.
+pub(super) fn query(pcm: &alsa::PCM, channels: ChannelCount) -> Option<Vec<ChannelPosition>> {
+    pcm.query_chmaps()
+        .map(|(_, chmap)| positions(&chmap))
+        .find(|map| map.len() == channels as usize)
+}
+
+/// The channel map in effect, if the device reports it.
+pub(super) fn current(pcm: &alsa::PCM) -> Option<Vec<ChannelPosition>> {
+    pcm.get_chmap().ok().map(|chmap| positions(&chmap))
+}
+
+/// Apply `map` to a device whose hardware parameters have been set.
+///
+/// The map must be one of those the device offers, or a reordering of one that the device allows
+/// to be reordered.
+pub(super) fn set(pcm: &alsa::PCM, map: &[ChannelPosition]) -> Result<(), BuildStreamError> {
+    for (kind, chmap) in pcm.query_chmaps() {
+        let offered = positions(&chmap);
+        if offered == map {
+            pcm.set_chmap(&chmap)?;
+            return Ok(());
+        }
+        if kind == ChmapType::Var && is_permutation(&offered, map) {
+            if let Some(raw) = RawChmap::new(map) {
+                unsafe { sys::set_chmap(pcm, raw.as_ptr())? };
+                return Ok(());
+            }
+        }
+    }
+    Err(BuildStreamError::StreamConfigNotSupported)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{alsa_position, is_permutation, parse_positions, sys, RawChmap};
+    use std::os::raw::c_char;
+    use ChannelPosition;
+
+    // The printed form of `raw`, as alsa-lib prints it.
+    fn print(raw: &RawChmap) -> String {
+        let mut buf = [0 as c_char; 256];
+        let len = unsafe { sys::snd_pcm_chmap_print(raw.as_ptr(), buf.len(), buf.as_mut_ptr()) };
+        assert!(len > 0);
+        let bytes: Vec<u8> = buf[..len as usize].iter().map(|&c| c as u8).collect();
+        String::from_utf8(bytes).unwrap()
+    }
+
+    #[test]
+    fn test_chmap_positions() {
+        let map = [
+            ChannelPosition::TopFrontLeft,
+            ChannelPosition::FrontRight,
+            ChannelPosition::Mono,
+        ];
+        assert_eq!(parse_positions(&print(&RawChmap::new(&map).unwrap())), map);
+        assert_eq!(alsa_position(ChannelPosition::Unknown), None);
+        assert!(RawChmap::new(&[ChannelPosition::Unknown]).is_none());
+
+        let surround = ChannelPosition::default_layout(6).unwrap();
+        let mut reordered = surround.clone();
+        reordered.swap(2, 4);
+        assert!(is_permutation(&surround, &reordered));
+        assert!(!is_permutation(&surround, &surround[..5]));
+    }
+
+    #[test]
+    fn test_chmap_round_trips_surround() {
+        let surround = ChannelPosition::default_layout(6).unwrap();
+        let raw = RawChmap::new(&surround).unwrap();
+        assert_eq!(print(&raw), "FL FR FC LFE RL RR");
+        assert_eq!(parse_positions(&print(&raw)), surround);
+
+        // A reordered 5.1 map with side rather than rear channels.
+        let reordered = [
+            ChannelPosition::FrontLeft,
+            ChannelPosition::FrontCenter,
+            ChannelPosition::FrontRight,
+            ChannelPosition::SideLeft,
+            ChannelPosition::SideRight,
+            ChannelPosition::LowFrequency,
+        ];
+        let raw = RawChmap::new(&reordered).unwrap();
+        assert_eq!(parse_positions(&print(&raw)), reordered);
+    }
+}
@@ -1,17 +1,162 @@
 use super::alsa;
 use super::parking_lot::Mutex;
 use super::{Device, DeviceHandles};
-use {BackendSpecificError, DevicesError};
+use {BackendSpecificError, DevicesError, StreamDirection};
+
+/// A sound card, as described by its control interface.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct CardInfo {
+    /// The index of the card, which may change when cards are added or removed.
+    pub index: i32,
+    /// The identifier of the card, e.g. `PCH`, which is stable across reboots and re-enumerations.
+    pub id: String,
+    pub name: String,
+    pub long_name: String,
+    pub driver: String,
+}
+
+/// The kind of PCM a device refers to.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum PcmKind {
+    /// Direct access to the hardware (`hw:`), limited to the configurations the hardware supports.
+    Hardware,
+    /// The hardware with automatic conversion of the sample format, rate and channel count
+    /// (`plughw:`).
+    PlugHardware,
+    /// Any other PCM defined by the ALSA configuration, such as `default`, `dmix` or `front`.
+    #[default]
+    Plugin,
+}
+
+/// The metadata of a PCM device, from its name and device name hint.
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+pub struct DeviceInfo {
+    /// An identifier of the device that survives re-enumerations, referring to the card by its ID
+    /// rather than its index. See `Host::device_by_id`.
+    pub id: String,
+    pub kind: PcmKind,
+    /// The card the device belongs to, if any.
+    pub card: Option<CardInfo>,
+    /// The device number on the card.
+    pub device: Option<u32>,
+    /// The subdevice number of the device.
+    pub subdevice: Option<u32>,
+    /// A human readable description of the device, which may span several lines.
+    pub description: Option<String>,
+    /// The only direction supported by the device, or `None` if it supports both.
+    pub direction: Option<StreamDirection>,
+}
+
+impl DeviceInfo {
+    // The metadata of the device `name`, as far as it can be derived from the name.
+    pub(super) fn from_name(name: &str, cards: &[CardInfo]) -> Self {
+        let (prefix, args) = match name.find(':') {
+            Some(pos) => (&name[..pos], &name[pos + 1..]),
+            None => (name, ""),
+        };
+        let kind = match prefix {
+            "hw" => PcmKind::Hardware,
+            "plughw" => PcmKind::PlugHardware,
+            _ => PcmKind::Plugin,
+        };
+
+        // Arguments are either given by name, e.g. `hw:CARD=PCH,DEV=0`, or by position, e.g.
+        // `hw:0,0`.
+        let (mut card, mut device, mut subdevice) = (None, None, None);
+        for (position, arg) in args.split(',').filter(|arg| !arg.is_empty()).enumerate() {
+            let (key, value) = match arg.find('=') {
+                Some(pos) => (&arg[..pos], &arg[pos + 1..]),
+                None => match position {
+                    0 => ("CARD", arg),
+                    1 => ("DEV", arg),
+                    2 => ("SUBDEV", arg),
+                    _ => continue,
+                },
+            };
+            let value = value.trim_matches('"');
+            match key {
+                "CARD" => card = Some(value),
+                "DEV" => device = value.parse().ok(),
+                "SUBDEV" => subdevice = value.parse().ok(),
+                _ => (),
+            }
+        }
+        let card = card.and_then(|card| {
+            cards
+                .iter()
+                .find(|info| info.id == card || card.parse() == Ok(info.index))
+                .cloned()
+        });
+
+        // Refer to the card by its ID, as its index depends on the order cards were detected in.
+        let id = match card {
+            Some(ref card) if !args.contains("CARD=") => {
+                let mut id = format!("{}:CARD={}", prefix, card.id);
+                if let Some(device) = device {
+                    id.push_str(&format!(",DEV={}", device));
+                }
+                if let Some(subdevice) = subdevice {
+                    id.push_str(&format!(",SUBDEV={}", subdevice));
+                }
+                id
+            }
+            _ => name.to_owned(),
+        };
+
+        DeviceInfo {
+            id,
+            kind,
+            card,
+            device,
+            subdevice,
+            description: None,
+            direction: None,
+        }
+    }
+
+    fn from_hint(name: &str, hint: alsa::device_name::Hint, cards: &[CardInfo]) -> Self {
+        let direction = hint.direction.map(|direction| match direction {
+            alsa::Direction::Capture => StreamDirection::Input,
+            alsa::Direction::Playback => StreamDirection::Output,
+        });
+        DeviceInfo {
+            description: hint.desc,
+            direction,
+            ..DeviceInfo::from_name(name, cards)
+        }
+    }
+}
+
+/// The sound cards currently present, in index order, as enumerated through their control
+/// interfaces.
+pub fn cards() -> Vec<CardInfo> {
+    alsa::card::Iter::new()
+        .filter_map(Result::ok)
+        .filter_map(|card| {
+            let ctl = alsa::Ctl::from_card(&card, false).ok()?;
+            let info = ctl.card_info().ok()?;
+            Some(CardInfo {
+                index: card.get_index(),
+                id: info.get_id().ok()?.to_owned(),
+                name: info.get_name().unwrap_or_default().to_owned(),
+                long_name: info.get_longname().unwrap_or_default().to_owned(),
+                driver: info.get_driver().unwrap_or_default().to_owned(),
+            })
+        })
+        .collect()
+}
 
 /// ALSA implementation for `Devices`.
 pub struct Devices {
     hint_iter: alsa::device_name::HintIter,
+    cards: Vec<CardInfo>,
 }
 
 impl Devices {
     pub fn new() -> Result<Self, DevicesError> {
         Ok(Devices {
             hint_iter: alsa::device_name::HintIter::new_str(None, "pcm")?,
+            cards: cards(),
         })
     }
 }
@@ -30,16 +175,28 @@ impl Iterator for Devices {
                     let name = match hint.name {
                         None => continue,
                         // Ignoring the `null` device.
-                        Some(name) if name == "null" => continue,
-                        Some(name) => name,
+                        Some(ref name) if name == "null" => continue,
+                        Some(ref name) => name.clone(),
                     };
 
-                    if let Ok(handles) = DeviceHandles::open(&name) {
-                        return Some(Device {
-                            name,
-                            handles: Mutex::new(handles),
-                        });
-                    }
+                    // Devices that cannot be opened are skipped, as many of the hints refer to
+                    // PCMs the hardware cannot back, unless they are merely in use. Only the
+                    // direction of the hint is opened, if it names one.
+                    let directions: &[alsa::Direction] = match hint.direction {
+                        Some(alsa::Direction::Playback) => &[alsa::Direction::Playback],
+                        Some(alsa::Direction::Capture) => &[alsa::Direction::Capture],
+                        None => &[alsa::Direction::Playback, alsa::Direction::Capture],
+                    };
+                    let handles = match DeviceHandles::open(&name, directions) {
+                        Ok(handles) => handles,
+                        Err(_) => continue,
+                    };
+                    return Some(Device {
+                        info: DeviceInfo::from_hint(&name, hint, &self.cards),
+                        name,
+                        handles: Mutex::new(handles),
+                        access_mode: Default::default(),
+                    });
                 }
             }
         }
@@ -50,7 +207,9 @@ impl Iterator for Devices {
 pub fn default_input_device() -> Option<Device> {
     Some(Device {
         name: "default".to_owned(),
+        info: DeviceInfo::from_name("default", &[]),
         handles: Mutex::new(Default::default()),
+        access_mode: Default::default(),
     })
 }
 
@@ -58,7 +217,9 @@ pub fn default_input_device() -> Option<Device> {
 pub fn default_output_device() -> Option<Device> {
     Some(Device {
         name: "default".to_owned(),
+        info: DeviceInfo::from_name("default", &[]),
         handles: Mutex::new(Default::default()),
+        access_mode: Default::default(),
     })
 }
 
@@ -68,3 +229,35 @@ impl From<alsa::Error> for DevicesError {
         err.into()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{CardInfo, DeviceInfo, PcmKind};
+
+    #[test]
+    fn test_device_info_from_name() {
+        let cards = [CardInfo {
+            index: 1,
+            id: "PCH".to_owned(),
+            name: "HDA Intel PCH".to_owned(),
+            long_name: "HDA Intel PCH at 0xf7f00000 irq 32".to_owned(),
+            driver: "HDA-Intel".to_owned(),
+        }];
+
+        let info = DeviceInfo::from_name("hw:CARD=PCH,DEV=3", &cards);
+        assert_eq!(info.id, "hw:CARD=PCH,DEV=3");
+        assert_eq!(info.kind, PcmKind::Hardware);
+        assert_eq!(info.card.as_ref(), Some(&cards[0]));
+        assert_eq!((info.device, info.subdevice), (Some(3), None));
+
+        let info = DeviceInfo::from_name("plughw:1,0,2", &cards);
+        assert_eq!(info.id, "plughw:CARD=PCH,DEV=0,SUBDEV=2");
+        assert_eq!(info.kind, PcmKind::PlugHardware);
+        assert_eq!((info.device, info.subdevice), (Some(0), Some(2)));
+
+        let info = DeviceInfo::from_name("default", &cards);
+        assert_eq!(info.id, "default");
+        assert_eq!(info.kind, PcmKind::Plugin);
+        assert_eq!(info.card, None);
+    }
+}
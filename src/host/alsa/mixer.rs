@@ -0,0 +1,136 @@
+use super::alsa;
+use super::alsa::mixer::{MilliBel, Mixer, Selem, SelemChannelId, SelemId};
+use super::DeviceInfo;
+use crate::{BackendSpecificError, VolumeError, VolumeRange};
+
+/// The simple mixer elements that control the volume of the playback PCMs of a card, in order of
+/// preference.
+const PLAYBACK_ELEMENTS: &[&str] = &["Master", "PCM", "Speaker", "Headphone"];
+
+/// The simple mixer elements that control the volume of the capture PCMs of a card, in order of
+/// preference.
+const CAPTURE_ELEMENTS: &[&str] = &["Capture", "Mic"];
+
+/// The mixer element controlling the volume of a PCM, on the card the PCM belongs to.
+///
+/// Only the name of the element is kept, and the mixer is opened anew for every access, so that
+/// no handle has to be shared with the audio thread.
+pub(super) struct MixerControl {
+    mixer_name: String,
+    element: String,
+    direction: alsa::Direction,
+    range: VolumeRange,
+    has_switch: bool,
+}
+
+impl MixerControl {
+    /// The preferred element with a volume in decibels on the card of the device, if the device
+    /// belongs to a card.
+    pub(super) fn find(info: &DeviceInfo, direction: alsa::Direction) -> Option<Self> {
+        let card = info.card.as_ref()?;
+        let mixer_name = format!("hw:CARD={}", card.id);
+        let mixer = Mixer::new(&mixer_name, false).ok()?;
+        let elements = match direction {
+            alsa::Direction::Playback => PLAYBACK_ELEMENTS,
+            alsa::Direction::Capture => CAPTURE_ELEMENTS,
+        };
+        elements.iter().find_map(|&element| {
+            let selem = mixer.find_selem(&SelemId::new(element, 0))?;
+            let (has_volume, has_switch, (min, max)) = match direction {
+                alsa::Direction::Playback => (
+                    selem.has_playback_volume(),
+                    selem.has_playback_switch(),
+                    selem.get_playback_db_range(),
+                ),
+                alsa::Direction::Capture => (
+                    selem.has_capture_volume(),
+                    selem.has_capture_switch(),
+                    selem.get_capture_db_range(),
+                ),
+            };
+            // Elements without a decibel scale report an empty range.
+            if !has_volume || min.0 >= max.0 {
+                return None;
+            }
+            Some(MixerControl {
+                mixer_name: mixer_name.clone(),
+                element: element.to_owned(),
+                direction,
+                range: VolumeRange {
+                    min_db: min.to_db(),
+                    max_db: max.to_db(),
+                },
+                has_switch,
+            })
+        })
+    }
+
+    pub(super) fn range(&self) -> VolumeRange {
+        self.range
+    }
+
+    /// Whether the element can mute the PCM. If not, muting is left to the software gain stage.
+    pub(super) fn has_switch(&self) -> bool {
+        self.has_switch
+    }
+
+    pub(super) fn volume(&self) -> Result<f32, VolumeError> {
+        self.with_selem(|selem| {
+            let volume = match self.direction {
+                alsa::Direction::Playback => selem.get_playback_vol_db(SelemChannelId::mono())?,
+                alsa::Direction::Capture => selem.get_capture_vol_db(SelemChannelId::mono())?,
+            };
+            Ok(volume.to_db())
+        })
+    }
+
+    pub(super) fn set_volume(&self, volume_db: f32) -> Result<(), VolumeError> {
+        let volume = MilliBel::from_db(self.range.clamp(volume_db));
+        self.with_selem(|selem| match self.direction {
+            alsa::Direction::Playback => selem.set_playback_db_all(volume, alsa::Round::Floor),
+            alsa::Direction::Capture => selem.set_capture_db_all(volume, alsa::Round::Floor),
+        })
+    }
+
+    pub(super) fn is_muted(&self) -> Result<bool, VolumeError> {
+        self.with_selem(|selem| {
+            // The switch is on while the element is unmuted.
+            let switch = match self.direction {
+                alsa::Direction::Playback => selem.get_playback_switch(SelemChannelId::mono())?,
+                alsa::Direction::Capture => selem.get_capture_switch(SelemChannelId::mono())?,
+            };
+            Ok(switch == 0)
+        })
+    }
+
+    pub(super) fn set_muted(&self, muted: bool) -> Result<(), VolumeError> {
+        let switch = if muted { 0 } else { 1 };
+        self.with_selem(|selem| match self.direction {
+            alsa::Direction::Playback => selem.set_playback_switch_all(switch),
+            alsa::Direction::Capture => selem.set_capture_switch_all(switch),
+        })
+    }
+
+    fn with_selem<F, T>(&self, f: F) -> Result<T, VolumeError>
+    where
+        F: FnOnce(&Selem) -> Result<T, alsa::Error>,
+    {
+        let mixer = match Mixer::new(&self.mixer_name, false) {
+            Err(err) if err.errno() == Some(nix::errno::Errno::ENOENT) => {
+                return Err(VolumeError::DeviceNotAvailable)
+            }
+            result => result?,
+        };
+        let selem = mixer
+            .find_selem(&SelemId::new(&self.element, 0))
+            .ok_or(VolumeError::DeviceNotAvailable)?;
+        Ok(f(&selem)?)
+    }
+}
+
+impl From<alsa::Error> for VolumeError {
+    fn from(err: alsa::Error) -> Self {
+        let err: BackendSpecificError = err.into();
+        err.into()
+    }
+}
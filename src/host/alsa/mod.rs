@@ -3,13 +3,16 @@ extern crate libc;
 extern crate parking_lot;
 
 use self::alsa::poll::Descriptors;
+use self::blocking::BlockingStream;
+use self::mixer::MixerControl;
 use self::parking_lot::Mutex;
 use crate::{
     BackendSpecificError, BufferSize, BuildStreamError, ChannelCount, Data,
-    DefaultStreamConfigError, DeviceNameError, DevicesError, InputCallbackInfo, OutputCallbackInfo,
-    PauseStreamError, PlayStreamError, SampleFormat, SampleRate, StreamConfig, StreamError,
-    SupportedBufferSize, SupportedStreamConfig, SupportedStreamConfigRange,
-    SupportedStreamConfigsError,
+    DefaultStreamConfigError, DeviceEvent, DeviceNameError, DeviceWatcher, DevicesError,
+    DuplexCallbackInfo, FrameCount, InputCallbackInfo, InputStreamReader, OutputCallbackInfo,
+    OutputStreamWriter, PauseStreamError, PlayStreamError, SampleFormat, SampleRate, StreamConfig,
+    StreamError, SupportedBufferSize, SupportedStreamConfig, SupportedStreamConfigRange,
+    SupportedStreamConfigsError, VolumeError, VolumeRange, WatchDevicesError,
 };
 use std::cmp;
 use std::convert::TryInto;
@@ -17,13 +20,21 @@ use std::sync::Arc;
 use std::thread::{self, JoinHandle};
 use std::vec::IntoIter as VecIntoIter;
 use traits::{DeviceTrait, HostTrait, StreamTrait};
+use volume::SoftwareVolume;
 
-pub use self::enumerate::{default_input_device, default_output_device, Devices};
+pub use self::enumerate::{
+    cards, default_input_device, default_output_device, CardInfo, DeviceInfo, Devices, PcmKind,
+};
 
 pub type SupportedInputConfigs = VecIntoIter<SupportedStreamConfigRange>;
 pub type SupportedOutputConfigs = VecIntoIter<SupportedStreamConfigRange>;
 
+mod blocking;
+mod chmap;
 mod enumerate;
+mod mixer;
+mod sys;
+mod watch;
 
 /// The default linux, dragonfly and freebsd host type.
 #[derive(Debug)]
@@ -33,6 +44,19 @@ impl Host {
     pub fn new() -> Result<Self, crate::HostUnavailable> {
         Ok(Host)
     }
+
+    /// The sound cards currently present, in index order.
+    pub fn cards(&self) -> Vec<CardInfo> {
+        cards()
+    }
+
+    /// The device with the given identifier, as returned by `Device::id`, if it is present.
+    pub fn device_by_id(&self, id: &str) -> Option<Device> {
+        if id == "default" {
+            return default_output_device();
+        }
+        Devices::new().ok()?.find(|device| device.id() == id)
+    }
 }
 
 impl HostTrait for Host {
@@ -48,6 +72,10 @@ impl HostTrait for Host {
         Devices::new()
     }
 
+    fn device_by_id(&self, id: &str) -> Option<Self::Device> {
+        Host::device_by_id(self, id)
+    }
+
     fn default_input_device(&self) -> Option<Self::Device> {
         default_input_device()
     }
@@ -55,6 +83,14 @@ impl HostTrait for Host {
     fn default_output_device(&self) -> Option<Self::Device> {
         default_output_device()
     }
+
+    fn watch_devices<F>(&self, callback: F) -> Result<DeviceWatcher, WatchDevicesError>
+    where
+        F: FnMut(DeviceEvent) + Send + 'static,
+    {
+        let thread = watch::DeviceWatcherThread::spawn(callback)?;
+        Ok(DeviceWatcher::new(thread))
+    }
 }
 
 impl DeviceTrait for Device {
@@ -66,6 +102,10 @@ impl DeviceTrait for Device {
         Device::name(self)
     }
 
+    fn id(&self) -> Result<String, DeviceNameError> {
+        Ok(Device::id(self).to_owned())
+    }
+
     fn supported_input_configs(
         &self,
     ) -> Result<Self::SupportedInputConfigs, SupportedStreamConfigsError> {
@@ -97,8 +137,13 @@ impl DeviceTrait for Device {
         D: FnMut(&Data, &InputCallbackInfo) + Send + 'static,
         E: FnMut(StreamError) + Send + 'static,
     {
-        let stream_inner =
-            self.build_stream_inner(conf, sample_format, alsa::Direction::Capture)?;
+        let stream_inner = self.build_stream_inner(
+            conf,
+            sample_format,
+            alsa::Direction::Capture,
+            self.access_mode,
+            false,
+        )?;
         let stream = Stream::new_input(Arc::new(stream_inner), data_callback, error_callback);
         Ok(stream)
     }
@@ -114,11 +159,141 @@ impl DeviceTrait for Device {
         D: FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static,
         E: FnMut(StreamError) + Send + 'static,
     {
-        let stream_inner =
-            self.build_stream_inner(conf, sample_format, alsa::Direction::Playback)?;
+        let stream_inner = self.build_stream_inner(
+            conf,
+            sample_format,
+            alsa::Direction::Playback,
+            self.access_mode,
+            false,
+        )?;
         let stream = Stream::new_output(Arc::new(stream_inner), data_callback, error_callback);
         Ok(stream)
     }
+
+    fn build_input_stream_planar_raw<D, E>(
+        &self,
+        conf: &StreamConfig,
+        sample_format: SampleFormat,
+        data_callback: D,
+        error_callback: E,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        D: FnMut(&Data, &InputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        let stream_inner = self.build_stream_inner(
+            conf,
+            sample_format,
+            alsa::Direction::Capture,
+            AccessMode::ReadWrite,
+            true,
+        )?;
+        let stream_inner = Arc::new(stream_inner);
+        let stream = if stream_inner.planar {
+            Stream::new_input(stream_inner, data_callback, error_callback)
+        } else {
+            let frames = stream_inner.buffer_frames() as usize;
+            let data_callback = crate::planar::deinterleave_input(
+                stream_inner.conf.channels,
+                sample_format,
+                frames,
+                data_callback,
+            );
+            Stream::new_input(stream_inner, data_callback, error_callback)
+        };
+        Ok(stream)
+    }
+
+    fn build_output_stream_planar_raw<D, E>(
+        &self,
+        conf: &StreamConfig,
+        sample_format: SampleFormat,
+        data_callback: D,
+        error_callback: E,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        D: FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        let stream_inner = self.build_stream_inner(
+            conf,
+            sample_format,
+            alsa::Direction::Playback,
+            AccessMode::ReadWrite,
+            true,
+        )?;
+        let stream_inner = Arc::new(stream_inner);
+        let stream = if stream_inner.planar {
+            Stream::new_output(stream_inner, data_callback, error_callback)
+        } else {
+            let frames = stream_inner.buffer_frames() as usize;
+            let data_callback = crate::planar::interleave_output(
+                stream_inner.conf.channels,
+                sample_format,
+                frames,
+                data_callback,
+            );
+            Stream::new_output(stream_inner, data_callback, error_callback)
+        };
+        Ok(stream)
+    }
+
+    fn build_duplex_stream_raw<D, E>(
+        &self,
+        input_conf: &StreamConfig,
+        output_conf: &StreamConfig,
+        sample_format: SampleFormat,
+        data_callback: D,
+        error_callback: E,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        D: FnMut(&Data, &mut Data, &DuplexCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        let (input, output) =
+            self.build_duplex_stream_inner(input_conf, output_conf, sample_format)?;
+        let stream = Stream::new_duplex(
+            Arc::new(input),
+            Arc::new(output),
+            data_callback,
+            error_callback,
+        );
+        Ok(stream)
+    }
+
+    fn build_input_stream_blocking(
+        &self,
+        conf: &StreamConfig,
+        sample_format: SampleFormat,
+    ) -> Result<InputStreamReader, BuildStreamError> {
+        let stream_inner = self.build_stream_inner(
+            conf,
+            sample_format,
+            alsa::Direction::Capture,
+            AccessMode::ReadWrite,
+            false,
+        )?;
+        let stream = BlockingStream::new(stream_inner);
+        Ok(InputStreamReader::new(stream, conf, sample_format))
+    }
+
+    fn build_output_stream_blocking(
+        &self,
+        conf: &StreamConfig,
+        sample_format: SampleFormat,
+    ) -> Result<OutputStreamWriter, BuildStreamError> {
+        // Not started yet, as the stream would underrun right away. The first write starts it, or
+        // the write that fills the buffer if a period configuration was requested.
+        let stream_inner = self.open_stream_inner(
+            conf,
+            sample_format,
+            alsa::Direction::Playback,
+            AccessMode::ReadWrite,
+            false,
+        )?;
+        let stream = BlockingStream::new(stream_inner);
+        Ok(OutputStreamWriter::new(stream, conf, sample_format))
+    }
 }
 
 struct TriggerSender(libc::c_int);
@@ -172,17 +347,16 @@ struct DeviceHandles {
 }
 
 impl DeviceHandles {
-    /// Create `DeviceHandles` for `name` and try to open a handle for both
-    /// directions. Returns `Ok` if either direction is opened successfully.
-    fn open(name: &str) -> Result<Self, alsa::Error> {
+    /// Create `DeviceHandles` for `name` and try to open a handle for each of the `directions`.
+    /// Returns `Ok` if the device is present, see `device_presence`.
+    fn open(name: &str, directions: &[alsa::Direction]) -> Result<Self, alsa::Error> {
         let mut handles = Self::default();
-        let playback_err = handles.try_open(name, alsa::Direction::Playback).err();
-        let capture_err = handles.try_open(name, alsa::Direction::Capture).err();
-        if let Some(err) = capture_err.and(playback_err) {
-            Err(err)
-        } else {
-            Ok(handles)
-        }
+        let errors: Vec<_> = directions
+            .iter()
+            .map(|&direction| handles.try_open(name, direction).err())
+            .collect();
+        device_presence(errors)?;
+        Ok(handles)
     }
 
     /// Get a mutable reference to the `Option` for a specific `stream_type`.
@@ -223,17 +397,126 @@ impl DeviceHandles {
     }
 }
 
+// Whether a device is present given the errors of opening each of its directions: it is if any
+// direction was opened or is merely busy. Otherwise the first error is returned.
+fn device_presence<I>(errors: I) -> Result<(), alsa::Error>
+where
+    I: IntoIterator<Item = Option<alsa::Error>>,
+{
+    let mut first_err = None;
+    for err in errors {
+        match err {
+            None => return Ok(()),
+            Some(err) if err.errno() == Some(nix::errno::Errno::EBUSY) => return Ok(()),
+            Some(err) => {
+                first_err.get_or_insert(err);
+            }
+        }
+    }
+    first_err.map_or(Ok(()), Err)
+}
+
+/// How the audio of a stream is transferred between the data callback and the ring buffer of the
+/// device.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum AccessMode {
+    /// The audio is copied between an intermediate buffer passed to the data callback and the
+    /// device with `snd_pcm_readi` and `snd_pcm_writei`.
+    #[default]
+    ReadWrite,
+    /// The data callback reads from and writes to the memory mapped ring buffer of the device
+    /// directly, saving a copy per period.
+    MMap,
+}
+
 pub struct Device {
     name: String,
+    info: DeviceInfo,
     handles: Mutex<DeviceHandles>,
+    access_mode: AccessMode,
 }
 
 impl Device {
+    /// The metadata of the device.
+    pub fn info(&self) -> &DeviceInfo {
+        &self.info
+    }
+
+    /// An identifier of the device that survives re-enumerations, to find it again via
+    /// `Host::device_by_id`.
+    pub fn id(&self) -> &str {
+        &self.info.id
+    }
+
+    /// The access mode requested for the input and output streams built from this device.
+    pub fn access_mode(&self) -> AccessMode {
+        self.access_mode
+    }
+
+    /// Set the access mode requested for the input and output streams built from this device.
+    ///
+    /// Streams fall back to `AccessMode::ReadWrite` if the device does not support memory mapped
+    /// access, see `Stream::access_mode`. Duplex and blocking streams always use
+    /// `AccessMode::ReadWrite`.
+    pub fn set_access_mode(&mut self, access_mode: AccessMode) {
+        self.access_mode = access_mode;
+    }
+
     fn build_stream_inner(
         &self,
         conf: &StreamConfig,
         sample_format: SampleFormat,
         stream_type: alsa::Direction,
+        access_mode: AccessMode,
+        planar: bool,
+    ) -> Result<StreamInner, BuildStreamError> {
+        let stream_inner =
+            self.open_stream_inner(conf, sample_format, stream_type, access_mode, planar)?;
+        stream_inner.channel.start()?;
+        Ok(stream_inner)
+    }
+
+    // Opens both directions of the device and links them so that they are started, stopped and
+    // paused together by ALSA.
+    fn build_duplex_stream_inner(
+        &self,
+        input_conf: &StreamConfig,
+        output_conf: &StreamConfig,
+        sample_format: SampleFormat,
+    ) -> Result<(StreamInner, StreamInner), BuildStreamError> {
+        if input_conf.sample_rate != output_conf.sample_rate {
+            return Err(BuildStreamError::StreamConfigNotSupported);
+        }
+        let input = self.open_stream_inner(
+            input_conf,
+            sample_format,
+            alsa::Direction::Capture,
+            AccessMode::ReadWrite,
+            false,
+        )?;
+        let output = self.open_stream_inner(
+            output_conf,
+            sample_format,
+            alsa::Direction::Playback,
+            AccessMode::ReadWrite,
+            false,
+        )?;
+        input.channel.link(&output.channel)?;
+        start_duplex(&output)?;
+        Ok((input, output))
+    }
+
+    // Opens and prepares a handle for the given direction without starting it.
+    //
+    // If `planar` is set, non-interleaved access is requested in place of `access_mode`, falling
+    // back to interleaved read/write access if the device refuses it.
+    fn open_stream_inner(
+        &self,
+        conf: &StreamConfig,
+        sample_format: SampleFormat,
+        stream_type: alsa::Direction,
+        access_mode: AccessMode,
+        planar: bool,
     ) -> Result<StreamInner, BuildStreamError> {
         let handle_result = self
             .handles
@@ -251,11 +534,36 @@ impl Device {
             Err((e, _)) => return Err(e.into()),
             Ok(handle) => handle,
         };
-        let can_pause = {
-            let hw_params = set_hw_params_from_format(&handle, conf, sample_format)?;
-            hw_params.can_pause()
+        // The rate is only matched as closely as the device allows, so keep the one in effect.
+        let (can_pause, conf, access_mode, planar) = {
+            let hw_params =
+                set_hw_params_from_format(&handle, conf, sample_format, access_mode, planar)?;
+            let channels = hw_params.get_channels()? as ChannelCount;
+            let channel_map = match conf.channel_map {
+                Some(ref map) if map.len() != channels as usize => {
+                    return Err(BuildStreamError::StreamConfigNotSupported)
+                }
+                Some(ref map) => {
+                    chmap::set(&handle, map)?;
+                    Some(map.clone())
+                }
+                None => chmap::current(&handle),
+            };
+            let conf = StreamConfig {
+                channels,
+                sample_rate: SampleRate(hw_params.get_rate()?),
+                buffer_size: conf.buffer_size.clone(),
+                channel_map,
+            };
+            let access = hw_params.get_access()?;
+            let access_mode = match access {
+                alsa::pcm::Access::MMapInterleaved => AccessMode::MMap,
+                _ => AccessMode::ReadWrite,
+            };
+            let planar = access == alsa::pcm::Access::RWNonInterleaved;
+            (hw_params.can_pause(), conf, access_mode, planar)
         };
-        let (_buffer_len, period_len) = set_sw_params_from_format(&handle, conf)?;
+        let (buffer_len, period_len) = set_sw_params_from_format(&handle, &conf, stream_type)?;
 
         handle.prepare()?;
 
@@ -277,16 +585,20 @@ impl Device {
             _ => None,
         };
 
-        handle.start()?;
-
         let stream_inner = StreamInner {
             channel: handle,
+            stream_type,
             sample_format,
             num_descriptors,
-            conf: conf.clone(),
+            conf,
+            access_mode,
+            planar,
+            buffer_len,
             period_len,
             can_pause,
             creation_instant,
+            mixer: MixerControl::find(&self.info, stream_type),
+            volume: SoftwareVolume::new(),
         };
 
         Ok(stream_inner)
@@ -320,51 +632,22 @@ impl Device {
 
         let hw_params = alsa::pcm::HwParams::any(&handle)?;
 
-        // TODO: check endianess
-        const FORMATS: [(SampleFormat, alsa::pcm::Format); 3] = [
-            //SND_PCM_FORMAT_S8,
-            //SND_PCM_FORMAT_U8,
-            (SampleFormat::I16, alsa::pcm::Format::S16LE),
-            //SND_PCM_FORMAT_S16_BE,
-            (SampleFormat::U16, alsa::pcm::Format::U16LE),
-            //SND_PCM_FORMAT_U16_BE,
-            //SND_PCM_FORMAT_S24_LE,
-            //SND_PCM_FORMAT_S24_BE,
-            //SND_PCM_FORMAT_U24_LE,
-            //SND_PCM_FORMAT_U24_BE,
-            //SND_PCM_FORMAT_S32_LE,
-            //SND_PCM_FORMAT_S32_BE,
-            //SND_PCM_FORMAT_U32_LE,
-            //SND_PCM_FORMAT_U32_BE,
-            (SampleFormat::F32, alsa::pcm::Format::FloatLE),
-            //SND_PCM_FORMAT_FLOAT_BE,
-            //SND_PCM_FORMAT_FLOAT64_LE,
-            //SND_PCM_FORMAT_FLOAT64_BE,
-            //SND_PCM_FORMAT_IEC958_SUBFRAME_LE,
-            //SND_PCM_FORMAT_IEC958_SUBFRAME_BE,
-            //SND_PCM_FORMAT_MU_LAW,
-            //SND_PCM_FORMAT_A_LAW,
-            //SND_PCM_FORMAT_IMA_ADPCM,
-            //SND_PCM_FORMAT_MPEG,
-            //SND_PCM_FORMAT_GSM,
-            //SND_PCM_FORMAT_SPECIAL,
-            //SND_PCM_FORMAT_S24_3LE,
-            //SND_PCM_FORMAT_S24_3BE,
-            //SND_PCM_FORMAT_U24_3LE,
-            //SND_PCM_FORMAT_U24_3BE,
-            //SND_PCM_FORMAT_S20_3LE,
-            //SND_PCM_FORMAT_S20_3BE,
-            //SND_PCM_FORMAT_U20_3LE,
-            //SND_PCM_FORMAT_U20_3BE,
-            //SND_PCM_FORMAT_S18_3LE,
-            //SND_PCM_FORMAT_S18_3BE,
-            //SND_PCM_FORMAT_U18_3LE,
-            //SND_PCM_FORMAT_U18_3BE,
+        const FORMATS: [SampleFormat; 10] = [
+            SampleFormat::I8,
+            SampleFormat::U8,
+            SampleFormat::I16,
+            SampleFormat::U16,
+            SampleFormat::I24,
+            SampleFormat::I24Packed,
+            SampleFormat::I32,
+            SampleFormat::U32,
+            SampleFormat::F32,
+            SampleFormat::F64,
         ];
 
         let mut supported_formats = Vec::new();
-        for &(sample_format, alsa_format) in FORMATS.iter() {
-            if hw_params.test_format(alsa_format).is_ok() {
+        for &sample_format in FORMATS.iter() {
+            if hw_params.test_format(alsa_format(sample_format)).is_ok() {
                 supported_formats.push(sample_format);
             }
         }
@@ -482,7 +765,11 @@ impl Device {
                 if min_r <= HZ_44100 && HZ_44100 <= max_r {
                     format.sample_rate = HZ_44100;
                 }
-                Ok(format)
+                let channel_map = match self.handles.lock().get_mut(&self.name, stream_t) {
+                    Ok(handle) => chmap::query(handle, format.channels),
+                    Err(_) => None,
+                };
+                Ok(format.with_channel_map(channel_map))
             }
             None => Err(DefaultStreamConfigError::StreamTypeNotSupported),
         }
@@ -501,6 +788,9 @@ struct StreamInner {
     // The ALSA channel.
     channel: alsa::pcm::PCM,
 
+    // Whether the channel is used for playback or capture.
+    stream_type: alsa::Direction,
+
     // When converting between file descriptors and `snd_pcm_t`, this is the number of
     // file descriptors that this `snd_pcm_t` uses.
     num_descriptors: usize,
@@ -508,9 +798,19 @@ struct StreamInner {
     // Format of the samples.
     sample_format: SampleFormat,
 
-    // The configuration used to open this stream.
+    // The configuration used to open this stream, with the sample rate and channel count in effect.
     conf: StreamConfig,
 
+    // The access mode granted by the device.
+    access_mode: AccessMode,
+
+    // Whether the device granted non-interleaved read/write access, transferring a buffer per
+    // channel.
+    planar: bool,
+
+    // Number of samples the buffer of the channel holds.
+    buffer_len: usize,
+
     // Minimum number of samples to put in the buffer.
     period_len: usize,
 
@@ -528,11 +828,99 @@ struct StreamInner {
     // If this field is `None` then the elapsed duration between `get_trigger_htstamp` and
     // `get_htstamp` is used.
     creation_instant: Option<std::time::Instant>,
+
+    // The mixer element controlling the volume of the channel, if its card has one.
+    mixer: Option<MixerControl>,
+
+    // The gain stage applied to the data of the channel, used where `mixer` is `None` or cannot
+    // mute.
+    volume: SoftwareVolume,
 }
 
 // Assume that the ALSA library is built with thread safe option.
 unsafe impl Sync for StreamInner {}
 
+impl StreamInner {
+    fn buffer_frames(&self) -> FrameCount {
+        (self.buffer_len / self.conf.channels as usize) as FrameCount
+    }
+
+    fn period_frames(&self) -> FrameCount {
+        (self.period_len / self.conf.channels as usize) as FrameCount
+    }
+
+    // The nominal latency of the channel: a whole buffer for playback, as written frames are
+    // queued behind those already in the buffer, and a period for capture, as frames are
+    // delivered once a period has been captured.
+    fn latency(&self) -> std::time::Duration {
+        let frames = match self.stream_type {
+            alsa::Direction::Playback => self.buffer_frames(),
+            alsa::Direction::Capture => self.period_frames(),
+        };
+        frames_to_duration(frames as usize, self.conf.sample_rate)
+    }
+
+    // The data of `buffer` as passed to the data callback: interleaved samples, or a buffer per
+    // channel for a channel with non-interleaved access, whose pointers are stored in `planes`.
+    fn data(&self, buffer: &mut [u8], planes: &mut Vec<*mut ()>) -> Data {
+        let sample_format = self.sample_format;
+        if self.planar {
+            let channels = self.conf.channels as usize;
+            let frames = self.split_planes(buffer, planes);
+            // The planes are non-overlapping buffers of `frames` samples within `buffer`.
+            unsafe { Data::from_planar_parts(planes.as_ptr(), channels, frames, sample_format) }
+        } else {
+            let data = buffer.as_mut_ptr() as *mut ();
+            let len = buffer.len() / sample_format.sample_size();
+            unsafe { Data::from_parts(data, len, sample_format) }
+        }
+    }
+
+    // Splits `buffer` into a buffer per channel, stored in `planes`. Returns the number of frames
+    // each buffer holds.
+    fn split_planes(&self, buffer: &mut [u8], planes: &mut Vec<*mut ()>) -> usize {
+        let channels = self.conf.channels as usize;
+        let plane_size = buffer.len() / channels;
+        planes.clear();
+        planes.extend(
+            buffer
+                .chunks_exact_mut(plane_size)
+                .map(|plane| plane.as_mut_ptr() as *mut ()),
+        );
+        plane_size / self.sample_format.sample_size()
+    }
+
+    // Reads from the channel into `buffer`, see `data` for its layout.
+    fn read(&self, buffer: &mut [u8], planes: &mut Vec<*mut ()>) -> Result<usize, alsa::Error> {
+        if self.planar {
+            let frames = self.split_planes(buffer, planes);
+            unsafe { sys::readn(&self.channel, planes, frames) }
+        } else {
+            self.channel.io_bytes().readi(buffer)
+        }
+    }
+
+    // Writes `buffer` to the channel, see `data` for its layout.
+    fn write(&self, buffer: &mut [u8], planes: &mut Vec<*mut ()>) -> Result<usize, alsa::Error> {
+        if self.planar {
+            let frames = self.split_planes(buffer, planes);
+            unsafe { sys::writen(&self.channel, planes, frames) }
+        } else {
+            self.channel.io_bytes().writei(buffer)
+        }
+    }
+
+    // Prepares the channel after an xrun. Unlike with read/write access, a capture channel with
+    // mmap access is not started by the next transfer, so it is started right away.
+    fn recover_xrun(&self) -> Result<(), alsa::Error> {
+        self.channel.prepare()?;
+        if self.access_mode == AccessMode::MMap && self.stream_type == alsa::Direction::Capture {
+            self.channel.start()?;
+        }
+        Ok(())
+    }
+}
+
 #[derive(Debug, Eq, PartialEq)]
 enum StreamType {
     Input,
@@ -547,14 +935,30 @@ pub struct Stream {
     /// Handle to the underlying stream for playback controls.
     inner: Arc<StreamInner>,
 
+    /// The output handle of a duplex stream, whose `inner` handle is the input.
+    duplex_output: Option<Arc<StreamInner>>,
+
     /// Used to signal to stop processing.
     trigger: TriggerSender,
 }
 
-#[derive(Default)]
 struct StreamWorkerContext {
     descriptors: Vec<libc::pollfd>,
     buffer: Vec<u8>,
+    // The buffers of the channels within `buffer`, for a channel with non-interleaved access.
+    planes: Vec<*mut ()>,
+}
+
+impl StreamWorkerContext {
+    // A context whose buffers hold the whole buffer of the channel, so that they never grow on the
+    // audio thread.
+    fn new(stream: &StreamInner) -> Self {
+        StreamWorkerContext {
+            descriptors: Vec::new(),
+            buffer: Vec::with_capacity(stream.buffer_len * stream.sample_format.sample_size()),
+            planes: Vec::with_capacity(stream.conf.channels as usize),
+        }
+    }
 }
 
 fn input_stream_worker(
@@ -563,7 +967,7 @@ fn input_stream_worker(
     data_callback: &mut (dyn FnMut(&Data, &InputCallbackInfo) + Send + 'static),
     error_callback: &mut (dyn FnMut(StreamError) + Send + 'static),
 ) {
-    let mut ctxt = StreamWorkerContext::default();
+    let mut ctxt = StreamWorkerContext::new(stream);
     loop {
         let flow = report_error(
             poll_descriptors_and_prepare_buffer(&rx, stream, &mut ctxt),
@@ -576,13 +980,16 @@ fn input_stream_worker(
                 continue;
             }
             PollDescriptorsFlow::XRun => {
-                report_error(stream.channel.prepare(), error_callback);
+                error_callback(StreamError::BufferOverrun {
+                    frames_lost: xrun_frames_lost(stream),
+                });
+                report_error(stream.recover_xrun(), error_callback);
                 continue;
             }
             PollDescriptorsFlow::Return => return,
             PollDescriptorsFlow::Ready {
                 status,
-                avail_frames: _,
+                avail_frames,
                 delay_frames,
                 stream_type,
             } => {
@@ -591,13 +998,24 @@ fn input_stream_worker(
                     StreamType::Input,
                     "expected input stream, but polling descriptors indicated output",
                 );
-                let res = process_input(
-                    stream,
-                    &mut ctxt.buffer,
-                    status,
-                    delay_frames,
-                    data_callback,
-                );
+                let res = match stream.access_mode {
+                    AccessMode::ReadWrite => process_input(
+                        stream,
+                        &mut ctxt,
+                        status,
+                        delay_frames,
+                        data_callback,
+                        error_callback,
+                    ),
+                    AccessMode::MMap => process_input_mmap(
+                        stream,
+                        status,
+                        avail_frames,
+                        delay_frames,
+                        data_callback,
+                        error_callback,
+                    ),
+                };
                 report_error(res, error_callback);
             }
         }
@@ -610,7 +1028,7 @@ fn output_stream_worker(
     data_callback: &mut (dyn FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static),
     error_callback: &mut (dyn FnMut(StreamError) + Send + 'static),
 ) {
-    let mut ctxt = StreamWorkerContext::default();
+    let mut ctxt = StreamWorkerContext::new(stream);
     loop {
         let flow = report_error(
             poll_descriptors_and_prepare_buffer(&rx, stream, &mut ctxt),
@@ -624,6 +1042,9 @@ fn output_stream_worker(
                 continue;
             }
             PollDescriptorsFlow::XRun => {
+                error_callback(StreamError::BufferUnderrun {
+                    frames_lost: xrun_frames_lost(stream),
+                });
                 report_error(stream.channel.prepare(), error_callback);
                 continue;
             }
@@ -639,12 +1060,70 @@ fn output_stream_worker(
                     StreamType::Output,
                     "expected output stream, but polling descriptors indicated input",
                 );
-                let res = process_output(
-                    stream,
-                    &mut ctxt.buffer,
+                let res = match stream.access_mode {
+                    AccessMode::ReadWrite => process_output(
+                        stream,
+                        &mut ctxt,
+                        status,
+                        avail_frames,
+                        delay_frames,
+                        data_callback,
+                        error_callback,
+                    ),
+                    AccessMode::MMap => process_output_mmap(
+                        stream,
+                        status,
+                        avail_frames,
+                        delay_frames,
+                        data_callback,
+                        error_callback,
+                    ),
+                };
+                report_error(res, error_callback);
+            }
+        }
+    }
+}
+
+fn duplex_stream_worker(
+    rx: TriggerReceiver,
+    input: &StreamInner,
+    output: &StreamInner,
+    data_callback: &mut (dyn FnMut(&Data, &mut Data, &DuplexCallbackInfo) + Send + 'static),
+    error_callback: &mut (dyn FnMut(StreamError) + Send + 'static),
+) {
+    let mut ctxt = DuplexStreamWorkerContext::default();
+    loop {
+        let flow = report_error(
+            poll_duplex_descriptors_and_prepare_buffers(&rx, input, output, &mut ctxt),
+            error_callback,
+        )
+        .unwrap_or(DuplexPollDescriptorsFlow::Continue);
+
+        match flow {
+            DuplexPollDescriptorsFlow::Continue => {
+                continue;
+            }
+            DuplexPollDescriptorsFlow::XRun => {
+                report_duplex_xrun(input, output, error_callback);
+                report_error(recover_duplex_xrun(input, output), error_callback);
+                continue;
+            }
+            DuplexPollDescriptorsFlow::Return => return,
+            DuplexPollDescriptorsFlow::Ready {
+                status,
+                frames,
+                input_delay_frames,
+                output_delay_frames,
+            } => {
+                let res = process_duplex(
+                    input,
+                    output,
+                    &mut ctxt,
                     status,
-                    avail_frames,
-                    delay_frames,
+                    frames,
+                    input_delay_frames,
+                    output_delay_frames,
                     data_callback,
                     error_callback,
                 );
@@ -691,6 +1170,7 @@ fn poll_descriptors_and_prepare_buffer(
     let StreamWorkerContext {
         ref mut descriptors,
         ref mut buffer,
+        ..
     } = *ctxt;
 
     descriptors.clear();
@@ -745,7 +1225,7 @@ fn poll_descriptors_and_prepare_buffer(
         res => res,
     }? as usize;
     let delay_frames = match status.get_delay() {
-        // Buffer underrun. TODO: Notify the user.
+        // Buffer underrun, reported once `avail` or `writei` return `EPIPE`.
         d if d < 0 => 0,
         d => d as usize,
     };
@@ -756,9 +1236,11 @@ fn poll_descriptors_and_prepare_buffer(
         return Ok(PollDescriptorsFlow::Continue);
     }
 
-    // Prepare the data buffer.
-    let buffer_size = stream.sample_format.sample_size() * available_samples;
-    buffer.resize(buffer_size, 0u8);
+    // Prepare the data buffer, unless the ring buffer of the device is accessed directly.
+    if stream.access_mode == AccessMode::ReadWrite {
+        let buffer_size = stream.sample_format.sample_size() * available_samples;
+        buffer.resize(buffer_size, 0u8);
+    }
 
     Ok(PollDescriptorsFlow::Ready {
         stream_type,
@@ -768,37 +1250,286 @@ fn poll_descriptors_and_prepare_buffer(
     })
 }
 
+#[derive(Default)]
+struct DuplexStreamWorkerContext {
+    descriptors: Vec<libc::pollfd>,
+    input_buffer: Vec<u8>,
+    output_buffer: Vec<u8>,
+}
+
+enum DuplexPollDescriptorsFlow {
+    Continue,
+    Return,
+    Ready {
+        status: alsa::pcm::Status,
+        frames: usize,
+        input_delay_frames: usize,
+        output_delay_frames: usize,
+    },
+    XRun,
+}
+
+// Waits until both the capture and the playback handle have at least an input period of frames
+// available. Only the descriptors of the handles that are short of a period are polled, so that a
+// direction that is ready does not wake the worker again and again while the other catches up.
+fn poll_duplex_descriptors_and_prepare_buffers(
+    rx: &TriggerReceiver,
+    input: &StreamInner,
+    output: &StreamInner,
+    ctxt: &mut DuplexStreamWorkerContext,
+) -> Result<DuplexPollDescriptorsFlow, BackendSpecificError> {
+    let DuplexStreamWorkerContext {
+        ref mut descriptors,
+        ref mut input_buffer,
+        ref mut output_buffer,
+    } = *ctxt;
+
+    let input_avail_frames = match input.channel.avail() {
+        Err(err) if err.errno() == Some(nix::errno::Errno::EPIPE) => {
+            return Ok(DuplexPollDescriptorsFlow::XRun)
+        }
+        res => res,
+    }? as usize;
+    let output_avail_frames = match output.channel.avail() {
+        Err(err) if err.errno() == Some(nix::errno::Errno::EPIPE) => {
+            return Ok(DuplexPollDescriptorsFlow::XRun)
+        }
+        res => res,
+    }? as usize;
+    let input_period_frames = input.period_len / input.conf.channels as usize;
+    let input_short = input_avail_frames < input_period_frames;
+    let output_short = output_avail_frames < input_period_frames;
+
+    descriptors.clear();
+
+    // Add the self-pipe for signaling termination.
+    descriptors.push(libc::pollfd {
+        fd: rx.0,
+        events: libc::POLLIN,
+        revents: 0,
+    });
+
+    // Add ALSA polling fds of the handles that are short of a period.
+    let input_start = descriptors.len();
+    if input_short {
+        push_descriptors(descriptors, input)?;
+    }
+    let output_start = descriptors.len();
+    if output_short {
+        push_descriptors(descriptors, output)?;
+    }
+
+    // Wait forever for the short handles, or only check the self-pipe if neither is short.
+    let timeout = if input_short || output_short { -1 } else { 0 };
+    let res = alsa::poll::poll(descriptors, timeout)?;
+    if res == 0 && timeout != 0 {
+        let description = String::from("`alsa::poll()` spuriously returned");
+        return Err(BackendSpecificError { description });
+    }
+
+    if descriptors[0].revents != 0 {
+        // The stream has been requested to be destroyed.
+        rx.clear_pipe();
+        return Ok(DuplexPollDescriptorsFlow::Return);
+    }
+
+    if input_short || output_short {
+        // Let ALSA process the events of the short handles, then check the available frames again.
+        if input_short {
+            input
+                .channel
+                .revents(&descriptors[input_start..output_start])?;
+        }
+        if output_short {
+            output.channel.revents(&descriptors[output_start..])?;
+        }
+        return Ok(DuplexPollDescriptorsFlow::Continue);
+    }
+
+    let status = input.channel.status()?;
+    let input_delay_frames = match status.get_delay() {
+        d if d < 0 => 0,
+        d => d as usize,
+    };
+    let output_delay_frames = match output.channel.delay() {
+        Ok(d) if d > 0 => d as usize,
+        _ => 0,
+    };
+    let frames = cmp::min(input_avail_frames, output_avail_frames);
+
+    // Prepare the data buffers.
+    let input_samples = frames * input.conf.channels as usize;
+    input_buffer.resize(input.sample_format.sample_size() * input_samples, 0u8);
+    let output_samples = frames * output.conf.channels as usize;
+    output_buffer.resize(output.sample_format.sample_size() * output_samples, 0u8);
+
+    Ok(DuplexPollDescriptorsFlow::Ready {
+        status,
+        frames,
+        input_delay_frames,
+        output_delay_frames,
+    })
+}
+
+// Appends the ALSA polling fds of `stream` to `descriptors`.
+fn push_descriptors(
+    descriptors: &mut Vec<libc::pollfd>,
+    stream: &StreamInner,
+) -> Result<(), BackendSpecificError> {
+    let len = descriptors.len();
+    descriptors.resize(
+        stream.num_descriptors + len,
+        libc::pollfd {
+            fd: 0,
+            events: 0,
+            revents: 0,
+        },
+    );
+    let filled = stream.channel.fill(&mut descriptors[len..])?;
+    debug_assert_eq!(filled, stream.num_descriptors);
+    Ok(())
+}
+
 // Read input data from ALSA and deliver it to the user.
 fn process_input(
     stream: &StreamInner,
-    buffer: &mut [u8],
+    ctxt: &mut StreamWorkerContext,
     status: alsa::pcm::Status,
     delay_frames: usize,
     data_callback: &mut (dyn FnMut(&Data, &InputCallbackInfo) + Send + 'static),
+    error_callback: &mut dyn FnMut(StreamError),
 ) -> Result<(), BackendSpecificError> {
-    stream.channel.io_bytes().readi(buffer)?;
-    let sample_format = stream.sample_format;
-    let data = buffer.as_mut_ptr() as *mut ();
-    let len = buffer.len() / sample_format.sample_size();
-    let data = unsafe { Data::from_parts(data, len, sample_format) };
+    match stream.read(&mut ctxt.buffer, &mut ctxt.planes) {
+        Err(err) if err.errno() == Some(nix::errno::Errno::EPIPE) => {
+            // buffer overrun
+            error_callback(StreamError::BufferOverrun {
+                frames_lost: xrun_frames_lost(stream),
+            });
+            let _ = stream.channel.try_recover(err, false);
+            return Ok(());
+        }
+        res => res,
+    }?;
+    let mut data = stream.data(&mut ctxt.buffer, &mut ctxt.planes);
+    stream.volume.apply(&mut data);
     let callback = stream_timestamp(&status, stream.creation_instant)?;
     let delay_duration = frames_to_duration(delay_frames, stream.conf.sample_rate);
     let capture = callback
         .sub(delay_duration)
         .expect("`capture` is earlier than representation supported by `StreamInstant`");
     let timestamp = crate::InputStreamTimestamp { callback, capture };
-    let info = crate::InputCallbackInfo { timestamp };
+    let info = crate::InputCallbackInfo::new(timestamp);
     data_callback(&data, &info);
 
     Ok(())
 }
 
+// Deliver the input data in the memory mapped ring buffer of the device to the user.
+//
+// The callback is called once for each contiguous area of the ring buffer, which is usually the
+// whole of `available_frames` as the buffer holds a whole number of periods.
+fn process_input_mmap(
+    stream: &StreamInner,
+    status: alsa::pcm::Status,
+    available_frames: usize,
+    delay_frames: usize,
+    data_callback: &mut (dyn FnMut(&Data, &InputCallbackInfo) + Send + 'static),
+    error_callback: &mut dyn FnMut(StreamError),
+) -> Result<(), BackendSpecificError> {
+    let callback = stream_timestamp(&status, stream.creation_instant)?;
+    let delay_duration = frames_to_duration(delay_frames, stream.conf.sample_rate);
+    let capture = callback
+        .sub(delay_duration)
+        .expect("`capture` is earlier than representation supported by `StreamInstant`");
+    let timestamp = crate::InputStreamTimestamp { callback, capture };
+    let info = crate::InputCallbackInfo::new(timestamp);
+    let sample_format = stream.sample_format;
+    let channels = stream.conf.channels as usize;
+    let mut remaining = available_frames;
+    while remaining > 0 {
+        let result = stream.channel.io_bytes().mmap(remaining, |buffer| {
+            let data = buffer.as_mut_ptr() as *mut ();
+            let len = buffer.len() / sample_format.sample_size();
+            let mut data = unsafe { Data::from_parts(data, len, sample_format) };
+            stream.volume.apply(&mut data);
+            data_callback(&data, &info);
+            len / channels
+        });
+        match result {
+            Err(err) if err.errno() == Some(nix::errno::Errno::EPIPE) => {
+                // buffer overrun
+                error_callback(StreamError::BufferOverrun {
+                    frames_lost: xrun_frames_lost(stream),
+                });
+                stream.recover_xrun()?;
+                return Ok(());
+            }
+            Err(err) => return Err(err.into()),
+            Ok(0) => break,
+            Ok(frames) => remaining = remaining.saturating_sub(frames),
+        }
+    }
+    Ok(())
+}
+
+// Let the user's function write directly into the memory mapped ring buffer of the device.
+//
+// The callback is called once for each contiguous area of the ring buffer, which is usually the
+// whole of `available_frames` as the buffer holds a whole number of periods.
+fn process_output_mmap(
+    stream: &StreamInner,
+    status: alsa::pcm::Status,
+    available_frames: usize,
+    delay_frames: usize,
+    data_callback: &mut (dyn FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static),
+    error_callback: &mut dyn FnMut(StreamError),
+) -> Result<(), BackendSpecificError> {
+    let callback = stream_timestamp(&status, stream.creation_instant)?;
+    let delay_duration = frames_to_duration(delay_frames, stream.conf.sample_rate);
+    let playback = callback
+        .add(delay_duration)
+        .expect("`playback` occurs beyond representation supported by `StreamInstant`");
+    let timestamp = crate::OutputStreamTimestamp { callback, playback };
+    let info = crate::OutputCallbackInfo::new(timestamp);
+    let sample_format = stream.sample_format;
+    let channels = stream.conf.channels as usize;
+    let mut remaining = available_frames;
+    while remaining > 0 {
+        let result = stream.channel.io_bytes().mmap(remaining, |buffer| {
+            let data = buffer.as_mut_ptr() as *mut ();
+            let len = buffer.len() / sample_format.sample_size();
+            let mut data = unsafe { Data::from_parts(data, len, sample_format) };
+            data_callback(&mut data, &info);
+            stream.volume.apply(&mut data);
+            len / channels
+        });
+        match result {
+            Err(err) if err.errno() == Some(nix::errno::Errno::EPIPE) => {
+                // buffer underrun
+                error_callback(StreamError::BufferUnderrun {
+                    frames_lost: xrun_frames_lost(stream),
+                });
+                stream.recover_xrun()?;
+                return Ok(());
+            }
+            Err(err) => return Err(err.into()),
+            Ok(0) => break,
+            Ok(frames) => remaining = remaining.saturating_sub(frames),
+        }
+    }
+    // Committing frames does not start a prepared channel like writing them does.
+    if stream.channel.state() == alsa::pcm::State::Prepared {
+        stream.channel.start()?;
+    }
+    Ok(())
+}
+
 // Request data from the user's function and write it via ALSA.
 //
 // Returns `true`
 fn process_output(
     stream: &StreamInner,
-    buffer: &mut [u8],
+    ctxt: &mut StreamWorkerContext,
     status: alsa::pcm::Status,
     available_frames: usize,
     delay_frames: usize,
@@ -807,24 +1538,24 @@ fn process_output(
 ) -> Result<(), BackendSpecificError> {
     {
         // We're now sure that we're ready to write data.
-        let sample_format = stream.sample_format;
-        let data = buffer.as_mut_ptr() as *mut ();
-        let len = buffer.len() / sample_format.sample_size();
-        let mut data = unsafe { Data::from_parts(data, len, sample_format) };
+        let mut data = stream.data(&mut ctxt.buffer, &mut ctxt.planes);
         let callback = stream_timestamp(&status, stream.creation_instant)?;
         let delay_duration = frames_to_duration(delay_frames, stream.conf.sample_rate);
         let playback = callback
             .add(delay_duration)
             .expect("`playback` occurs beyond representation supported by `StreamInstant`");
         let timestamp = crate::OutputStreamTimestamp { callback, playback };
-        let info = crate::OutputCallbackInfo { timestamp };
+        let info = crate::OutputCallbackInfo::new(timestamp);
         data_callback(&mut data, &info);
+        stream.volume.apply(&mut data);
     }
     loop {
-        match stream.channel.io_bytes().writei(buffer) {
+        match stream.write(&mut ctxt.buffer, &mut ctxt.planes) {
             Err(err) if err.errno() == Some(nix::errno::Errno::EPIPE) => {
                 // buffer underrun
-                // TODO: Notify the user of this.
+                error_callback(StreamError::BufferUnderrun {
+                    frames_lost: xrun_frames_lost(stream),
+                });
                 let _ = stream.channel.try_recover(err, false);
             }
             Err(err) => {
@@ -848,6 +1579,88 @@ fn process_output(
     Ok(())
 }
 
+// Read input data from ALSA, deliver it to the user along with the output buffer and write the
+// output via ALSA.
+#[allow(clippy::too_many_arguments)]
+fn process_duplex(
+    input: &StreamInner,
+    output: &StreamInner,
+    ctxt: &mut DuplexStreamWorkerContext,
+    status: alsa::pcm::Status,
+    frames: usize,
+    input_delay_frames: usize,
+    output_delay_frames: usize,
+    data_callback: &mut (dyn FnMut(&Data, &mut Data, &DuplexCallbackInfo) + Send + 'static),
+    error_callback: &mut dyn FnMut(StreamError),
+) -> Result<(), BackendSpecificError> {
+    match input.channel.io_bytes().readi(&mut ctxt.input_buffer) {
+        Err(err) if err.errno() == Some(nix::errno::Errno::EPIPE) => {
+            report_duplex_xrun(input, output, error_callback);
+            recover_duplex_xrun(input, output)?;
+            return Ok(());
+        }
+        res => res,
+    }?;
+    {
+        let sample_format = input.sample_format;
+        let input_data = ctxt.input_buffer.as_mut_ptr() as *mut ();
+        let input_len = ctxt.input_buffer.len() / sample_format.sample_size();
+        let input_data = unsafe { Data::from_parts(input_data, input_len, sample_format) };
+        let output_data = ctxt.output_buffer.as_mut_ptr() as *mut ();
+        let output_len = ctxt.output_buffer.len() / sample_format.sample_size();
+        let mut output_data = unsafe { Data::from_parts(output_data, output_len, sample_format) };
+        let callback = stream_timestamp(&status, input.creation_instant)?;
+        let capture = callback
+            .sub(frames_to_duration(
+                input_delay_frames,
+                input.conf.sample_rate,
+            ))
+            .expect("`capture` is earlier than representation supported by `StreamInstant`");
+        let playback = callback
+            .add(frames_to_duration(
+                output_delay_frames,
+                output.conf.sample_rate,
+            ))
+            .expect("`playback` occurs beyond representation supported by `StreamInstant`");
+        let timestamp = crate::DuplexStreamTimestamp {
+            callback,
+            capture,
+            playback,
+        };
+        let info = crate::DuplexCallbackInfo::new(timestamp);
+        data_callback(&input_data, &mut output_data, &info);
+        output.volume.apply(&mut output_data);
+    }
+    loop {
+        match output.channel.io_bytes().writei(&ctxt.output_buffer) {
+            Err(err) if err.errno() == Some(nix::errno::Errno::EPIPE) => {
+                // The output is dropped, as the restarted stream starts with a buffer of silence.
+                report_duplex_xrun(input, output, error_callback);
+                recover_duplex_xrun(input, output)?;
+                break;
+            }
+            Err(err) => {
+                error_callback(err.into());
+                continue;
+            }
+            Ok(result) if result != frames => {
+                let description = format!(
+                    "unexpected number of frames written: expected {}, \
+                     result {} (this should never happen)",
+                    frames, result,
+                );
+                error_callback(BackendSpecificError { description }.into());
+                continue;
+            }
+            _ => {
+                break;
+            }
+        }
+    }
+    Ok(())
+}
+
+// Fill the given buffer with the silent value of `sample_format`.
 // Use the elapsed duration since the start of the stream.
 //
 // This ensures positive values that are compatible with our `StreamInstant` representation.
@@ -891,6 +1704,63 @@ fn timespec_diff_nanos(a: libc::timespec, b: libc::timespec) -> i64 {
     timespec_to_nanos(a) - timespec_to_nanos(b)
 }
 
+// The number of frames lost since the stream entered the XRUN state, or `None` if the stream is
+// not in the XRUN state or ALSA cannot tell.
+//
+// ALSA records the time at which the xrun occurred as the trigger timestamp, so the number of
+// frames lost is the time elapsed since then at the stream's sample rate.
+fn xrun_frames_lost(stream: &StreamInner) -> Option<crate::FrameCount> {
+    let status = stream.channel.status().ok()?;
+    if status.get_state() != alsa::pcm::State::XRun {
+        return None;
+    }
+    let nanos = timespec_diff_nanos(status.get_htstamp(), status.get_trigger_htstamp());
+    if nanos < 0 {
+        return None;
+    }
+    let frames = nanos as u64 * stream.conf.sample_rate.0 as u64 / 1_000_000_000;
+    frames.try_into().ok()
+}
+
+// Fills the playback buffer of a prepared duplex stream with silence, which starts both linked
+// handles with the playback a buffer ahead of the capture.
+fn start_duplex(output: &StreamInner) -> Result<(), alsa::Error> {
+    let mut silence = vec![0u8; output.buffer_len * output.sample_format.sample_size()];
+    crate::samples_formats::fill_with_silence(&mut silence, output.sample_format);
+    output.channel.io_bytes().writei(&silence)?;
+    if output.channel.state() != alsa::pcm::State::Running {
+        output.channel.start()?;
+    }
+    Ok(())
+}
+
+// Restarts a linked duplex stream after an xrun. Preparing the handles leaves both stopped, and
+// the capture handle is never started by reading, so the stream is started as when it was built.
+fn recover_duplex_xrun(input: &StreamInner, output: &StreamInner) -> Result<(), alsa::Error> {
+    // The streams are linked, so preparing one prepares both.
+    input.channel.prepare()?;
+    start_duplex(output)
+}
+
+// Report an xrun of a linked duplex stream to the user, once for each direction that is in the
+// XRUN state.
+fn report_duplex_xrun(
+    input: &StreamInner,
+    output: &StreamInner,
+    error_callback: &mut dyn FnMut(StreamError),
+) {
+    if input.channel.state() == alsa::pcm::State::XRun {
+        error_callback(StreamError::BufferOverrun {
+            frames_lost: xrun_frames_lost(input),
+        });
+    }
+    if output.channel.state() == alsa::pcm::State::XRun {
+        error_callback(StreamError::BufferUnderrun {
+            frames_lost: xrun_frames_lost(output),
+        });
+    }
+}
+
 // Convert the given duration in frames at the given sample rate to a `std::time::Duration`.
 fn frames_to_duration(frames: usize, rate: crate::SampleRate) -> std::time::Duration {
     let secsf = frames as f64 / rate.0 as f64;
@@ -918,6 +1788,7 @@ impl Stream {
         Stream {
             thread: Some(thread),
             inner,
+            duplex_output: None,
             trigger: tx,
         }
     }
@@ -940,9 +1811,52 @@ impl Stream {
         Stream {
             thread: Some(thread),
             inner,
+            duplex_output: None,
             trigger: tx,
         }
     }
+
+    fn new_duplex<D, E>(
+        input: Arc<StreamInner>,
+        output: Arc<StreamInner>,
+        mut data_callback: D,
+        mut error_callback: E,
+    ) -> Stream
+    where
+        D: FnMut(&Data, &mut Data, &DuplexCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        let (tx, rx) = trigger();
+        // Clone the handle for passing into worker thread. The handles are linked, so the
+        // playback controls of the input handle apply to both.
+        let (stream, duplex_output) = (input.clone(), output.clone());
+        let thread = thread::spawn(move || {
+            duplex_stream_worker(
+                rx,
+                &stream,
+                &output,
+                &mut data_callback,
+                &mut error_callback,
+            );
+        });
+        Stream {
+            thread: Some(thread),
+            inner: input,
+            duplex_output: Some(duplex_output),
+            trigger: tx,
+        }
+    }
+
+    /// The access mode granted by the device, which is `AccessMode::ReadWrite` if memory mapped
+    /// access was requested but refused.
+    pub fn access_mode(&self) -> AccessMode {
+        self.inner.access_mode
+    }
+
+    // The handle that describes the stream: the output of a duplex stream, otherwise the only one.
+    fn output(&self) -> &StreamInner {
+        self.duplex_output.as_ref().unwrap_or(&self.inner)
+    }
 }
 
 impl Drop for Stream {
@@ -961,55 +1875,161 @@ impl StreamTrait for Stream {
         self.inner.channel.pause(true).ok();
         Ok(())
     }
+
+    fn config(&self) -> Option<StreamConfig> {
+        let output = self.output();
+        Some(StreamConfig {
+            buffer_size: BufferSize::Fixed(output.buffer_frames()),
+            ..output.conf.clone()
+        })
+    }
+
+    fn buffer_size(&self) -> Option<FrameCount> {
+        Some(self.output().buffer_frames())
+    }
+
+    fn period_size(&self) -> Option<FrameCount> {
+        Some(self.output().period_frames())
+    }
+
+    fn latency(&self) -> Option<std::time::Duration> {
+        let latency = match self.duplex_output {
+            Some(ref output) => self.inner.latency() + output.latency(),
+            None => self.inner.latency(),
+        };
+        Some(latency)
+    }
+
+    fn volume_range(&self) -> Option<VolumeRange> {
+        match self.output().mixer {
+            Some(ref mixer) => Some(mixer.range()),
+            None => Some(SoftwareVolume::RANGE),
+        }
+    }
+
+    fn volume(&self) -> Result<f32, VolumeError> {
+        let output = self.output();
+        match output.mixer {
+            Some(ref mixer) => mixer.volume(),
+            None => Ok(output.volume.volume()),
+        }
+    }
+
+    fn set_volume(&self, volume_db: f32) -> Result<(), VolumeError> {
+        let output = self.output();
+        match output.mixer {
+            Some(ref mixer) => mixer.set_volume(volume_db),
+            None => {
+                output.volume.set_volume(volume_db);
+                Ok(())
+            }
+        }
+    }
+
+    fn is_muted(&self) -> Result<bool, VolumeError> {
+        let output = self.output();
+        match output.mixer {
+            Some(ref mixer) if mixer.has_switch() => mixer.is_muted(),
+            _ => Ok(output.volume.is_muted()),
+        }
+    }
+
+    fn set_muted(&self, muted: bool) -> Result<(), VolumeError> {
+        let output = self.output();
+        match output.mixer {
+            Some(ref mixer) if mixer.has_switch() => mixer.set_muted(muted),
+            _ => {
+                output.volume.set_muted(muted);
+                Ok(())
+            }
+        }
+    }
 }
 
 fn set_hw_params_from_format<'a>(
     pcm_handle: &'a alsa::pcm::PCM,
     config: &StreamConfig,
     sample_format: SampleFormat,
-) -> Result<alsa::pcm::HwParams<'a>, BackendSpecificError> {
+    access_mode: AccessMode,
+    planar: bool,
+) -> Result<alsa::pcm::HwParams<'a>, BuildStreamError> {
     let hw_params = alsa::pcm::HwParams::any(pcm_handle)?;
-    hw_params.set_access(alsa::pcm::Access::RWInterleaved)?;
+    // Fall back to interleaved read/write access if the device refuses the requested access.
+    let access = match access_mode {
+        _ if planar => Some(alsa::pcm::Access::RWNonInterleaved),
+        AccessMode::MMap => Some(alsa::pcm::Access::MMapInterleaved),
+        AccessMode::ReadWrite => None,
+    };
+    let granted = access.is_some_and(|access| hw_params.set_access(access).is_ok());
+    if !granted {
+        hw_params.set_access(alsa::pcm::Access::RWInterleaved)?;
+    }
+
+    hw_params.set_format(alsa_format(sample_format))?;
+    hw_params.set_rate(config.sample_rate.0, alsa::ValueOr::Nearest)?;
+    hw_params.set_channels(config.channels as u32)?;
+
+    if let Some((period_size, periods)) = config.buffer_size.periods(config.sample_rate) {
+        // ALSA would round an empty period or buffer up to whatever the device allows.
+        if period_size == 0 || periods == 0 {
+            return Err(BuildStreamError::StreamConfigNotSupported);
+        }
+        // The period size is set first so that it takes precedence, the buffer then holds as
+        // close to the requested number of periods as the device allows.
+        let period_size = hw_params
+            .set_period_size_near(period_size as alsa::pcm::Frames, alsa::ValueOr::Nearest)?;
+        hw_params.set_buffer_size_near(period_size * periods as alsa::pcm::Frames)?;
+    } else if let BufferSize::Fixed(v) = config.buffer_size {
+        hw_params.set_buffer_size(v as alsa::pcm::Frames)?;
+    } else {
+        // These values together represent a moderate latency and wakeup interval.
+        // Without them we are at the mercy of the device
+        hw_params.set_period_time_near(25_000, alsa::ValueOr::Nearest)?;
+        hw_params.set_buffer_time_near(100_000, alsa::ValueOr::Nearest)?;
+    }
+
+    pcm_handle.hw_params(&hw_params)?;
 
-    let sample_format = if cfg!(target_endian = "big") {
+    Ok(hw_params)
+}
+
+// Map a sample format to the ALSA format with the native endianness of the target.
+fn alsa_format(sample_format: SampleFormat) -> alsa::pcm::Format {
+    if cfg!(target_endian = "big") {
         match sample_format {
+            SampleFormat::I8 => alsa::pcm::Format::S8,
             SampleFormat::I16 => alsa::pcm::Format::S16BE,
+            SampleFormat::I24 => alsa::pcm::Format::S24BE,
+            SampleFormat::I24Packed => alsa::pcm::Format::S243BE,
+            SampleFormat::I32 => alsa::pcm::Format::S32BE,
+            SampleFormat::U8 => alsa::pcm::Format::U8,
             SampleFormat::U16 => alsa::pcm::Format::U16BE,
+            SampleFormat::U32 => alsa::pcm::Format::U32BE,
             SampleFormat::F32 => alsa::pcm::Format::FloatBE,
+            SampleFormat::F64 => alsa::pcm::Format::Float64BE,
         }
     } else {
         match sample_format {
+            SampleFormat::I8 => alsa::pcm::Format::S8,
             SampleFormat::I16 => alsa::pcm::Format::S16LE,
+            SampleFormat::I24 => alsa::pcm::Format::S24LE,
+            SampleFormat::I24Packed => alsa::pcm::Format::S243LE,
+            SampleFormat::I32 => alsa::pcm::Format::S32LE,
+            SampleFormat::U8 => alsa::pcm::Format::U8,
             SampleFormat::U16 => alsa::pcm::Format::U16LE,
+            SampleFormat::U32 => alsa::pcm::Format::U32LE,
             SampleFormat::F32 => alsa::pcm::Format::FloatLE,
-        }
-    };
-
-    hw_params.set_format(sample_format)?;
-    hw_params.set_rate(config.sample_rate.0, alsa::ValueOr::Nearest)?;
-    hw_params.set_channels(config.channels as u32)?;
-
-    match config.buffer_size {
-        BufferSize::Fixed(v) => hw_params.set_buffer_size(v as alsa::pcm::Frames)?,
-        BufferSize::Default => {
-            // These values together represent a moderate latency and wakeup interval.
-            // Without them we are at the mercy of the device
-            hw_params.set_period_time_near(25_000, alsa::ValueOr::Nearest)?;
-            hw_params.set_buffer_time_near(100_000, alsa::ValueOr::Nearest)?;
+            SampleFormat::F64 => alsa::pcm::Format::Float64LE,
         }
     }
-
-    pcm_handle.hw_params(&hw_params)?;
-
-    Ok(hw_params)
 }
 
 fn set_sw_params_from_format(
     pcm_handle: &alsa::pcm::PCM,
     config: &StreamConfig,
+    stream_type: alsa::Direction,
 ) -> Result<(usize, usize), BackendSpecificError> {
     let sw_params = pcm_handle.sw_params_current()?;
-    sw_params.set_start_threshold(0)?;
 
     let (buffer_len, period_len) = {
         let (buffer, period) = pcm_handle.get_params()?;
@@ -1018,6 +2038,17 @@ fn set_sw_params_from_format(
                 description: "initialization resulted in a null buffer".to_string(),
             });
         }
+        // With a requested period configuration, playback only starts once all requested periods
+        // are queued, so that the requested latency applies from the first frame. Otherwise it
+        // starts as soon as the first frames are written.
+        let requested = config.buffer_size.periods(config.sample_rate).is_some();
+        let start_threshold = match stream_type {
+            alsa::Direction::Playback if requested => buffer,
+            _ => 0,
+        };
+        sw_params.set_start_threshold(start_threshold as alsa::pcm::Frames)?;
+        // Wake up the worker once a whole period of the granted size can be transferred, which is
+        // the requested period size as closely as the device allows.
         sw_params.set_avail_min(period as alsa::pcm::Frames)?;
         let buffer = buffer as usize * config.channels as usize;
         let period = period as usize * config.channels as usize;
@@ -1076,3 +2107,145 @@ impl From<alsa::Error> for StreamError {
         err.into()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::parking_lot::Mutex;
+    use super::{alsa, device_presence, libc, Device, DeviceInfo, Host};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{mpsc, Arc};
+    use std::thread;
+    use std::time::Duration;
+    use traits::{DeviceTrait, HostTrait, StreamTrait};
+    use {BufferSize, BuildStreamError, SampleFormat, SampleRate, StreamConfig, StreamError};
+
+    // The `null` PCM of alsa-lib, which discards what is written to it.
+    fn null_device() -> Device {
+        Device {
+            name: "null".to_owned(),
+            info: DeviceInfo::from_name("null", &[]),
+            handles: Mutex::new(Default::default()),
+            access_mode: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_device_presence() {
+        let err = |errno| Some(alsa::Error::new("snd_pcm_open", errno));
+        // A capture-only device that is in use.
+        assert!(device_presence(vec![err(libc::ENOENT), err(libc::EBUSY)]).is_ok());
+        assert!(device_presence(vec![err(libc::EBUSY)]).is_ok());
+        assert!(device_presence(vec![None, err(libc::ENOENT)]).is_ok());
+        let absent = device_presence(vec![err(libc::ENOENT), err(libc::EINVAL)]).unwrap_err();
+        assert_eq!(absent.errno(), Some(nix::errno::Errno::ENOENT));
+    }
+
+    #[test]
+    fn test_empty_periods_are_rejected() {
+        let requests = [
+            BufferSize::Periods {
+                period_size: 0,
+                periods: 2,
+            },
+            BufferSize::Periods {
+                period_size: 256,
+                periods: 0,
+            },
+            // Rounds down to periods of 0 frames at 48 kHz.
+            BufferSize::Latency(Duration::from_micros(10)),
+        ];
+        for buffer_size in requests.iter().cloned() {
+            let config = StreamConfig {
+                channels: 2,
+                sample_rate: SampleRate(48_000),
+                buffer_size,
+                channel_map: None,
+            };
+            let result = null_device().build_output_stream_raw(
+                &config,
+                SampleFormat::F32,
+                |_, _| {},
+                |err| panic!("unexpected error {}", err),
+            );
+            match result {
+                Err(BuildStreamError::StreamConfigNotSupported) => (),
+                _ => panic!("expected `StreamConfigNotSupported`"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_planar_output_stream_uses_non_interleaved_access() {
+        let config = StreamConfig {
+            channels: 2,
+            sample_rate: SampleRate(48_000),
+            buffer_size: BufferSize::Fixed(1024),
+            channel_map: None,
+        };
+        let (tx, rx) = mpsc::channel();
+        let stream = null_device()
+            .build_output_stream_planar_raw(
+                &config,
+                SampleFormat::F32,
+                move |data, _| {
+                    let channels = data.planes();
+                    for channel in 0..channels.unwrap_or(0) {
+                        data.channel_mut::<f32>(channel).unwrap()[0] = channel as f32;
+                    }
+                    let _ = tx.send((channels, data.len()));
+                },
+                |err| panic!("unexpected error {}", err),
+            )
+            .unwrap();
+        assert!(stream.inner.planar);
+        // The second callback follows a successful write of the first buffer.
+        for _ in 0..2 {
+            let (channels, len) = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+            assert_eq!(channels, Some(2));
+            assert_eq!(len % 2, 0);
+        }
+    }
+
+    // Needs a default device that can link its capture and playback, such as a card of the
+    // `snd-dummy` kernel module. Run with `cargo test -- --ignored`.
+    #[test]
+    #[ignore]
+    fn test_duplex_stream_recovers_from_xrun() {
+        let device = Host::new().unwrap().default_output_device().unwrap();
+        let supported = device.default_output_config().unwrap();
+        let config = supported.config();
+        let callbacks = Arc::new(AtomicUsize::new(0));
+        let counted = callbacks.clone();
+        let (xrun_tx, xrun_rx) = mpsc::channel();
+        let stream = device
+            .build_duplex_stream_raw(
+                &config,
+                &config,
+                supported.sample_format(),
+                move |_, _, _| {
+                    // Stall the first callback for longer than the buffer lasts.
+                    if counted.fetch_add(1, Ordering::SeqCst) == 0 {
+                        thread::sleep(Duration::from_secs(1));
+                    }
+                },
+                move |err| match err {
+                    StreamError::BufferUnderrun { .. } | StreamError::BufferOverrun { .. } => {
+                        let _ = xrun_tx.send(());
+                    }
+                    err => panic!("unexpected error {}", err),
+                },
+            )
+            .unwrap();
+        stream.play().unwrap();
+
+        xrun_rx
+            .recv_timeout(Duration::from_secs(5))
+            .expect("no xrun was reported");
+        let after_xrun = callbacks.load(Ordering::SeqCst);
+        thread::sleep(Duration::from_millis(500));
+        assert!(
+            callbacks.load(Ordering::SeqCst) > after_xrun,
+            "the callbacks did not resume after the xrun"
+        );
+    }
+}
@@ -0,0 +1,79 @@
+//! The functions of alsa-lib that the `alsa` crate does not wrap, called on the handles it opens.
+
+extern crate alsa_sys;
+
+use super::alsa;
+use super::libc;
+use std::cell::Cell;
+use std::mem;
+use std::os::raw::c_void;
+
+pub(super) use self::alsa_sys::*;
+
+// The declaration of `alsa::PCM` in the `alsa` 0.4.3 the crate is pinned to, which does not give
+// access to the `snd_pcm_t` it wraps.
+#[allow(dead_code)]
+struct Pcm(*mut snd_pcm_t, Cell<bool>);
+
+const _: () = assert!(mem::size_of::<Pcm>() == mem::size_of::<alsa::PCM>());
+
+/// The `snd_pcm_t` wrapped by `pcm`.
+pub(super) fn pcm_handle(pcm: &alsa::PCM) -> *mut snd_pcm_t {
+    // `Pcm` is declared like `alsa::PCM` and thus shares its layout.
+    unsafe { (*(pcm as *const alsa::PCM as *const Pcm)).0 }
+}
+
+/// Read `frames` frames from a handle with non-interleaved access into the buffers of the
+/// channels that `planes` points to.
+///
+/// Each buffer must hold at least `frames` samples.
+pub(super) unsafe fn readn(
+    pcm: &alsa::PCM,
+    planes: &mut [*mut ()],
+    frames: usize,
+) -> Result<usize, alsa::Error> {
+    let res = snd_pcm_readn(
+        pcm_handle(pcm),
+        planes.as_mut_ptr() as *mut *mut c_void,
+        frames as snd_pcm_uframes_t,
+    );
+    frames_result("snd_pcm_readn", res)
+}
+
+/// Write `frames` frames to a handle with non-interleaved access from the buffers of the channels
+/// that `planes` points to.
+///
+/// Each buffer must hold at least `frames` samples.
+pub(super) unsafe fn writen(
+    pcm: &alsa::PCM,
+    planes: &mut [*mut ()],
+    frames: usize,
+) -> Result<usize, alsa::Error> {
+    let res = snd_pcm_writen(
+        pcm_handle(pcm),
+        planes.as_mut_ptr() as *mut *mut c_void,
+        frames as snd_pcm_uframes_t,
+    );
+    frames_result("snd_pcm_writen", res)
+}
+
+/// Apply the channel map `chmap` to `pcm`.
+pub(super) unsafe fn set_chmap(
+    pcm: &alsa::PCM,
+    chmap: *const snd_pcm_chmap_t,
+) -> Result<(), alsa::Error> {
+    match snd_pcm_set_chmap(pcm_handle(pcm), chmap) {
+        res if res < 0 => Err(alsa::Error::new("snd_pcm_set_chmap", -res)),
+        _ => Ok(()),
+    }
+}
+
+// The number of frames transferred, or the error of a negative result as the `alsa` crate reports
+// it.
+fn frames_result(func: &'static str, res: snd_pcm_sframes_t) -> Result<usize, alsa::Error> {
+    if res < 0 {
+        Err(alsa::Error::new(func, -res as libc::c_int))
+    } else {
+        Ok(res as usize)
+    }
+}
@@ -0,0 +1,248 @@
+use super::alsa::{self, PollDescriptors};
+use super::libc;
+use super::{trigger, TriggerReceiver, TriggerSender};
+use std::collections::BTreeSet;
+use std::thread::{self, JoinHandle};
+use {BackendSpecificError, DeviceEvent, StreamDirection, WatchDevicesError};
+
+/// Keeps the device watcher thread running until dropped.
+pub struct DeviceWatcherThread {
+    trigger: TriggerSender,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl DeviceWatcherThread {
+    pub fn spawn<F>(callback: F) -> Result<Self, WatchDevicesError>
+    where
+        F: FnMut(DeviceEvent) + Send + 'static,
+    {
+        let (tx, rx) = trigger();
+        let thread = thread::Builder::new()
+            .name("cpal_alsa_device_watcher".to_owned())
+            .spawn(move || device_watcher(rx, callback))
+            .map_err(|err| BackendSpecificError {
+                description: err.to_string(),
+            })?;
+        Ok(DeviceWatcherThread {
+            trigger: tx,
+            thread: Some(thread),
+        })
+    }
+}
+
+impl Drop for DeviceWatcherThread {
+    fn drop(&mut self) {
+        self.trigger.wakeup();
+        self.thread.take().unwrap().join().unwrap();
+    }
+}
+
+// A sound card whose control events are subscribed to.
+//
+// Subscribing is what makes the control device report the disconnection of the card when polled.
+struct WatchedCard {
+    ctl: alsa::Ctl,
+    num_descriptors: usize,
+}
+
+// Opens the control device of every sound card currently present and subscribes to its events.
+//
+// Cards whose control device cannot be opened, e.g. as its permissions have not been set up yet,
+// are skipped until the next change to the set of cards.
+fn watch_cards() -> Vec<WatchedCard> {
+    alsa::card::Iter::new()
+        .filter_map(Result::ok)
+        .filter_map(|card| {
+            let ctl = alsa::Ctl::from_card(&card, true).ok()?;
+            ctl.subscribe_events(true).ok()?;
+            let num_descriptors = ctl.count();
+            Some(WatchedCard {
+                ctl,
+                num_descriptors,
+            })
+        })
+        .collect()
+}
+
+// Reads all pending events of `card`. Returns `false` if the card was disconnected.
+fn drain_events(card: &WatchedCard, descriptors: &[libc::pollfd]) -> bool {
+    match card.ctl.revents(descriptors) {
+        Ok(flags) if flags.intersects(alsa::poll::Flags::ERR | alsa::poll::Flags::HUP) => false,
+        Ok(flags) if flags.contains(alsa::poll::Flags::IN) => loop {
+            match card.ctl.read() {
+                Ok(Some(_)) => continue,
+                Ok(None) => return true,
+                Err(_) => return false,
+            }
+        },
+        Ok(_) => true,
+        Err(_) => false,
+    }
+}
+
+// The id of the card backing the "default" device, as resolved by the ALSA configuration.
+//
+// With a sound server plugin configured as the default, the id is that of the plugin and never
+// changes, as the sound server rather than ALSA decides which card is used.
+fn default_card() -> Option<String> {
+    let ctl = alsa::Ctl::new("default", true).ok()?;
+    let info = ctl.card_info().ok()?;
+    info.get_id().ok().map(str::to_owned)
+}
+
+// Notifies of sound cards appearing and disappearing by watching the device nodes in `/dev/snd`.
+//
+// The control devices of the cards present only report their own disconnection, so this is what
+// reveals cards that are plugged in.
+struct Hotplug(libc::c_int);
+
+impl Hotplug {
+    #[cfg(target_os = "linux")]
+    fn new() -> Option<Self> {
+        let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+        if fd < 0 {
+            return None;
+        }
+        let hotplug = Hotplug(fd);
+        // The permissions of new device nodes are only set up after they were created.
+        let mask = libc::IN_CREATE | libc::IN_DELETE | libc::IN_ATTRIB;
+        let path = b"/dev/snd\0".as_ptr() as *const libc::c_char;
+        if unsafe { libc::inotify_add_watch(fd, path, mask) } < 0 {
+            return None;
+        }
+        Some(hotplug)
+    }
+
+    // Cards plugged in are not detected without inotify.
+    #[cfg(not(target_os = "linux"))]
+    fn new() -> Option<Self> {
+        None
+    }
+
+    // Reads all pending notifications.
+    fn clear(&self) {
+        let mut buf = [0u8; 4096];
+        while unsafe { libc::read(self.0, buf.as_mut_ptr() as *mut _, buf.len()) } > 0 {}
+    }
+}
+
+impl Drop for Hotplug {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.0);
+        }
+    }
+}
+
+// The names of all PCM devices as they would be yielded by `Devices`, without opening them.
+fn pcm_names() -> BTreeSet<String> {
+    match alsa::device_name::HintIter::new_str(None, "pcm") {
+        Ok(hints) => hints
+            .filter_map(|hint| hint.name)
+            .filter(|name| name != "null")
+            .collect(),
+        Err(_) => BTreeSet::new(),
+    }
+}
+
+fn device_watcher<F>(rx: TriggerReceiver, mut callback: F)
+where
+    F: FnMut(DeviceEvent),
+{
+    let hotplug = Hotplug::new();
+    let mut cards = watch_cards();
+    let mut known_names = pcm_names();
+    let mut known_default = default_card();
+    let mut descriptors = Vec::new();
+
+    loop {
+        descriptors.clear();
+        descriptors.push(libc::pollfd {
+            fd: rx.0,
+            events: libc::POLLIN,
+            revents: 0,
+        });
+        if let Some(ref hotplug) = hotplug {
+            descriptors.push(libc::pollfd {
+                fd: hotplug.0,
+                events: libc::POLLIN,
+                revents: 0,
+            });
+        }
+        let cards_start = descriptors.len();
+        for card in &cards {
+            // Negative descriptors are ignored by `poll`, should filling them in fail.
+            let len = descriptors.len();
+            descriptors.resize(
+                len + card.num_descriptors,
+                libc::pollfd {
+                    fd: -1,
+                    events: 0,
+                    revents: 0,
+                },
+            );
+            let _ = card.ctl.fill(&mut descriptors[len..]);
+        }
+
+        let res = unsafe {
+            libc::poll(
+                descriptors.as_mut_ptr(),
+                descriptors.len() as libc::nfds_t,
+                -1,
+            )
+        };
+        if res < 0 {
+            if nix::errno::Errno::last() == nix::errno::Errno::EINTR {
+                continue;
+            }
+            return;
+        }
+        if descriptors[0].revents != 0 {
+            // The `DeviceWatcher` was dropped.
+            rx.clear_pipe();
+            return;
+        }
+
+        let mut changed = false;
+        if let Some(ref hotplug) = hotplug {
+            if descriptors[1].revents != 0 {
+                hotplug.clear();
+                changed = true;
+            }
+        }
+        let mut start = cards_start;
+        for card in &cards {
+            let end = start + card.num_descriptors;
+            if !drain_events(card, &descriptors[start..end]) {
+                changed = true;
+            }
+            start = end;
+        }
+        if !changed {
+            continue;
+        }
+
+        // Hints are only re-enumerated when a card appears or disappears, as doing so is
+        // considerably more expensive than handling the events of the cards.
+        cards = watch_cards();
+        let current_names = pcm_names();
+        for name in known_names.difference(&current_names) {
+            callback(DeviceEvent::DeviceRemoved { name: name.clone() });
+        }
+        for name in current_names.difference(&known_names) {
+            callback(DeviceEvent::DeviceAdded { name: name.clone() });
+        }
+        known_names = current_names;
+
+        let current_default = default_card();
+        if current_default != known_default {
+            callback(DeviceEvent::DefaultDeviceChanged {
+                direction: StreamDirection::Input,
+            });
+            callback(DeviceEvent::DefaultDeviceChanged {
+                direction: StreamDirection::Output,
+            });
+            known_default = current_default;
+        }
+    }
+}
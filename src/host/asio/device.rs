@@ -145,6 +145,7 @@ impl Device {
             sample_rate,
             buffer_size,
             sample_format,
+            channel_map: None,
         })
     }
 
@@ -165,6 +166,7 @@ impl Device {
             sample_rate,
             buffer_size,
             sample_format,
+            channel_map: None,
         })
     }
 }
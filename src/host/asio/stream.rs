@@ -5,9 +5,8 @@ use self::num_traits::PrimInt;
 use super::parking_lot::Mutex;
 use super::Device;
 use crate::{
-    BackendSpecificError, BufferSize, BuildStreamError, Data, InputCallbackInfo,
-    OutputCallbackInfo, PauseStreamError, PlayStreamError, Sample, SampleFormat, StreamConfig,
-    StreamError,
+    BackendSpecificError, BuildStreamError, Data, InputCallbackInfo, OutputCallbackInfo,
+    PauseStreamError, PlayStreamError, Sample, SampleFormat, StreamConfig, StreamError,
 };
 use std;
 use std::sync::atomic::{AtomicBool, Ordering};
@@ -138,7 +137,7 @@ impl Device {
                     .sub(delay)
                     .expect("`capture` occurs before origin of alsa `StreamInstant`");
                 let timestamp = crate::InputStreamTimestamp { callback, capture };
-                let info = InputCallbackInfo { timestamp };
+                let info = InputCallbackInfo::new(timestamp);
                 data_callback(&data, &info);
             }
 
@@ -336,7 +335,7 @@ impl Device {
                     .add(delay)
                     .expect("`playback` occurs beyond representation supported by `StreamInstant`");
                 let timestamp = crate::OutputStreamTimestamp { callback, playback };
-                let info = OutputCallbackInfo { timestamp };
+                let info = OutputCallbackInfo::new(timestamp);
                 data_callback(&mut data, &info);
 
                 // 2. Silence ASIO channels if necessary.
@@ -483,10 +482,10 @@ impl Device {
         let num_channels = config.channels as usize;
         let ref mut streams = *self.asio_streams.lock();
 
-        let buffer_size = match config.buffer_size {
-            BufferSize::Fixed(v) => Some(v as i32),
-            BufferSize::Default => None,
-        };
+        let buffer_size = config
+            .buffer_size
+            .period_frames(config.sample_rate)
+            .map(|v| v as i32);
 
         // Either create a stream if thers none or had back the
         // size of the current one.
@@ -530,10 +529,10 @@ impl Device {
         let num_channels = config.channels as usize;
         let ref mut streams = *self.asio_streams.lock();
 
-        let buffer_size = match config.buffer_size {
-            BufferSize::Fixed(v) => Some(v as i32),
-            BufferSize::Default => None,
-        };
+        let buffer_size = config
+            .buffer_size
+            .period_frames(config.sample_rate)
+            .map(|v| v as i32);
 
         // Either create a stream if thers none or had back the
         // size of the current one.
@@ -658,6 +657,7 @@ fn check_config(
         channels,
         sample_rate,
         buffer_size,
+        ..
     } = config;
     // Try and set the sample rate to what the user selected.
     let sample_rate = sample_rate.0.into();
@@ -673,10 +673,10 @@ fn check_config(
             return Err(BuildStreamError::StreamConfigNotSupported);
         }
     }
-    // unsigned formats are not supported by asio
+    // only i16 and f32 samples are currently supported by the asio host
     match sample_format {
         SampleFormat::I16 | SampleFormat::F32 => (),
-        SampleFormat::U16 => return Err(BuildStreamError::StreamConfigNotSupported),
+        _ => return Err(BuildStreamError::StreamConfigNotSupported),
     }
     if *channels > num_asio_channels {
         return Err(BuildStreamError::StreamConfigNotSupported);
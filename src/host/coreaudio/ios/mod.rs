@@ -194,10 +194,10 @@ impl DeviceTrait for Device {
 
         // Set the buffersize
         match config.buffer_size {
-            BufferSize::Fixed(_) => {
+            BufferSize::Default => (),
+            _ => {
                 return Err(BuildStreamError::StreamConfigNotSupported);
             }
-            BufferSize::Default => (),
         }
 
         // Register the callback that is being called by coreaudio whenever it needs data to be
@@ -236,7 +236,7 @@ impl DeviceTrait for Device {
                 .expect("`capture` occurs before origin of alsa `StreamInstant`");
             let timestamp = crate::InputStreamTimestamp { callback, capture };
 
-            let info = InputCallbackInfo { timestamp };
+            let info = InputCallbackInfo::new(timestamp);
             data_callback(&data, &info);
             Ok(())
         })?;
@@ -262,10 +262,10 @@ impl DeviceTrait for Device {
         E: FnMut(StreamError) + Send + 'static,
     {
         match config.buffer_size {
-            BufferSize::Fixed(_) => {
+            BufferSize::Default => (),
+            _ => {
                 return Err(BuildStreamError::StreamConfigNotSupported);
             }
-            BufferSize::Default => (),
         };
 
         let mut audio_unit = create_audio_unit()?;
@@ -312,7 +312,7 @@ impl DeviceTrait for Device {
                 .expect("`playback` occurs beyond representation supported by `StreamInstant`");
             let timestamp = crate::OutputStreamTimestamp { callback, playback };
 
-            let info = OutputCallbackInfo { timestamp };
+            let info = OutputCallbackInfo::new(timestamp);
             data_callback(&mut data, &info);
             Ok(())
         })?;
@@ -427,5 +427,6 @@ fn stream_config_from_asbd(asbd: AudioStreamBasicDescription) -> SupportedStream
         sample_rate: SampleRate(asbd.mSampleRate as u32),
         buffer_size: buffer_size.clone(),
         sample_format: SUPPORTED_SAMPLE_FORMAT,
+        channel_map: None,
     }
 }
@@ -22,11 +22,10 @@ use self::coreaudio::sys::{
 };
 use crate::traits::{DeviceTrait, HostTrait, StreamTrait};
 use crate::{
-    BackendSpecificError, BufferSize, BuildStreamError, ChannelCount, Data,
-    DefaultStreamConfigError, DeviceNameError, DevicesError, InputCallbackInfo, OutputCallbackInfo,
-    PauseStreamError, PlayStreamError, SampleFormat, SampleRate, StreamConfig, StreamError,
-    SupportedBufferSize, SupportedStreamConfig, SupportedStreamConfigRange,
-    SupportedStreamConfigsError,
+    BackendSpecificError, BuildStreamError, ChannelCount, Data, DefaultStreamConfigError,
+    DeviceNameError, DevicesError, InputCallbackInfo, OutputCallbackInfo, PauseStreamError,
+    PlayStreamError, SampleFormat, SampleRate, StreamConfig, StreamError, SupportedBufferSize,
+    SupportedStreamConfig, SupportedStreamConfigRange, SupportedStreamConfigsError,
 };
 use std::cell::RefCell;
 use std::ffi::CStr;
@@ -386,6 +385,7 @@ impl Device {
                 channels: asbd.mChannelsPerFrame as _,
                 buffer_size,
                 sample_format,
+                channel_map: None,
             };
             Ok(config)
         }
@@ -618,8 +618,8 @@ impl Device {
         audio_unit.set_property(kAudioUnitProperty_StreamFormat, scope, element, Some(&asbd))?;
 
         // Set the buffersize
-        match config.buffer_size {
-            BufferSize::Fixed(v) => {
+        match config.buffer_size.period_frames(config.sample_rate) {
+            Some(v) => {
                 let buffer_size_range = get_io_buffer_frame_size_range(&audio_unit)?;
                 match buffer_size_range {
                     SupportedBufferSize::Range { min, max } => {
@@ -637,7 +637,7 @@ impl Device {
                     SupportedBufferSize::Unknown => (),
                 }
             }
-            BufferSize::Default => (),
+            None => (),
         }
 
         // Register the callback that is being called by coreaudio whenever it needs data to be
@@ -676,7 +676,7 @@ impl Device {
                 .expect("`capture` occurs before origin of alsa `StreamInstant`");
             let timestamp = crate::InputStreamTimestamp { callback, capture };
 
-            let info = InputCallbackInfo { timestamp };
+            let info = InputCallbackInfo::new(timestamp);
             data_callback(&data, &info);
             Ok(())
         })?;
@@ -712,8 +712,8 @@ impl Device {
         audio_unit.set_property(kAudioUnitProperty_StreamFormat, scope, element, Some(&asbd))?;
 
         // Set the buffersize
-        match config.buffer_size {
-            BufferSize::Fixed(v) => {
+        match config.buffer_size.period_frames(config.sample_rate) {
+            Some(v) => {
                 let buffer_size_range = get_io_buffer_frame_size_range(&audio_unit)?;
                 match buffer_size_range {
                     SupportedBufferSize::Range { min, max } => {
@@ -731,7 +731,7 @@ impl Device {
                     SupportedBufferSize::Unknown => (),
                 }
             }
-            BufferSize::Default => (),
+            None => (),
         }
 
         // Register the callback that is being called by coreaudio whenever it needs data to be
@@ -768,7 +768,7 @@ impl Device {
                 .expect("`playback` occurs beyond representation supported by `StreamInstant`");
             let timestamp = crate::OutputStreamTimestamp { callback, playback };
 
-            let info = OutputCallbackInfo { timestamp };
+            let info = OutputCallbackInfo::new(timestamp);
             data_callback(&mut data, &info);
             Ok(())
         })?;
@@ -8,7 +8,7 @@ use stdweb::web::TypedArray;
 use stdweb::Reference;
 
 use crate::{
-    BufferSize, BuildStreamError, Data, DefaultStreamConfigError, DeviceNameError, DevicesError,
+    BuildStreamError, Data, DefaultStreamConfigError, DeviceNameError, DevicesError,
     InputCallbackInfo, OutputCallbackInfo, PauseStreamError, PlayStreamError, SampleFormat,
     SampleRate, StreamConfig, StreamError, SupportedBufferSize, SupportedStreamConfig,
     SupportedStreamConfigRange, SupportedStreamConfigsError,
@@ -194,15 +194,15 @@ impl DeviceTrait for Device {
             return Err(BuildStreamError::StreamConfigNotSupported);
         }
 
-        let buffer_size_frames = match config.buffer_size {
-            BufferSize::Fixed(v) => {
+        let buffer_size_frames = match config.buffer_size.period_frames(config.sample_rate) {
+            Some(v) => {
                 if v == 0 {
                     return Err(BuildStreamError::StreamConfigNotSupported);
                 } else {
                     v as usize
                 }
             }
-            BufferSize::Default => DEFAULT_BUFFER_SIZE,
+            None => DEFAULT_BUFFER_SIZE,
         };
 
         // Create the stream.
@@ -291,7 +291,7 @@ fn audio_callback_fn<D, E>(
                 .add(buffer_duration)
                 .expect("`playback` occurs beyond representation supported by `StreamInstant`");
             let timestamp = crate::OutputStreamTimestamp { callback, playback };
-            let info = OutputCallbackInfo { timestamp };
+            let info = OutputCallbackInfo::new(timestamp);
             data_cb(&mut data, &info);
         }
 
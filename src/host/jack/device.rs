@@ -1,13 +1,14 @@
 use crate::{
-    BackendSpecificError, BuildStreamError, Data, DefaultStreamConfigError, DeviceNameError,
-    InputCallbackInfo, OutputCallbackInfo, SampleFormat, SampleRate, StreamConfig, StreamError,
-    SupportedBufferSize, SupportedStreamConfig, SupportedStreamConfigRange,
-    SupportedStreamConfigsError,
+    BackendSpecificError, BuildStreamError, ChannelPosition, Data, DefaultStreamConfigError,
+    DeviceNameError, DuplexCallbackInfo, InputCallbackInfo, OutputCallbackInfo, SampleFormat,
+    SampleRate, StreamConfig, StreamError, SupportedBufferSize, SupportedStreamConfig,
+    SupportedStreamConfigRange, SupportedStreamConfigsError,
 };
 use std::hash::{Hash, Hasher};
 use traits::DeviceTrait;
 
-use super::stream::Stream;
+use super::midi::Midi;
+use super::stream::{PortNames, Stream};
 use super::JACK_SAMPLE_FORMAT;
 
 pub type SupportedInputConfigs = std::vec::IntoIter<SupportedStreamConfigRange>;
@@ -17,7 +18,7 @@ const DEFAULT_NUM_CHANNELS: u16 = 2;
 const DEFAULT_SUPPORTED_CHANNELS: [u16; 10] = [1, 2, 4, 6, 8, 16, 24, 32, 48, 64];
 
 /// If a device is for input or output.
-/// Until we have duplex stream support JACK clients and CPAL devices for JACK will be either input or output.
+/// Regular streams are either input or output, duplex streams may be built from either device type.
 #[derive(Clone, Debug)]
 pub enum DeviceType {
     InputDevice,
@@ -31,6 +32,8 @@ pub struct Device {
     device_type: DeviceType,
     start_server_automatically: bool,
     connect_ports_automatically: bool,
+    /// The names of the ports of the streams built from this device.
+    port_names: PortNames,
 }
 
 impl Device {
@@ -58,6 +61,7 @@ impl Device {
                 device_type,
                 start_server_automatically,
                 connect_ports_automatically,
+                port_names: PortNames::default(),
             }),
             Err(e) => Err(e),
         }
@@ -104,6 +108,7 @@ impl Device {
             sample_rate,
             buffer_size,
             sample_format,
+            channel_map: ChannelPosition::default_layout(channels),
         })
     }
 
@@ -127,65 +132,78 @@ impl Device {
         supported_configs
     }
 
-    pub fn is_input(&self) -> bool {
-        match self.device_type {
-            DeviceType::InputDevice => true,
-            _ => false,
-        }
+    /// Set the short names of the input ports registered by the streams built from this device,
+    /// in channel order. Channels without a name use the default name `in_<index>`.
+    pub fn set_input_port_names(&mut self, names: Vec<String>) {
+        self.port_names.input = names;
     }
 
-    pub fn is_output(&self) -> bool {
-        match self.device_type {
-            DeviceType::OutputDevice => true,
-            _ => false,
-        }
+    /// Set the short names of the output ports registered by the streams built from this device,
+    /// in channel order. Channels without a name use the default name `out_<index>`.
+    pub fn set_output_port_names(&mut self, names: Vec<String>) {
+        self.port_names.output = names;
     }
-}
 
-impl DeviceTrait for Device {
-    type SupportedInputConfigs = SupportedInputConfigs;
-    type SupportedOutputConfigs = SupportedOutputConfigs;
-    type Stream = Stream;
-
-    fn name(&self) -> Result<String, DeviceNameError> {
-        Ok(self.name.clone())
+    /// Set the short names of the MIDI input ports registered by the streams built from this
+    /// device, one port per name. No MIDI ports are registered by default.
+    ///
+    /// The events of these ports are passed to the data callbacks of the streams built with
+    /// `build_input_stream_raw_with_midi` and its siblings.
+    pub fn set_midi_input_port_names(&mut self, names: Vec<String>) {
+        self.port_names.midi_input = names;
     }
 
-    fn supported_input_configs(
-        &self,
-    ) -> Result<Self::SupportedInputConfigs, SupportedStreamConfigsError> {
-        Ok(self.supported_configs().into_iter())
+    /// Set the short names of the MIDI output ports registered by the streams built from this
+    /// device, one port per name. No MIDI ports are registered by default.
+    ///
+    /// These ports are written to by the data callbacks of the streams built with
+    /// `build_output_stream_raw_with_midi` and its siblings.
+    pub fn set_midi_output_port_names(&mut self, names: Vec<String>) {
+        self.port_names.midi_output = names;
     }
 
-    fn supported_output_configs(
+    /// Like `build_input_stream_raw`, with the events of the MIDI input ports of the stream
+    /// passed to the data callback along with the audio of the same process cycle.
+    pub fn build_input_stream_raw_with_midi<D, E>(
         &self,
-    ) -> Result<Self::SupportedOutputConfigs, SupportedStreamConfigsError> {
-        Ok(self.supported_configs().into_iter())
-    }
-
-    /// Returns the default input config
-    /// The sample format for JACK audio ports is always "32 bit float mono audio" unless using a custom type.
-    /// The sample rate is set by the JACK server.
-    fn default_input_config(&self) -> Result<SupportedStreamConfig, DefaultStreamConfigError> {
-        self.default_config()
+        conf: &StreamConfig,
+        sample_format: SampleFormat,
+        data_callback: D,
+        error_callback: E,
+    ) -> Result<Stream, BuildStreamError>
+    where
+        D: FnMut(&Data, &InputCallbackInfo, &mut Midi) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        self.build_input_stream(conf, sample_format, false, data_callback, error_callback)
     }
 
-    /// Returns the default output config
-    /// The sample format for JACK audio ports is always "32 bit float mono audio" unless using a custom type.
-    /// The sample rate is set by the JACK server.
-    fn default_output_config(&self) -> Result<SupportedStreamConfig, DefaultStreamConfigError> {
-        self.default_config()
+    /// Like `build_output_stream_raw`, with the data callback writing the events of the MIDI
+    /// output ports of the stream along with the audio of the same process cycle.
+    pub fn build_output_stream_raw_with_midi<D, E>(
+        &self,
+        conf: &StreamConfig,
+        sample_format: SampleFormat,
+        data_callback: D,
+        error_callback: E,
+    ) -> Result<Stream, BuildStreamError>
+    where
+        D: FnMut(&mut Data, &OutputCallbackInfo, &mut Midi) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        self.build_output_stream(conf, sample_format, false, data_callback, error_callback)
     }
 
-    fn build_input_stream_raw<D, E>(
+    fn build_input_stream<D, E>(
         &self,
         conf: &StreamConfig,
         sample_format: SampleFormat,
+        planar: bool,
         data_callback: D,
         error_callback: E,
-    ) -> Result<Self::Stream, BuildStreamError>
+    ) -> Result<Stream, BuildStreamError>
     where
-        D: FnMut(&Data, &InputCallbackInfo) + Send + 'static,
+        D: FnMut(&Data, &InputCallbackInfo, &mut Midi) + Send + 'static,
         E: FnMut(StreamError) + Send + 'static,
     {
         if let DeviceType::OutputDevice = &self.device_type {
@@ -195,6 +213,7 @@ impl DeviceTrait for Device {
         if conf.sample_rate != self.sample_rate || sample_format != JACK_SAMPLE_FORMAT {
             return Err(BuildStreamError::StreamConfigNotSupported);
         }
+        let channel_map = channel_map(conf)?;
         // The settings should be fine, create a Client
         let client_options = super::get_client_options(self.start_server_automatically);
         let client;
@@ -208,7 +227,15 @@ impl DeviceTrait for Device {
                 })
             }
         };
-        let mut stream = Stream::new_input(client, conf.channels, data_callback, error_callback);
+        let mut stream = Stream::new_input(
+            client,
+            conf.channels,
+            &self.port_names,
+            planar,
+            data_callback,
+            error_callback,
+        );
+        stream.set_channel_map(channel_map);
 
         if self.connect_ports_automatically {
             stream.connect_to_system_inputs();
@@ -217,15 +244,16 @@ impl DeviceTrait for Device {
         Ok(stream)
     }
 
-    fn build_output_stream_raw<D, E>(
+    fn build_output_stream<D, E>(
         &self,
         conf: &StreamConfig,
         sample_format: SampleFormat,
+        planar: bool,
         data_callback: D,
         error_callback: E,
-    ) -> Result<Self::Stream, BuildStreamError>
+    ) -> Result<Stream, BuildStreamError>
     where
-        D: FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static,
+        D: FnMut(&mut Data, &OutputCallbackInfo, &mut Midi) + Send + 'static,
         E: FnMut(StreamError) + Send + 'static,
     {
         if let DeviceType::InputDevice = &self.device_type {
@@ -235,6 +263,60 @@ impl DeviceTrait for Device {
         if conf.sample_rate != self.sample_rate || sample_format != JACK_SAMPLE_FORMAT {
             return Err(BuildStreamError::StreamConfigNotSupported);
         }
+        let channel_map = channel_map(conf)?;
+
+        // The settings should be fine, create a Client
+        let client_options = super::get_client_options(self.start_server_automatically);
+        let client;
+        match super::get_client(&self.name, client_options) {
+            Ok(c) => client = c,
+            Err(e) => {
+                return Err(BuildStreamError::BackendSpecific {
+                    err: BackendSpecificError {
+                        description: e.to_string(),
+                    },
+                })
+            }
+        };
+        let mut stream = Stream::new_output(
+            client,
+            conf.channels,
+            &self.port_names,
+            planar,
+            data_callback,
+            error_callback,
+        );
+        stream.set_channel_map(channel_map);
+
+        if self.connect_ports_automatically {
+            stream.connect_to_system_outputs();
+        }
+
+        Ok(stream)
+    }
+
+    /// Like `build_duplex_stream_raw`, with the data callback reading and writing the events of
+    /// the MIDI ports of the stream along with the audio of the same process cycle.
+    pub fn build_duplex_stream_raw_with_midi<D, E>(
+        &self,
+        input_conf: &StreamConfig,
+        output_conf: &StreamConfig,
+        sample_format: SampleFormat,
+        data_callback: D,
+        error_callback: E,
+    ) -> Result<Stream, BuildStreamError>
+    where
+        D: FnMut(&Data, &mut Data, &DuplexCallbackInfo, &mut Midi) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        if input_conf.sample_rate != self.sample_rate
+            || output_conf.sample_rate != self.sample_rate
+            || sample_format != JACK_SAMPLE_FORMAT
+        {
+            return Err(BuildStreamError::StreamConfigNotSupported);
+        }
+        channel_map(input_conf)?;
+        let channel_map = channel_map(output_conf)?;
 
         // The settings should be fine, create a Client
         let client_options = super::get_client_options(self.start_server_automatically);
@@ -249,14 +331,194 @@ impl DeviceTrait for Device {
                 })
             }
         };
-        let mut stream = Stream::new_output(client, conf.channels, data_callback, error_callback);
+        let mut stream = Stream::new_duplex(
+            client,
+            input_conf.channels,
+            output_conf.channels,
+            &self.port_names,
+            data_callback,
+            error_callback,
+        );
+        stream.set_channel_map(channel_map);
 
         if self.connect_ports_automatically {
+            stream.connect_to_system_inputs();
             stream.connect_to_system_outputs();
         }
 
         Ok(stream)
     }
+
+    pub fn is_input(&self) -> bool {
+        match self.device_type {
+            DeviceType::InputDevice => true,
+            _ => false,
+        }
+    }
+
+    pub fn is_output(&self) -> bool {
+        match self.device_type {
+            DeviceType::OutputDevice => true,
+            _ => false,
+        }
+    }
+}
+
+// JACK ports carry no speaker positions, so the positions of the ports of a stream are the
+// requested ones or else the conventional layout for their number, in port order.
+fn channel_map(conf: &StreamConfig) -> Result<Option<Vec<ChannelPosition>>, BuildStreamError> {
+    match conf.channel_map {
+        Some(ref map) if map.len() != conf.channels as usize => {
+            Err(BuildStreamError::StreamConfigNotSupported)
+        }
+        Some(ref map) => Ok(Some(map.clone())),
+        None => Ok(ChannelPosition::default_layout(conf.channels)),
+    }
+}
+
+impl DeviceTrait for Device {
+    type SupportedInputConfigs = SupportedInputConfigs;
+    type SupportedOutputConfigs = SupportedOutputConfigs;
+    type Stream = Stream;
+
+    fn name(&self) -> Result<String, DeviceNameError> {
+        Ok(self.name.clone())
+    }
+
+    fn supported_input_configs(
+        &self,
+    ) -> Result<Self::SupportedInputConfigs, SupportedStreamConfigsError> {
+        Ok(self.supported_configs().into_iter())
+    }
+
+    fn supported_output_configs(
+        &self,
+    ) -> Result<Self::SupportedOutputConfigs, SupportedStreamConfigsError> {
+        Ok(self.supported_configs().into_iter())
+    }
+
+    /// Returns the default input config
+    /// The sample format for JACK audio ports is always "32 bit float mono audio" unless using a custom type.
+    /// The sample rate is set by the JACK server.
+    fn default_input_config(&self) -> Result<SupportedStreamConfig, DefaultStreamConfigError> {
+        self.default_config()
+    }
+
+    /// Returns the default output config
+    /// The sample format for JACK audio ports is always "32 bit float mono audio" unless using a custom type.
+    /// The sample rate is set by the JACK server.
+    fn default_output_config(&self) -> Result<SupportedStreamConfig, DefaultStreamConfigError> {
+        self.default_config()
+    }
+
+    fn build_input_stream_raw<D, E>(
+        &self,
+        conf: &StreamConfig,
+        sample_format: SampleFormat,
+        mut data_callback: D,
+        error_callback: E,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        D: FnMut(&Data, &InputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        self.build_input_stream_raw_with_midi(
+            conf,
+            sample_format,
+            move |data: &Data, info: &InputCallbackInfo, _: &mut Midi| data_callback(data, info),
+            error_callback,
+        )
+    }
+
+    fn build_output_stream_raw<D, E>(
+        &self,
+        conf: &StreamConfig,
+        sample_format: SampleFormat,
+        mut data_callback: D,
+        error_callback: E,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        D: FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        self.build_output_stream_raw_with_midi(
+            conf,
+            sample_format,
+            move |data: &mut Data, info: &OutputCallbackInfo, _: &mut Midi| {
+                data_callback(data, info)
+            },
+            error_callback,
+        )
+    }
+
+    /// Passes the buffers of the input ports to the data callback without copying them.
+    fn build_input_stream_planar_raw<D, E>(
+        &self,
+        conf: &StreamConfig,
+        sample_format: SampleFormat,
+        mut data_callback: D,
+        error_callback: E,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        D: FnMut(&Data, &InputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        self.build_input_stream(
+            conf,
+            sample_format,
+            true,
+            move |data: &Data, info: &InputCallbackInfo, _: &mut Midi| data_callback(data, info),
+            error_callback,
+        )
+    }
+
+    /// Lets the data callback write directly into the buffers of the output ports.
+    fn build_output_stream_planar_raw<D, E>(
+        &self,
+        conf: &StreamConfig,
+        sample_format: SampleFormat,
+        mut data_callback: D,
+        error_callback: E,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        D: FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        self.build_output_stream(
+            conf,
+            sample_format,
+            true,
+            move |data: &mut Data, info: &OutputCallbackInfo, _: &mut Midi| {
+                data_callback(data, info)
+            },
+            error_callback,
+        )
+    }
+
+    /// Registers both the input and the output ports on a single client so that they are
+    /// processed within the same JACK process cycle.
+    fn build_duplex_stream_raw<D, E>(
+        &self,
+        input_conf: &StreamConfig,
+        output_conf: &StreamConfig,
+        sample_format: SampleFormat,
+        mut data_callback: D,
+        error_callback: E,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        D: FnMut(&Data, &mut Data, &DuplexCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        self.build_duplex_stream_raw_with_midi(
+            input_conf,
+            output_conf,
+            sample_format,
+            move |input: &Data, output: &mut Data, info: &DuplexCallbackInfo, _: &mut Midi| {
+                data_callback(input, output, info)
+            },
+            error_callback,
+        )
+    }
 }
 
 impl PartialEq for Device {
@@ -0,0 +1,211 @@
+use super::jack;
+use crate::FrameCount;
+use thiserror::Error;
+
+/// The maximum number of events that may be written to each MIDI output port per process cycle.
+const MAX_OUTPUT_EVENTS: usize = 1024;
+/// The maximum number of bytes of the events written to each MIDI output port per process cycle.
+const MAX_OUTPUT_BYTES: usize = 16 * 1024;
+
+/// A MIDI event within the current process cycle.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct MidiEvent<'a> {
+    /// The offset of the event from the start of the process cycle, in frames.
+    pub time: FrameCount,
+    /// The raw bytes of the MIDI message.
+    pub bytes: &'a [u8],
+}
+
+/// An error that may occur when writing a MIDI event.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Error)]
+pub enum MidiWriteError {
+    /// The time of the event is earlier than that of the last event written to the port, or
+    /// beyond the end of the process cycle.
+    #[error("the MIDI event is out of order or beyond the end of the process cycle")]
+    InvalidTime,
+    /// The port cannot take any more events in this process cycle.
+    #[error("the MIDI output buffer is full")]
+    BufferFull,
+}
+
+/// The MIDI ports of a JACK stream during a process cycle, passed to the data callback alongside
+/// the audio of the same cycle.
+///
+/// Ports are indexed in the order of the names given to `Device::set_midi_input_port_names` and
+/// `Device::set_midi_output_port_names`.
+pub struct Midi<'a> {
+    scope: &'a jack::ProcessScope,
+    inputs: &'a [jack::Port<jack::MidiIn>],
+    outputs: &'a mut [MidiOutputBuffer],
+}
+
+impl<'a> Midi<'a> {
+    pub(super) fn new(
+        scope: &'a jack::ProcessScope,
+        inputs: &'a [jack::Port<jack::MidiIn>],
+        outputs: &'a mut [MidiOutputBuffer],
+    ) -> Self {
+        Midi {
+            scope,
+            inputs,
+            outputs,
+        }
+    }
+
+    /// The number of MIDI input ports.
+    pub fn input_port_count(&self) -> usize {
+        self.inputs.len()
+    }
+
+    /// The number of MIDI output ports.
+    pub fn output_port_count(&self) -> usize {
+        self.outputs.len()
+    }
+
+    /// The events received by the MIDI input port `port` in this process cycle, in time order.
+    ///
+    /// Panics if there is no such port.
+    pub fn input_events(&self, port: usize) -> impl Iterator<Item = MidiEvent<'a>> + 'a {
+        self.inputs[port].iter(self.scope).map(|raw| MidiEvent {
+            time: raw.time,
+            bytes: raw.bytes,
+        })
+    }
+
+    /// Write an event to the MIDI output port `port`.
+    ///
+    /// Events must be written in time order and within the process cycle. They are sent once the
+    /// data callback returns.
+    ///
+    /// Panics if there is no such port.
+    pub fn write_event(&mut self, port: usize, event: MidiEvent) -> Result<(), MidiWriteError> {
+        if event.time >= self.scope.n_frames() {
+            return Err(MidiWriteError::InvalidTime);
+        }
+        self.outputs[port].push(event)
+    }
+}
+
+/// The events written to a MIDI output port during a process cycle.
+///
+/// The events are buffered as JACK clears the port whenever it is written to anew, and the
+/// buffers are allocated up front so that no allocation happens on the process thread.
+pub(super) struct MidiOutputBuffer {
+    /// The time and the range of the bytes of each event.
+    events: Vec<(FrameCount, usize, usize)>,
+    bytes: Vec<u8>,
+}
+
+impl MidiOutputBuffer {
+    pub(super) fn new() -> Self {
+        MidiOutputBuffer {
+            events: Vec::with_capacity(MAX_OUTPUT_EVENTS),
+            bytes: Vec::with_capacity(MAX_OUTPUT_BYTES),
+        }
+    }
+
+    fn push(&mut self, event: MidiEvent) -> Result<(), MidiWriteError> {
+        if let Some(&(time, _, _)) = self.events.last() {
+            if event.time < time {
+                return Err(MidiWriteError::InvalidTime);
+            }
+        }
+        if self.events.len() == self.events.capacity()
+            || self.bytes.len() + event.bytes.len() > self.bytes.capacity()
+        {
+            return Err(MidiWriteError::BufferFull);
+        }
+        let start = self.bytes.len();
+        self.bytes.extend_from_slice(event.bytes);
+        self.events.push((event.time, start, self.bytes.len()));
+        Ok(())
+    }
+
+    /// Write the buffered events to `port`, replacing its contents, and empty the buffer.
+    ///
+    /// Events that do not fit into the port are dropped.
+    pub(super) fn flush(
+        &mut self,
+        port: &mut jack::Port<jack::MidiOut>,
+        scope: &jack::ProcessScope,
+    ) {
+        let mut writer = port.writer(scope);
+        self.drain(|raw| {
+            let _ = writer.write(&raw);
+        });
+    }
+
+    // Pass the buffered events to `write` in order and empty the buffer.
+    fn drain<F>(&mut self, mut write: F)
+    where
+        F: FnMut(jack::RawMidi),
+    {
+        for &(time, start, end) in &self.events {
+            write(jack::RawMidi {
+                time,
+                bytes: &self.bytes[start..end],
+            });
+        }
+        self.events.clear();
+        self.bytes.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MidiEvent, MidiOutputBuffer, MidiWriteError};
+
+    fn event<'a>(time: u32, bytes: &'a [u8]) -> MidiEvent<'a> {
+        MidiEvent { time, bytes }
+    }
+
+    #[test]
+    fn test_midi_output_order() {
+        let mut buffer = MidiOutputBuffer::new();
+        assert_eq!(buffer.push(event(5, &[0x90, 60, 100])), Ok(()));
+        // Events at the same time are kept in the order they were written.
+        assert_eq!(buffer.push(event(5, &[0x90, 64, 100])), Ok(()));
+        assert_eq!(
+            buffer.push(event(4, &[0x80, 60, 0])),
+            Err(MidiWriteError::InvalidTime)
+        );
+        assert_eq!(buffer.push(event(9, &[0x80, 60, 0])), Ok(()));
+    }
+
+    #[test]
+    fn test_midi_output_capacity() {
+        let mut buffer = MidiOutputBuffer::new();
+        for _ in 0..buffer.events.capacity() {
+            assert_eq!(buffer.push(event(0, &[0xf8])), Ok(()));
+        }
+        assert_eq!(
+            buffer.push(event(0, &[0xf8])),
+            Err(MidiWriteError::BufferFull)
+        );
+
+        let mut buffer = MidiOutputBuffer::new();
+        let sysex = vec![0u8; buffer.bytes.capacity() - 1];
+        assert_eq!(buffer.push(event(0, &sysex)), Ok(()));
+        assert_eq!(
+            buffer.push(event(0, &[0x90, 60, 100])),
+            Err(MidiWriteError::BufferFull)
+        );
+        assert_eq!(buffer.push(event(0, &[0xf8])), Ok(()));
+    }
+
+    #[test]
+    fn test_midi_output_drain() {
+        let mut buffer = MidiOutputBuffer::new();
+        buffer.push(event(2, &[0x90, 60, 100])).unwrap();
+        buffer.push(event(7, &[0xf8])).unwrap();
+        let mut written = Vec::new();
+        buffer.drain(|raw| written.push((raw.time, raw.bytes.to_vec())));
+        assert_eq!(written, vec![(2, vec![0x90, 60, 100]), (7, vec![0xf8])]);
+
+        // The next cycle starts from an empty buffer.
+        written.clear();
+        buffer.drain(|raw| written.push((raw.time, raw.bytes.to_vec())));
+        assert!(written.is_empty());
+        assert_eq!(buffer.push(event(0, &[0x80, 60, 0])), Ok(()));
+    }
+}
@@ -4,9 +4,13 @@ use crate::{DevicesError, SampleFormat, SupportedStreamConfigRange};
 use traits::HostTrait;
 
 mod device;
+mod midi;
 pub use self::device::Device;
-pub use self::stream::Stream;
+pub use self::midi::{Midi, MidiEvent, MidiWriteError};
+pub use self::stream::{PortDirection, PortEvent, Stream};
+pub use self::transport::{CallbackInfoExt, TransportBbt, TransportInfo, TransportState};
 mod stream;
+mod transport;
 
 const JACK_SAMPLE_FORMAT: SampleFormat = SampleFormat::F32;
 
@@ -18,8 +22,8 @@ pub type Devices = std::vec::IntoIter<Device>;
 #[derive(Debug)]
 pub struct Host {
     /// The name that the client will have in JACK.
-    /// Until we have duplex streams two clients will be created adding "out" or "in" to the name
-    /// since names have to be unique.
+    /// Separate input and output devices are created adding "out" or "in" to the name since names
+    /// have to be unique. Duplex streams use a single client with both input and output ports.
     name: String,
     /// If ports are to be connected to the system (soundcard) ports automatically (default is true).
     connect_ports_automatically: bool,
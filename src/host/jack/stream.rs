@@ -1,14 +1,60 @@
-use crate::ChannelCount;
+use super::jack::PortSpec;
+use crate::{ChannelCount, ChannelPosition};
+use std::collections::BTreeSet;
+use std::mem;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use traits::StreamTrait;
 
 use crate::{
-    BackendSpecificError, Data, InputCallbackInfo, OutputCallbackInfo, PauseStreamError,
-    PlayStreamError, SampleRate, StreamError,
+    BackendSpecificError, BufferSize, Data, DuplexCallbackInfo, FrameCount, InputCallbackInfo,
+    OutputCallbackInfo, PauseStreamError, PlayStreamError, SampleRate, StreamConfig, StreamError,
 };
 
+use super::midi::{Midi, MidiOutputBuffer};
+use super::transport::{self, TransportInfo};
 use super::JACK_SAMPLE_FORMAT;
+
+/// The direction of the data flowing through a JACK port, from the point of view of the port.
+///
+/// The ports of the sound card are seen as the outputs of the capture side (`system:capture_*`)
+/// and the inputs of the playback side (`system:playback_*`).
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum PortDirection {
+    /// The port receives data, it may be connected to from output ports.
+    Input,
+    /// The port produces data, it may be connected to input ports.
+    Output,
+}
+
+/// A change of the ports registered with the JACK server, by any client.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum PortEvent {
+    /// A port with the given full name has been registered.
+    Registered { name: String },
+    /// The port with the given full name has been unregistered.
+    Unregistered { name: String },
+}
+
+type PortEventCallback = Box<dyn FnMut(PortEvent) + Send + 'static>;
+
+type InputDataCallback = Box<dyn FnMut(&Data, &InputCallbackInfo, &mut Midi) + Send + 'static>;
+type OutputDataCallback =
+    Box<dyn FnMut(&mut Data, &OutputCallbackInfo, &mut Midi) + Send + 'static>;
+type DuplexDataCallback =
+    Box<dyn FnMut(&Data, &mut Data, &DuplexCallbackInfo, &mut Midi) + Send + 'static>;
+
+/// The short names of the ports registered by a stream. Audio ports without a name are named
+/// after their channel.
+#[derive(Clone, Debug, Default)]
+pub struct PortNames {
+    pub input: Vec<String>,
+    pub output: Vec<String>,
+    pub midi_input: Vec<String>,
+    pub midi_output: Vec<String>,
+}
+
 pub struct Stream {
     // TODO: It might be faster to send a message when playing/pausing than to check this every iteration
     playing: Arc<AtomicBool>,
@@ -16,59 +62,70 @@ pub struct Stream {
     // Port names are stored in order to connect them to other ports in jack automatically
     input_port_names: Vec<String>,
     output_port_names: Vec<String>,
+    midi_input_port_names: Vec<String>,
+    midi_output_port_names: Vec<String>,
+    // The positions of the audio ports, in port order. For duplex streams those of the outputs.
+    channel_map: Option<Vec<ChannelPosition>>,
+    // Shared with the notification handler, which invokes it on port (un)registrations.
+    port_event_callback: Arc<Mutex<Option<PortEventCallback>>>,
 }
 
 impl Stream {
     // TODO: Return error messages
+    /// If `planar` is set, the data callback receives the buffers of the ports as they are rather
+    /// than interleaved.
     pub fn new_input<D, E>(
         client: jack::Client,
         channels: ChannelCount,
+        port_names: &PortNames,
+        planar: bool,
         data_callback: D,
         mut error_callback: E,
     ) -> Stream
     where
-        D: FnMut(&Data, &InputCallbackInfo) + Send + 'static,
+        D: FnMut(&Data, &InputCallbackInfo, &mut Midi) + Send + 'static,
         E: FnMut(StreamError) + Send + 'static,
     {
-        let mut ports = vec![];
-        let mut port_names: Vec<String> = vec![];
-        // Create ports
-        for i in 0..channels {
-            let port_try = client.register_port(&format!("in_{}", i), jack::AudioIn::default());
-            match port_try {
-                Ok(port) => {
-                    // Get the port name in order to later connect it automatically
-                    if let Ok(port_name) = port.name() {
-                        port_names.push(port_name);
-                    }
-                    // Store the port into a Vec to move to the ProcessHandler
-                    ports.push(port);
-                }
-                Err(e) => {
-                    // If port creation failed, send the error back via the error_callback
-                    error_callback(
-                        BackendSpecificError {
-                            description: e.to_string(),
-                        }
-                        .into(),
-                    );
-                }
-            }
-        }
+        let (ports, input_port_names) =
+            register_input_ports(&client, channels, &port_names.input, &mut error_callback);
+        let (midi_in_ports, midi_input_port_names) = register_ports(
+            &client,
+            &port_names.midi_input,
+            jack::MidiIn,
+            &mut error_callback,
+        );
+        let (midi_out_ports, midi_output_port_names) = register_ports(
+            &client,
+            &port_names.midi_output,
+            jack::MidiOut,
+            &mut error_callback,
+        );
 
         let playing = Arc::new(AtomicBool::new(true));
 
         let input_process_handler = LocalProcessHandler::new(
             vec![],
             ports,
+            midi_in_ports,
+            midi_out_ports,
             SampleRate(client.sample_rate() as u32),
             Some(Box::new(data_callback)),
             None,
+            None,
             playing.clone(),
             client.buffer_size() as usize,
+            planar,
         );
 
-        let notification_handler = JackNotificationHandler::new(error_callback);
+        let port_event_callback = Arc::new(Mutex::new(None));
+        let notification_handler = JackNotificationHandler::new(
+            &client,
+            error_callback,
+            port_event_callback.clone(),
+            input_process_handler.buffer_resize.clone(),
+            true,
+            false,
+        );
 
         let async_client = client
             .activate_async(notification_handler, input_process_handler)
@@ -77,60 +134,69 @@ impl Stream {
         Stream {
             playing,
             async_client,
-            input_port_names: port_names,
+            input_port_names,
             output_port_names: vec![],
+            midi_input_port_names,
+            midi_output_port_names,
+            channel_map: None,
+            port_event_callback,
         }
     }
 
+    /// If `planar` is set, the data callback writes directly into the buffers of the ports rather
+    /// than into an interleaved buffer.
     pub fn new_output<D, E>(
         client: jack::Client,
         channels: ChannelCount,
+        port_names: &PortNames,
+        planar: bool,
         data_callback: D,
         mut error_callback: E,
     ) -> Stream
     where
-        D: FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static,
+        D: FnMut(&mut Data, &OutputCallbackInfo, &mut Midi) + Send + 'static,
         E: FnMut(StreamError) + Send + 'static,
     {
-        let mut ports = vec![];
-        let mut port_names: Vec<String> = vec![];
-        // Create ports
-        for i in 0..channels {
-            let port_try = client.register_port(&format!("out_{}", i), jack::AudioOut::default());
-            match port_try {
-                Ok(port) => {
-                    // Get the port name in order to later connect it automatically
-                    if let Ok(port_name) = port.name() {
-                        port_names.push(port_name);
-                    }
-                    // Store the port into a Vec to move to the ProcessHandler
-                    ports.push(port);
-                }
-                Err(e) => {
-                    // If port creation failed, send the error back via the error_callback
-                    error_callback(
-                        BackendSpecificError {
-                            description: e.to_string(),
-                        }
-                        .into(),
-                    );
-                }
-            }
-        }
+        let (ports, output_port_names) =
+            register_output_ports(&client, channels, &port_names.output, &mut error_callback);
+        let (midi_in_ports, midi_input_port_names) = register_ports(
+            &client,
+            &port_names.midi_input,
+            jack::MidiIn,
+            &mut error_callback,
+        );
+        let (midi_out_ports, midi_output_port_names) = register_ports(
+            &client,
+            &port_names.midi_output,
+            jack::MidiOut,
+            &mut error_callback,
+        );
 
         let playing = Arc::new(AtomicBool::new(true));
 
         let output_process_handler = LocalProcessHandler::new(
             ports,
             vec![],
+            midi_in_ports,
+            midi_out_ports,
             SampleRate(client.sample_rate() as u32),
             None,
             Some(Box::new(data_callback)),
+            None,
             playing.clone(),
             client.buffer_size() as usize,
+            planar,
         );
 
-        let notification_handler = JackNotificationHandler::new(error_callback);
+        let port_event_callback = Arc::new(Mutex::new(None));
+        let notification_handler = JackNotificationHandler::new(
+            &client,
+            error_callback,
+            port_event_callback.clone(),
+            output_process_handler.buffer_resize.clone(),
+            false,
+            true,
+        );
 
         let async_client = client
             .activate_async(notification_handler, output_process_handler)
@@ -140,7 +206,90 @@ impl Stream {
             playing,
             async_client,
             input_port_names: vec![],
-            output_port_names: port_names,
+            output_port_names,
+            midi_input_port_names,
+            midi_output_port_names,
+            channel_map: None,
+            port_event_callback,
+        }
+    }
+
+    pub fn new_duplex<D, E>(
+        client: jack::Client,
+        input_channels: ChannelCount,
+        output_channels: ChannelCount,
+        port_names: &PortNames,
+        data_callback: D,
+        mut error_callback: E,
+    ) -> Stream
+    where
+        D: FnMut(&Data, &mut Data, &DuplexCallbackInfo, &mut Midi) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        let (in_ports, input_port_names) = register_input_ports(
+            &client,
+            input_channels,
+            &port_names.input,
+            &mut error_callback,
+        );
+        let (out_ports, output_port_names) = register_output_ports(
+            &client,
+            output_channels,
+            &port_names.output,
+            &mut error_callback,
+        );
+        let (midi_in_ports, midi_input_port_names) = register_ports(
+            &client,
+            &port_names.midi_input,
+            jack::MidiIn,
+            &mut error_callback,
+        );
+        let (midi_out_ports, midi_output_port_names) = register_ports(
+            &client,
+            &port_names.midi_output,
+            jack::MidiOut,
+            &mut error_callback,
+        );
+
+        let playing = Arc::new(AtomicBool::new(true));
+
+        let duplex_process_handler = LocalProcessHandler::new(
+            out_ports,
+            in_ports,
+            midi_in_ports,
+            midi_out_ports,
+            SampleRate(client.sample_rate() as u32),
+            None,
+            None,
+            Some(Box::new(data_callback)),
+            playing.clone(),
+            client.buffer_size() as usize,
+            false,
+        );
+
+        let port_event_callback = Arc::new(Mutex::new(None));
+        let notification_handler = JackNotificationHandler::new(
+            &client,
+            error_callback,
+            port_event_callback.clone(),
+            duplex_process_handler.buffer_resize.clone(),
+            true,
+            true,
+        );
+
+        let async_client = client
+            .activate_async(notification_handler, duplex_process_handler)
+            .unwrap();
+
+        Stream {
+            playing,
+            async_client,
+            input_port_names,
+            output_port_names,
+            midi_input_port_names,
+            midi_output_port_names,
+            channel_map: None,
+            port_event_callback,
         }
     }
 
@@ -195,6 +344,287 @@ impl Stream {
             }
         }
     }
+
+    /// The full names of the input ports of the stream, in channel order.
+    pub fn input_port_names(&self) -> &[String] {
+        &self.input_port_names
+    }
+
+    /// The full names of the output ports of the stream, in channel order.
+    pub fn output_port_names(&self) -> &[String] {
+        &self.output_port_names
+    }
+
+    /// The full names of the MIDI input ports of the stream.
+    pub fn midi_input_port_names(&self) -> &[String] {
+        &self.midi_input_port_names
+    }
+
+    /// The full names of the MIDI output ports of the stream.
+    pub fn midi_output_port_names(&self) -> &[String] {
+        &self.midi_output_port_names
+    }
+
+    // Set the positions of the audio ports, as requested by or derived for the stream config.
+    pub(super) fn set_channel_map(&mut self, channel_map: Option<Vec<ChannelPosition>>) {
+        self.channel_map = channel_map;
+    }
+
+    /// The full names of the audio ports of all JACK clients, including this one.
+    ///
+    /// The ports may be restricted to those whose name matches the regular expression
+    /// `name_pattern` and to those of the given direction.
+    pub fn ports(
+        &self,
+        name_pattern: Option<&str>,
+        direction: Option<PortDirection>,
+    ) -> Vec<String> {
+        self.ports_of_type(name_pattern, jack::AudioIn.jack_port_type(), direction)
+    }
+
+    /// The full names of the MIDI ports of all JACK clients, including this one.
+    ///
+    /// The ports may be restricted to those whose name matches the regular expression
+    /// `name_pattern` and to those of the given direction.
+    pub fn midi_ports(
+        &self,
+        name_pattern: Option<&str>,
+        direction: Option<PortDirection>,
+    ) -> Vec<String> {
+        self.ports_of_type(name_pattern, jack::MidiIn.jack_port_type(), direction)
+    }
+
+    fn ports_of_type(
+        &self,
+        name_pattern: Option<&str>,
+        port_type: &str,
+        direction: Option<PortDirection>,
+    ) -> Vec<String> {
+        let flags = match direction {
+            None => jack::PortFlags::empty(),
+            Some(PortDirection::Input) => jack::PortFlags::IS_INPUT,
+            Some(PortDirection::Output) => jack::PortFlags::IS_OUTPUT,
+        };
+        self.async_client
+            .as_client()
+            .ports(name_pattern, Some(port_type), flags)
+    }
+
+    /// Connect the output port with the full name `source_port` to the input port with the full
+    /// name `destination_port`, for example a MIDI port of the stream to that of another client.
+    pub fn connect_ports(
+        &self,
+        source_port: &str,
+        destination_port: &str,
+    ) -> Result<(), BackendSpecificError> {
+        self.async_client
+            .as_client()
+            .connect_ports_by_name(source_port, destination_port)
+            .map_err(jack_error)
+    }
+
+    /// Disconnect the output port with the full name `source_port` from the input port with the
+    /// full name `destination_port`.
+    pub fn disconnect_ports(
+        &self,
+        source_port: &str,
+        destination_port: &str,
+    ) -> Result<(), BackendSpecificError> {
+        self.async_client
+            .as_client()
+            .disconnect_ports_by_name(source_port, destination_port)
+            .map_err(jack_error)
+    }
+
+    /// Connect the output port with the full name `source_port` to the input port of `channel`.
+    pub fn connect_input(
+        &self,
+        channel: usize,
+        source_port: &str,
+    ) -> Result<(), BackendSpecificError> {
+        let port_name = port_name(&self.input_port_names, channel)?;
+        self.async_client
+            .as_client()
+            .connect_ports_by_name(source_port, port_name)
+            .map_err(jack_error)
+    }
+
+    /// Connect the output port of `channel` to the input port with the full name
+    /// `destination_port`.
+    pub fn connect_output(
+        &self,
+        channel: usize,
+        destination_port: &str,
+    ) -> Result<(), BackendSpecificError> {
+        let port_name = port_name(&self.output_port_names, channel)?;
+        self.async_client
+            .as_client()
+            .connect_ports_by_name(port_name, destination_port)
+            .map_err(jack_error)
+    }
+
+    /// Disconnect the output port with the full name `source_port` from the input port of
+    /// `channel`.
+    pub fn disconnect_input(
+        &self,
+        channel: usize,
+        source_port: &str,
+    ) -> Result<(), BackendSpecificError> {
+        let port_name = port_name(&self.input_port_names, channel)?;
+        self.async_client
+            .as_client()
+            .disconnect_ports_by_name(source_port, port_name)
+            .map_err(jack_error)
+    }
+
+    /// Disconnect the output port of `channel` from the input port with the full name
+    /// `destination_port`.
+    pub fn disconnect_output(
+        &self,
+        channel: usize,
+        destination_port: &str,
+    ) -> Result<(), BackendSpecificError> {
+        let port_name = port_name(&self.output_port_names, channel)?;
+        self.async_client
+            .as_client()
+            .disconnect_ports_by_name(port_name, destination_port)
+            .map_err(jack_error)
+    }
+
+    /// The current state and position of the JACK transport.
+    pub fn transport(&self) -> Result<TransportInfo, BackendSpecificError> {
+        transport::query(self.async_client.as_client()).ok_or_else(|| BackendSpecificError {
+            description: "failed to query the JACK transport".to_string(),
+        })
+    }
+
+    /// Start the JACK transport rolling, no sooner than the next process cycle.
+    pub fn start_transport(&self) -> Result<(), BackendSpecificError> {
+        self.async_client
+            .as_client()
+            .transport()
+            .start()
+            .map_err(jack_error)
+    }
+
+    /// Stop the JACK transport, no sooner than the next process cycle.
+    pub fn stop_transport(&self) -> Result<(), BackendSpecificError> {
+        self.async_client
+            .as_client()
+            .transport()
+            .stop()
+            .map_err(jack_error)
+    }
+
+    /// Relocate the JACK transport to the given frame, no sooner than the next process cycle.
+    pub fn locate_transport(&self, frame: FrameCount) -> Result<(), BackendSpecificError> {
+        self.async_client
+            .as_client()
+            .transport()
+            .locate(frame)
+            .map_err(jack_error)
+    }
+
+    /// Set the callback invoked whenever a port is registered or unregistered by any JACK client,
+    /// replacing the previous one.
+    ///
+    /// The callback is invoked from the JACK notification thread.
+    pub fn set_port_event_callback<F>(&self, callback: F)
+    where
+        F: FnMut(PortEvent) + Send + 'static,
+    {
+        *self.port_event_callback.lock().unwrap() = Some(Box::new(callback));
+    }
+}
+
+fn port_name(port_names: &[String], channel: usize) -> Result<&str, BackendSpecificError> {
+    match port_names.get(channel) {
+        Some(port_name) => Ok(port_name),
+        None => Err(BackendSpecificError {
+            description: format!("the stream has no port for channel {}", channel),
+        }),
+    }
+}
+
+fn jack_error(err: jack::Error) -> BackendSpecificError {
+    BackendSpecificError {
+        description: err.to_string(),
+    }
+}
+
+fn register_input_ports<E>(
+    client: &jack::Client,
+    channels: ChannelCount,
+    names: &[String],
+    error_callback: &mut E,
+) -> (Vec<jack::Port<jack::AudioIn>>, Vec<String>)
+where
+    E: FnMut(StreamError),
+{
+    let names = channel_port_names(names, channels, "in");
+    register_ports(client, &names, jack::AudioIn, error_callback)
+}
+
+fn register_output_ports<E>(
+    client: &jack::Client,
+    channels: ChannelCount,
+    names: &[String],
+    error_callback: &mut E,
+) -> (Vec<jack::Port<jack::AudioOut>>, Vec<String>)
+where
+    E: FnMut(StreamError),
+{
+    let names = channel_port_names(names, channels, "out");
+    register_ports(client, &names, jack::AudioOut, error_callback)
+}
+
+// The names of the ports of `channels` channels, where channels without a custom name are named
+// after their index.
+fn channel_port_names(names: &[String], channels: ChannelCount, prefix: &str) -> Vec<String> {
+    (0..channels as usize)
+        .map(|i| match names.get(i) {
+            Some(name) => name.clone(),
+            None => format!("{}_{}", prefix, i),
+        })
+        .collect()
+}
+
+fn register_ports<PS, E>(
+    client: &jack::Client,
+    names: &[String],
+    port_spec: PS,
+    error_callback: &mut E,
+) -> (Vec<jack::Port<PS>>, Vec<String>)
+where
+    PS: PortSpec + Clone,
+    E: FnMut(StreamError),
+{
+    let mut ports = vec![];
+    let mut port_names: Vec<String> = vec![];
+    // Create ports
+    for name in names {
+        let port_try = client.register_port(name, port_spec.clone());
+        match port_try {
+            Ok(port) => {
+                // Get the port name in order to later connect it automatically
+                if let Ok(port_name) = port.name() {
+                    port_names.push(port_name);
+                }
+                // Store the port into a Vec to move to the ProcessHandler
+                ports.push(port);
+            }
+            Err(e) => {
+                // If port creation failed, send the error back via the error_callback
+                error_callback(
+                    BackendSpecificError {
+                        description: e.to_string(),
+                    }
+                    .into(),
+                );
+            }
+        }
+    }
+    (ports, port_names)
 }
 
 impl StreamTrait for Stream {
@@ -207,68 +637,228 @@ impl StreamTrait for Stream {
         self.playing.store(false, Ordering::SeqCst);
         Ok(())
     }
+
+    fn config(&self) -> Option<StreamConfig> {
+        let client = self.async_client.as_client();
+        let channels = if self.output_port_names.is_empty() {
+            self.input_port_names.len()
+        } else {
+            self.output_port_names.len()
+        };
+        Some(StreamConfig {
+            channels: channels as ChannelCount,
+            sample_rate: SampleRate(client.sample_rate() as u32),
+            buffer_size: BufferSize::Fixed(client.buffer_size()),
+            channel_map: self.channel_map.clone(),
+        })
+    }
+
+    fn buffer_size(&self) -> Option<FrameCount> {
+        Some(self.async_client.as_client().buffer_size())
+    }
+
+    fn period_size(&self) -> Option<FrameCount> {
+        Some(self.async_client.as_client().buffer_size())
+    }
+
+    /// The latency is taken from the latency ranges of the ports of the stream, which include the
+    /// latency of the ports they are connected to.
+    fn latency(&self) -> Option<Duration> {
+        let client = self.async_client.as_client();
+        let max_latency = |port_names: &[String], latency_type| {
+            port_names
+                .iter()
+                .filter_map(|name| client.port_by_name(name))
+                .map(|port| port.get_latency_range(latency_type).1)
+                .max()
+                .unwrap_or(0)
+        };
+        let frames = max_latency(&self.output_port_names, jack::LatencyType::Playback)
+            + max_latency(&self.input_port_names, jack::LatencyType::Capture);
+        let nanos = frames as u64 * 1_000_000_000 / client.sample_rate() as u64;
+        Some(Duration::from_nanos(nanos))
+    }
 }
 
 struct LocalProcessHandler {
     /// No new ports are allowed to be created after the creation of the LocalProcessHandler as that would invalidate the buffer sizes
     out_ports: Vec<jack::Port<jack::AudioOut>>,
     in_ports: Vec<jack::Port<jack::AudioIn>>,
+    midi_in_ports: Vec<jack::Port<jack::MidiIn>>,
+    midi_out_ports: Vec<jack::Port<jack::MidiOut>>,
+    /// The events written to each MIDI output port during the current cycle.
+    midi_output_buffers: Vec<MidiOutputBuffer>,
 
     sample_rate: SampleRate,
-    input_data_callback: Option<Box<dyn FnMut(&Data, &InputCallbackInfo) + Send + 'static>>,
-    output_data_callback: Option<Box<dyn FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static>>,
-
-    temp_input_buffer: Vec<f32>,
+    input_data_callback: Option<InputDataCallback>,
+    output_data_callback: Option<OutputDataCallback>,
+    duplex_data_callback: Option<DuplexDataCallback>,
 
     // JACK audio samples are 32 bit float (unless you do some custom dark magic)
-    temp_output_buffer: Vec<f32>,
-    /// The number of frames in the temp_output_buffer
-    temp_output_buffer_size_in_frames: usize,
+    temp: TempBuffers,
     temp_output_buffer_frames_index: usize,
+    /// The buffers of the ports of the current cycle, if the data is passed to the input or output
+    /// callback without interleaving.
+    planes: Option<Planes>,
+    /// Delivers the temporary buffers reallocated for a new buffer size.
+    buffer_resize: Arc<BufferResize>,
     playing: Arc<AtomicBool>,
     creation_timestamp: std::time::Instant,
 }
 
 impl LocalProcessHandler {
+    #[allow(clippy::too_many_arguments)]
     fn new(
         out_ports: Vec<jack::Port<jack::AudioOut>>,
         in_ports: Vec<jack::Port<jack::AudioIn>>,
+        midi_in_ports: Vec<jack::Port<jack::MidiIn>>,
+        midi_out_ports: Vec<jack::Port<jack::MidiOut>>,
         sample_rate: SampleRate,
-        input_data_callback: Option<Box<dyn FnMut(&Data, &InputCallbackInfo) + Send + 'static>>,
-        output_data_callback: Option<
-            Box<dyn FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static>,
-        >,
+        input_data_callback: Option<InputDataCallback>,
+        output_data_callback: Option<OutputDataCallback>,
+        duplex_data_callback: Option<DuplexDataCallback>,
         playing: Arc<AtomicBool>,
         buffer_size: usize,
+        planar: bool,
     ) -> Self {
         // buffer_size is the maximum number of samples per port JACK can request/provide in a single call
         // If it can be fewer than that per call the temp_input_buffer needs to be the smallest multiple of that.
-        let temp_input_buffer = vec![0.0; in_ports.len() * buffer_size];
-
-        let temp_output_buffer = vec![0.0; out_ports.len() * buffer_size];
+        let (in_channels, out_channels) = (in_ports.len(), out_ports.len());
+        let temp = TempBuffers::new(in_channels, out_channels, buffer_size);
 
         // let out_port_buffers = Vec::with_capacity(out_ports.len());
         // let in_port_buffers = Vec::with_capacity(in_ports.len());
 
+        let midi_output_buffers = midi_out_ports
+            .iter()
+            .map(|_| MidiOutputBuffer::new())
+            .collect();
+
+        let planes = if planar {
+            Some(Planes(Vec::with_capacity(in_channels + out_channels)))
+        } else {
+            None
+        };
+
         LocalProcessHandler {
             out_ports,
             in_ports,
+            midi_in_ports,
+            midi_out_ports,
+            midi_output_buffers,
             // out_port_buffers,
             // in_port_buffers,
             sample_rate,
             input_data_callback,
             output_data_callback,
-            temp_input_buffer,
-            temp_output_buffer,
-            temp_output_buffer_size_in_frames: buffer_size,
+            duplex_data_callback,
+            temp,
             temp_output_buffer_frames_index: 0,
+            planes,
+            buffer_resize: Arc::new(BufferResize::new(in_channels, out_channels)),
             playing,
             creation_timestamp: std::time::Instant::now(),
         }
     }
+
+    // Swap in the temporary buffers reallocated for a new buffer size, if any.
+    fn swap_resized_buffers(&mut self) {
+        if self.buffer_resize.swap(&mut self.temp) {
+            // Request new output data at the start of the cycle
+            self.temp_output_buffer_frames_index = self.temp.frames;
+        }
+    }
+
+    // Write the MIDI events of the cycle to the MIDI output ports.
+    fn flush_midi_outputs(&mut self, process_scope: &jack::ProcessScope) {
+        let ports = self.midi_out_ports.iter_mut();
+        for (port, buffer) in ports.zip(self.midi_output_buffers.iter_mut()) {
+            buffer.flush(port, process_scope);
+        }
+    }
 }
 
-fn temp_output_buffer_to_data(temp_output_buffer: &mut Vec<f32>) -> Data {
+/// The pointers to the buffers of the ports, from which planar `Data` is created.
+struct Planes(Vec<*mut ()>);
+
+// The pointers are only valid, and only used, within the process cycle that they were taken in.
+unsafe impl Send for Planes {}
+
+/// The temporary buffers of a `LocalProcessHandler`, holding `frames` frames.
+struct TempBuffers {
+    input: Vec<f32>,
+    output: Vec<f32>,
+    frames: usize,
+}
+
+impl TempBuffers {
+    fn new(input_channels: usize, output_channels: usize, frames: usize) -> Self {
+        TempBuffers {
+            input: vec![0.0; input_channels * frames],
+            output: vec![0.0; output_channels * frames],
+            frames,
+        }
+    }
+
+    // Whether a process cycle of `frames` frames fits into the buffers.
+    fn fits(&self, frames: usize) -> bool {
+        frames <= self.frames
+    }
+}
+
+/// Hands the temporary buffers reallocated by the notification handler for a new buffer size over
+/// to the process handler, which must not allocate.
+struct BufferResize {
+    input_channels: usize,
+    output_channels: usize,
+    /// Whether `buffers` holds buffers that have not been swapped in yet.
+    pending: AtomicBool,
+    buffers: Mutex<Option<TempBuffers>>,
+}
+
+impl BufferResize {
+    fn new(input_channels: usize, output_channels: usize) -> Self {
+        BufferResize {
+            input_channels,
+            output_channels,
+            pending: AtomicBool::new(false),
+            buffers: Mutex::new(None),
+        }
+    }
+
+    // Allocate the buffers for `frames` frames, to be swapped in by the next process cycle. This
+    // frees the buffers that were swapped out by the previous resize.
+    fn resize(&self, frames: usize) {
+        let buffers = TempBuffers::new(self.input_channels, self.output_channels, frames);
+        let mut guard = self.buffers.lock().unwrap();
+        *guard = Some(buffers);
+        self.pending.store(true, Ordering::Release);
+    }
+
+    // Swap the buffers allocated by `resize` with `temp`, if any, returning whether they were
+    // swapped. The old buffers are left to the notification handler to free, as the process
+    // thread must not deallocate.
+    fn swap(&self, temp: &mut TempBuffers) -> bool {
+        if !self.pending.load(Ordering::Acquire) {
+            return false;
+        }
+        // The notification handler only holds the lock briefly, retry in the next cycle otherwise
+        let mut buffers = match self.buffers.try_lock() {
+            Ok(buffers) => buffers,
+            Err(_) => return false,
+        };
+        self.pending.store(false, Ordering::Release);
+        match buffers.as_mut() {
+            Some(buffers) => {
+                mem::swap(temp, buffers);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+fn temp_output_buffer_to_data(temp_output_buffer: &mut [f32]) -> Data {
     let data = temp_output_buffer.as_mut_ptr() as *mut ();
     let len = temp_output_buffer.len();
     let data = unsafe { Data::from_parts(data, len, JACK_SAMPLE_FORMAT) };
@@ -284,12 +874,34 @@ fn temp_input_buffer_to_data(temp_input_buffer: &mut Vec<f32>, total_buffer_size
 }
 
 impl jack::ProcessHandler for LocalProcessHandler {
-    fn process(&mut self, _: &jack::Client, process_scope: &jack::ProcessScope) -> jack::Control {
+    fn process(
+        &mut self,
+        client: &jack::Client,
+        process_scope: &jack::ProcessScope,
+    ) -> jack::Control {
         if !self.playing.load(Ordering::SeqCst) {
+            // The MIDI output ports still have to be cleared, or their events would be repeated
+            self.flush_midi_outputs(process_scope);
             return jack::Control::Continue;
         }
 
+        // The sample rate and buffer size may have been changed by the JACK server
+        self.sample_rate = SampleRate(client.sample_rate() as u32);
+        self.swap_resized_buffers();
+
+        let transport = transport::query(client);
+
         let current_frame_count = process_scope.n_frames() as usize;
+        if !self.temp.fits(current_frame_count) {
+            // The buffers for the new buffer size have not been swapped in yet, output silence
+            for port in &mut self.out_ports {
+                for sample in port.as_mut_slice(process_scope).iter_mut() {
+                    *sample = 0.0;
+                }
+            }
+            self.flush_midi_outputs(process_scope);
+            return jack::Control::Continue;
+        }
 
         // Get timestamp data
         let cycle_times = process_scope.cycle_times();
@@ -316,19 +928,37 @@ impl jack::ProcessHandler for LocalProcessHandler {
 
             let num_in_channels = self.in_ports.len();
 
-            // Read the data from the input ports into the temporary buffer
-            // Go through every channel and store its data in the temporary input buffer
-            for ch_ix in 0..num_in_channels {
-                let input_channel = &self.in_ports[ch_ix].as_slice(process_scope);
-                for i in 0..current_frame_count {
-                    self.temp_input_buffer[ch_ix + i * num_in_channels] = input_channel[i];
+            let data = if let Some(planes) = &mut self.planes {
+                // Pass the buffers of the input ports to the callback as they are
+                planes.0.clear();
+                for port in &self.in_ports {
+                    planes
+                        .0
+                        .push(port.as_slice(process_scope).as_ptr() as *mut ());
                 }
-            }
-            // Create a slice of exactly current_frame_count frames
-            let data = temp_input_buffer_to_data(
-                &mut self.temp_input_buffer,
-                current_frame_count * num_in_channels,
-            );
+                unsafe {
+                    Data::from_planar_parts(
+                        planes.0.as_ptr(),
+                        num_in_channels,
+                        current_frame_count,
+                        JACK_SAMPLE_FORMAT,
+                    )
+                }
+            } else {
+                // Read the data from the input ports into the temporary buffer
+                // Go through every channel and store its data in the temporary input buffer
+                for ch_ix in 0..num_in_channels {
+                    let input_channel = &self.in_ports[ch_ix].as_slice(process_scope);
+                    for i in 0..current_frame_count {
+                        self.temp.input[ch_ix + i * num_in_channels] = input_channel[i];
+                    }
+                }
+                // Create a slice of exactly current_frame_count frames
+                temp_input_buffer_to_data(
+                    &mut self.temp.input,
+                    current_frame_count * num_in_channels,
+                )
+            };
             // Create timestamp
             let frames_since_cycle_start = process_scope.frames_since_cycle_start() as usize;
             let duration_since_cycle_start =
@@ -338,55 +968,142 @@ impl jack::ProcessHandler for LocalProcessHandler {
                 .expect("`playback` occurs beyond representation supported by `StreamInstant`");
             let capture = start_callback_instant;
             let timestamp = crate::InputStreamTimestamp { callback, capture };
-            let info = crate::InputCallbackInfo { timestamp };
-            input_callback(&data, &info);
+            let mut info = crate::InputCallbackInfo::new(timestamp);
+            info.jack_transport = transport;
+            let mut midi = Midi::new(
+                process_scope,
+                &self.midi_in_ports,
+                &mut self.midi_output_buffers,
+            );
+            input_callback(&data, &info, &mut midi);
         }
 
         if let Some(output_callback) = &mut self.output_data_callback {
             let num_out_channels = self.out_ports.len();
 
-            // Run the output callback on the temporary output buffer until we have filled the output ports
-            // JACK ports each provide a mutable slice to be filled with samples whereas CPAL uses interleaved
-            // channels. The formats therefore have to be bridged.
-            for i in 0..current_frame_count {
-                // Check if we have gotten all of the frames from the temp_output_buffer
-                if self.temp_output_buffer_frames_index == self.temp_output_buffer_size_in_frames {
-                    // Get new samples if the temporary buffer is depleted. This can theoretically happen
-                    // several times per cycle or once every few cycles if the buffer size changes, but in practice
-                    // it should generally happen once per cycle if the buffer size is not changed.
-                    let mut data = temp_output_buffer_to_data(&mut self.temp_output_buffer);
-                    // Create timestamp
-                    let frames_since_cycle_start =
-                        process_scope.frames_since_cycle_start() as usize;
-                    let duration_since_cycle_start =
-                        frames_to_duration(frames_since_cycle_start, self.sample_rate);
-                    let callback = start_callback_instant
-                        .add(duration_since_cycle_start)
-                        .expect(
-                            "`playback` occurs beyond representation supported by `StreamInstant`",
+            let sample_rate = self.sample_rate;
+            let output_timestamp = || {
+                let frames_since_cycle_start = process_scope.frames_since_cycle_start() as usize;
+                let duration_since_cycle_start =
+                    frames_to_duration(frames_since_cycle_start, sample_rate);
+                let callback = start_callback_instant
+                    .add(duration_since_cycle_start)
+                    .expect("`playback` occurs beyond representation supported by `StreamInstant`");
+                let buffer_duration = frames_to_duration(current_frame_count, sample_rate);
+                let playback = start_cycle_instant
+                    .add(buffer_duration)
+                    .expect("`playback` occurs beyond representation supported by `StreamInstant`");
+                crate::OutputStreamTimestamp { callback, playback }
+            };
+
+            if let Some(planes) = &mut self.planes {
+                // Let the callback write directly into the buffers of the output ports
+                planes.0.clear();
+                for port in &mut self.out_ports {
+                    planes
+                        .0
+                        .push(port.as_mut_slice(process_scope).as_mut_ptr() as *mut ());
+                }
+                let mut data = unsafe {
+                    Data::from_planar_parts(
+                        planes.0.as_ptr(),
+                        num_out_channels,
+                        current_frame_count,
+                        JACK_SAMPLE_FORMAT,
+                    )
+                };
+                let mut info = crate::OutputCallbackInfo::new(output_timestamp());
+                info.jack_transport = transport;
+                let mut midi = Midi::new(
+                    process_scope,
+                    &self.midi_in_ports,
+                    &mut self.midi_output_buffers,
+                );
+                output_callback(&mut data, &info, &mut midi);
+            } else {
+                // Run the output callback on the temporary output buffer until we have filled the output ports
+                // JACK ports each provide a mutable slice to be filled with samples whereas CPAL uses interleaved
+                // channels. The formats therefore have to be bridged.
+                for i in 0..current_frame_count {
+                    // Check if we have gotten all of the frames from the temp_output_buffer
+                    if self.temp_output_buffer_frames_index == self.temp.frames {
+                        // Get new samples if the temporary buffer is depleted. This can theoretically happen
+                        // several times per cycle or once every few cycles if the buffer size changes, but in practice
+                        // it should generally happen once per cycle if the buffer size is not changed.
+                        let mut data = temp_output_buffer_to_data(&mut self.temp.output);
+                        let mut info = crate::OutputCallbackInfo::new(output_timestamp());
+                        info.jack_transport = transport;
+                        let mut midi = Midi::new(
+                            process_scope,
+                            &self.midi_in_ports,
+                            &mut self.midi_output_buffers,
                         );
-                    let buffer_duration = frames_to_duration(current_frame_count, self.sample_rate);
-                    let playback = start_cycle_instant.add(buffer_duration).expect(
-                        "`playback` occurs beyond representation supported by `StreamInstant`",
-                    );
-                    let timestamp = crate::OutputStreamTimestamp { callback, playback };
-                    let info = crate::OutputCallbackInfo { timestamp };
-                    output_callback(&mut data, &info);
-                    self.temp_output_buffer_frames_index = 0;
+                        output_callback(&mut data, &info, &mut midi);
+                        self.temp_output_buffer_frames_index = 0;
+                    }
+                    // Write the interleaved samples e.g. [l0, r0, l1, r1, ..] to each output buffer
+                    for ch_ix in 0..num_out_channels {
+                        // TODO: It should be marginally faster to store pointers to these slices, but I don't know how
+                        // to avoid lifetime issues and allocation
+                        let output_channel = &mut self.out_ports[ch_ix].as_mut_slice(process_scope);
+                        output_channel[i] = self.temp.output
+                            [ch_ix + self.temp_output_buffer_frames_index * num_out_channels];
+                    }
+                    // Count the number of frames that have been read from the temp buffer
+                    self.temp_output_buffer_frames_index += 1;
+                }
+            }
+        }
+
+        if let Some(duplex_callback) = &mut self.duplex_data_callback {
+            let num_in_channels = self.in_ports.len();
+            let num_out_channels = self.out_ports.len();
+
+            // Interleave the input ports into the temporary input buffer
+            for ch_ix in 0..num_in_channels {
+                let input_channel = &self.in_ports[ch_ix].as_slice(process_scope);
+                for i in 0..current_frame_count {
+                    self.temp.input[ch_ix + i * num_in_channels] = input_channel[i];
                 }
-                // Write the interleaved samples e.g. [l0, r0, l1, r1, ..] to each output buffer
-                for ch_ix in 0..num_out_channels {
-                    // TODO: It should be marginally faster to store pointers to these slices, but I don't know how
-                    // to avoid lifetime issues and allocation
-                    let output_channel = &mut self.out_ports[ch_ix].as_mut_slice(process_scope);
-                    output_channel[i] = self.temp_output_buffer
-                        [ch_ix + self.temp_output_buffer_frames_index * num_out_channels];
+            }
+            let input_data = temp_input_buffer_to_data(
+                &mut self.temp.input,
+                current_frame_count * num_in_channels,
+            );
+            // Both buffers cover exactly the frames of this cycle
+            let mut output_data = temp_output_buffer_to_data(
+                &mut self.temp.output[0..current_frame_count * num_out_channels],
+            );
+            // Create timestamp
+            let buffer_duration = frames_to_duration(current_frame_count, self.sample_rate);
+            let playback = start_cycle_instant
+                .add(buffer_duration)
+                .expect("`playback` occurs beyond representation supported by `StreamInstant`");
+            let timestamp = crate::DuplexStreamTimestamp {
+                callback: start_callback_instant,
+                capture: start_cycle_instant,
+                playback,
+            };
+            let mut info = crate::DuplexCallbackInfo::new(timestamp);
+            info.jack_transport = transport;
+            let mut midi = Midi::new(
+                process_scope,
+                &self.midi_in_ports,
+                &mut self.midi_output_buffers,
+            );
+            duplex_callback(&input_data, &mut output_data, &info, &mut midi);
+
+            // De-interleave the temporary output buffer into the output ports
+            for ch_ix in 0..num_out_channels {
+                let output_channel = &mut self.out_ports[ch_ix].as_mut_slice(process_scope);
+                for i in 0..current_frame_count {
+                    output_channel[i] = self.temp.output[ch_ix + i * num_out_channels];
                 }
-                // Count the number of frames that have been read from the temp buffer
-                self.temp_output_buffer_frames_index += 1;
             }
         }
 
+        self.flush_midi_outputs(process_scope);
+
         // Continue as normal
         jack::Control::Continue
     }
@@ -407,31 +1124,102 @@ fn frames_to_duration(frames: usize, rate: crate::SampleRate) -> std::time::Dura
     std::time::Duration::new(secs, nanos)
 }
 
+/// The full names of the ports registered with the JACK server.
+///
+/// A port may no longer be found by its id by the time the notification of its unregistration
+/// arrives, and its id may even have been reused by another port, so the name reported for it is
+/// the one missing from the ports currently registered.
+#[derive(Debug, Default)]
+struct RegisteredPorts(BTreeSet<String>);
+
+impl RegisteredPorts {
+    /// Records the ports currently registered with the server.
+    fn current(client: &jack::Client) -> Self {
+        RegisteredPorts(all_ports(client).into_iter().collect())
+    }
+
+    /// Updates the record for a port registration notification, returning the event to report.
+    ///
+    /// `name` is the name of the port found by its id and `current` the names of the ports
+    /// currently registered. Ports registered while notifications were not delivered, such as
+    /// before the client was activated, are recorded without an event. Returns `None` if the name
+    /// of the port is unknown.
+    fn update(
+        &mut self,
+        is_registered: bool,
+        name: Option<String>,
+        current: Vec<String>,
+    ) -> Option<PortEvent> {
+        let current: BTreeSet<String> = current.into_iter().collect();
+        let event = if is_registered {
+            name.map(|name| PortEvent::Registered { name })
+        } else {
+            let name = match name {
+                Some(name) if self.0.contains(&name) && !current.contains(&name) => Some(name),
+                _ => self.0.difference(&current).next().cloned(),
+            };
+            name.map(|name| {
+                self.0.remove(&name);
+                PortEvent::Unregistered { name }
+            })
+        };
+        self.0.extend(current);
+        event
+    }
+}
+
+// The full names of the ports of all JACK clients.
+fn all_ports(client: &jack::Client) -> Vec<String> {
+    client.ports(None, None, jack::PortFlags::empty())
+}
+
 /// Receives notifications from the JACK server. It is unclear if this may be run concurrent with itself under JACK2 specs
 /// so it needs to be Sync.
 struct JackNotificationHandler {
     error_callback_ptr: Arc<Mutex<Box<dyn FnMut(StreamError) + Send + 'static>>>,
+    port_event_callback: Arc<Mutex<Option<PortEventCallback>>>,
+    registered_ports: RegisteredPorts,
+    buffer_resize: Arc<BufferResize>,
     init_block_size_flag: Arc<AtomicBool>,
     init_sample_rate_flag: Arc<AtomicBool>,
+    // Whether the stream has input and/or output ports, used to tell xruns apart.
+    has_input: bool,
+    has_output: bool,
 }
 
 impl JackNotificationHandler {
-    pub fn new<E>(error_callback: E) -> Self
+    pub fn new<E>(
+        client: &jack::Client,
+        error_callback: E,
+        port_event_callback: Arc<Mutex<Option<PortEventCallback>>>,
+        buffer_resize: Arc<BufferResize>,
+        has_input: bool,
+        has_output: bool,
+    ) -> Self
     where
         E: FnMut(StreamError) + Send + 'static,
     {
         JackNotificationHandler {
             error_callback_ptr: Arc::new(Mutex::new(Box::new(error_callback))),
+            port_event_callback,
+            registered_ports: RegisteredPorts::current(client),
+            buffer_resize,
             init_block_size_flag: Arc::new(AtomicBool::new(false)),
             init_sample_rate_flag: Arc::new(AtomicBool::new(false)),
+            has_input,
+            has_output,
         }
     }
 
     fn send_error(&mut self, description: String) {
+        self.send_stream_error(BackendSpecificError { description }.into());
+    }
+
+    fn send_stream_error(&mut self, error: StreamError) {
         // This thread isn't the audio thread, it's fine to block
         if let Ok(mut mutex_guard) = self.error_callback_ptr.lock() {
             let err = &mut *mutex_guard;
-            err(BackendSpecificError { description }.into());
+            err(error);
         }
     }
 }
@@ -441,41 +1229,219 @@ impl jack::NotificationHandler for JackNotificationHandler {
         self.send_error(format!("JACK was shut down for reason: {}", reason));
     }
 
-    fn sample_rate(&mut self, _: &jack::Client, srate: jack::Frames) -> jack::Control {
+    fn sample_rate(&mut self, client: &jack::Client, srate: jack::Frames) -> jack::Control {
         match self.init_sample_rate_flag.load(Ordering::SeqCst) {
             false => {
                 // One of these notifications is sent every time a client is started.
                 self.init_sample_rate_flag.store(true, Ordering::SeqCst);
-                jack::Control::Continue
             }
             true => {
-                self.send_error(format!("sample rate changed to: {}", srate));
-                // Since CPAL currently has no way of signaling a sample rate change in order to make
-                // all necessary changes that would bring we choose to quit.
-                jack::Control::Quit
+                // The process handler picks up the new rate by itself, so the stream keeps running
+                // and the application gets the chance to adapt.
+                self.send_stream_error(StreamError::ConfigChanged {
+                    sample_rate: SampleRate(srate),
+                    buffer_size: client.buffer_size(),
+                });
             }
         }
+        jack::Control::Continue
     }
 
-    fn buffer_size(&mut self, _: &jack::Client, size: jack::Frames) -> jack::Control {
+    fn buffer_size(&mut self, client: &jack::Client, size: jack::Frames) -> jack::Control {
         match self.init_block_size_flag.load(Ordering::SeqCst) {
             false => {
                 // One of these notifications is sent every time a client is started.
                 self.init_block_size_flag.store(true, Ordering::SeqCst)
             }
             true => {
-                self.send_error(format!("buffer size changed to: {}", size));
+                // Reallocate the temporary processing buffers here rather than in the process
+                // thread.
+                self.buffer_resize.resize(size as usize);
+                self.send_stream_error(StreamError::ConfigChanged {
+                    sample_rate: SampleRate(client.sample_rate() as u32),
+                    buffer_size: size,
+                });
             }
         }
-
-        // The current implementation should work even if the buffer size changes, although
-        // potentially with poorer performance. However, reallocating the temporary processing
-        // buffers would be expensive so we choose to just continue in this case.
         jack::Control::Continue
     }
 
+    fn port_registration(
+        &mut self,
+        client: &jack::Client,
+        port_id: jack::PortId,
+        is_registered: bool,
+    ) {
+        let name = client.port_by_id(port_id).and_then(|port| port.name().ok());
+        let current = all_ports(client);
+        let event = match self.registered_ports.update(is_registered, name, current) {
+            Some(event) => event,
+            None => return,
+        };
+        if let Ok(mut callback) = self.port_event_callback.lock() {
+            if let Some(callback) = callback.as_mut() {
+                callback(event);
+            }
+        }
+    }
+
     fn xrun(&mut self, _: &jack::Client) -> jack::Control {
-        self.send_error(String::from("xrun (buffer over or under run)"));
+        // JACK does not tell which ports were affected or how much data was lost, so report the
+        // xrun for every direction the stream has.
+        if self.has_input {
+            self.send_stream_error(StreamError::BufferOverrun { frames_lost: None });
+        }
+        if self.has_output {
+            self.send_stream_error(StreamError::BufferUnderrun { frames_lost: None });
+        }
         jack::Control::Continue
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::super::{jack, Host};
+    use super::{BufferResize, PortDirection, PortEvent, RegisteredPorts, TempBuffers};
+    use std::sync::atomic::Ordering;
+    use std::sync::mpsc;
+    use std::time::{Duration, Instant};
+    use traits::{DeviceTrait, HostTrait};
+    use SampleFormat;
+
+    #[test]
+    fn test_registered_ports() {
+        let names =
+            |names: &[&str]| -> Vec<String> { names.iter().map(|&name| name.to_owned()).collect() };
+        let event = |is_registered, name: &str| {
+            let name = name.to_owned();
+            Some(match is_registered {
+                true => PortEvent::Registered { name },
+                false => PortEvent::Unregistered { name },
+            })
+        };
+        let mut ports = RegisteredPorts(names(&["system:capture_1"]).into_iter().collect());
+        let current = names(&["system:capture_1", "app:out"]);
+        assert_eq!(
+            ports.update(true, Some("app:out".to_owned()), current),
+            event(true, "app:out")
+        );
+
+        // The port is gone by the time its unregistration is notified.
+        let current = names(&["app:out"]);
+        assert_eq!(
+            ports.update(false, None, current),
+            event(false, "system:capture_1")
+        );
+
+        // The id is reused by another port, which was registered before the stream was notified.
+        let current = names(&["other:port"]);
+        assert_eq!(
+            ports.update(false, Some("other:port".to_owned()), current),
+            event(false, "app:out")
+        );
+        let current = names(&[]);
+        assert_eq!(
+            ports.update(false, None, current),
+            event(false, "other:port")
+        );
+
+        // Several ports are gone by the time the first unregistration is notified.
+        let mut ports = RegisteredPorts(names(&["a:1", "a:2", "b:1"]).into_iter().collect());
+        let current = names(&["b:1"]);
+        assert_eq!(
+            ports.update(false, Some("a:2".to_owned()), current),
+            event(false, "a:2")
+        );
+        let current = names(&["b:1"]);
+        assert_eq!(ports.update(false, None, current), event(false, "a:1"));
+        let current = names(&["b:1"]);
+        assert_eq!(ports.update(false, None, current), None);
+        assert_eq!(ports.update(true, None, names(&["b:1"])), None);
+    }
+
+    // Needs a running JACK server, such as `jackd -d dummy`. Run with
+    // `cargo test --features jack -- --ignored`.
+    #[test]
+    #[ignore]
+    fn test_port_routing() {
+        let device = Host::new().unwrap().default_output_device().unwrap();
+        let config = device.default_output_config().unwrap().config();
+        let stream = device
+            .build_output_stream_raw(&config, SampleFormat::F32, |_, _| {}, |_| {})
+            .unwrap();
+        let (events_tx, events) = mpsc::channel();
+        stream.set_port_event_callback(move |event| {
+            let _ = events_tx.send(event);
+        });
+        let expect = |expected: PortEvent| {
+            let deadline = Instant::now() + Duration::from_secs(5);
+            loop {
+                let timeout = deadline.saturating_duration_since(Instant::now());
+                match events.recv_timeout(timeout) {
+                    Ok(ref event) if *event == expected => break,
+                    Ok(_) => (),
+                    Err(_) => panic!("{:?} was not reported", expected),
+                }
+            }
+        };
+
+        let (peer, _) =
+            jack::Client::new("cpal_routing_peer", jack::ClientOptions::NO_START_SERVER).unwrap();
+        let port = peer.register_port("in", jack::AudioIn).unwrap();
+        let name = "cpal_routing_peer:in".to_owned();
+        expect(PortEvent::Registered { name: name.clone() });
+        assert_eq!(
+            stream.ports(Some("cpal_routing_peer"), Some(PortDirection::Input)),
+            vec![name.clone()]
+        );
+        assert!(stream
+            .ports(Some("cpal_routing_peer"), Some(PortDirection::Output))
+            .is_empty());
+
+        let output_port = stream.output_port_names()[0].clone();
+        stream.connect_output(0, &name).unwrap();
+        assert!(port.is_connected_to(&output_port).unwrap());
+        stream.disconnect_output(0, &name).unwrap();
+        assert!(!port.is_connected_to(&output_port).unwrap());
+        stream.connect_ports(&output_port, &name).unwrap();
+        assert!(port.is_connected_to(&output_port).unwrap());
+
+        peer.unregister_port(port).unwrap();
+        expect(PortEvent::Unregistered { name });
+    }
+
+    #[test]
+    fn test_buffer_resize() {
+        let resize = BufferResize::new(1, 2);
+        let mut temp = TempBuffers::new(1, 2, 256);
+        assert!(!resize.swap(&mut temp));
+
+        // Until the resized buffers are swapped in, a larger cycle does not fit and is silenced.
+        resize.resize(512);
+        assert!(resize.pending.load(Ordering::Acquire));
+        assert!(!temp.fits(512));
+
+        // While the notification handler holds the lock, the swap is retried in the next cycle.
+        {
+            let _guard = resize.buffers.lock().unwrap();
+            assert!(!resize.swap(&mut temp));
+            assert!(resize.pending.load(Ordering::Acquire));
+        }
+
+        assert!(resize.swap(&mut temp));
+        assert!(!resize.pending.load(Ordering::Acquire));
+        assert!(temp.fits(512));
+        assert_eq!(temp.frames, 512);
+        assert_eq!(temp.input.len(), 512);
+        assert_eq!(temp.output.len(), 1024);
+
+        // The old buffers are left for the notification handler to free.
+        if let Some(old) = resize.buffers.lock().unwrap().as_ref() {
+            assert_eq!(old.frames, 256);
+            assert_eq!(old.output.len(), 512);
+        } else {
+            panic!("the old buffers were dropped on the process thread");
+        }
+        assert!(!resize.swap(&mut temp));
+    }
+}
@@ -0,0 +1,92 @@
+use super::jack;
+use crate::{DuplexCallbackInfo, FrameCount, InputCallbackInfo, OutputCallbackInfo};
+
+/// The state of the JACK transport.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum TransportState {
+    Stopped,
+    Rolling,
+    /// The transport is waiting for slow-sync clients to get ready before rolling.
+    Starting,
+}
+
+/// The musical position of the JACK transport, if the timebase master provides one.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TransportBbt {
+    /// The current bar, starting at 1.
+    pub bar: usize,
+    /// The current beat within the bar, starting at 1.
+    pub beat: usize,
+    /// The current tick within the beat, starting at 0.
+    pub tick: usize,
+    /// The numerator of the time signature.
+    pub beats_per_bar: f32,
+    /// The denominator of the time signature.
+    pub beat_type: f32,
+    pub ticks_per_beat: f64,
+    pub beats_per_minute: f64,
+    /// The number of ticks between the start of the timeline and the start of the current bar.
+    pub bar_start_tick: f64,
+}
+
+/// The state and position of the JACK transport at the start of a process cycle.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TransportInfo {
+    pub state: TransportState,
+    /// The position on the transport timeline, in frames.
+    pub frame: FrameCount,
+    pub bbt: Option<TransportBbt>,
+}
+
+/// JACK-specific extensions to the information passed to the data callbacks.
+pub trait CallbackInfoExt {
+    /// The state and position of the JACK transport for the process cycle of the callback.
+    ///
+    /// Returns `None` if the stream does not belong to the JACK host or if the transport could not
+    /// be queried.
+    fn transport(&self) -> Option<&TransportInfo>;
+}
+
+impl CallbackInfoExt for InputCallbackInfo {
+    fn transport(&self) -> Option<&TransportInfo> {
+        self.jack_transport.as_ref()
+    }
+}
+
+impl CallbackInfoExt for OutputCallbackInfo {
+    fn transport(&self) -> Option<&TransportInfo> {
+        self.jack_transport.as_ref()
+    }
+}
+
+impl CallbackInfoExt for DuplexCallbackInfo {
+    fn transport(&self) -> Option<&TransportInfo> {
+        self.jack_transport.as_ref()
+    }
+}
+
+// Query the state and position of the transport. This is realtime safe, and when called from the
+// process thread the result is valid for the whole cycle.
+pub(super) fn query(client: &jack::Client) -> Option<TransportInfo> {
+    let jack::TransportStatePosition { pos, state } = client.transport().query().ok()?;
+    let state = match state {
+        jack::TransportState::Stopped => TransportState::Stopped,
+        jack::TransportState::Rolling => TransportState::Rolling,
+        jack::TransportState::Starting => TransportState::Starting,
+    };
+    let bbt = pos.bbt().map(|bbt| TransportBbt {
+        bar: bbt.bar,
+        beat: bbt.beat,
+        tick: bbt.tick,
+        beats_per_bar: bbt.sig_num,
+        beat_type: bbt.sig_denom,
+        ticks_per_beat: bbt.ticks_per_beat,
+        beats_per_minute: bbt.bpm,
+        bar_start_tick: bbt.bar_start_tick,
+    });
+    Some(TransportInfo {
+        state,
+        frame: pos.frame(),
+        bbt,
+    })
+}
@@ -1,35 +1,212 @@
 use crate::{
-    BuildStreamError, Data, DefaultStreamConfigError, DeviceNameError, DevicesError,
-    InputCallbackInfo, OutputCallbackInfo, PauseStreamError, PlayStreamError, SampleFormat,
-    StreamConfig, StreamError, SupportedStreamConfig, SupportedStreamConfigRange,
-    SupportedStreamConfigsError,
+    BufferSize, BuildStreamError, Data, DefaultStreamConfigError, DeviceEvent, DeviceNameError,
+    DeviceWatcher, DevicesError, FrameCount, InputCallbackInfo, InputStreamTimestamp,
+    OutputCallbackInfo, OutputStreamTimestamp, PauseStreamError, PlayStreamError, SampleFormat,
+    SampleRate, StreamConfig, StreamError, StreamInstant, SupportedBufferSize,
+    SupportedStreamConfig, SupportedStreamConfigRange, SupportedStreamConfigsError, VolumeError,
+    VolumeRange, WatchDevicesError,
 };
+use std::sync::{Arc, Condvar, Mutex, Weak};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use std::vec::IntoIter as VecIntoIter;
 use traits::{DeviceTrait, HostTrait, StreamTrait};
+use volume::SoftwareVolume;
 
-#[derive(Default)]
-pub struct Devices;
+pub type SupportedInputConfigs = VecIntoIter<SupportedStreamConfigRange>;
+pub type SupportedOutputConfigs = VecIntoIter<SupportedStreamConfigRange>;
+
+/// The buffer size used by streams created with `BufferSize::Default`, clamped to the supported
+/// range of the device.
+const DEFAULT_BUFFER_SIZE: FrameCount = 512;
 
+/// An iterator over the virtual devices of a null `Host`.
+pub struct Devices(VecIntoIter<Device>);
+
+/// A virtual device that never produces or consumes any real audio.
+///
+/// Input streams deliver silence and output streams discard their data, with data callbacks
+/// invoked from a timer thread at the rate a real device with the same config would invoke them.
 #[derive(Clone, Debug, PartialEq, Eq)]
-pub struct Device;
+pub struct Device {
+    name: String,
+    input_configs: Vec<SupportedStreamConfigRange>,
+    output_configs: Vec<SupportedStreamConfigRange>,
+}
+
+/// A virtual host whose devices are configured by the user rather than discovered on the system.
+///
+/// Useful for exercising audio code on machines without any audio hardware, such as CI servers.
+pub struct Host {
+    devices: Vec<Device>,
+    watchers: Arc<Mutex<Watchers>>,
+}
+
+type DeviceEventCallback = Arc<Mutex<dyn FnMut(DeviceEvent) + Send>>;
+
+/// The callbacks registered via `watch_devices`, keyed by a unique ID.
+///
+/// Each callback has its own lock, so that events are delivered without holding the lock of the
+/// `Watchers`, which watchers being dropped or registered by the callbacks take.
+#[derive(Default)]
+struct Watchers {
+    next_id: usize,
+    callbacks: Vec<(usize, DeviceEventCallback)>,
+}
+
+/// Unregisters a callback from its host's `Watchers` when dropped.
+struct WatcherRegistration {
+    id: usize,
+    watchers: Weak<Mutex<Watchers>>,
+}
 
-pub struct Host;
+pub struct Stream {
+    /// State shared with the timer thread.
+    shared: Arc<StreamShared>,
+    /// The timer thread invoking the data callback.
+    thread: Option<JoinHandle<()>>,
+    config: StreamConfig,
+    /// The number of frames passed to each data callback.
+    period_frames: FrameCount,
+    /// The number of frames of all periods together.
+    buffer_frames: FrameCount,
+}
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
-pub struct Stream;
+struct StreamShared {
+    state: Mutex<StreamState>,
+    /// Notified whenever `state` changes.
+    condvar: Condvar,
+    /// Applied to the data of input streams before and output streams after the data callback.
+    volume: SoftwareVolume,
+}
 
-pub struct SupportedInputConfigs;
-pub struct SupportedOutputConfigs;
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum StreamState {
+    Playing,
+    Paused,
+    Stopped,
+}
 
 impl Host {
+    /// A host with a single device named "null" supporting input and output with one or two
+    /// channels, every sample format and sample rates from 8kHz to 192kHz.
     #[allow(dead_code)]
     pub fn new() -> Result<Self, crate::HostUnavailable> {
-        Ok(Host)
+        Ok(Host::with_devices(vec![Device::default()]))
+    }
+
+    /// A host exposing the given virtual devices.
+    ///
+    /// The first device supporting input is the default input device, and the first device
+    /// supporting output is the default output device.
+    #[allow(dead_code)]
+    pub fn with_devices(devices: Vec<Device>) -> Self {
+        Host {
+            devices,
+            watchers: Default::default(),
+        }
+    }
+
+    /// Deliver `event` to every callback currently registered via `watch_devices`.
+    ///
+    /// The null host has no hardware to watch, so this allows simulating device hot-plugging.
+    ///
+    /// The callbacks may drop their watchers or register new ones while the event is delivered.
+    #[allow(dead_code)]
+    pub fn trigger_device_event(&self, event: DeviceEvent) {
+        let callbacks = self.watchers.lock().unwrap().callbacks.clone();
+        for (id, callback) in callbacks {
+            // Skip callbacks whose watcher was dropped by one of the previous callbacks.
+            let registered = self
+                .watchers
+                .lock()
+                .unwrap()
+                .callbacks
+                .iter()
+                .any(|&(registered, _)| registered == id);
+            if registered {
+                (callback.lock().unwrap())(event.clone());
+            }
+        }
+    }
+}
+
+impl Drop for WatcherRegistration {
+    fn drop(&mut self) {
+        if let Some(watchers) = self.watchers.upgrade() {
+            let mut watchers = watchers.lock().unwrap();
+            watchers.callbacks.retain(|&(id, _)| id != self.id);
+        }
+    }
+}
+
+impl Device {
+    /// A virtual device with the given name and supported input and output configs.
+    ///
+    /// Leave `input_configs` or `output_configs` empty for a device that only supports output or
+    /// input respectively.
+    #[allow(dead_code)]
+    pub fn new(
+        name: impl Into<String>,
+        input_configs: Vec<SupportedStreamConfigRange>,
+        output_configs: Vec<SupportedStreamConfigRange>,
+    ) -> Self {
+        Device {
+            name: name.into(),
+            input_configs,
+            output_configs,
+        }
+    }
+
+    fn default_config(
+        configs: &[SupportedStreamConfigRange],
+    ) -> Result<SupportedStreamConfig, DefaultStreamConfigError> {
+        let range = configs
+            .iter()
+            .max_by(|a, b| a.cmp_default_heuristics(b))
+            .ok_or(DefaultStreamConfigError::StreamTypeNotSupported)?;
+        let mut config = range.clone().with_max_sample_rate();
+        const HZ_44100: SampleRate = SampleRate(44_100);
+        if range.min_sample_rate <= HZ_44100 && HZ_44100 <= range.max_sample_rate {
+            config.sample_rate = HZ_44100;
+        }
+        Ok(config)
+    }
+}
+
+impl Default for Device {
+    fn default() -> Self {
+        const FORMATS: [SampleFormat; 10] = [
+            SampleFormat::I8,
+            SampleFormat::I16,
+            SampleFormat::I24,
+            SampleFormat::I24Packed,
+            SampleFormat::I32,
+            SampleFormat::U8,
+            SampleFormat::U16,
+            SampleFormat::U32,
+            SampleFormat::F32,
+            SampleFormat::F64,
+        ];
+        let mut configs = Vec::with_capacity(2 * FORMATS.len());
+        for &channels in &[1, 2] {
+            for &sample_format in FORMATS.iter() {
+                configs.push(SupportedStreamConfigRange::new(
+                    channels,
+                    SampleRate(8_000),
+                    SampleRate(192_000),
+                    SupportedBufferSize::Range { min: 16, max: 8192 },
+                    sample_format,
+                ));
+            }
+        }
+        Device::new("null", configs.clone(), configs)
     }
 }
 
 impl Devices {
-    pub fn new() -> Result<Self, DevicesError> {
-        Ok(Devices)
+    pub fn new(devices: Vec<Device>) -> Result<Self, DevicesError> {
+        Ok(Devices(devices.into_iter()))
     }
 }
 
@@ -40,60 +217,94 @@ impl DeviceTrait for Device {
 
     #[inline]
     fn name(&self) -> Result<String, DeviceNameError> {
-        Ok("null".to_owned())
+        Ok(self.name.clone())
     }
 
     #[inline]
     fn supported_input_configs(
         &self,
     ) -> Result<SupportedInputConfigs, SupportedStreamConfigsError> {
-        unimplemented!()
+        Ok(self.input_configs.clone().into_iter())
     }
 
     #[inline]
     fn supported_output_configs(
         &self,
     ) -> Result<SupportedOutputConfigs, SupportedStreamConfigsError> {
-        unimplemented!()
+        Ok(self.output_configs.clone().into_iter())
     }
 
     #[inline]
     fn default_input_config(&self) -> Result<SupportedStreamConfig, DefaultStreamConfigError> {
-        unimplemented!()
+        Device::default_config(&self.input_configs)
     }
 
     #[inline]
     fn default_output_config(&self) -> Result<SupportedStreamConfig, DefaultStreamConfigError> {
-        unimplemented!()
+        Device::default_config(&self.output_configs)
     }
 
     fn build_input_stream_raw<D, E>(
         &self,
-        _config: &StreamConfig,
-        _sample_format: SampleFormat,
-        _data_callback: D,
+        config: &StreamConfig,
+        sample_format: SampleFormat,
+        mut data_callback: D,
         _error_callback: E,
     ) -> Result<Self::Stream, BuildStreamError>
     where
         D: FnMut(&Data, &InputCallbackInfo) + Send + 'static,
         E: FnMut(StreamError) + Send + 'static,
     {
-        unimplemented!()
+        let (period_frames, buffer_frames) =
+            stream_buffer_frames(&self.input_configs, config, sample_format)?;
+        let stream = Stream::new(
+            config,
+            sample_format,
+            period_frames,
+            buffer_frames,
+            move |data, callback, buffer_duration, volume| {
+                crate::samples_formats::fill_with_silence(data.bytes_mut(), sample_format);
+                volume.apply(data);
+                // The callback is invoked once the buffer has been captured in full.
+                let capture = callback;
+                let callback = capture
+                    .add(buffer_duration)
+                    .expect("`callback` occurs beyond representation supported by `StreamInstant`");
+                let timestamp = InputStreamTimestamp { callback, capture };
+                data_callback(data, &InputCallbackInfo::new(timestamp));
+            },
+        );
+        Ok(stream)
     }
 
-    /// Create an output stream.
     fn build_output_stream_raw<D, E>(
         &self,
-        _config: &StreamConfig,
-        _sample_format: SampleFormat,
-        _data_callback: D,
+        config: &StreamConfig,
+        sample_format: SampleFormat,
+        mut data_callback: D,
         _error_callback: E,
     ) -> Result<Self::Stream, BuildStreamError>
     where
         D: FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static,
         E: FnMut(StreamError) + Send + 'static,
     {
-        unimplemented!()
+        let (period_frames, buffer_frames) =
+            stream_buffer_frames(&self.output_configs, config, sample_format)?;
+        let stream = Stream::new(
+            config,
+            sample_format,
+            period_frames,
+            buffer_frames,
+            move |data, callback, buffer_duration, volume| {
+                let playback = callback
+                    .add(buffer_duration)
+                    .expect("`playback` occurs beyond representation supported by `StreamInstant`");
+                let timestamp = OutputStreamTimestamp { callback, playback };
+                data_callback(data, &OutputCallbackInfo::new(timestamp));
+                volume.apply(data);
+            },
+        );
+        Ok(stream)
     }
 }
 
@@ -102,55 +313,758 @@ impl HostTrait for Host {
     type Devices = Devices;
 
     fn is_available() -> bool {
-        false
+        // The null host does not depend on anything from the system.
+        true
     }
 
     fn devices(&self) -> Result<Self::Devices, DevicesError> {
-        Devices::new()
+        Devices::new(self.devices.clone())
     }
 
     fn default_input_device(&self) -> Option<Device> {
-        None
+        self.devices
+            .iter()
+            .find(|device| !device.input_configs.is_empty())
+            .cloned()
     }
 
     fn default_output_device(&self) -> Option<Device> {
-        None
+        self.devices
+            .iter()
+            .find(|device| !device.output_configs.is_empty())
+            .cloned()
+    }
+
+    fn watch_devices<F>(&self, callback: F) -> Result<DeviceWatcher, WatchDevicesError>
+    where
+        F: FnMut(DeviceEvent) + Send + 'static,
+    {
+        let mut watchers = self.watchers.lock().unwrap();
+        let id = watchers.next_id;
+        watchers.next_id += 1;
+        watchers
+            .callbacks
+            .push((id, Arc::new(Mutex::new(callback))));
+        Ok(DeviceWatcher::new(WatcherRegistration {
+            id,
+            watchers: Arc::downgrade(&self.watchers),
+        }))
+    }
+}
+
+impl Stream {
+    // Spawn the timer thread, which invokes `process` with a buffer of `period_frames` frames, the
+    // instant of the buffer's first frame, the duration of the buffer and the volume of the stream
+    // once per buffer duration while playing. `buffer_frames` is only reported.
+    //
+    // The stream starts out playing.
+    fn new<F>(
+        config: &StreamConfig,
+        sample_format: SampleFormat,
+        period_frames: FrameCount,
+        buffer_frames: FrameCount,
+        mut process: F,
+    ) -> Self
+    where
+        F: FnMut(&mut Data, StreamInstant, Duration, &SoftwareVolume) + Send + 'static,
+    {
+        let shared = Arc::new(StreamShared {
+            state: Mutex::new(StreamState::Playing),
+            condvar: Condvar::new(),
+            volume: SoftwareVolume::new(),
+        });
+        let sample_rate = config.sample_rate;
+        let len = period_frames as usize * config.channels as usize;
+        let buffer_duration = frames_to_duration(period_frames as u64, sample_rate);
+
+        let thread_shared = shared.clone();
+        let thread = thread::Builder::new()
+            .name("cpal_null_stream".to_owned())
+            .spawn(move || {
+                let mut buffer = vec![0u8; len * sample_format.sample_size()];
+                let mut frames_elapsed = 0u64;
+                run_timer(&thread_shared, buffer_duration, || {
+                    // Timestamps are derived from the number of frames processed so far, as if
+                    // the stream was driven by a perfectly accurate device clock.
+                    let callback = frames_to_duration(frames_elapsed, sample_rate);
+                    let callback =
+                        StreamInstant::new(callback.as_secs() as i64, callback.subsec_nanos());
+                    let data = buffer.as_mut_ptr() as *mut ();
+                    let mut data = unsafe { Data::from_parts(data, len, sample_format) };
+                    process(&mut data, callback, buffer_duration, &thread_shared.volume);
+                    frames_elapsed += period_frames as u64;
+                });
+            })
+            .unwrap();
+
+        Stream {
+            shared,
+            thread: Some(thread),
+            config: StreamConfig {
+                buffer_size: BufferSize::Fixed(buffer_frames),
+                ..config.clone()
+            },
+            period_frames,
+            buffer_frames,
+        }
+    }
+
+    fn set_state(&self, state: StreamState) {
+        *self.shared.state.lock().unwrap() = state;
+        self.shared.condvar.notify_all();
+    }
+}
+
+impl Drop for Stream {
+    fn drop(&mut self) {
+        self.set_state(StreamState::Stopped);
+        self.thread.take().unwrap().join().unwrap();
     }
 }
 
 impl StreamTrait for Stream {
     fn play(&self) -> Result<(), PlayStreamError> {
-        unimplemented!()
+        self.set_state(StreamState::Playing);
+        Ok(())
     }
 
     fn pause(&self) -> Result<(), PauseStreamError> {
-        unimplemented!()
+        self.set_state(StreamState::Paused);
+        Ok(())
+    }
+
+    fn config(&self) -> Option<StreamConfig> {
+        Some(self.config.clone())
+    }
+
+    fn buffer_size(&self) -> Option<FrameCount> {
+        Some(self.buffer_frames)
+    }
+
+    fn period_size(&self) -> Option<FrameCount> {
+        Some(self.period_frames)
+    }
+
+    fn latency(&self) -> Option<Duration> {
+        Some(frames_to_duration(
+            self.buffer_frames as u64,
+            self.config.sample_rate,
+        ))
+    }
+
+    fn volume_range(&self) -> Option<VolumeRange> {
+        Some(SoftwareVolume::RANGE)
+    }
+
+    fn volume(&self) -> Result<f32, VolumeError> {
+        Ok(self.shared.volume.volume())
+    }
+
+    fn set_volume(&self, volume_db: f32) -> Result<(), VolumeError> {
+        self.shared.volume.set_volume(volume_db);
+        Ok(())
+    }
+
+    fn is_muted(&self) -> Result<bool, VolumeError> {
+        Ok(self.shared.volume.is_muted())
+    }
+
+    fn set_muted(&self, muted: bool) -> Result<(), VolumeError> {
+        self.shared.volume.set_muted(muted);
+        Ok(())
+    }
+}
+
+// Invoke `tick` once every `period` while the stream is playing, until it is stopped.
+//
+// Deadlines are advanced by exactly `period` so that the rate does not drift with the time spent
+// in `tick`, and are reset when resuming so that no ticks are made up for the paused time.
+fn run_timer<F>(shared: &StreamShared, period: Duration, mut tick: F)
+where
+    F: FnMut(),
+{
+    let mut deadline = Instant::now() + period;
+    let mut state = shared.state.lock().unwrap();
+    loop {
+        match *state {
+            StreamState::Stopped => return,
+            StreamState::Paused => {
+                state = shared.condvar.wait(state).unwrap();
+                deadline = Instant::now() + period;
+            }
+            StreamState::Playing => {
+                let now = Instant::now();
+                if now < deadline {
+                    state = shared
+                        .condvar
+                        .wait_timeout(state, deadline - now)
+                        .unwrap()
+                        .0;
+                    continue;
+                }
+                drop(state);
+                tick();
+                deadline += period;
+                state = shared.state.lock().unwrap();
+            }
+        }
     }
 }
 
+// The number of frames per period, passed to each data callback, and of the whole buffer for a
+// stream with the given config, or an error if none of the `supported` configs allows it.
+fn stream_buffer_frames(
+    supported: &[SupportedStreamConfigRange],
+    config: &StreamConfig,
+    sample_format: SampleFormat,
+) -> Result<(FrameCount, FrameCount), BuildStreamError> {
+    let range = supported
+        .iter()
+        .find(|range| {
+            range.channels == config.channels
+                && range.sample_format == sample_format
+                && range.min_sample_rate <= config.sample_rate
+                && config.sample_rate <= range.max_sample_rate
+        })
+        .ok_or(BuildStreamError::StreamConfigNotSupported)?;
+    let (min, max) = match range.buffer_size {
+        SupportedBufferSize::Range { min, max } => (min, max),
+        SupportedBufferSize::Unknown => (1, FrameCount::MAX),
+    };
+    let period_frames = match config.buffer_size.period_frames(config.sample_rate) {
+        Some(frames) if frames < min || frames > max || frames == 0 => {
+            return Err(BuildStreamError::StreamConfigNotSupported)
+        }
+        Some(frames) => frames,
+        None => DEFAULT_BUFFER_SIZE.max(min).min(max),
+    };
+    let periods = match config.buffer_size.periods(config.sample_rate) {
+        Some((_, 0)) => return Err(BuildStreamError::StreamConfigNotSupported),
+        Some((_, periods)) => periods,
+        None => 1,
+    };
+    Ok((period_frames, period_frames.saturating_mul(periods)))
+}
+
+/// A config range of a device that supports only the given channels, sample rate and format, in
+/// buffers of 64 frames, for testing the adapters that make up for what a device lacks.
+#[cfg(test)]
+pub(crate) fn single_config(
+    channels: crate::ChannelCount,
+    sample_rate: SampleRate,
+    sample_format: SampleFormat,
+) -> SupportedStreamConfigRange {
+    SupportedStreamConfigRange::new(
+        channels,
+        sample_rate,
+        sample_rate,
+        SupportedBufferSize::Range { min: 64, max: 64 },
+        sample_format,
+    )
+}
+
+// Convert the given duration in frames at the given sample rate to a `std::time::Duration`.
+fn frames_to_duration(frames: u64, rate: SampleRate) -> Duration {
+    let nanos = frames as u128 * 1_000_000_000 / rate.0 as u128;
+    Duration::new(
+        (nanos / 1_000_000_000) as u64,
+        (nanos % 1_000_000_000) as u32,
+    )
+}
+
 impl Iterator for Devices {
     type Item = Device;
 
     #[inline]
     fn next(&mut self) -> Option<Device> {
-        None
+        self.0.next()
     }
 }
 
-impl Iterator for SupportedInputConfigs {
-    type Item = SupportedStreamConfigRange;
+#[cfg(test)]
+mod tests {
+    use super::{single_config, Device, Host};
+    use std::sync::mpsc;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::Duration;
+    use traits::{DeviceTrait, HostTrait, StreamTrait};
+    use {
+        platform, BufferSize, BuildStreamError, ChannelMix, DeviceDescriptor, DeviceEvent, HostId,
+        ResampleQuality, ResolveDeviceError, SampleFormat, SampleRate, StreamConfig,
+        StreamDirection, SupportedBufferSize, SupportedStreamConfigRange,
+    };
 
-    #[inline]
-    fn next(&mut self) -> Option<SupportedStreamConfigRange> {
-        None
+    fn recording_watcher(host: &Host) -> (::DeviceWatcher, Arc<Mutex<Vec<DeviceEvent>>>) {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let events2 = events.clone();
+        let watcher = host
+            .watch_devices(move |event| events2.lock().unwrap().push(event))
+            .unwrap();
+        (watcher, events)
     }
-}
 
-impl Iterator for SupportedOutputConfigs {
-    type Item = SupportedStreamConfigRange;
+    fn config(buffer_size: BufferSize) -> StreamConfig {
+        StreamConfig {
+            channels: 2,
+            sample_rate: SampleRate(48_000),
+            buffer_size,
+            channel_map: None,
+        }
+    }
 
-    #[inline]
-    fn next(&mut self) -> Option<SupportedStreamConfigRange> {
-        None
+    #[test]
+    fn test_watch_devices_delivers_events() {
+        let host = Host::new().unwrap();
+        let (_watcher, events) = recording_watcher(&host);
+        let added = DeviceEvent::DeviceAdded {
+            name: "usb".to_owned(),
+        };
+        let changed = DeviceEvent::DefaultDeviceChanged {
+            direction: StreamDirection::Output,
+        };
+        host.trigger_device_event(added.clone());
+        host.trigger_device_event(changed.clone());
+        assert_eq!(*events.lock().unwrap(), vec![added, changed]);
+    }
+
+    #[test]
+    fn test_watch_devices_multiple_watchers() {
+        let host = Host::new().unwrap();
+        let (_watcher_a, events_a) = recording_watcher(&host);
+        let (_watcher_b, events_b) = recording_watcher(&host);
+        let removed = DeviceEvent::DeviceRemoved {
+            name: "usb".to_owned(),
+        };
+        host.trigger_device_event(removed.clone());
+        assert_eq!(*events_a.lock().unwrap(), vec![removed.clone()]);
+        assert_eq!(*events_b.lock().unwrap(), vec![removed]);
+    }
+
+    #[test]
+    fn test_dropped_watcher_receives_no_events() {
+        let host = Host::new().unwrap();
+        let (watcher_a, events_a) = recording_watcher(&host);
+        let (_watcher_b, events_b) = recording_watcher(&host);
+        drop(watcher_a);
+        let event = DeviceEvent::DeviceAdded {
+            name: "usb".to_owned(),
+        };
+        host.trigger_device_event(event.clone());
+        assert!(events_a.lock().unwrap().is_empty());
+        assert_eq!(*events_b.lock().unwrap(), vec![event]);
+    }
+
+    #[test]
+    fn test_callbacks_manage_watchers() {
+        let host = Arc::new(Host::new().unwrap());
+        let watcher_b = Arc::new(Mutex::new(None));
+        let registered = Arc::new(Mutex::new(Vec::new()));
+        let (host2, watcher_b2, registered2) =
+            (host.clone(), watcher_b.clone(), registered.clone());
+        // A callback that drops another watcher and registers a new one.
+        let watcher_a = host
+            .watch_devices(move |_| {
+                watcher_b2.lock().unwrap().take();
+                let watcher = host2.watch_devices(|_| ()).unwrap();
+                registered2.lock().unwrap().push(watcher);
+            })
+            .unwrap();
+        let (watcher, events_b) = recording_watcher(&host);
+        *watcher_b.lock().unwrap() = Some(watcher);
+        let event = DeviceEvent::DeviceAdded {
+            name: "usb".to_owned(),
+        };
+        host.trigger_device_event(event.clone());
+        assert!(watcher_b.lock().unwrap().is_none());
+        assert!(events_b.lock().unwrap().is_empty());
+        assert_eq!(registered.lock().unwrap().len(), 1);
+        drop(watcher_a);
+        host.trigger_device_event(event);
+        assert_eq!(registered.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_watcher_outliving_host() {
+        let host = Host::new().unwrap();
+        let (watcher, _events) = recording_watcher(&host);
+        drop(host);
+        drop(watcher);
+    }
+
+    #[test]
+    fn test_configured_devices() {
+        let mono_input = SupportedStreamConfigRange::new(
+            1,
+            SampleRate(16_000),
+            SampleRate(16_000),
+            SupportedBufferSize::Range { min: 64, max: 64 },
+            SampleFormat::I16,
+        );
+        let host = Host::with_devices(vec![
+            Device::new("speaker", vec![], Device::default().output_configs),
+            Device::new("mic", vec![mono_input.clone()], vec![]),
+        ]);
+        let names: Vec<_> = host.devices().unwrap().map(|d| d.name().unwrap()).collect();
+        assert_eq!(names, vec!["speaker", "mic"]);
+
+        let input = host.default_input_device().unwrap();
+        assert_eq!(input.name().unwrap(), "mic");
+        let configs: Vec<_> = input.supported_input_configs().unwrap().collect();
+        assert_eq!(configs, vec![mono_input.clone()]);
+        assert_eq!(
+            input.default_input_config().unwrap(),
+            mono_input.with_max_sample_rate()
+        );
+        assert!(input.default_output_config().is_err());
+
+        let output = host.default_output_device().unwrap();
+        assert_eq!(output.name().unwrap(), "speaker");
+        let default_config = output.default_output_config().unwrap();
+        assert_eq!(default_config.channels(), 2);
+        assert_eq!(default_config.sample_rate(), SampleRate(44_100));
+        assert_eq!(default_config.sample_format(), SampleFormat::F32);
+    }
+
+    #[test]
+    fn test_unsupported_stream_config() {
+        let device = Device::default();
+        let unsupported = [
+            StreamConfig {
+                channels: 3,
+                ..config(BufferSize::Default)
+            },
+            StreamConfig {
+                sample_rate: SampleRate(4_000),
+                ..config(BufferSize::Default)
+            },
+            config(BufferSize::Fixed(8)),
+            config(BufferSize::Fixed(16_384)),
+        ];
+        for config in unsupported.iter() {
+            let result =
+                device.build_output_stream(config, |_: &mut [f32], _: &_| {}, |_| panic!());
+            match result {
+                Err(BuildStreamError::StreamConfigNotSupported) => (),
+                _ => panic!("expected {:?} to be unsupported", config),
+            }
+        }
+    }
+
+    #[test]
+    fn test_output_stream_callbacks() {
+        let device = Device::default();
+        let (tx, rx) = mpsc::channel();
+        let _stream = device
+            .build_output_stream(
+                &config(BufferSize::Fixed(64)),
+                move |data: &mut [i16], info: &_| {
+                    let _ = tx.send((data.len(), info.timestamp()));
+                },
+                |_| panic!(),
+            )
+            .unwrap();
+        let timeout = Duration::from_secs(1);
+        let (len, first) = rx.recv_timeout(timeout).unwrap();
+        let (_, second) = rx.recv_timeout(timeout).unwrap();
+        assert_eq!(len, 128);
+        let buffer_duration = Duration::from_nanos(64 * 1_000_000_000 / 48_000);
+        assert_eq!(
+            second.callback.duration_since(&first.callback),
+            Some(buffer_duration)
+        );
+        assert_eq!(
+            first.playback.duration_since(&first.callback),
+            Some(buffer_duration)
+        );
+    }
+
+    #[test]
+    fn test_stream_reports_negotiated_config() {
+        let device = Device::default();
+        let stream = device
+            .build_output_stream(
+                &config(BufferSize::Default),
+                |_: &mut [f32], _: &_| {},
+                |_| panic!(),
+            )
+            .unwrap();
+        let buffer_size = super::DEFAULT_BUFFER_SIZE;
+        assert_eq!(
+            stream.config(),
+            Some(config(BufferSize::Fixed(buffer_size)))
+        );
+        assert_eq!(stream.buffer_size(), Some(buffer_size));
+        assert_eq!(stream.period_size(), Some(buffer_size));
+        assert_eq!(
+            stream.latency(),
+            Some(Duration::from_nanos(
+                buffer_size as u64 * 1_000_000_000 / 48_000
+            ))
+        );
+    }
+
+    #[test]
+    fn test_stream_uses_requested_period_size() {
+        let device = Device::default();
+        let requests = [
+            (
+                BufferSize::Periods {
+                    period_size: 128,
+                    periods: 3,
+                },
+                128,
+                384,
+            ),
+            // Split into two periods of 5 ms at 48 kHz.
+            (BufferSize::Latency(Duration::from_millis(10)), 240, 480),
+        ];
+        for (buffer_size, period_size, total_size) in requests.iter().cloned() {
+            let stream = device
+                .build_output_stream(
+                    &config(buffer_size),
+                    |_: &mut [f32], _: &_| {},
+                    |_| panic!(),
+                )
+                .unwrap();
+            assert_eq!(stream.period_size(), Some(period_size));
+            assert_eq!(stream.buffer_size(), Some(total_size));
+        }
+        let rejected = [
+            BufferSize::Periods {
+                period_size: 128,
+                periods: 0,
+            },
+            BufferSize::Periods {
+                period_size: 0,
+                periods: 3,
+            },
+            // Rounds down to periods of 0 frames at 48 kHz.
+            BufferSize::Latency(Duration::from_micros(10)),
+        ];
+        for buffer_size in rejected.iter().cloned() {
+            let result = device.build_output_stream(
+                &config(buffer_size),
+                |_: &mut [f32], _: &_| {},
+                |_| panic!(),
+            );
+            match result {
+                Err(BuildStreamError::StreamConfigNotSupported) => (),
+                _ => panic!("expected `StreamConfigNotSupported`"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_stream_volume() {
+        let device = Device::default();
+        let stream = device
+            .build_output_stream(
+                &config(BufferSize::Fixed(64)),
+                |_: &mut [f32], _: &_| {},
+                |_| panic!(),
+            )
+            .unwrap();
+        let range = stream.volume_range().unwrap();
+        assert!(range.min_db < range.max_db);
+        assert_eq!(stream.volume().unwrap(), range.max_db);
+        stream.set_volume(range.min_db - 10.0).unwrap();
+        assert_eq!(stream.volume().unwrap(), range.min_db);
+        assert!(!stream.is_muted().unwrap());
+        stream.set_muted(true).unwrap();
+        assert!(stream.is_muted().unwrap());
+    }
+
+    #[test]
+    fn test_input_stream_delivers_silence() {
+        let device = Device::default();
+        let (tx, rx) = mpsc::channel();
+        let _stream = device
+            .build_input_stream(
+                &config(BufferSize::Default),
+                move |data: &[u16], _: &_| {
+                    let _ = tx.send(data.to_vec());
+                },
+                |_| panic!(),
+            )
+            .unwrap();
+        let data = rx.recv_timeout(Duration::from_secs(1)).unwrap();
+        assert_eq!(data.len(), 2 * super::DEFAULT_BUFFER_SIZE as usize);
+        assert!(data.iter().all(|&sample| sample == 0x8000));
+    }
+
+    #[test]
+    fn test_converted_input_stream() {
+        let u16_input = single_config(2, SampleRate(48_000), SampleFormat::U16);
+        let device = Device::new("mic", vec![u16_input], vec![]);
+        let config = config(BufferSize::Fixed(64));
+        match device.build_input_stream(&config, |_: &[f32], _: &_| {}, |_| panic!()) {
+            Err(BuildStreamError::StreamConfigNotSupported) => (),
+            _ => panic!("expected f32 to be unsupported"),
+        }
+        device
+            .build_input_stream_converted(&config, |_: &[f32], _: &_| {}, |_| panic!())
+            .unwrap();
+    }
+
+    #[test]
+    fn test_resampled_output_stream() {
+        let output = single_config(2, SampleRate(48_000), SampleFormat::F32);
+        let device = Device::new("speakers", vec![], vec![output]);
+        let config = StreamConfig {
+            sample_rate: SampleRate(44_100),
+            ..config(BufferSize::Fixed(64))
+        };
+        match device.build_output_stream(&config, |_: &mut [f32], _: &_| {}, |_| panic!()) {
+            Err(BuildStreamError::StreamConfigNotSupported) => (),
+            _ => panic!("expected 44.1 kHz to be unsupported"),
+        }
+        device
+            .build_output_stream_resampled(
+                &config,
+                ResampleQuality::Fast,
+                |_: &mut [i16], _: &_| {},
+                |_| panic!(),
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn test_remixed_output_stream() {
+        let mono_output = single_config(1, SampleRate(48_000), SampleFormat::I16);
+        let device = Device::new("speaker", vec![], vec![mono_output]);
+        let config = config(BufferSize::Fixed(64));
+        match device.build_output_stream(&config, |_: &mut [f32], _: &_| {}, |_| panic!()) {
+            Err(BuildStreamError::StreamConfigNotSupported) => (),
+            _ => panic!("expected stereo to be unsupported"),
+        }
+        let mismatched = ChannelMix::Matrix(vec![vec![0.5, 0.5, 0.5]]);
+        match device.build_output_stream_remixed(
+            &config,
+            mismatched,
+            |_: &mut [f32], _: &_| {},
+            |_| panic!(),
+        ) {
+            Err(BuildStreamError::InvalidArgument) => (),
+            _ => panic!("expected a matrix with three columns to be rejected"),
+        }
+        device
+            .build_output_stream_remixed(
+                &config,
+                ChannelMix::Auto,
+                |_: &mut [f32], _: &_| {},
+                |_| panic!(),
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn test_device_descriptor() {
+        let host = platform::host_from_id(HostId::Null).unwrap();
+        let device = host.default_output_device().unwrap();
+        let descriptor = device.descriptor().unwrap();
+        assert_eq!(descriptor.host, HostId::Null);
+        assert_eq!(descriptor.id, device.name().unwrap());
+        let resolved = descriptor.resolve().unwrap();
+        assert_eq!(resolved.name().unwrap(), device.name().unwrap());
+
+        let missing = DeviceDescriptor {
+            id: "missing".to_owned(),
+            ..descriptor
+        };
+        match missing.resolve() {
+            Err(ResolveDeviceError::DeviceNotFound) => (),
+            _ => panic!("expected the device not to be found"),
+        }
+    }
+
+    #[test]
+    fn test_planar_input_stream_delivers_silence() {
+        let device = Device::default();
+        let (tx, rx) = mpsc::channel();
+        let _stream = device
+            .build_input_stream_planar_raw(
+                &config(BufferSize::Default),
+                SampleFormat::U16,
+                move |data, _| {
+                    let channels: Vec<Vec<u16>> = (0..data.planes().unwrap())
+                        .map(|channel| data.channel::<u16>(channel).unwrap().to_vec())
+                        .collect();
+                    let _ = tx.send(channels);
+                },
+                |_| panic!(),
+            )
+            .unwrap();
+        let channels = rx.recv_timeout(Duration::from_secs(1)).unwrap();
+        assert_eq!(channels.len(), 2);
+        for channel in channels {
+            assert_eq!(channel.len(), super::DEFAULT_BUFFER_SIZE as usize);
+            assert!(channel.iter().all(|&sample| sample == 0x8000));
+        }
+    }
+
+    #[test]
+    fn test_blocking_input_reads_silence() {
+        let device = Device::default();
+        let mut reader = device
+            .build_input_stream_blocking(&config(BufferSize::Fixed(64)), SampleFormat::U16)
+            .unwrap();
+        // Longer than a single callback and with an incomplete frame at the end.
+        let mut samples = vec![0u16; 301];
+        assert_eq!(reader.read(&mut samples).unwrap(), 300);
+        assert!(samples[..300].iter().all(|&sample| sample == 0x8000));
+        assert_eq!(samples[300], 0);
+    }
+
+    #[test]
+    fn test_blocking_output_consumes_writes() {
+        let device = Device::default();
+        let mut writer = device
+            .build_output_stream_blocking(&config(BufferSize::Fixed(64)), SampleFormat::F32)
+            .unwrap();
+        // More than the ring buffer holds, so that writing blocks until the stream has played
+        // some of it. The writer is moved to the thread that writes to it.
+        let writer = thread::spawn(move || {
+            let samples = vec![0.5f32; 48_000];
+            assert_eq!(writer.write(&samples).unwrap(), samples.len());
+            writer
+        });
+        drop(writer.join().unwrap());
+    }
+
+    #[test]
+    fn test_blocking_stream_build_error() {
+        let device = Device::default();
+        let config = StreamConfig {
+            channels: 8,
+            ..config(BufferSize::Default)
+        };
+        match device.build_input_stream_blocking(&config, SampleFormat::F32) {
+            Err(BuildStreamError::StreamConfigNotSupported) => (),
+            _ => panic!("expected the config to be rejected"),
+        }
+    }
+
+    #[test]
+    fn test_pause_and_play() {
+        let device = Device::default();
+        let (tx, rx) = mpsc::channel();
+        let stream = device
+            .build_output_stream(
+                &config(BufferSize::Fixed(64)),
+                move |_: &mut [f32], _: &_| {
+                    let _ = tx.send(());
+                },
+                |_| panic!(),
+            )
+            .unwrap();
+        let timeout = Duration::from_secs(1);
+        rx.recv_timeout(timeout).unwrap();
+        stream.pause().unwrap();
+        // Drain any callback that was already in progress when pausing.
+        while rx.recv_timeout(Duration::from_millis(20)).is_ok() {}
+        assert!(rx.recv_timeout(Duration::from_millis(50)).is_err());
+        stream.play().unwrap();
+        rx.recv_timeout(timeout).unwrap();
     }
 }
@@ -33,12 +33,10 @@ impl<I, C> CpalInputCallback<I, C> {
         &self,
         audio_stream: &mut dyn oboe::AudioInputStreamSafe,
     ) -> InputCallbackInfo {
-        InputCallbackInfo {
-            timestamp: InputStreamTimestamp {
-                callback: to_stream_instant(self.created.elapsed()),
-                capture: stream_instant(audio_stream),
-            },
-        }
+        InputCallbackInfo::new(InputStreamTimestamp {
+            callback: to_stream_instant(self.created.elapsed()),
+            capture: stream_instant(audio_stream),
+        })
     }
 }
 
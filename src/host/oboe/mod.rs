@@ -7,11 +7,10 @@ extern crate oboe;
 
 use crate::traits::{DeviceTrait, HostTrait, StreamTrait};
 use crate::{
-    BackendSpecificError, BufferSize, BuildStreamError, Data, DefaultStreamConfigError,
-    DeviceNameError, DevicesError, InputCallbackInfo, OutputCallbackInfo, PauseStreamError,
-    PlayStreamError, Sample, SampleFormat, SampleRate, StreamConfig, StreamError,
-    SupportedBufferSize, SupportedStreamConfig, SupportedStreamConfigRange,
-    SupportedStreamConfigsError,
+    BackendSpecificError, BuildStreamError, Data, DefaultStreamConfigError, DeviceNameError,
+    DevicesError, InputCallbackInfo, OutputCallbackInfo, PauseStreamError, PlayStreamError, Sample,
+    SampleFormat, SampleRate, StreamConfig, StreamError, SupportedBufferSize,
+    SupportedStreamConfig, SupportedStreamConfigRange, SupportedStreamConfigsError,
 };
 
 mod android_media;
@@ -219,9 +218,9 @@ fn configure_for_device<D, C, I>(
         builder
     };
     builder = builder.set_sample_rate(config.sample_rate.0.try_into().unwrap());
-    match &config.buffer_size {
-        BufferSize::Default => builder,
-        BufferSize::Fixed(size) => builder.set_buffer_capacity_in_frames(*size as i32),
+    match config.buffer_size.buffer_frames(config.sample_rate) {
+        None => builder,
+        Some(size) => builder.set_buffer_capacity_in_frames(size as i32),
     }
 }
 
@@ -393,8 +392,8 @@ impl DeviceTrait for Device {
                     .into())
                 }
             }
-            SampleFormat::U16 => Err(BackendSpecificError {
-                description: "U16 format is not supported on Android.".to_owned(),
+            sample_format => Err(BackendSpecificError {
+                description: format!("{:?} format is not supported on Android.", sample_format),
             }
             .into()),
         }
@@ -466,8 +465,8 @@ impl DeviceTrait for Device {
                     .into())
                 }
             }
-            SampleFormat::U16 => Err(BackendSpecificError {
-                description: "U16 format is not supported on Android.".to_owned(),
+            sample_format => Err(BackendSpecificError {
+                description: format!("{:?} format is not supported on Android.", sample_format),
             }
             .into()),
         }
@@ -33,12 +33,10 @@ impl<I, C> CpalOutputCallback<I, C> {
         &self,
         audio_stream: &mut dyn oboe::AudioOutputStreamSafe,
     ) -> OutputCallbackInfo {
-        OutputCallbackInfo {
-            timestamp: OutputStreamTimestamp {
-                callback: to_stream_instant(self.created.elapsed()),
-                playback: stream_instant(audio_stream),
-            },
-        }
+        OutputCallbackInfo::new(OutputStreamTimestamp {
+            callback: to_stream_instant(self.created.elapsed()),
+            playback: stream_instant(audio_stream),
+        })
     }
 }
 
@@ -0,0 +1,262 @@
+use super::pw::spa::utils::dict::DictRef;
+use super::stream::{DataCallback, Stream, StreamParams};
+use super::{SupportedInputConfigs, SupportedOutputConfigs};
+use crate::{
+    BuildStreamError, Data, DefaultStreamConfigError, DeviceNameError, InputCallbackInfo,
+    OutputCallbackInfo, SampleFormat, SampleRate, StreamConfig, StreamError, SupportedBufferSize,
+    SupportedStreamConfig, SupportedStreamConfigRange, SupportedStreamConfigsError,
+};
+use std::hash::{Hash, Hasher};
+use traits::DeviceTrait;
+
+/// The channel count assumed for nodes that do not advertise one.
+const DEFAULT_NUM_CHANNELS: u16 = 2;
+/// The default rate of the PipeWire graph.
+const DEFAULT_SAMPLE_RATE: SampleRate = SampleRate(48_000);
+/// The sample format offered by default, as it is the one PipeWire processes audio in.
+const DEFAULT_SAMPLE_FORMAT: SampleFormat = SampleFormat::F32;
+const MIN_SAMPLE_RATE: SampleRate = SampleRate(8_000);
+const MAX_SAMPLE_RATE: SampleRate = SampleRate(384_000);
+/// The default limits of the graph quantum, in frames.
+const MIN_QUANTUM: u32 = 32;
+const MAX_QUANTUM: u32 = 8192;
+
+/// The sample formats that the stream nodes can convert to and from the format of the graph.
+const SUPPORTED_SAMPLE_FORMATS: [SampleFormat; 10] = [
+    SampleFormat::I8,
+    SampleFormat::I16,
+    SampleFormat::I24,
+    SampleFormat::I24Packed,
+    SampleFormat::I32,
+    SampleFormat::U8,
+    SampleFormat::U16,
+    SampleFormat::U32,
+    SampleFormat::F32,
+    SampleFormat::F64,
+];
+
+/// Whether a device is an `Audio/Sink` node, used for output, or an `Audio/Source` node, used for
+/// input.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DeviceType {
+    Sink,
+    Source,
+}
+
+#[derive(Clone, Debug)]
+pub struct Device {
+    /// The global id of the node, which is only valid while the node exists.
+    id: u32,
+    /// The `node.name` by which streams target the node.
+    name: String,
+    /// The human readable `node.description`.
+    description: String,
+    device_type: DeviceType,
+    channels: u16,
+    /// The `priority.session` of the node.
+    priority: i32,
+}
+
+impl Device {
+    /// Creates a device from the properties of a node, if it is an audio sink or source.
+    pub(super) fn new(id: u32, props: &DictRef) -> Option<Self> {
+        let device_type = match props.get("media.class")? {
+            "Audio/Sink" => DeviceType::Sink,
+            "Audio/Source" => DeviceType::Source,
+            _ => return None,
+        };
+        let name = props.get("node.name")?.to_string();
+        let description = props
+            .get("node.description")
+            .or_else(|| props.get("node.nick"))
+            .unwrap_or(name.as_str())
+            .to_string();
+        let channels = props
+            .get("audio.channels")
+            .and_then(|channels| channels.parse().ok())
+            .filter(|&channels| channels > 0)
+            .unwrap_or(DEFAULT_NUM_CHANNELS);
+        let priority = props
+            .get("priority.session")
+            .and_then(|priority| priority.parse().ok())
+            .unwrap_or(0);
+        Some(Device {
+            id,
+            name,
+            description,
+            device_type,
+            channels,
+            priority,
+        })
+    }
+
+    pub(super) fn device_type(&self) -> DeviceType {
+        self.device_type
+    }
+
+    pub(super) fn priority(&self) -> i32 {
+        self.priority
+    }
+
+    fn default_config(&self) -> SupportedStreamConfig {
+        SupportedStreamConfig {
+            channels: self.channels,
+            sample_rate: DEFAULT_SAMPLE_RATE,
+            buffer_size: SupportedBufferSize::Range {
+                min: MIN_QUANTUM,
+                max: MAX_QUANTUM,
+            },
+            sample_format: DEFAULT_SAMPLE_FORMAT,
+            channel_map: None,
+        }
+    }
+
+    fn supported_configs(&self) -> Vec<SupportedStreamConfigRange> {
+        let mut supported_configs = vec![];
+        for channels in 1..=self.channels {
+            for &sample_format in SUPPORTED_SAMPLE_FORMATS.iter() {
+                supported_configs.push(SupportedStreamConfigRange {
+                    channels,
+                    min_sample_rate: MIN_SAMPLE_RATE,
+                    max_sample_rate: MAX_SAMPLE_RATE,
+                    buffer_size: SupportedBufferSize::Range {
+                        min: MIN_QUANTUM,
+                        max: MAX_QUANTUM,
+                    },
+                    sample_format,
+                });
+            }
+        }
+        supported_configs
+    }
+
+    fn stream_params(
+        &self,
+        conf: &StreamConfig,
+        sample_format: SampleFormat,
+        device_type: DeviceType,
+    ) -> Result<StreamParams, BuildStreamError> {
+        if self.device_type != device_type
+            || conf.channels == 0
+            || conf.channels > self.channels
+            || conf.sample_rate < MIN_SAMPLE_RATE
+            || conf.sample_rate > MAX_SAMPLE_RATE
+        {
+            return Err(BuildStreamError::StreamConfigNotSupported);
+        }
+        let quantum = match conf.buffer_size.period_frames(conf.sample_rate) {
+            None => None,
+            Some(frames) if frames >= MIN_QUANTUM && frames <= MAX_QUANTUM => Some(frames),
+            Some(_) => return Err(BuildStreamError::StreamConfigNotSupported),
+        };
+        Ok(StreamParams {
+            node_id: self.id,
+            node_name: self.name.clone(),
+            device_type,
+            channels: conf.channels,
+            sample_rate: conf.sample_rate,
+            sample_format,
+            quantum,
+        })
+    }
+}
+
+impl DeviceTrait for Device {
+    type SupportedInputConfigs = SupportedInputConfigs;
+    type SupportedOutputConfigs = SupportedOutputConfigs;
+    type Stream = Stream;
+
+    fn name(&self) -> Result<String, DeviceNameError> {
+        Ok(self.description.clone())
+    }
+
+    fn id(&self) -> Result<String, DeviceNameError> {
+        Ok(self.name.clone())
+    }
+
+    fn supported_input_configs(
+        &self,
+    ) -> Result<Self::SupportedInputConfigs, SupportedStreamConfigsError> {
+        match self.device_type {
+            DeviceType::Source => Ok(self.supported_configs().into_iter()),
+            DeviceType::Sink => Ok(vec![].into_iter()),
+        }
+    }
+
+    fn supported_output_configs(
+        &self,
+    ) -> Result<Self::SupportedOutputConfigs, SupportedStreamConfigsError> {
+        match self.device_type {
+            DeviceType::Sink => Ok(self.supported_configs().into_iter()),
+            DeviceType::Source => Ok(vec![].into_iter()),
+        }
+    }
+
+    /// Returns the channel count of the node at the default graph rate, with samples in `f32`.
+    fn default_input_config(&self) -> Result<SupportedStreamConfig, DefaultStreamConfigError> {
+        match self.device_type {
+            DeviceType::Source => Ok(self.default_config()),
+            DeviceType::Sink => Err(DefaultStreamConfigError::StreamTypeNotSupported),
+        }
+    }
+
+    /// Returns the channel count of the node at the default graph rate, with samples in `f32`.
+    fn default_output_config(&self) -> Result<SupportedStreamConfig, DefaultStreamConfigError> {
+        match self.device_type {
+            DeviceType::Sink => Ok(self.default_config()),
+            DeviceType::Source => Err(DefaultStreamConfigError::StreamTypeNotSupported),
+        }
+    }
+
+    fn build_input_stream_raw<D, E>(
+        &self,
+        conf: &StreamConfig,
+        sample_format: SampleFormat,
+        data_callback: D,
+        error_callback: E,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        D: FnMut(&Data, &InputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        let params = self.stream_params(conf, sample_format, DeviceType::Source)?;
+        Stream::new(
+            params,
+            DataCallback::Input(Box::new(data_callback)),
+            Box::new(error_callback),
+        )
+    }
+
+    fn build_output_stream_raw<D, E>(
+        &self,
+        conf: &StreamConfig,
+        sample_format: SampleFormat,
+        data_callback: D,
+        error_callback: E,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        D: FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        let params = self.stream_params(conf, sample_format, DeviceType::Sink)?;
+        Stream::new(
+            params,
+            DataCallback::Output(Box::new(data_callback)),
+            Box::new(error_callback),
+        )
+    }
+}
+
+impl PartialEq for Device {
+    fn eq(&self, other: &Self) -> bool {
+        self.device_type == other.device_type && self.name == other.name
+    }
+}
+
+impl Eq for Device {}
+
+impl Hash for Device {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.name.hash(state);
+    }
+}
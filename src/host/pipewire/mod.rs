@@ -0,0 +1,159 @@
+extern crate pipewire as pw;
+
+use self::pw::context::Context;
+use self::pw::core::{Core, PW_ID_CORE};
+use self::pw::main_loop::MainLoop;
+use self::pw::types::ObjectType;
+use crate::{BackendSpecificError, BuildStreamError, DevicesError, SupportedStreamConfigRange};
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use traits::HostTrait;
+
+mod device;
+pub use self::device::Device;
+pub use self::stream::Stream;
+mod stream;
+
+use self::device::DeviceType;
+
+pub type SupportedInputConfigs = std::vec::IntoIter<SupportedStreamConfigRange>;
+pub type SupportedOutputConfigs = std::vec::IntoIter<SupportedStreamConfigRange>;
+pub type Devices = std::vec::IntoIter<Device>;
+
+/// The PipeWire Host type.
+///
+/// Every `Audio/Sink` and `Audio/Source` node in the graph is exposed as a device. Streams are
+/// created as nodes of their own that PipeWire links to the node of the device, converting the
+/// sample format, rate and channels as needed.
+#[derive(Debug)]
+pub struct Host;
+
+impl Host {
+    pub fn new() -> Result<Self, crate::HostUnavailable> {
+        nodes().map(|_| Host).map_err(|_| crate::HostUnavailable)
+    }
+}
+
+impl HostTrait for Host {
+    type Devices = Devices;
+    type Device = Device;
+
+    fn is_available() -> bool {
+        nodes().is_ok()
+    }
+
+    fn devices(&self) -> Result<Self::Devices, DevicesError> {
+        Ok(nodes()?.into_iter())
+    }
+
+    fn default_input_device(&self) -> Option<Self::Device> {
+        default_device(DeviceType::Source)
+    }
+
+    fn default_output_device(&self) -> Option<Self::Device> {
+        default_device(DeviceType::Sink)
+    }
+}
+
+// The node the session manager falls back to when no default has been configured, which is the one
+// with the highest session priority.
+fn default_device(device_type: DeviceType) -> Option<Device> {
+    nodes()
+        .ok()?
+        .into_iter()
+        .filter(|device| device.device_type() == device_type)
+        .max_by_key(|device| device.priority())
+}
+
+/// Lists all audio sink and source nodes currently in the graph.
+fn nodes() -> Result<Vec<Device>, BackendSpecificError> {
+    pw::init();
+    let mainloop = MainLoop::new(None)?;
+    let context = Context::new(&mainloop)?;
+    let core = context.connect(None)?;
+    let registry = core.get_registry()?;
+
+    let devices = Rc::new(RefCell::new(Vec::new()));
+    let _listener = {
+        let devices = devices.clone();
+        registry
+            .add_listener_local()
+            .global(move |global| {
+                if global.type_ != ObjectType::Node {
+                    return;
+                }
+                if let Some(device) = global.props.and_then(|props| Device::new(global.id, props)) {
+                    devices.borrow_mut().push(device);
+                }
+            })
+            .register()
+    };
+    roundtrip(&mainloop, &core)?;
+
+    let devices = devices.borrow().clone();
+    Ok(devices)
+}
+
+// Blocks until the server has processed every request made so far and all resulting events have
+// been dispatched.
+fn roundtrip(mainloop: &MainLoop, core: &Core) -> Result<(), BackendSpecificError> {
+    let done = Rc::new(Cell::new(false));
+    let error = Rc::new(RefCell::new(None));
+    let pending = core.sync(0)?;
+
+    let _listener = {
+        let (on_done, on_done_mainloop) = (done.clone(), mainloop.downgrade());
+        let (on_error, on_error_mainloop) = (done.clone(), mainloop.downgrade());
+        let error = error.clone();
+        core.add_listener_local()
+            .done(move |id, seq| {
+                if id == PW_ID_CORE && seq == pending {
+                    on_done.set(true);
+                    if let Some(mainloop) = on_done_mainloop.upgrade() {
+                        mainloop.quit();
+                    }
+                }
+            })
+            .error(move |id, _seq, _res, message| {
+                if id == PW_ID_CORE {
+                    *error.borrow_mut() = Some(message.to_string());
+                    on_error.set(true);
+                    if let Some(mainloop) = on_error_mainloop.upgrade() {
+                        mainloop.quit();
+                    }
+                }
+            })
+            .register()
+    };
+
+    while !done.get() {
+        mainloop.run();
+    }
+    let error = error.borrow_mut().take();
+    match error {
+        Some(description) => Err(BackendSpecificError { description }),
+        None => Ok(()),
+    }
+}
+
+impl From<pw::Error> for BackendSpecificError {
+    fn from(err: pw::Error) -> Self {
+        BackendSpecificError {
+            description: err.to_string(),
+        }
+    }
+}
+
+impl From<pw::Error> for DevicesError {
+    fn from(err: pw::Error) -> Self {
+        let err: BackendSpecificError = err.into();
+        err.into()
+    }
+}
+
+impl From<pw::Error> for BuildStreamError {
+    fn from(err: pw::Error) -> Self {
+        let err: BackendSpecificError = err.into();
+        err.into()
+    }
+}
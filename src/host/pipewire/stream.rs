@@ -0,0 +1,438 @@
+use super::device::DeviceType;
+use super::pw;
+use super::pw::context::Context;
+use super::pw::core::PW_ID_CORE;
+use super::pw::main_loop::MainLoop;
+use super::pw::properties::Properties;
+use super::pw::spa;
+use super::pw::spa::param::audio::{AudioFormat, AudioInfoRaw};
+use super::pw::spa::param::ParamType;
+use super::pw::spa::pod::serialize::PodSerializer;
+use super::pw::spa::pod::{Object, Pod, Value};
+use super::pw::spa::utils::{Direction, SpaTypes};
+use super::pw::stream::{StreamFlags, StreamRef, StreamState};
+use crate::{
+    BackendSpecificError, BuildStreamError, Data, FrameCount, InputCallbackInfo,
+    InputStreamTimestamp, OutputCallbackInfo, OutputStreamTimestamp, PauseStreamError,
+    PlayStreamError, SampleFormat, SampleRate, StreamError, StreamInstant,
+};
+use std::cell::RefCell;
+use std::io::Cursor;
+use std::rc::Rc;
+use std::sync::mpsc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use traits::StreamTrait;
+
+/// The name of the nodes created for streams.
+const STREAM_NAME: &str = "cpal";
+
+pub type InputDataCallback = Box<dyn FnMut(&Data, &InputCallbackInfo) + Send + 'static>;
+pub type OutputDataCallback = Box<dyn FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static>;
+pub type ErrorCallback = Box<dyn FnMut(StreamError) + Send + 'static>;
+
+pub enum DataCallback {
+    Input(InputDataCallback),
+    Output(OutputDataCallback),
+}
+
+/// Everything needed to create the node of a stream.
+#[derive(Clone, Debug)]
+pub struct StreamParams {
+    pub node_id: u32,
+    pub node_name: String,
+    pub device_type: DeviceType,
+    pub channels: u16,
+    pub sample_rate: SampleRate,
+    pub sample_format: SampleFormat,
+    /// The requested quantum, if any.
+    pub quantum: Option<FrameCount>,
+}
+
+impl StreamParams {
+    fn frame_size(&self) -> usize {
+        self.channels as usize * self.sample_format.sample_size()
+    }
+}
+
+// Messages sent from the `Stream` handle to the main loop of the stream's thread.
+enum Message {
+    Play,
+    Pause,
+    Terminate,
+}
+
+/// A PipeWire stream node.
+///
+/// PipeWire objects may not leave the thread they were created on, so every stream runs its own
+/// main loop on a dedicated thread. The data callback is called from that same thread.
+pub struct Stream {
+    sender: pw::channel::Sender<Message>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Stream {
+    pub(super) fn new(
+        params: StreamParams,
+        data_callback: DataCallback,
+        error_callback: ErrorCallback,
+    ) -> Result<Stream, BuildStreamError> {
+        let (sender, receiver) = pw::channel::channel();
+        let (ready_tx, ready_rx) = mpsc::channel();
+        let thread = thread::Builder::new()
+            .name("cpal_pipewire_stream".to_owned())
+            .spawn(move || {
+                let result = run_stream(params, data_callback, error_callback, receiver, &ready_tx);
+                if let Err(err) = result {
+                    let _ = ready_tx.send(Err(err));
+                }
+            })
+            .map_err(|err| BackendSpecificError {
+                description: err.to_string(),
+            })?;
+
+        let stream = Stream {
+            sender,
+            thread: Some(thread),
+        };
+        match ready_rx.recv() {
+            Ok(Ok(())) => Ok(stream),
+            Ok(Err(err)) => Err(err),
+            Err(_) => Err(BuildStreamError::DeviceNotAvailable),
+        }
+    }
+}
+
+// Creates the stream node and runs the main loop until the `Stream` is dropped.
+//
+// `ready` is sent `Ok` once the stream has been connected. Any error before that point is returned
+// instead.
+fn run_stream(
+    params: StreamParams,
+    data_callback: DataCallback,
+    error_callback: ErrorCallback,
+    receiver: pw::channel::Receiver<Message>,
+    ready: &mpsc::Sender<Result<(), BuildStreamError>>,
+) -> Result<(), BuildStreamError> {
+    pw::init();
+    let mainloop = MainLoop::new(None)?;
+    let context = Context::new(&mainloop)?;
+    let core = context.connect(None)?;
+    let registry = core.get_registry()?;
+    let error_callback = Rc::new(RefCell::new(error_callback));
+
+    // The connection to the server is lost.
+    let _core_listener = {
+        let (error_callback, mainloop) = (error_callback.clone(), mainloop.downgrade());
+        core.add_listener_local()
+            .error(move |id, _seq, _res, _message| {
+                if id == PW_ID_CORE {
+                    (error_callback.borrow_mut())(StreamError::DeviceNotAvailable);
+                    if let Some(mainloop) = mainloop.upgrade() {
+                        mainloop.quit();
+                    }
+                }
+            })
+            .register()
+    };
+
+    // The node of the device is removed from the graph.
+    let _registry_listener = {
+        let (error_callback, node_id) = (error_callback.clone(), params.node_id);
+        registry
+            .add_listener_local()
+            .global_remove(move |id| {
+                if id == node_id {
+                    (error_callback.borrow_mut())(StreamError::DeviceNotAvailable);
+                }
+            })
+            .register()
+    };
+
+    let stream = Rc::new(pw::stream::Stream::new(
+        &core,
+        STREAM_NAME,
+        stream_properties(&params),
+    )?);
+
+    let creation_instant = Instant::now();
+    let _stream_listener = {
+        let state_error_callback = error_callback.clone();
+        let format_error_callback = error_callback.clone();
+        let format_params = params.clone();
+        let process_params = params.clone();
+        stream
+            .add_local_listener_with_user_data(data_callback)
+            .state_changed(move |_, _, _old, new| {
+                if let StreamState::Error(description) = new {
+                    let err = BackendSpecificError { description };
+                    (state_error_callback.borrow_mut())(err.into());
+                }
+            })
+            .param_changed(move |_, _, id, param| {
+                if id != ParamType::Format.as_raw() {
+                    return;
+                }
+                if let Some(err) = param.and_then(|param| format_change(&format_params, param)) {
+                    (format_error_callback.borrow_mut())(err);
+                }
+            })
+            .process(move |stream, data_callback| {
+                process(stream, data_callback, &process_params, creation_instant)
+            })
+            .register()?
+    };
+
+    let format = format_param(&params)?;
+    let mut format_params = [
+        Pod::from_bytes(&format).ok_or_else(|| BackendSpecificError {
+            description: "failed to build the stream format".to_string(),
+        })?,
+    ];
+    let direction = match params.device_type {
+        DeviceType::Sink => Direction::Output,
+        DeviceType::Source => Direction::Input,
+    };
+    // Without `DONT_RECONNECT` the session manager would move the stream to another node when the
+    // node of the device is removed, rather than letting it be reported.
+    stream.connect(
+        direction,
+        None,
+        StreamFlags::AUTOCONNECT | StreamFlags::MAP_BUFFERS | StreamFlags::DONT_RECONNECT,
+        &mut format_params,
+    )?;
+
+    let _receiver = {
+        let (stream, weak_mainloop) = (stream.clone(), mainloop.downgrade());
+        let error_callback = error_callback.clone();
+        receiver.attach(mainloop.loop_(), move |message| {
+            let result = match message {
+                Message::Play => stream.set_active(true),
+                Message::Pause => stream.set_active(false),
+                Message::Terminate => {
+                    if let Some(mainloop) = weak_mainloop.upgrade() {
+                        mainloop.quit();
+                    }
+                    Ok(())
+                }
+            };
+            if let Err(err) = result {
+                let err: BackendSpecificError = err.into();
+                (error_callback.borrow_mut())(err.into());
+            }
+        })
+    };
+
+    let _ = ready.send(Ok(()));
+    mainloop.run();
+    Ok(())
+}
+
+fn stream_properties(params: &StreamParams) -> Properties {
+    let mut properties = Properties::new();
+    properties.insert(*pw::keys::MEDIA_TYPE, "Audio");
+    let category = match params.device_type {
+        DeviceType::Sink => "Playback",
+        DeviceType::Source => "Capture",
+    };
+    properties.insert(*pw::keys::MEDIA_CATEGORY, category);
+    properties.insert(*pw::keys::TARGET_OBJECT, params.node_name.as_str());
+    if let Some(quantum) = params.quantum {
+        let latency = format!("{}/{}", quantum, params.sample_rate.0);
+        properties.insert(*pw::keys::NODE_LATENCY, latency);
+    }
+    properties
+}
+
+fn spa_format(sample_format: SampleFormat) -> AudioFormat {
+    let little_endian = cfg!(target_endian = "little");
+    match sample_format {
+        SampleFormat::I8 => AudioFormat::S8,
+        SampleFormat::U8 => AudioFormat::U8,
+        SampleFormat::I16 if little_endian => AudioFormat::S16LE,
+        SampleFormat::I16 => AudioFormat::S16BE,
+        SampleFormat::U16 if little_endian => AudioFormat::U16LE,
+        SampleFormat::U16 => AudioFormat::U16BE,
+        SampleFormat::I24 if little_endian => AudioFormat::S24_32LE,
+        SampleFormat::I24 => AudioFormat::S24_32BE,
+        SampleFormat::I24Packed if little_endian => AudioFormat::S24LE,
+        SampleFormat::I24Packed => AudioFormat::S24BE,
+        SampleFormat::I32 if little_endian => AudioFormat::S32LE,
+        SampleFormat::I32 => AudioFormat::S32BE,
+        SampleFormat::U32 if little_endian => AudioFormat::U32LE,
+        SampleFormat::U32 => AudioFormat::U32BE,
+        SampleFormat::F32 if little_endian => AudioFormat::F32LE,
+        SampleFormat::F32 => AudioFormat::F32BE,
+        SampleFormat::F64 if little_endian => AudioFormat::F64LE,
+        SampleFormat::F64 => AudioFormat::F64BE,
+    }
+}
+
+fn audio_info(params: &StreamParams) -> AudioInfoRaw {
+    let mut info = AudioInfoRaw::new();
+    info.set_format(spa_format(params.sample_format));
+    info.set_rate(params.sample_rate.0);
+    info.set_channels(params.channels as u32);
+    // Without positions the channels would not be mixed into the channels of the node.
+    let mut position = [0; 64];
+    match params.channels {
+        1 => position[0] = spa::sys::SPA_AUDIO_CHANNEL_MONO,
+        2 => {
+            position[0] = spa::sys::SPA_AUDIO_CHANNEL_FL;
+            position[1] = spa::sys::SPA_AUDIO_CHANNEL_FR;
+        }
+        _ => {
+            for (i, position) in position.iter_mut().enumerate() {
+                *position = spa::sys::SPA_AUDIO_CHANNEL_AUX0 + i as u32;
+            }
+        }
+    }
+    info.set_position(position);
+    info
+}
+
+// The `EnumFormat` parameter offering exactly the format of the stream.
+fn format_param(params: &StreamParams) -> Result<Vec<u8>, BackendSpecificError> {
+    let object = Object {
+        type_: SpaTypes::ObjectParamFormat.as_raw(),
+        id: ParamType::EnumFormat.as_raw(),
+        properties: audio_info(params).into(),
+    };
+    PodSerializer::serialize(Cursor::new(Vec::new()), &Value::Object(object))
+        .map(|(cursor, _)| cursor.into_inner())
+        .map_err(|_| BackendSpecificError {
+            description: "failed to serialize the stream format".to_string(),
+        })
+}
+
+// Describes how the negotiated format differs from the requested one, if it does.
+fn format_change(params: &StreamParams, param: &Pod) -> Option<StreamError> {
+    let mut info = AudioInfoRaw::new();
+    info.parse(param).ok()?;
+    if info.format() == spa_format(params.sample_format)
+        && info.rate() == params.sample_rate.0
+        && info.channels() == params.channels as u32
+    {
+        return None;
+    }
+    let description = format!(
+        "the stream format changed to {:?} with {} channels at {} Hz",
+        info.format(),
+        info.channels(),
+        info.rate(),
+    );
+    Some(BackendSpecificError { description }.into())
+}
+
+// Hands the next buffer of the stream to the data callback.
+fn process(
+    stream: &StreamRef,
+    data_callback: &mut DataCallback,
+    params: &StreamParams,
+    creation_instant: Instant,
+) {
+    let mut buffer = match stream.dequeue_buffer() {
+        Some(buffer) => buffer,
+        None => return,
+    };
+    let requested = buffer.requested() as usize;
+    let datas = buffer.datas_mut();
+    let spa_data = match datas.first_mut() {
+        Some(spa_data) => spa_data,
+        None => return,
+    };
+    let frame_size = params.frame_size();
+    let sample_format = params.sample_format;
+
+    match data_callback {
+        DataCallback::Input(data_callback) => {
+            let offset = spa_data.chunk().offset() as usize;
+            let size = spa_data.chunk().size() as usize;
+            let bytes = match spa_data.data() {
+                Some(bytes) => bytes,
+                None => return,
+            };
+            let start = offset.min(bytes.len());
+            let end = (start + size).min(bytes.len());
+            let bytes = &mut bytes[start..end];
+            let frames = bytes.len() / frame_size;
+            let len = bytes.len() / sample_format.sample_size();
+            let data = bytes.as_mut_ptr() as *mut ();
+            let data = unsafe { Data::from_parts(data, len, sample_format) };
+            let callback = stream_instant(creation_instant);
+            let delay = frames_to_duration(frames, params.sample_rate);
+            let capture = callback
+                .sub(delay)
+                .expect("`capture` is earlier than representation supported by `StreamInstant`");
+            let timestamp = InputStreamTimestamp { callback, capture };
+            data_callback(&data, &InputCallbackInfo::new(timestamp));
+        }
+        DataCallback::Output(data_callback) => {
+            let bytes = match spa_data.data() {
+                Some(bytes) => bytes,
+                None => return,
+            };
+            let mut frames = bytes.len() / frame_size;
+            if requested > 0 {
+                frames = frames.min(requested);
+            }
+            let bytes = &mut bytes[..frames * frame_size];
+            let len = bytes.len() / sample_format.sample_size();
+            let data = bytes.as_mut_ptr() as *mut ();
+            let mut data = unsafe { Data::from_parts(data, len, sample_format) };
+            let callback = stream_instant(creation_instant);
+            let delay = frames_to_duration(frames, params.sample_rate);
+            let playback = callback
+                .add(delay)
+                .expect("`playback` occurs beyond representation supported by `StreamInstant`");
+            let timestamp = OutputStreamTimestamp { callback, playback };
+            data_callback(&mut data, &OutputCallbackInfo::new(timestamp));
+
+            let chunk = spa_data.chunk_mut();
+            *chunk.offset_mut() = 0;
+            *chunk.stride_mut() = frame_size as i32;
+            *chunk.size_mut() = (frames * frame_size) as u32;
+        }
+    }
+}
+
+// Use the elapsed duration since the start of the stream.
+//
+// This ensures positive values that are compatible with our `StreamInstant` representation.
+fn stream_instant(creation_instant: Instant) -> StreamInstant {
+    let duration = creation_instant.elapsed();
+    StreamInstant::from_nanos_i128(duration.as_nanos() as i128)
+        .expect("stream duration has exceeded `StreamInstant` representation")
+}
+
+// The graph processes one quantum ahead of the device, so the delay is estimated to be the duration
+// of the buffer.
+fn frames_to_duration(frames: usize, rate: SampleRate) -> Duration {
+    let secsf = frames as f64 / rate.0 as f64;
+    let secs = secsf as u64;
+    let nanos = ((secsf - secs as f64) * 1_000_000_000.0) as u32;
+    Duration::new(secs, nanos)
+}
+
+impl StreamTrait for Stream {
+    fn play(&self) -> Result<(), PlayStreamError> {
+        self.sender
+            .send(Message::Play)
+            .map_err(|_| PlayStreamError::DeviceNotAvailable)
+    }
+
+    fn pause(&self) -> Result<(), PauseStreamError> {
+        self.sender
+            .send(Message::Pause)
+            .map_err(|_| PauseStreamError::DeviceNotAvailable)
+    }
+}
+
+impl Drop for Stream {
+    fn drop(&mut self) {
+        let _ = self.sender.send(Message::Terminate);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
@@ -0,0 +1,272 @@
+use super::pulse::context::introspect::{SinkInfo, SourceInfo};
+use super::pulse::def::BufferAttr;
+use super::pulse::sample::Spec;
+use super::stream::Stream;
+use super::{
+    pulse_format, SupportedInputConfigs, SupportedOutputConfigs, SUPPORTED_SAMPLE_FORMATS,
+};
+use crate::traits::DeviceTrait;
+use crate::{
+    BuildStreamError, Data, DefaultStreamConfigError, DeviceNameError, InputCallbackInfo,
+    OutputCallbackInfo, SampleFormat, SampleRate, StreamConfig, StreamError, SupportedBufferSize,
+    SupportedStreamConfig, SupportedStreamConfigRange, SupportedStreamConfigsError,
+};
+use std::hash::{Hash, Hasher};
+
+/// The sample format offered by default, as it is the one PulseAudio mixes in.
+const DEFAULT_SAMPLE_FORMAT: SampleFormat = SampleFormat::F32;
+
+/// Whether a device is a sink, used for output, or a source, used for input.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DeviceType {
+    Sink,
+    Source,
+}
+
+#[derive(Clone, Debug)]
+pub struct Device {
+    /// The name by which PulseAudio identifies the sink or source.
+    pa_name: String,
+    /// The human readable description of the sink or source.
+    description: String,
+    device_type: DeviceType,
+    channels: u16,
+    sample_rate: SampleRate,
+}
+
+impl Device {
+    pub(super) fn from_sink(info: &SinkInfo) -> Self {
+        Device::new(
+            info.name.as_deref(),
+            info.description.as_deref(),
+            DeviceType::Sink,
+            &info.sample_spec,
+        )
+    }
+
+    pub(super) fn from_source(info: &SourceInfo) -> Self {
+        Device::new(
+            info.name.as_deref(),
+            info.description.as_deref(),
+            DeviceType::Source,
+            &info.sample_spec,
+        )
+    }
+
+    fn new(
+        pa_name: Option<&str>,
+        description: Option<&str>,
+        device_type: DeviceType,
+        spec: &Spec,
+    ) -> Self {
+        let pa_name = pa_name.unwrap_or_default().to_string();
+        let description = description.map_or_else(|| pa_name.clone(), str::to_string);
+        Device {
+            pa_name,
+            description,
+            device_type,
+            channels: spec.channels as u16,
+            sample_rate: SampleRate(spec.rate),
+        }
+    }
+
+    pub(super) fn pa_name(&self) -> &str {
+        &self.pa_name
+    }
+
+    pub(super) fn device_type(&self) -> DeviceType {
+        self.device_type
+    }
+
+    fn default_config(&self) -> SupportedStreamConfig {
+        SupportedStreamConfig {
+            channels: self.channels,
+            sample_rate: self.sample_rate,
+            buffer_size: SupportedBufferSize::Unknown,
+            sample_format: DEFAULT_SAMPLE_FORMAT,
+            channel_map: None,
+        }
+    }
+
+    fn supported_configs(&self) -> Vec<SupportedStreamConfigRange> {
+        let mut supported_configs = vec![];
+        for channels in 1..=self.channels {
+            for &sample_format in SUPPORTED_SAMPLE_FORMATS.iter() {
+                supported_configs.push(SupportedStreamConfigRange {
+                    channels,
+                    min_sample_rate: SampleRate(1),
+                    max_sample_rate: SampleRate(Spec::RATE_MAX),
+                    buffer_size: SupportedBufferSize::Unknown,
+                    sample_format,
+                });
+            }
+        }
+        supported_configs
+    }
+
+    /// The sample specification and buffer attributes for a stream on this device.
+    fn stream_parameters(
+        &self,
+        conf: &StreamConfig,
+        sample_format: SampleFormat,
+        device_type: DeviceType,
+    ) -> Result<(Spec, Option<BufferAttr>), BuildStreamError> {
+        if self.device_type != device_type || conf.channels > self.channels {
+            return Err(BuildStreamError::StreamConfigNotSupported);
+        }
+        let format =
+            pulse_format(sample_format).ok_or(BuildStreamError::StreamConfigNotSupported)?;
+        let spec = Spec {
+            format,
+            rate: conf.sample_rate.0,
+            channels: conf.channels as u8,
+        };
+        if !spec.is_valid() {
+            return Err(BuildStreamError::StreamConfigNotSupported);
+        }
+        // The target length of playback buffers determines the latency, while capture is
+        // delivered in fragments of the requested size.
+        let frames = match device_type {
+            DeviceType::Sink => conf.buffer_size.buffer_frames(conf.sample_rate),
+            DeviceType::Source => conf.buffer_size.period_frames(conf.sample_rate),
+        };
+        let buffer_attr = match frames {
+            None => None,
+            Some(0) => return Err(BuildStreamError::StreamConfigNotSupported),
+            Some(frames) => {
+                let bytes = (frames as usize)
+                    .checked_mul(spec.frame_size())
+                    .filter(|&bytes| bytes < u32::MAX as usize)
+                    .ok_or(BuildStreamError::StreamConfigNotSupported)?
+                    as u32;
+                Some(buffer_attr(bytes, device_type))
+            }
+        };
+        Ok((spec, buffer_attr))
+    }
+}
+
+/// Buffer attributes requesting `bytes` of latency, leaving everything else to the server.
+///
+/// For playback this is the target length of the server side buffer, for recording it is the
+/// size of the fragments in which the server delivers data.
+fn buffer_attr(bytes: u32, device_type: DeviceType) -> BufferAttr {
+    let (tlength, fragsize) = match device_type {
+        DeviceType::Sink => (bytes, u32::MAX),
+        DeviceType::Source => (u32::MAX, bytes),
+    };
+    BufferAttr {
+        maxlength: u32::MAX,
+        tlength,
+        prebuf: u32::MAX,
+        minreq: u32::MAX,
+        fragsize,
+    }
+}
+
+impl DeviceTrait for Device {
+    type SupportedInputConfigs = SupportedInputConfigs;
+    type SupportedOutputConfigs = SupportedOutputConfigs;
+    type Stream = Stream;
+
+    fn name(&self) -> Result<String, DeviceNameError> {
+        Ok(self.description.clone())
+    }
+
+    fn id(&self) -> Result<String, DeviceNameError> {
+        Ok(self.pa_name.clone())
+    }
+
+    fn supported_input_configs(
+        &self,
+    ) -> Result<Self::SupportedInputConfigs, SupportedStreamConfigsError> {
+        match self.device_type {
+            DeviceType::Source => Ok(self.supported_configs().into_iter()),
+            DeviceType::Sink => Ok(vec![].into_iter()),
+        }
+    }
+
+    fn supported_output_configs(
+        &self,
+    ) -> Result<Self::SupportedOutputConfigs, SupportedStreamConfigsError> {
+        match self.device_type {
+            DeviceType::Sink => Ok(self.supported_configs().into_iter()),
+            DeviceType::Source => Ok(vec![].into_iter()),
+        }
+    }
+
+    /// Returns the channel count and sample rate of the source, with samples in `f32`.
+    fn default_input_config(&self) -> Result<SupportedStreamConfig, DefaultStreamConfigError> {
+        match self.device_type {
+            DeviceType::Source => Ok(self.default_config()),
+            DeviceType::Sink => Err(DefaultStreamConfigError::StreamTypeNotSupported),
+        }
+    }
+
+    /// Returns the channel count and sample rate of the sink, with samples in `f32`.
+    fn default_output_config(&self) -> Result<SupportedStreamConfig, DefaultStreamConfigError> {
+        match self.device_type {
+            DeviceType::Sink => Ok(self.default_config()),
+            DeviceType::Source => Err(DefaultStreamConfigError::StreamTypeNotSupported),
+        }
+    }
+
+    fn build_input_stream_raw<D, E>(
+        &self,
+        conf: &StreamConfig,
+        sample_format: SampleFormat,
+        data_callback: D,
+        error_callback: E,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        D: FnMut(&Data, &InputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        let (spec, buffer_attr) =
+            self.stream_parameters(conf, sample_format, DeviceType::Source)?;
+        Stream::new_input(
+            &self.pa_name,
+            spec,
+            buffer_attr,
+            sample_format,
+            data_callback,
+            error_callback,
+        )
+    }
+
+    fn build_output_stream_raw<D, E>(
+        &self,
+        conf: &StreamConfig,
+        sample_format: SampleFormat,
+        data_callback: D,
+        error_callback: E,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        D: FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        let (spec, buffer_attr) = self.stream_parameters(conf, sample_format, DeviceType::Sink)?;
+        Stream::new_output(
+            &self.pa_name,
+            spec,
+            buffer_attr,
+            sample_format,
+            data_callback,
+            error_callback,
+        )
+    }
+}
+
+impl PartialEq for Device {
+    fn eq(&self, other: &Self) -> bool {
+        self.device_type == other.device_type && self.pa_name == other.pa_name
+    }
+}
+
+impl Eq for Device {}
+
+impl Hash for Device {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.pa_name.hash(state);
+    }
+}
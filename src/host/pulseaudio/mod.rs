@@ -0,0 +1,323 @@
+extern crate libpulse_binding as pulse;
+
+use self::pulse::callbacks::ListResult;
+use self::pulse::context::{self, Context};
+use self::pulse::error::PAErr;
+use self::pulse::mainloop::threaded::Mainloop;
+use self::pulse::operation::{self, Operation};
+use self::pulse::sample::Format;
+use crate::traits::HostTrait;
+use crate::{
+    BackendSpecificError, BuildStreamError, DevicesError, SampleFormat, StreamError,
+    SupportedStreamConfigRange,
+};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+mod device;
+pub use self::device::Device;
+pub use self::stream::Stream;
+mod stream;
+
+use self::device::DeviceType;
+
+/// The name under which cpal's connections show up in PulseAudio.
+const CLIENT_NAME: &str = "cpal";
+
+pub type SupportedInputConfigs = std::vec::IntoIter<SupportedStreamConfigRange>;
+pub type SupportedOutputConfigs = std::vec::IntoIter<SupportedStreamConfigRange>;
+pub type Devices = std::vec::IntoIter<Device>;
+
+/// The PulseAudio Host type.
+///
+/// Every sink and every source of the server is exposed as a device. The server converts between
+/// the stream configuration and the configuration of the device, so any sample rate and channel
+/// count up to the channels of the device may be requested.
+#[derive(Debug)]
+pub struct Host;
+
+impl Host {
+    pub fn new() -> Result<Self, crate::HostUnavailable> {
+        Connection::new()
+            .map(|_| Host)
+            .map_err(|_| crate::HostUnavailable)
+    }
+}
+
+impl HostTrait for Host {
+    type Devices = Devices;
+    type Device = Device;
+
+    fn is_available() -> bool {
+        Connection::new().is_ok()
+    }
+
+    fn devices(&self) -> Result<Self::Devices, DevicesError> {
+        let connection = Connection::new()?;
+        Ok(connection.devices()?.into_iter())
+    }
+
+    fn default_input_device(&self) -> Option<Self::Device> {
+        default_device(DeviceType::Source)
+    }
+
+    fn default_output_device(&self) -> Option<Self::Device> {
+        default_device(DeviceType::Sink)
+    }
+}
+
+fn default_device(device_type: DeviceType) -> Option<Device> {
+    let connection = Connection::new().ok()?;
+    let (default_sink, default_source) = connection.default_device_names().ok()?;
+    let default_name = match device_type {
+        DeviceType::Sink => default_sink?,
+        DeviceType::Source => default_source?,
+    };
+    connection
+        .devices()
+        .ok()?
+        .into_iter()
+        .find(|device| device.device_type() == device_type && device.pa_name() == default_name)
+}
+
+/// The PulseAudio sample format used for the given `SampleFormat`, if there is one.
+fn pulse_format(sample_format: SampleFormat) -> Option<Format> {
+    match sample_format {
+        SampleFormat::U8 => Some(Format::U8),
+        SampleFormat::I16 => Some(Format::S16NE),
+        SampleFormat::I24 => Some(Format::S24_32NE),
+        SampleFormat::I24Packed => Some(Format::S24NE),
+        SampleFormat::I32 => Some(Format::S32NE),
+        SampleFormat::F32 => Some(Format::FLOAT32NE),
+        _ => None,
+    }
+}
+
+/// The sample formats that PulseAudio can convert to and from the format of any device.
+const SUPPORTED_SAMPLE_FORMATS: [SampleFormat; 6] = [
+    SampleFormat::U8,
+    SampleFormat::I16,
+    SampleFormat::I24,
+    SampleFormat::I24Packed,
+    SampleFormat::I32,
+    SampleFormat::F32,
+];
+
+/// A connection to the PulseAudio server, driven by its own threaded main loop.
+///
+/// The main loop, the context and everything created from them may only be used while the main
+/// loop is locked. The callbacks registered with PulseAudio are run on the main loop thread, which
+/// holds the lock while running them.
+pub(crate) struct Connection {
+    mainloop: Rc<RefCell<Mainloop>>,
+    context: Rc<RefCell<Context>>,
+}
+
+impl Connection {
+    /// Connects to the default server and blocks until the connection is ready.
+    fn new() -> Result<Self, BackendSpecificError> {
+        let mainloop = Mainloop::new().ok_or_else(|| BackendSpecificError {
+            description: "failed to create the PulseAudio main loop".to_string(),
+        })?;
+        let mainloop = Rc::new(RefCell::new(mainloop));
+        let context =
+            Context::new(&*mainloop.borrow(), CLIENT_NAME).ok_or_else(|| BackendSpecificError {
+                description: "failed to create the PulseAudio context".to_string(),
+            })?;
+        let connection = Connection {
+            context: Rc::new(RefCell::new(context)),
+            mainloop,
+        };
+
+        // Wake up anyone waiting on the main loop whenever the state of the context changes, so
+        // that pending operations are not waited on forever if the server goes away.
+        let signal = connection.signaller();
+        connection
+            .context
+            .borrow_mut()
+            .set_state_callback(Some(Box::new(signal)));
+        connection
+            .context
+            .borrow_mut()
+            .connect(None, context::FlagSet::NOFLAGS, None)?;
+
+        connection.lock();
+        let result = connection.start_and_wait_until_ready();
+        connection.unlock();
+        result.map(|()| connection)
+    }
+
+    // Must be called with the main loop locked.
+    fn start_and_wait_until_ready(&self) -> Result<(), BackendSpecificError> {
+        self.mainloop.borrow_mut().start()?;
+        loop {
+            match self.context.borrow().get_state() {
+                context::State::Ready => return Ok(()),
+                context::State::Failed | context::State::Terminated => {
+                    let err = self.context.borrow().errno();
+                    return Err(err.into());
+                }
+                _ => (),
+            }
+            self.wait();
+        }
+    }
+
+    fn lock(&self) {
+        self.mainloop.borrow_mut().lock();
+    }
+
+    fn unlock(&self) {
+        self.mainloop.borrow_mut().unlock();
+    }
+
+    // Releases the lock until the main loop thread signals. Must be called with the main loop
+    // locked and without any `RefCell` borrows that callbacks could need.
+    fn wait(&self) {
+        // Borrowing the `RefCell` here would make any `signal` in the meantime panic.
+        unsafe { (*self.mainloop.as_ptr()).wait() };
+    }
+
+    /// A callback that wakes up the thread waiting on the main loop.
+    fn signaller(&self) -> impl FnMut() + 'static {
+        let mainloop = Rc::downgrade(&self.mainloop);
+        move || {
+            if let Some(mainloop) = mainloop.upgrade() {
+                unsafe { (*mainloop.as_ptr()).signal(false) };
+            }
+        }
+    }
+
+    fn is_ready(&self) -> bool {
+        self.context.borrow().get_state() == context::State::Ready
+    }
+
+    // Waits for an operation whose callback signals the main loop. Must be called with the main
+    // loop locked.
+    fn wait_for_operation<C: ?Sized>(&self, operation: Operation<C>) {
+        while operation.get_state() == operation::State::Running {
+            self.wait();
+        }
+    }
+
+    /// Lists all sinks and sources.
+    fn devices(&self) -> Result<Vec<Device>, BackendSpecificError> {
+        let devices = Rc::new(RefCell::new(Vec::new()));
+        let failed = Rc::new(RefCell::new(false));
+
+        self.lock();
+        if !self.is_ready() {
+            self.unlock();
+            return Err(self.context.borrow().errno().into());
+        }
+        let introspector = self.context.borrow().introspect();
+
+        let operation = {
+            let (devices, failed, mut signal) = (devices.clone(), failed.clone(), self.signaller());
+            introspector.get_sink_info_list(move |result| match result {
+                ListResult::Item(info) => devices.borrow_mut().push(Device::from_sink(info)),
+                ListResult::End => signal(),
+                ListResult::Error => {
+                    *failed.borrow_mut() = true;
+                    signal();
+                }
+            })
+        };
+        self.wait_for_operation(operation);
+
+        let operation = {
+            let (devices, failed, mut signal) = (devices.clone(), failed.clone(), self.signaller());
+            introspector.get_source_info_list(move |result| match result {
+                ListResult::Item(info) => devices.borrow_mut().push(Device::from_source(info)),
+                ListResult::End => signal(),
+                ListResult::Error => {
+                    *failed.borrow_mut() = true;
+                    signal();
+                }
+            })
+        };
+        self.wait_for_operation(operation);
+        self.unlock();
+
+        if *failed.borrow() || !self.is_ready() {
+            return Err(self.context.borrow().errno().into());
+        }
+        let devices = devices.borrow().clone();
+        Ok(devices)
+    }
+
+    /// The names of the default sink and the default source.
+    fn default_device_names(
+        &self,
+    ) -> Result<(Option<String>, Option<String>), BackendSpecificError> {
+        let names = Rc::new(RefCell::new((None, None)));
+
+        self.lock();
+        if !self.is_ready() {
+            self.unlock();
+            return Err(self.context.borrow().errno().into());
+        }
+        let operation = {
+            let (names, mut signal) = (names.clone(), self.signaller());
+            self.context
+                .borrow()
+                .introspect()
+                .get_server_info(move |info| {
+                    let sink = info.default_sink_name.as_ref().map(|name| name.to_string());
+                    let source = info
+                        .default_source_name
+                        .as_ref()
+                        .map(|name| name.to_string());
+                    *names.borrow_mut() = (sink, source);
+                    signal();
+                })
+        };
+        self.wait_for_operation(operation);
+        self.unlock();
+
+        let names = names.borrow().clone();
+        Ok(names)
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        self.lock();
+        {
+            let mut context = self.context.borrow_mut();
+            context.set_state_callback(None);
+            context.disconnect();
+        }
+        self.unlock();
+        self.mainloop.borrow_mut().stop();
+    }
+}
+
+impl From<PAErr> for BackendSpecificError {
+    fn from(err: PAErr) -> Self {
+        BackendSpecificError {
+            description: format!("{}", err),
+        }
+    }
+}
+
+impl From<PAErr> for DevicesError {
+    fn from(err: PAErr) -> Self {
+        let err: BackendSpecificError = err.into();
+        err.into()
+    }
+}
+
+impl From<PAErr> for BuildStreamError {
+    fn from(err: PAErr) -> Self {
+        let err: BackendSpecificError = err.into();
+        err.into()
+    }
+}
+
+impl From<PAErr> for StreamError {
+    fn from(err: PAErr) -> Self {
+        let err: BackendSpecificError = err.into();
+        err.into()
+    }
+}
@@ -0,0 +1,383 @@
+use super::device::DeviceType;
+use super::pulse::context;
+use super::pulse::def::BufferAttr;
+use super::pulse::sample::Spec;
+use super::pulse::stream::{self as pa_stream, FlagSet, PeekResult, SeekMode};
+use super::{Connection, CLIENT_NAME};
+use crate::traits::StreamTrait;
+use crate::{
+    BuildStreamError, Data, InputCallbackInfo, InputStreamTimestamp, OutputCallbackInfo,
+    OutputStreamTimestamp, PauseStreamError, PlayStreamError, SampleFormat, StreamError,
+    StreamInstant,
+};
+use std::cell::{Cell, RefCell};
+use std::rc::{Rc, Weak};
+use std::time::{Duration, Instant};
+
+type PaStream = pa_stream::Stream;
+type ErrorCallback = Rc<RefCell<dyn FnMut(StreamError)>>;
+type RequestCallback = Box<dyn FnMut(usize)>;
+
+pub struct Stream {
+    // Dropped before `stream`, so that the main loop thread has stopped by the time the stream is
+    // released.
+    connection: Connection,
+    stream: Rc<RefCell<PaStream>>,
+}
+
+impl Stream {
+    pub(super) fn new_input<D, E>(
+        device: &str,
+        spec: Spec,
+        buffer_attr: Option<BufferAttr>,
+        sample_format: SampleFormat,
+        mut data_callback: D,
+        error_callback: E,
+    ) -> Result<Stream, BuildStreamError>
+    where
+        D: FnMut(&Data, &InputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        let error_callback: ErrorCallback = Rc::new(RefCell::new(error_callback));
+        let creation_instant = Instant::now();
+        let read_callback = |stream: Weak<RefCell<PaStream>>| -> RequestCallback {
+            let error_callback = error_callback.clone();
+            Box::new(move |_| {
+                let stream = match stream.upgrade() {
+                    Some(stream) => stream,
+                    None => return,
+                };
+                let mut stream = stream.borrow_mut();
+                loop {
+                    match stream.peek() {
+                        Ok(PeekResult::Empty) => return,
+                        Ok(PeekResult::Hole(_)) => (),
+                        Ok(PeekResult::Data(buffer)) => {
+                            let data = buffer.as_ptr() as *mut ();
+                            let len = buffer.len() / sample_format.sample_size();
+                            let data = unsafe { Data::from_parts(data, len, sample_format) };
+                            let callback = stream_instant(creation_instant);
+                            let delay = stream_delay(&mut stream, DeviceType::Source);
+                            let capture = callback.sub(delay).expect(
+                                "`capture` is earlier than representation supported by `StreamInstant`",
+                            );
+                            let timestamp = InputStreamTimestamp { callback, capture };
+                            data_callback(&data, &InputCallbackInfo::new(timestamp));
+                        }
+                        Err(err) => {
+                            (error_callback.borrow_mut())(err.into());
+                            return;
+                        }
+                    }
+                    if let Err(err) = stream.discard() {
+                        (error_callback.borrow_mut())(err.into());
+                        return;
+                    }
+                }
+            })
+        };
+        Stream::new(
+            device,
+            spec,
+            buffer_attr,
+            DeviceType::Source,
+            error_callback.clone(),
+            read_callback,
+        )
+    }
+
+    pub(super) fn new_output<D, E>(
+        device: &str,
+        spec: Spec,
+        buffer_attr: Option<BufferAttr>,
+        sample_format: SampleFormat,
+        mut data_callback: D,
+        error_callback: E,
+    ) -> Result<Stream, BuildStreamError>
+    where
+        D: FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        let error_callback: ErrorCallback = Rc::new(RefCell::new(error_callback));
+        let creation_instant = Instant::now();
+        let frame_size = spec.frame_size();
+        let write_callback = |stream: Weak<RefCell<PaStream>>| -> RequestCallback {
+            let error_callback = error_callback.clone();
+            Box::new(move |requested_bytes| {
+                let stream = match stream.upgrade() {
+                    Some(stream) => stream,
+                    None => return,
+                };
+                let mut stream = stream.borrow_mut();
+                let buffer = match stream.begin_write(Some(requested_bytes)) {
+                    Ok(Some(buffer)) => buffer,
+                    Ok(None) => return,
+                    Err(err) => {
+                        (error_callback.borrow_mut())(err.into());
+                        return;
+                    }
+                };
+                // Only whole frames may be written.
+                let len = buffer.len() - buffer.len() % frame_size;
+                let buffer = &mut buffer[..len];
+                let data = buffer.as_mut_ptr() as *mut ();
+                let len = buffer.len() / sample_format.sample_size();
+                let mut data = unsafe { Data::from_parts(data, len, sample_format) };
+                let callback = stream_instant(creation_instant);
+                let delay = stream_delay(&mut stream, DeviceType::Sink);
+                let playback = callback
+                    .add(delay)
+                    .expect("`playback` occurs beyond representation supported by `StreamInstant`");
+                let timestamp = OutputStreamTimestamp { callback, playback };
+                data_callback(&mut data, &OutputCallbackInfo::new(timestamp));
+                if let Err(err) = stream.write(buffer, None, 0, SeekMode::Relative) {
+                    (error_callback.borrow_mut())(err.into());
+                }
+            })
+        };
+        Stream::new(
+            device,
+            spec,
+            buffer_attr,
+            DeviceType::Sink,
+            error_callback.clone(),
+            write_callback,
+        )
+    }
+
+    fn new<F>(
+        device: &str,
+        spec: Spec,
+        buffer_attr: Option<BufferAttr>,
+        device_type: DeviceType,
+        error_callback: ErrorCallback,
+        request_callback: F,
+    ) -> Result<Stream, BuildStreamError>
+    where
+        F: FnOnce(Weak<RefCell<PaStream>>) -> RequestCallback,
+    {
+        let connection = Connection::new()?;
+        connection.lock();
+        let result = connect(
+            &connection,
+            device,
+            spec,
+            buffer_attr,
+            device_type,
+            error_callback,
+            request_callback,
+        );
+        connection.unlock();
+        let stream = result?;
+        Ok(Stream { connection, stream })
+    }
+
+    // Returns `false` if the stream is no longer connected to the server.
+    fn set_corked(&self, corked: bool) -> bool {
+        self.connection.lock();
+        let ready = self.stream.borrow().get_state() == pa_stream::State::Ready;
+        if ready {
+            let mut signal = self.connection.signaller();
+            let operation = self
+                .stream
+                .borrow_mut()
+                .set_corked_state(corked, Some(Box::new(move |_| signal())));
+            self.connection.wait_for_operation(operation);
+        }
+        self.connection.unlock();
+        ready
+    }
+}
+
+// Creates a stream on the device and blocks until it is ready. Must be called with the main loop
+// locked.
+fn connect<F>(
+    connection: &Connection,
+    device: &str,
+    spec: Spec,
+    buffer_attr: Option<BufferAttr>,
+    device_type: DeviceType,
+    error_callback: ErrorCallback,
+    request_callback: F,
+) -> Result<Rc<RefCell<PaStream>>, BuildStreamError>
+where
+    F: FnOnce(Weak<RefCell<PaStream>>) -> RequestCallback,
+{
+    let stream = PaStream::new(
+        &mut connection.context.borrow_mut(),
+        CLIENT_NAME,
+        &spec,
+        None,
+    )
+    .ok_or_else(|| connection.context.borrow().errno())?;
+    let stream = Rc::new(RefCell::new(stream));
+
+    // Errors are only reported through the error callback once the stream has been built, as any
+    // earlier failure is returned from here instead.
+    let established = Rc::new(Cell::new(false));
+    let report_disconnect = {
+        let (established, error_callback) = (established.clone(), error_callback.clone());
+        move || {
+            if established.get() {
+                (error_callback.borrow_mut())(StreamError::DeviceNotAvailable);
+            }
+        }
+    };
+
+    {
+        let mut pa_stream = stream.borrow_mut();
+        let state_callback = {
+            let (stream, report_disconnect) = (Rc::downgrade(&stream), report_disconnect.clone());
+            let mut signal = connection.signaller();
+            move || {
+                signal();
+                let stream = match stream.upgrade() {
+                    Some(stream) => stream,
+                    None => return,
+                };
+                let state = unsafe { (*stream.as_ptr()).get_state() };
+                if let pa_stream::State::Failed | pa_stream::State::Terminated = state {
+                    report_disconnect();
+                }
+            }
+        };
+        pa_stream.set_state_callback(Some(Box::new(state_callback)));
+        let request_callback = request_callback(Rc::downgrade(&stream));
+        let mut flags = FlagSet::INTERPOLATE_TIMING | FlagSet::AUTO_TIMING_UPDATE;
+        if buffer_attr.is_some() {
+            flags |= FlagSet::ADJUST_LATENCY;
+        }
+        match device_type {
+            DeviceType::Sink => {
+                let error_callback = error_callback.clone();
+                pa_stream.set_underflow_callback(Some(Box::new(move || {
+                    (error_callback.borrow_mut())(StreamError::BufferUnderrun {
+                        frames_lost: None,
+                    });
+                })));
+                pa_stream.set_write_callback(Some(request_callback));
+                pa_stream.connect_playback(
+                    Some(device),
+                    buffer_attr.as_ref(),
+                    flags,
+                    None,
+                    None,
+                )?;
+            }
+            DeviceType::Source => {
+                let error_callback = error_callback.clone();
+                pa_stream.set_overflow_callback(Some(Box::new(move || {
+                    (error_callback.borrow_mut())(StreamError::BufferOverrun { frames_lost: None });
+                })));
+                pa_stream.set_read_callback(Some(request_callback));
+                pa_stream.connect_record(Some(device), buffer_attr.as_ref(), flags)?;
+            }
+        }
+    }
+
+    loop {
+        let state = stream.borrow().get_state();
+        match state {
+            pa_stream::State::Ready => break,
+            pa_stream::State::Failed | pa_stream::State::Terminated => {
+                return Err(connection.context.borrow().errno().into());
+            }
+            _ => connection.wait(),
+        }
+    }
+
+    // From here on the loss of the server is reported as well.
+    let context_state_callback = {
+        let (context, report_disconnect) = (Rc::downgrade(&connection.context), report_disconnect);
+        let mut signal = connection.signaller();
+        move || {
+            signal();
+            let context = match context.upgrade() {
+                Some(context) => context,
+                None => return,
+            };
+            let state = unsafe { (*context.as_ptr()).get_state() };
+            if let context::State::Failed | context::State::Terminated = state {
+                report_disconnect();
+            }
+        }
+    };
+    connection
+        .context
+        .borrow_mut()
+        .set_state_callback(Some(Box::new(context_state_callback)));
+    established.set(true);
+
+    Ok(stream)
+}
+
+// Use the elapsed duration since the start of the stream.
+//
+// This ensures positive values that are compatible with our `StreamInstant` representation.
+fn stream_instant(creation_instant: Instant) -> StreamInstant {
+    let duration = creation_instant.elapsed();
+    StreamInstant::from_nanos_i128(duration.as_nanos() as i128)
+        .expect("stream duration has exceeded `StreamInstant` representation")
+}
+
+// The time between data being written and it being played back, or between data being captured
+// and it being read, according to the latest timing info of the stream.
+fn stream_delay(stream: &mut PaStream, device_type: DeviceType) -> Duration {
+    let spec = match stream.get_sample_spec() {
+        Some(spec) => *spec,
+        None => return Duration::default(),
+    };
+    let info = match stream.get_timing_info() {
+        Some(info) => info,
+        None => return Duration::default(),
+    };
+    let device_usec = match device_type {
+        DeviceType::Sink => info.sink_usec,
+        DeviceType::Source => info.source_usec,
+    };
+    let buffered_bytes = info.write_index.saturating_sub(info.read_index).max(0) as u64;
+    let buffered_usec = spec.bytes_to_usec(buffered_bytes);
+    Duration::from(device_usec)
+        + Duration::from(info.transport_usec)
+        + Duration::from(buffered_usec)
+}
+
+impl StreamTrait for Stream {
+    fn play(&self) -> Result<(), PlayStreamError> {
+        if self.set_corked(false) {
+            Ok(())
+        } else {
+            Err(PlayStreamError::DeviceNotAvailable)
+        }
+    }
+
+    fn pause(&self) -> Result<(), PauseStreamError> {
+        if self.set_corked(true) {
+            Ok(())
+        } else {
+            Err(PauseStreamError::DeviceNotAvailable)
+        }
+    }
+}
+
+impl Drop for Stream {
+    fn drop(&mut self) {
+        self.connection.lock();
+        {
+            let mut stream = self.stream.borrow_mut();
+            stream.set_state_callback(None);
+            stream.set_write_callback(None);
+            stream.set_read_callback(None);
+            stream.set_underflow_callback(None);
+            stream.set_overflow_callback(None);
+            if stream.get_state() == pa_stream::State::Ready {
+                let _ = stream.disconnect();
+            }
+        }
+        self.connection
+            .context
+            .borrow_mut()
+            .set_state_callback(None);
+        self.connection.unlock();
+    }
+}
@@ -0,0 +1,816 @@
+//! A host for testing code built on CPAL deterministically, without relying on real time passing.
+//!
+//! Streams created by this host never run on their own. Instead, a `StreamHandle` retrieved via
+//! `Stream::handle` lets the test advance the stream's clock frame by frame, which synchronously
+//! invokes the data callback on the calling thread.
+
+use crate::{
+    BuildStreamError, Data, DefaultStreamConfigError, DeviceNameError, DevicesError,
+    DuplexCallbackInfo, DuplexStreamTimestamp, FrameCount, InputCallbackInfo, InputStreamTimestamp,
+    OutputCallbackInfo, OutputStreamTimestamp, PauseStreamError, PlayStreamError, Sample,
+    SampleFormat, SampleRate, StreamConfig, StreamError, StreamInstant, SupportedStreamConfig,
+    SupportedStreamConfigRange, SupportedStreamConfigsError,
+};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use traits::{DeviceTrait, HostTrait, StreamTrait};
+
+pub use super::null::{SupportedInputConfigs, SupportedOutputConfigs};
+
+/// An iterator over the devices of a test `Host`.
+pub struct Devices(std::vec::IntoIter<Device>);
+
+/// A test device, backed by a virtual null host device.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Device(super::null::Device);
+
+/// The test host.
+pub struct Host {
+    devices: Vec<Device>,
+}
+
+/// A stream that only processes audio when driven via its `StreamHandle`.
+pub struct Stream {
+    handle: StreamHandle,
+}
+
+/// Drives a test `Stream` and inspects the audio it processes.
+///
+/// Handles may be cloned and sent to other threads. Every method is synchronous: the stream's
+/// callbacks are invoked on the calling thread before the method returns.
+#[derive(Clone)]
+pub struct StreamHandle {
+    inner: Arc<Mutex<StreamInner>>,
+}
+
+struct StreamInner {
+    channels: usize,
+    sample_rate: SampleRate,
+    sample_format: SampleFormat,
+    playing: bool,
+    disconnected: bool,
+    /// The number of frames processed so far, from which callback timestamps are derived.
+    frames_elapsed: u64,
+    /// Input bytes injected via `StreamHandle::push_input` that were not yet delivered.
+    input: VecDeque<u8>,
+    /// Output bytes produced by the data callback that were not yet taken.
+    output: Vec<u8>,
+    buffer: Vec<u8>,
+    /// The buffer of the input side of a duplex stream, whose `buffer` is the output side.
+    duplex_input_buffer: Vec<u8>,
+    /// `None` while the data callback is being invoked by `StreamHandle::advance`.
+    data_callback: Option<DataCallback>,
+    /// Errors reported while the error callback is being invoked, delivered once it returns.
+    pending_errors: VecDeque<StreamError>,
+    /// `None` while the error callback is being invoked by `StreamHandle::report`.
+    error_callback: Option<ErrorCallback>,
+}
+
+type ErrorCallback = Box<dyn FnMut(StreamError) + Send + 'static>;
+
+type InputDataCallback = Box<dyn FnMut(&Data, &InputCallbackInfo) + Send + 'static>;
+type OutputDataCallback = Box<dyn FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static>;
+type DuplexDataCallback = Box<dyn FnMut(&Data, &mut Data, &DuplexCallbackInfo) + Send + 'static>;
+
+enum DataCallback {
+    Input(InputDataCallback),
+    Output(OutputDataCallback),
+    /// A duplex stream with the given number of input channels. The stream's `channels` are the
+    /// output channels.
+    Duplex(usize, DuplexDataCallback),
+}
+
+impl Host {
+    /// A host with a single device named "test", supporting the same configurations as the
+    /// default null host device.
+    pub fn new() -> Result<Self, crate::HostUnavailable> {
+        let null = super::null::Device::default();
+        let device = Device::new(
+            "test",
+            null.supported_input_configs().unwrap().collect(),
+            null.supported_output_configs().unwrap().collect(),
+        );
+        Ok(Host::with_devices(vec![device]))
+    }
+
+    /// A host exposing the given devices.
+    ///
+    /// The first device supporting input is the default input device, and the first device
+    /// supporting output is the default output device.
+    pub fn with_devices(devices: Vec<Device>) -> Self {
+        Host { devices }
+    }
+}
+
+impl Device {
+    /// A test device with the given name and supported input and output configs.
+    pub fn new(
+        name: impl Into<String>,
+        input_configs: Vec<SupportedStreamConfigRange>,
+        output_configs: Vec<SupportedStreamConfigRange>,
+    ) -> Self {
+        Device(super::null::Device::new(
+            name,
+            input_configs,
+            output_configs,
+        ))
+    }
+}
+
+impl HostTrait for Host {
+    type Devices = Devices;
+    type Device = Device;
+
+    fn is_available() -> bool {
+        true
+    }
+
+    fn devices(&self) -> Result<Self::Devices, DevicesError> {
+        Ok(Devices(self.devices.clone().into_iter()))
+    }
+
+    fn default_input_device(&self) -> Option<Self::Device> {
+        self.devices
+            .iter()
+            .find(|device| device.default_input_config().is_ok())
+            .cloned()
+    }
+
+    fn default_output_device(&self) -> Option<Self::Device> {
+        self.devices
+            .iter()
+            .find(|device| device.default_output_config().is_ok())
+            .cloned()
+    }
+}
+
+impl Iterator for Devices {
+    type Item = Device;
+
+    fn next(&mut self) -> Option<Device> {
+        self.0.next()
+    }
+}
+
+impl DeviceTrait for Device {
+    type SupportedInputConfigs = SupportedInputConfigs;
+    type SupportedOutputConfigs = SupportedOutputConfigs;
+    type Stream = Stream;
+
+    fn name(&self) -> Result<String, DeviceNameError> {
+        self.0.name()
+    }
+
+    fn supported_input_configs(
+        &self,
+    ) -> Result<Self::SupportedInputConfigs, SupportedStreamConfigsError> {
+        self.0.supported_input_configs()
+    }
+
+    fn supported_output_configs(
+        &self,
+    ) -> Result<Self::SupportedOutputConfigs, SupportedStreamConfigsError> {
+        self.0.supported_output_configs()
+    }
+
+    fn default_input_config(&self) -> Result<SupportedStreamConfig, DefaultStreamConfigError> {
+        self.0.default_input_config()
+    }
+
+    fn default_output_config(&self) -> Result<SupportedStreamConfig, DefaultStreamConfigError> {
+        self.0.default_output_config()
+    }
+
+    fn build_input_stream_raw<D, E>(
+        &self,
+        config: &StreamConfig,
+        sample_format: SampleFormat,
+        data_callback: D,
+        error_callback: E,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        D: FnMut(&Data, &InputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        check_config(self.0.supported_input_configs(), config, sample_format)?;
+        Ok(Stream::new(
+            config,
+            sample_format,
+            DataCallback::Input(Box::new(data_callback)),
+            Box::new(error_callback),
+        ))
+    }
+
+    fn build_output_stream_raw<D, E>(
+        &self,
+        config: &StreamConfig,
+        sample_format: SampleFormat,
+        data_callback: D,
+        error_callback: E,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        D: FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        check_config(self.0.supported_output_configs(), config, sample_format)?;
+        Ok(Stream::new(
+            config,
+            sample_format,
+            DataCallback::Output(Box::new(data_callback)),
+            Box::new(error_callback),
+        ))
+    }
+
+    fn build_duplex_stream_raw<D, E>(
+        &self,
+        input_config: &StreamConfig,
+        output_config: &StreamConfig,
+        sample_format: SampleFormat,
+        data_callback: D,
+        error_callback: E,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        D: FnMut(&Data, &mut Data, &DuplexCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        if input_config.sample_rate != output_config.sample_rate {
+            return Err(BuildStreamError::StreamConfigNotSupported);
+        }
+        check_config(
+            self.0.supported_input_configs(),
+            input_config,
+            sample_format,
+        )?;
+        check_config(
+            self.0.supported_output_configs(),
+            output_config,
+            sample_format,
+        )?;
+        Ok(Stream::new(
+            output_config,
+            sample_format,
+            DataCallback::Duplex(input_config.channels as usize, Box::new(data_callback)),
+            Box::new(error_callback),
+        ))
+    }
+}
+
+// Check that one of the `supported` configs allows the given config. The buffer size is ignored,
+// as the size of each buffer is chosen with every call to `StreamHandle::advance`.
+fn check_config(
+    supported: Result<SupportedInputConfigs, SupportedStreamConfigsError>,
+    config: &StreamConfig,
+    sample_format: SampleFormat,
+) -> Result<(), BuildStreamError> {
+    let mut supported = supported.map_err(|_| BuildStreamError::StreamConfigNotSupported)?;
+    let found = supported.any(|range| {
+        range.channels() == config.channels
+            && range.sample_format() == sample_format
+            && range.min_sample_rate() <= config.sample_rate
+            && config.sample_rate <= range.max_sample_rate()
+    });
+    if found {
+        Ok(())
+    } else {
+        Err(BuildStreamError::StreamConfigNotSupported)
+    }
+}
+
+impl Stream {
+    fn new(
+        config: &StreamConfig,
+        sample_format: SampleFormat,
+        data_callback: DataCallback,
+        error_callback: ErrorCallback,
+    ) -> Self {
+        let inner = StreamInner {
+            channels: config.channels as usize,
+            sample_rate: config.sample_rate,
+            sample_format,
+            playing: true,
+            disconnected: false,
+            frames_elapsed: 0,
+            input: VecDeque::new(),
+            output: Vec::new(),
+            buffer: Vec::new(),
+            duplex_input_buffer: Vec::new(),
+            data_callback: Some(data_callback),
+            pending_errors: VecDeque::new(),
+            error_callback: Some(error_callback),
+        };
+        Stream {
+            handle: StreamHandle {
+                inner: Arc::new(Mutex::new(inner)),
+            },
+        }
+    }
+
+    /// A handle for driving this stream.
+    pub fn handle(&self) -> StreamHandle {
+        self.handle.clone()
+    }
+}
+
+impl StreamTrait for Stream {
+    fn play(&self) -> Result<(), PlayStreamError> {
+        let mut inner = self.handle.inner.lock().unwrap();
+        if inner.disconnected {
+            return Err(PlayStreamError::DeviceNotAvailable);
+        }
+        inner.playing = true;
+        Ok(())
+    }
+
+    fn pause(&self) -> Result<(), PauseStreamError> {
+        let mut inner = self.handle.inner.lock().unwrap();
+        if inner.disconnected {
+            return Err(PauseStreamError::DeviceNotAvailable);
+        }
+        inner.playing = false;
+        Ok(())
+    }
+}
+
+impl StreamHandle {
+    /// Process the next `frames` frames, invoking the data callback once with a buffer of that
+    /// many frames.
+    ///
+    /// Input and duplex streams receive the samples injected via `push_input`, padded with silence
+    /// if not enough were injected. The samples written by output and duplex streams can be read
+    /// back via `take_output`.
+    ///
+    /// Returns `false` without invoking the data callback or advancing the stream's clock if the
+    /// stream is paused, its device was disconnected or the data callback is already running.
+    ///
+    /// The data callback may use the handle, as no lock is held while it runs.
+    pub fn advance(&self, frames: FrameCount) -> bool {
+        // The data callback is taken out of the stream and invoked without holding the lock, so
+        // that it may use the handle itself.
+        let mut inner = self.inner.lock().unwrap();
+        if !inner.playing || inner.disconnected {
+            return false;
+        }
+        let mut data_callback = match inner.data_callback.take() {
+            Some(data_callback) => data_callback,
+            // The data callback is running already, on this or another thread.
+            None => return false,
+        };
+        let sample_format = inner.sample_format;
+        let len = frames as usize * inner.channels;
+        let mut buffer = std::mem::take(&mut inner.buffer);
+        buffer.resize(len * sample_format.sample_size(), 0);
+        crate::samples_formats::fill_with_silence(&mut buffer, sample_format);
+        let mut input_buffer = std::mem::take(&mut inner.duplex_input_buffer);
+        match data_callback {
+            DataCallback::Input(_) => fill_input(&mut inner.input, &mut buffer),
+            DataCallback::Output(_) => (),
+            DataCallback::Duplex(input_channels, _) => {
+                let input_len = frames as usize * input_channels;
+                input_buffer.resize(input_len * sample_format.sample_size(), 0);
+                crate::samples_formats::fill_with_silence(&mut input_buffer, sample_format);
+                fill_input(&mut inner.input, &mut input_buffer);
+            }
+        }
+        let callback = frames_to_instant(inner.frames_elapsed, inner.sample_rate);
+        let duration = frames_to_duration(frames as u64, inner.sample_rate);
+        inner.frames_elapsed += frames as u64;
+        drop(inner);
+
+        match data_callback {
+            DataCallback::Input(ref mut data_callback) => {
+                let data = buffer.as_mut_ptr() as *mut ();
+                let data = unsafe { Data::from_parts(data, len, sample_format) };
+                // The callback is invoked once the buffer has been captured in full.
+                let capture = callback;
+                let callback = capture
+                    .add(duration)
+                    .expect("`callback` occurs beyond representation supported by `StreamInstant`");
+                let timestamp = InputStreamTimestamp { callback, capture };
+                data_callback(&data, &InputCallbackInfo::new(timestamp));
+            }
+            DataCallback::Output(ref mut data_callback) => {
+                let data = buffer.as_mut_ptr() as *mut ();
+                let mut data = unsafe { Data::from_parts(data, len, sample_format) };
+                let playback = callback
+                    .add(duration)
+                    .expect("`playback` occurs beyond representation supported by `StreamInstant`");
+                let timestamp = OutputStreamTimestamp { callback, playback };
+                data_callback(&mut data, &OutputCallbackInfo::new(timestamp));
+            }
+            DataCallback::Duplex(_, ref mut data_callback) => {
+                let input_len = input_buffer.len() / sample_format.sample_size();
+                let input_data = input_buffer.as_mut_ptr() as *mut ();
+                let input_data = unsafe { Data::from_parts(input_data, input_len, sample_format) };
+                let output_data = buffer.as_mut_ptr() as *mut ();
+                let mut output_data = unsafe { Data::from_parts(output_data, len, sample_format) };
+                // As for input streams, the callback is invoked once the input has been captured,
+                // and the output is played back a buffer later.
+                let capture = callback;
+                let callback = capture
+                    .add(duration)
+                    .expect("`callback` occurs beyond representation supported by `StreamInstant`");
+                let playback = callback
+                    .add(duration)
+                    .expect("`playback` occurs beyond representation supported by `StreamInstant`");
+                let timestamp = DuplexStreamTimestamp {
+                    callback,
+                    capture,
+                    playback,
+                };
+                data_callback(
+                    &input_data,
+                    &mut output_data,
+                    &DuplexCallbackInfo::new(timestamp),
+                );
+            }
+        }
+
+        let mut inner = self.inner.lock().unwrap();
+        match data_callback {
+            DataCallback::Input(_) => (),
+            DataCallback::Output(_) | DataCallback::Duplex(..) => {
+                inner.output.extend_from_slice(&buffer)
+            }
+        }
+        inner.data_callback = Some(data_callback);
+        inner.buffer = buffer;
+        inner.duplex_input_buffer = input_buffer;
+        true
+    }
+
+    /// Queue interleaved samples to be delivered to the data callback of an input or duplex stream
+    /// by the following calls to `advance`.
+    ///
+    /// **panic!**s if `T` does not match the sample format of the stream.
+    pub fn push_input<T: Sample>(&self, samples: &[T]) {
+        let mut inner = self.inner.lock().unwrap();
+        assert_eq!(
+            T::FORMAT,
+            inner.sample_format,
+            "sample type does not match the format of the stream"
+        );
+        let bytes = unsafe {
+            std::slice::from_raw_parts(
+                samples.as_ptr() as *const u8,
+                samples.len() * T::FORMAT.sample_size(),
+            )
+        };
+        inner.input.extend(bytes);
+    }
+
+    /// Take the interleaved samples written by the data callback of an output or duplex stream
+    /// since the last call to `take_output`.
+    ///
+    /// **panic!**s if `T` does not match the sample format of the stream.
+    pub fn take_output<T: Sample>(&self) -> Vec<T> {
+        let mut inner = self.inner.lock().unwrap();
+        assert_eq!(
+            T::FORMAT,
+            inner.sample_format,
+            "sample type does not match the format of the stream"
+        );
+        let bytes = std::mem::take(&mut inner.output);
+        bytes
+            .chunks_exact(T::FORMAT.sample_size())
+            .map(|sample| unsafe { std::ptr::read_unaligned(sample.as_ptr() as *const T) })
+            .collect()
+    }
+
+    /// Deliver `err` to the stream's error callback.
+    pub fn inject_error(&self, err: StreamError) {
+        self.report(err);
+    }
+
+    /// Simulate the disconnection of the stream's device.
+    ///
+    /// Delivers `StreamError::DeviceNotAvailable` to the error callback. From then on `advance`
+    /// no longer invokes the data callback, and `play` and `pause` return `DeviceNotAvailable`.
+    pub fn disconnect(&self) {
+        self.inner.lock().unwrap().disconnected = true;
+        self.report(StreamError::DeviceNotAvailable);
+    }
+
+    // Deliver `err` to the error callback. As with the data callback, the error callback is taken
+    // out of the stream and invoked without holding the lock, so that it may use the handle
+    // itself. Errors reported meanwhile, on this or another thread, are delivered in turn by the
+    // invocation in progress.
+    fn report(&self, err: StreamError) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.pending_errors.push_back(err);
+        let mut error_callback = match inner.error_callback.take() {
+            Some(error_callback) => error_callback,
+            None => return,
+        };
+        while let Some(err) = inner.pending_errors.pop_front() {
+            drop(inner);
+            error_callback(err);
+            inner = self.inner.lock().unwrap();
+        }
+        inner.error_callback = Some(error_callback);
+    }
+
+    /// The number of frames processed by the stream so far.
+    pub fn frames_elapsed(&self) -> u64 {
+        self.inner.lock().unwrap().frames_elapsed
+    }
+
+    /// Whether the stream is playing, i.e. whether `advance` would invoke the data callback.
+    pub fn is_playing(&self) -> bool {
+        let inner = self.inner.lock().unwrap();
+        inner.playing && !inner.disconnected
+    }
+}
+
+// Move as many injected input bytes as fit into `buffer`.
+fn fill_input(input: &mut VecDeque<u8>, buffer: &mut [u8]) {
+    let available = input.len().min(buffer.len());
+    for (byte, value) in buffer.iter_mut().zip(input.drain(..available)) {
+        *byte = value;
+    }
+}
+
+// Convert the given duration in frames at the given sample rate to a `std::time::Duration`.
+fn frames_to_duration(frames: u64, rate: SampleRate) -> Duration {
+    let nanos = frames as u128 * 1_000_000_000 / rate.0 as u128;
+    Duration::new(
+        (nanos / 1_000_000_000) as u64,
+        (nanos % 1_000_000_000) as u32,
+    )
+}
+
+// The instant at which the given number of frames have elapsed since the start of the stream.
+fn frames_to_instant(frames: u64, rate: SampleRate) -> StreamInstant {
+    let duration = frames_to_duration(frames, rate);
+    StreamInstant::new(duration.as_secs() as i64, duration.subsec_nanos())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Host, StreamHandle};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use traits::{DeviceTrait, HostTrait, StreamTrait};
+    use {
+        BufferSize, BuildStreamError, DuplexStreamTimestamp, InputStreamTimestamp,
+        OutputStreamTimestamp, PlayStreamError, SampleRate, StreamConfig, StreamError,
+    };
+
+    fn config() -> StreamConfig {
+        StreamConfig {
+            channels: 2,
+            sample_rate: SampleRate(48_000),
+            buffer_size: BufferSize::Default,
+            channel_map: None,
+        }
+    }
+
+    #[test]
+    fn test_output_stream() {
+        let device = Host::new().unwrap().default_output_device().unwrap();
+        let timestamps = Arc::new(Mutex::new(Vec::<OutputStreamTimestamp>::new()));
+        let timestamps2 = timestamps.clone();
+        let mut next = 0i16;
+        let stream = device
+            .build_output_stream(
+                &config(),
+                move |data: &mut [i16], info: &_| {
+                    timestamps2.lock().unwrap().push(info.timestamp());
+                    for sample in data.iter_mut() {
+                        *sample = next;
+                        next += 1;
+                    }
+                },
+                |_| panic!(),
+            )
+            .unwrap();
+        let handle = stream.handle();
+        assert!(handle.take_output::<i16>().is_empty());
+        assert!(handle.advance(2));
+        assert!(handle.advance(1));
+        assert_eq!(handle.take_output::<i16>(), vec![0, 1, 2, 3, 4, 5]);
+        assert!(handle.take_output::<i16>().is_empty());
+        assert_eq!(handle.frames_elapsed(), 3);
+
+        let timestamps = timestamps.lock().unwrap();
+        let callback = timestamps[1]
+            .callback
+            .duration_since(&timestamps[0].callback);
+        assert_eq!(
+            callback,
+            Some(Duration::from_nanos(2 * 1_000_000_000 / 48_000))
+        );
+        let latency = timestamps[1]
+            .playback
+            .duration_since(&timestamps[1].callback);
+        assert_eq!(latency, Some(Duration::from_nanos(1_000_000_000 / 48_000)));
+    }
+
+    #[test]
+    fn test_input_stream() {
+        let device = Host::new().unwrap().default_input_device().unwrap();
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let received2 = received.clone();
+        let stream = device
+            .build_input_stream(
+                &config(),
+                move |data: &[f32], info: &_| {
+                    let timestamp: InputStreamTimestamp = info.timestamp();
+                    received2.lock().unwrap().push((data.to_vec(), timestamp));
+                },
+                |_| panic!(),
+            )
+            .unwrap();
+        let handle = stream.handle();
+        handle.push_input(&[0.25f32, -0.25, 0.5]);
+        assert!(handle.advance(1));
+        assert!(handle.advance(2));
+
+        let received = received.lock().unwrap();
+        assert_eq!(received[0].0, vec![0.25, -0.25]);
+        assert_eq!(received[1].0, vec![0.5, 0.0, 0.0, 0.0]);
+        let latency = received[1]
+            .1
+            .callback
+            .duration_since(&received[1].1.capture);
+        assert_eq!(
+            latency,
+            Some(Duration::from_nanos(2 * 1_000_000_000 / 48_000))
+        );
+    }
+
+    #[test]
+    fn test_callback_uses_handle() {
+        let device = Host::new().unwrap().default_output_device().unwrap();
+        let handle = Arc::new(Mutex::new(None::<StreamHandle>));
+        let handle2 = handle.clone();
+        let stream = device
+            .build_output_stream(
+                &config(),
+                move |data: &mut [i16], _: &_| {
+                    let handle = handle2.lock().unwrap();
+                    let handle = handle.as_ref().unwrap();
+                    // A nested call does not run the callback again.
+                    assert!(!handle.advance(1));
+                    let frames_elapsed = handle.frames_elapsed() as i16;
+                    for sample in data.iter_mut() {
+                        *sample = frames_elapsed;
+                    }
+                },
+                |_| panic!(),
+            )
+            .unwrap();
+        *handle.lock().unwrap() = Some(stream.handle());
+        assert!(stream.handle().advance(1));
+        assert!(stream.handle().advance(1));
+        assert_eq!(stream.handle().take_output::<i16>(), vec![1, 1, 2, 2]);
+    }
+
+    #[test]
+    fn test_duplex_stream() {
+        let device = Host::new().unwrap().default_output_device().unwrap();
+        let input_config = StreamConfig {
+            channels: 1,
+            ..config()
+        };
+        let timestamps = Arc::new(Mutex::new(Vec::<DuplexStreamTimestamp>::new()));
+        let timestamps2 = timestamps.clone();
+        let stream = device
+            .build_duplex_stream(
+                &input_config,
+                &config(),
+                move |input: &[i16], output: &mut [i16], info: &_| {
+                    timestamps2.lock().unwrap().push(info.timestamp());
+                    for (frame, &sample) in output.chunks_mut(2).zip(input) {
+                        frame[0] = sample;
+                        frame[1] = -sample;
+                    }
+                },
+                |_| panic!(),
+            )
+            .unwrap();
+        let handle = stream.handle();
+        handle.push_input(&[1i16, 2, 3]);
+        assert!(handle.advance(2));
+        assert!(handle.advance(2));
+        assert_eq!(handle.take_output::<i16>(), vec![1, -1, 2, -2, 3, -3, 0, 0]);
+
+        let timestamps = timestamps.lock().unwrap();
+        let buffer_duration = Some(Duration::from_nanos(2 * 1_000_000_000 / 48_000));
+        let timestamp = timestamps[1];
+        assert_eq!(
+            timestamp.callback.duration_since(&timestamp.capture),
+            buffer_duration
+        );
+        assert_eq!(
+            timestamp.playback.duration_since(&timestamp.callback),
+            buffer_duration
+        );
+
+        let output_config = StreamConfig {
+            sample_rate: SampleRate(44_100),
+            ..config()
+        };
+        let result = device.build_duplex_stream(
+            &input_config,
+            &output_config,
+            |_: &[i16], _: &mut [i16], _: &_| {},
+            |_| panic!(),
+        );
+        match result {
+            Err(BuildStreamError::StreamConfigNotSupported) => (),
+            _ => panic!("expected mismatched sample rates to be rejected"),
+        }
+    }
+
+    #[test]
+    fn test_pause_and_play() {
+        let device = Host::new().unwrap().default_output_device().unwrap();
+        let stream = device
+            .build_output_stream(&config(), |_: &mut [f32], _: &_| {}, |_| panic!())
+            .unwrap();
+        let handle = stream.handle();
+        stream.pause().unwrap();
+        assert!(!handle.is_playing());
+        assert!(!handle.advance(64));
+        assert_eq!(handle.frames_elapsed(), 0);
+        stream.play().unwrap();
+        assert!(handle.advance(64));
+        assert_eq!(handle.take_output::<f32>().len(), 128);
+    }
+
+    #[test]
+    fn test_errors_and_disconnect() {
+        let device = Host::new().unwrap().default_output_device().unwrap();
+        let errors = Arc::new(Mutex::new(Vec::new()));
+        let errors2 = errors.clone();
+        let stream = device
+            .build_output_stream(
+                &config(),
+                |_: &mut [f32], _: &_| {},
+                move |err| errors2.lock().unwrap().push(err),
+            )
+            .unwrap();
+        let handle = stream.handle();
+        handle.inject_error(StreamError::BufferUnderrun {
+            frames_lost: Some(16),
+        });
+        handle.disconnect();
+        assert!(!handle.advance(64));
+        match stream.play() {
+            Err(PlayStreamError::DeviceNotAvailable) => (),
+            _ => panic!("expected the device to be unavailable"),
+        }
+
+        let errors = errors.lock().unwrap();
+        match errors[..] {
+            [StreamError::BufferUnderrun {
+                frames_lost: Some(16),
+            }, StreamError::DeviceNotAvailable] => (),
+            ref errors => panic!("unexpected errors {:?}", errors),
+        }
+    }
+
+    #[test]
+    fn test_error_callback_uses_handle() {
+        let device = Host::new().unwrap().default_output_device().unwrap();
+        let handle = Arc::new(Mutex::new(None::<StreamHandle>));
+        let handle2 = handle.clone();
+        let errors = Arc::new(Mutex::new(Vec::new()));
+        let errors2 = errors.clone();
+        let stream = device
+            .build_output_stream(
+                &config(),
+                |_: &mut [f32], _: &_| {},
+                move |err| {
+                    let handle = handle2.lock().unwrap();
+                    let handle = handle.as_ref().unwrap();
+                    assert!(!handle.is_playing());
+                    // A nested error is delivered once this one has been handled.
+                    if let StreamError::DeviceNotAvailable = err {
+                        handle.inject_error(StreamError::BufferOverrun { frames_lost: None });
+                    }
+                    errors2.lock().unwrap().push(err);
+                },
+            )
+            .unwrap();
+        *handle.lock().unwrap() = Some(stream.handle());
+        stream.handle().disconnect();
+
+        let errors = errors.lock().unwrap();
+        match errors[..] {
+            [StreamError::DeviceNotAvailable, StreamError::BufferOverrun { .. }] => (),
+            ref errors => panic!("unexpected errors {:?}", errors),
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_sample_type_mismatch() {
+        let device = Host::new().unwrap().default_output_device().unwrap();
+        let stream = device
+            .build_output_stream(&config(), |_: &mut [f32], _: &_| {}, |_| panic!())
+            .unwrap();
+        stream.handle().take_output::<i16>();
+    }
+}
@@ -325,6 +325,7 @@ unsafe fn format_from_waveformatex_ptr(
         sample_rate: SampleRate((*waveformatex_ptr).nSamplesPerSec),
         buffer_size: SupportedBufferSize::Unknown,
         sample_format,
+        channel_map: None,
     };
     Some(format)
 }
@@ -648,13 +649,13 @@ impl Device {
             };
 
             match config.buffer_size {
-                BufferSize::Fixed(_) => {
+                BufferSize::Default => (),
+                _ => {
                     // TO DO: We need IAudioClient3 to get buffersize ranges first
                     // Otherwise the supported ranges are unknown. In the mean time
                     // the smallest buffersize is selected and used.
                     return Err(BuildStreamError::StreamConfigNotSupported);
                 }
-                BufferSize::Default => (),
             };
 
             let mut stream_flags: DWORD = AUDCLNT_STREAMFLAGS_EVENTCALLBACK;
@@ -816,13 +817,13 @@ impl Device {
             };
 
             match config.buffer_size {
-                BufferSize::Fixed(_) => {
+                BufferSize::Default => (),
+                _ => {
                     // TO DO: We need IAudioClient3 to get buffersize ranges first
                     // Otherwise the supported ranges are unknown. In the mean time
                     // the smallest buffersize is selected and used.
                     return Err(BuildStreamError::StreamConfigNotSupported);
                 }
-                BufferSize::Default => (),
             };
 
             // Computing the format and initializing the device.
@@ -1228,7 +1229,7 @@ fn config_to_waveformatextensible(
     let format_tag = match sample_format {
         SampleFormat::I16 => mmreg::WAVE_FORMAT_PCM,
         SampleFormat::F32 => mmreg::WAVE_FORMAT_EXTENSIBLE,
-        SampleFormat::U16 => return None,
+        _ => return None,
     };
     let channels = config.channels as WORD;
     let sample_rate = config.sample_rate.0 as DWORD;
@@ -1243,7 +1244,7 @@ fn config_to_waveformatextensible(
             let ex_size = mem::size_of::<mmreg::WAVEFORMATEX>();
             (extensible_size - ex_size) as WORD
         }
-        SampleFormat::U16 => return None,
+        _ => return None,
     };
     let waveformatex = mmreg::WAVEFORMATEX {
         wFormatTag: format_tag,
@@ -1263,7 +1264,7 @@ fn config_to_waveformatextensible(
     let sub_format = match sample_format {
         SampleFormat::I16 => ksmedia::KSDATAFORMAT_SUBTYPE_PCM,
         SampleFormat::F32 => ksmedia::KSDATAFORMAT_SUBTYPE_IEEE_FLOAT,
-        SampleFormat::U16 => return None,
+        _ => return None,
     };
     let waveformatextensible = mmreg::WAVEFORMATEXTENSIBLE {
         Format: waveformatex,
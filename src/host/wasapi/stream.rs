@@ -424,7 +424,7 @@ fn process_input(
                     return ControlFlow::Break;
                 }
             };
-            let info = InputCallbackInfo { timestamp };
+            let info = InputCallbackInfo::new(timestamp);
             data_callback(&data, &info);
 
             // Release the buffer.
@@ -477,7 +477,7 @@ fn process_output(
                 return ControlFlow::Break;
             }
         };
-        let info = OutputCallbackInfo { timestamp };
+        let info = OutputCallbackInfo::new(timestamp);
         data_callback(&mut data, &info);
 
         let hresult = (*render_client).ReleaseBuffer(frames_available as u32, 0);
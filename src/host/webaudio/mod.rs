@@ -7,9 +7,9 @@ use self::wasm_bindgen::prelude::*;
 use self::wasm_bindgen::JsCast;
 use self::web_sys::{AudioContext, AudioContextOptions};
 use crate::{
-    BackendSpecificError, BufferSize, BuildStreamError, Data, DefaultStreamConfigError,
-    DeviceNameError, DevicesError, InputCallbackInfo, OutputCallbackInfo, PauseStreamError,
-    PlayStreamError, SampleFormat, SampleRate, StreamConfig, StreamError, SupportedBufferSize,
+    BackendSpecificError, BuildStreamError, Data, DefaultStreamConfigError, DeviceNameError,
+    DevicesError, InputCallbackInfo, OutputCallbackInfo, PauseStreamError, PlayStreamError,
+    SampleFormat, SampleRate, StreamConfig, StreamError, SupportedBufferSize,
     SupportedStreamConfig, SupportedStreamConfigRange, SupportedStreamConfigsError,
 };
 use std::ops::DerefMut;
@@ -199,15 +199,15 @@ impl DeviceTrait for Device {
 
         let n_channels = config.channels as usize;
 
-        let buffer_size_frames = match config.buffer_size {
-            BufferSize::Fixed(v) => {
+        let buffer_size_frames = match config.buffer_size.period_frames(config.sample_rate) {
+            Some(v) => {
                 if v == 0 {
                     return Err(BuildStreamError::StreamConfigNotSupported);
                 } else {
                     v as usize
                 }
             }
-            BufferSize::Default => DEFAULT_BUFFER_SIZE,
+            None => DEFAULT_BUFFER_SIZE,
         };
         let buffer_size_samples = buffer_size_frames * n_channels;
         let buffer_time_step_secs = buffer_time_step_secs(buffer_size_frames, config.sample_rate);
@@ -292,7 +292,7 @@ impl DeviceTrait for Device {
                         let callback = crate::StreamInstant::from_secs_f64(now);
                         let playback = crate::StreamInstant::from_secs_f64(time_at_start_of_buffer);
                         let timestamp = crate::OutputStreamTimestamp { callback, playback };
-                        let info = OutputCallbackInfo { timestamp };
+                        let info = OutputCallbackInfo::new(timestamp);
                         (data_callback.deref_mut())(&mut data, &info);
                     }
 
@@ -100,6 +100,7 @@
 //!     SampleFormat::F32 => device.build_output_stream(&config, write_silence::<f32>, err_fn),
 //!     SampleFormat::I16 => device.build_output_stream(&config, write_silence::<i16>, err_fn),
 //!     SampleFormat::U16 => device.build_output_stream(&config, write_silence::<u16>, err_fn),
+//!     sample_format => panic!("unsupported sample format '{:?}'", sample_format),
 //! }.unwrap();
 //!
 //! fn write_silence<T: Sample>(data: &mut [T], _: &cpal::OutputCallbackInfo) {
@@ -147,26 +148,42 @@
 #[macro_use]
 extern crate lazy_static;
 // Extern crate declarations with `#[macro_use]` must unfortunately be at crate root.
+#[cfg(feature = "serde")]
+#[macro_use]
+extern crate serde;
+#[cfg(all(test, feature = "serde"))]
+extern crate serde_json;
 #[cfg(target_os = "emscripten")]
 #[macro_use]
 extern crate stdweb;
 extern crate thiserror;
 
+pub use blocking::{InputStreamReader, OutputStreamWriter};
 pub use error::*;
+pub use matching::{ConfigMatch, ConfigRequest};
 pub use platform::{
-    available_hosts, default_host, host_from_id, Device, Devices, Host, HostId, Stream,
-    SupportedInputConfigs, SupportedOutputConfigs, ALL_HOSTS,
+    available_hosts, default_host, host_from_id, Device, DeviceDescriptor, Devices, Host, HostId,
+    Stream, SupportedInputConfigs, SupportedOutputConfigs, ALL_HOSTS,
 };
-pub use samples_formats::{Sample, SampleFormat};
+pub use remix::ChannelMix;
+pub use resample::ResampleQuality;
+pub use samples_formats::{I24Packed, Sample, SampleFormat, I24};
 use std::convert::TryInto;
 use std::ops::{Div, Mul};
 use std::time::Duration;
 
+mod blocking;
+mod convert;
 mod error;
 mod host;
+mod matching;
+mod planar;
 pub mod platform;
+mod remix;
+mod resample;
 mod samples_formats;
 pub mod traits;
+mod volume;
 
 /// A host's device iterator yielding only *input* devices.
 pub type InputDevices<I> = std::iter::Filter<I, fn(&<I as Iterator>::Item) -> bool>;
@@ -177,8 +194,74 @@ pub type OutputDevices<I> = std::iter::Filter<I, fn(&<I as Iterator>::Item) -> b
 /// Number of channels.
 pub type ChannelCount = u16;
 
+/// The position of the speaker a channel is meant for, relative to the listener.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum ChannelPosition {
+    Mono,
+    FrontLeft,
+    FrontRight,
+    FrontCenter,
+    /// The low frequency effects channel, the ".1" of surround layouts.
+    LowFrequency,
+    RearLeft,
+    RearRight,
+    RearCenter,
+    SideLeft,
+    SideRight,
+    FrontLeftOfCenter,
+    FrontRightOfCenter,
+    TopCenter,
+    TopFrontLeft,
+    TopFrontCenter,
+    TopFrontRight,
+    TopRearLeft,
+    TopRearCenter,
+    TopRearRight,
+    /// A channel without a known position, or one that is not meant for a speaker at all.
+    Unknown,
+}
+
+impl ChannelPosition {
+    /// The conventional layout for the given number of channels, in the order used by ALSA and
+    /// WAV files: mono, stereo, 2.1, quad, 5.0, 5.1 and 7.1.
+    ///
+    /// Returns `None` for channel counts without a conventional layout.
+    pub fn default_layout(channels: ChannelCount) -> Option<Vec<ChannelPosition>> {
+        use self::ChannelPosition::*;
+        let layout: &[ChannelPosition] = match channels {
+            1 => &[Mono],
+            2 => &[FrontLeft, FrontRight],
+            3 => &[FrontLeft, FrontRight, LowFrequency],
+            4 => &[FrontLeft, FrontRight, RearLeft, RearRight],
+            5 => &[FrontLeft, FrontRight, RearLeft, RearRight, FrontCenter],
+            6 => &[
+                FrontLeft,
+                FrontRight,
+                FrontCenter,
+                LowFrequency,
+                RearLeft,
+                RearRight,
+            ],
+            8 => &[
+                FrontLeft,
+                FrontRight,
+                FrontCenter,
+                LowFrequency,
+                RearLeft,
+                RearRight,
+                SideLeft,
+                SideRight,
+            ],
+            _ => return None,
+        };
+        Some(layout.to_vec())
+    }
+}
+
 /// The number of samples processed per second for a single channel of audio.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct SampleRate(pub u32);
 
 impl<T> Mul<T> for SampleRate
@@ -211,24 +294,94 @@ pub type FrameCount = u32;
 /// large, leading to latency issues. If low latency is desired, Fixed(BufferSize)
 /// should be used in accordance with the SupportedBufferSize range produced by
 /// the SupportedStreamConfig API.  
+///
+/// `Periods` and `Latency` describe both the number of frames passed to each data callback and the
+/// total amount of audio buffered by the device. Hosts that only control the former use the
+/// period size, and `StreamTrait::buffer_size` and `period_size` report what was granted.
 #[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum BufferSize {
     Default,
     Fixed(FrameCount),
+    /// A buffer of `periods` periods of `period_size` frames each. The data callback is called
+    /// whenever a period has been played or captured.
+    ///
+    /// A small period size and few periods favour low latency, a large period size favours fewer
+    /// wakeups and thereby power saving.
+    Periods {
+        period_size: FrameCount,
+        periods: u32,
+    },
+    /// A target for the total amount of audio buffered by the device, split into two periods.
+    Latency(Duration),
+}
+
+/// The number of periods a `BufferSize::Latency` is split into.
+const LATENCY_PERIODS: u32 = 2;
+
+impl BufferSize {
+    // The period size and period count requested at the given sample rate, unless the request is
+    // `Default` or `Fixed`.
+    pub(crate) fn periods(&self, sample_rate: SampleRate) -> Option<(FrameCount, u32)> {
+        match *self {
+            BufferSize::Default | BufferSize::Fixed(_) => None,
+            BufferSize::Periods {
+                period_size,
+                periods,
+            } => Some((period_size, periods)),
+            BufferSize::Latency(latency) => {
+                let frames = latency.as_secs_f64() * sample_rate.0 as f64;
+                let period_size = (frames / LATENCY_PERIODS as f64).round() as FrameCount;
+                Some((period_size, LATENCY_PERIODS))
+            }
+        }
+    }
+
+    // The number of frames requested per data callback, for hosts with a single buffer size
+    // that determines how often the callback is called. `None` for `Default`.
+    pub(crate) fn period_frames(&self, sample_rate: SampleRate) -> Option<FrameCount> {
+        match *self {
+            BufferSize::Default => None,
+            BufferSize::Fixed(frames) => Some(frames),
+            _ => self
+                .periods(sample_rate)
+                .map(|(period_size, _)| period_size),
+        }
+    }
+
+    // The total number of frames requested to be buffered, for hosts with a single buffer size
+    // that determines the latency. `None` for `Default`.
+    pub(crate) fn buffer_frames(&self, sample_rate: SampleRate) -> Option<FrameCount> {
+        match *self {
+            BufferSize::Default => None,
+            BufferSize::Fixed(frames) => Some(frames),
+            _ => self
+                .periods(sample_rate)
+                .map(|(period_size, periods)| period_size.saturating_mul(periods)),
+        }
+    }
 }
 
 /// The set of parameters used to describe how to open a stream.
 ///
 /// The sample format is omitted in favour of using a sample type.
 #[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct StreamConfig {
     pub channels: ChannelCount,
     pub sample_rate: SampleRate,
     pub buffer_size: BufferSize,
+    /// The position of each of the `channels`, in the order the channels are interleaved in, or
+    /// `None` to leave the order to the host.
+    ///
+    /// Hosts that support channel maps apply the requested map when building the stream and
+    /// report the map in effect via `StreamTrait::config`. Other hosts ignore it.
+    pub channel_map: Option<Vec<ChannelPosition>>,
 }
 
 /// Describes the minimum and maximum supported buffer size for the device
 #[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum SupportedBufferSize {
     Range {
         min: FrameCount,
@@ -239,9 +392,25 @@ pub enum SupportedBufferSize {
     Unknown,
 }
 
+/// The range of volumes a stream can be set to, in decibels relative to full scale, retrieved via
+/// `StreamTrait::volume_range`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct VolumeRange {
+    pub min_db: f32,
+    pub max_db: f32,
+}
+
+impl VolumeRange {
+    // Clamp `volume_db` to the range. NaN is taken to be the minimum.
+    pub(crate) fn clamp(&self, volume_db: f32) -> f32 {
+        volume_db.max(self.min_db).min(self.max_db)
+    }
+}
+
 /// Describes a range of supported stream configurations, retrieved via the
 /// `Device::supported_input/output_configs` method.
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct SupportedStreamConfigRange {
     pub(crate) channels: ChannelCount,
     /// Minimum value for the samples rate of the supported formats.
@@ -262,17 +431,24 @@ pub struct SupportedStreamConfig {
     sample_rate: SampleRate,
     buffer_size: SupportedBufferSize,
     sample_format: SampleFormat,
+    channel_map: Option<Vec<ChannelPosition>>,
 }
 
 /// A buffer of dynamically typed audio data, passed to raw stream callbacks.
 ///
 /// Raw input stream callbacks receive `&Data`, while raw output stream callbacks expect `&mut
 /// Data`.
+///
+/// The samples of the channels are interleaved, unless the data is passed to the callback of a
+/// planar stream, in which case each channel is held in its own buffer.
 #[derive(Debug)]
 pub struct Data {
     data: *mut (),
     len: usize,
     sample_format: SampleFormat,
+    // The pointers to the buffers of the channels and their number if the data is planar, in
+    // which case `data` is unused.
+    planes: Option<(*const *mut (), usize)>,
 }
 
 /// A monotonic time instance associated with a stream, retrieved from either:
@@ -321,16 +497,80 @@ pub struct OutputStreamTimestamp {
     pub playback: StreamInstant,
 }
 
+/// A timestamp associated with a call to a duplex stream's data callback.
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+pub struct DuplexStreamTimestamp {
+    /// The instant the stream's data callback was invoked.
+    pub callback: StreamInstant,
+    /// The instant that the input data was captured from the device.
+    pub capture: StreamInstant,
+    /// The predicted instant that the output data will be delivered to the device for playback.
+    pub playback: StreamInstant,
+}
+
 /// Information relevant to a single call to the user's input stream data callback.
 #[derive(Debug, Clone, PartialEq)]
 pub struct InputCallbackInfo {
     timestamp: InputStreamTimestamp,
+    // The JACK transport, exposed through `platform::JackCallbackInfoExt`.
+    #[cfg(all(
+        any(target_os = "linux", target_os = "dragonfly", target_os = "freebsd"),
+        feature = "jack"
+    ))]
+    jack_transport: Option<host::jack::TransportInfo>,
 }
 
 /// Information relevant to a single call to the user's output stream data callback.
 #[derive(Debug, Clone, PartialEq)]
 pub struct OutputCallbackInfo {
     timestamp: OutputStreamTimestamp,
+    // The JACK transport, exposed through `platform::JackCallbackInfoExt`.
+    #[cfg(all(
+        any(target_os = "linux", target_os = "dragonfly", target_os = "freebsd"),
+        feature = "jack"
+    ))]
+    jack_transport: Option<host::jack::TransportInfo>,
+}
+
+/// Information relevant to a single call to the user's duplex stream data callback.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DuplexCallbackInfo {
+    timestamp: DuplexStreamTimestamp,
+    // The JACK transport, exposed through `platform::JackCallbackInfoExt`.
+    #[cfg(all(
+        any(target_os = "linux", target_os = "dragonfly", target_os = "freebsd"),
+        feature = "jack"
+    ))]
+    jack_transport: Option<host::jack::TransportInfo>,
+}
+
+/// The direction of a stream, i.e. whether audio flows from a device (input) or to a device
+/// (output).
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+pub enum StreamDirection {
+    Input,
+    Output,
+}
+
+/// A change to the set of devices available to a host, delivered to the callback registered via
+/// `HostTrait::watch_devices`.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum DeviceEvent {
+    /// A device with the given name has become available.
+    DeviceAdded { name: String },
+    /// The device with the given name is no longer available.
+    DeviceRemoved { name: String },
+    /// The system's default device for the given direction has changed.
+    ///
+    /// Call `default_input_device` or `default_output_device` to retrieve the new default.
+    DefaultDeviceChanged { direction: StreamDirection },
+}
+
+/// A subscription to device events, returned by `HostTrait::watch_devices`.
+///
+/// The callback stops receiving events once the `DeviceWatcher` is dropped.
+pub struct DeviceWatcher {
+    _inner: Box<dyn Send>,
 }
 
 impl SupportedStreamConfig {
@@ -350,11 +590,25 @@ impl SupportedStreamConfig {
         self.sample_format
     }
 
+    /// The position of each channel, if the host reports it.
+    pub fn channel_map(&self) -> Option<&[ChannelPosition]> {
+        self.channel_map.as_deref()
+    }
+
     pub fn config(&self) -> StreamConfig {
         StreamConfig {
             channels: self.channels,
             sample_rate: self.sample_rate,
             buffer_size: BufferSize::Default,
+            channel_map: self.channel_map.clone(),
+        }
+    }
+
+    // Attach the channel map reported by the host.
+    pub(crate) fn with_channel_map(self, channel_map: Option<Vec<ChannelPosition>>) -> Self {
+        SupportedStreamConfig {
+            channel_map,
+            ..self
         }
     }
 }
@@ -431,6 +685,18 @@ impl StreamInstant {
 }
 
 impl InputCallbackInfo {
+    // Internal constructor for host implementations to use.
+    pub(crate) fn new(timestamp: InputStreamTimestamp) -> Self {
+        InputCallbackInfo {
+            timestamp,
+            #[cfg(all(
+                any(target_os = "linux", target_os = "dragonfly", target_os = "freebsd"),
+                feature = "jack"
+            ))]
+            jack_transport: None,
+        }
+    }
+
     /// The timestamp associated with the call to an input stream's data callback.
     pub fn timestamp(&self) -> InputStreamTimestamp {
         self.timestamp
@@ -438,12 +704,61 @@ impl InputCallbackInfo {
 }
 
 impl OutputCallbackInfo {
+    // Internal constructor for host implementations to use.
+    pub(crate) fn new(timestamp: OutputStreamTimestamp) -> Self {
+        OutputCallbackInfo {
+            timestamp,
+            #[cfg(all(
+                any(target_os = "linux", target_os = "dragonfly", target_os = "freebsd"),
+                feature = "jack"
+            ))]
+            jack_transport: None,
+        }
+    }
+
     /// The timestamp associated with the call to an output stream's data callback.
     pub fn timestamp(&self) -> OutputStreamTimestamp {
         self.timestamp
     }
 }
 
+impl DuplexCallbackInfo {
+    // Internal constructor for host implementations to use.
+    pub(crate) fn new(timestamp: DuplexStreamTimestamp) -> Self {
+        DuplexCallbackInfo {
+            timestamp,
+            #[cfg(all(
+                any(target_os = "linux", target_os = "dragonfly", target_os = "freebsd"),
+                feature = "jack"
+            ))]
+            jack_transport: None,
+        }
+    }
+
+    /// The timestamp associated with the call to a duplex stream's data callback.
+    pub fn timestamp(&self) -> DuplexStreamTimestamp {
+        self.timestamp
+    }
+}
+
+impl DeviceWatcher {
+    // Internal constructor for host implementations to use.
+    //
+    // Dropping `inner` must stop the delivery of events to the user's callback.
+    #[allow(dead_code)]
+    pub(crate) fn new<T: Send + 'static>(inner: T) -> Self {
+        DeviceWatcher {
+            _inner: Box::new(inner),
+        }
+    }
+}
+
+impl std::fmt::Debug for DeviceWatcher {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("DeviceWatcher").finish()
+    }
+}
+
 #[allow(clippy::len_without_is_empty)]
 impl Data {
     // Internal constructor for host implementations to use.
@@ -464,6 +779,28 @@ impl Data {
             data,
             len,
             sample_format,
+            planes: None,
+        }
+    }
+
+    // Internal constructor for host implementations to use with planar buffers.
+    //
+    // The following requirements must be met in order for the safety of `Data`'s public API.
+    //
+    // - The `planes` pointer must point to `channels` pointers, each pointing to the first sample
+    //   of a buffer of `frames` samples in the format specified via the `sample_format` argument.
+    // - The buffers of the channels must not overlap.
+    pub(crate) unsafe fn from_planar_parts(
+        planes: *const *mut (),
+        channels: usize,
+        frames: usize,
+        sample_format: SampleFormat,
+    ) -> Self {
+        Data {
+            data: std::ptr::null_mut(),
+            len: channels * frames,
+            sample_format,
+            planes: Some((planes, channels)),
         }
     }
 
@@ -475,15 +812,26 @@ impl Data {
     /// The full length of the buffer in samples.
     ///
     /// The returned length is the same length as the slice of type `T` that would be returned via
-    /// `as_slice` given a sample type that matches the inner sample format.
+    /// `as_slice` given a sample type that matches the inner sample format. For planar data it is
+    /// the total length of the buffers of all channels.
     pub fn len(&self) -> usize {
         self.len
     }
 
+    /// The number of channels of planar data, each held in its own buffer.
+    ///
+    /// Returns `None` if the samples of the channels are interleaved.
+    pub fn planes(&self) -> Option<usize> {
+        self.planes.map(|(_, channels)| channels)
+    }
+
     /// The raw slice of memory representing the underlying audio data as a slice of bytes.
     ///
     /// It is up to the user to interpret the slice of memory based on `Data::sample_format`.
+    ///
+    /// Panics if the data is planar, see `channel` instead.
     pub fn bytes(&self) -> &[u8] {
+        assert!(self.planes.is_none(), "planar data is not contiguous");
         let len = self.len * self.sample_format.sample_size();
         // The safety of this block relies on correct construction of the `Data` instance. See
         // the unsafe `from_parts` constructor for these requirements.
@@ -493,7 +841,10 @@ impl Data {
     /// The raw slice of memory representing the underlying audio data as a slice of bytes.
     ///
     /// It is up to the user to interpret the slice of memory based on `Data::sample_format`.
+    ///
+    /// Panics if the data is planar, see `channel_mut` instead.
     pub fn bytes_mut(&mut self) -> &mut [u8] {
+        assert!(self.planes.is_none(), "planar data is not contiguous");
         let len = self.len * self.sample_format.sample_size();
         // The safety of this block relies on correct construction of the `Data` instance. See
         // the unsafe `from_parts` constructor for these requirements.
@@ -502,12 +853,13 @@ impl Data {
 
     /// Access the data as a slice of sample type `T`.
     ///
-    /// Returns `None` if the sample type does not match the expected sample format.
+    /// Returns `None` if the sample type does not match the expected sample format, or if the data
+    /// is planar.
     pub fn as_slice<T>(&self) -> Option<&[T]>
     where
         T: Sample,
     {
-        if T::FORMAT == self.sample_format {
+        if T::FORMAT == self.sample_format && self.planes.is_none() {
             // The safety of this block relies on correct construction of the `Data` instance. See
             // the unsafe `from_parts` constructor for these requirements.
             unsafe { Some(std::slice::from_raw_parts(self.data as *const T, self.len)) }
@@ -518,12 +870,13 @@ impl Data {
 
     /// Access the data as a slice of sample type `T`.
     ///
-    /// Returns `None` if the sample type does not match the expected sample format.
+    /// Returns `None` if the sample type does not match the expected sample format, or if the data
+    /// is planar.
     pub fn as_slice_mut<T>(&mut self) -> Option<&mut [T]>
     where
         T: Sample,
     {
-        if T::FORMAT == self.sample_format {
+        if T::FORMAT == self.sample_format && self.planes.is_none() {
             // The safety of this block relies on correct construction of the `Data` instance. See
             // the unsafe `from_parts` constructor for these requirements.
             unsafe {
@@ -536,9 +889,66 @@ impl Data {
             None
         }
     }
+    /// Access the buffer of the channel `index` of planar data as a slice of sample type `T`.
+    ///
+    /// Returns `None` if the sample type does not match the expected sample format, if the data is
+    /// interleaved or if there is no such channel.
+    pub fn channel<T>(&self, index: usize) -> Option<&[T]>
+    where
+        T: Sample,
+    {
+        match self.planes {
+            Some((planes, channels)) if T::FORMAT == self.sample_format && index < channels => {
+                // The safety of this block relies on correct construction of the `Data` instance.
+                // See the unsafe `from_planar_parts` constructor for these requirements.
+                unsafe {
+                    let plane = *planes.add(index) as *const T;
+                    Some(std::slice::from_raw_parts(plane, self.len / channels))
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Access the buffer of the channel `index` of planar data as a slice of sample type `T`.
+    ///
+    /// Returns `None` if the sample type does not match the expected sample format, if the data is
+    /// interleaved or if there is no such channel.
+    pub fn channel_mut<T>(&mut self, index: usize) -> Option<&mut [T]>
+    where
+        T: Sample,
+    {
+        match self.planes {
+            Some((planes, channels)) if T::FORMAT == self.sample_format && index < channels => {
+                // The safety of this block relies on correct construction of the `Data` instance.
+                // See the unsafe `from_planar_parts` constructor for these requirements.
+                unsafe {
+                    let plane = *planes.add(index) as *mut T;
+                    Some(std::slice::from_raw_parts_mut(plane, self.len / channels))
+                }
+            }
+            _ => None,
+        }
+    }
 }
 
 impl SupportedStreamConfigRange {
+    pub fn new(
+        channels: ChannelCount,
+        min_sample_rate: SampleRate,
+        max_sample_rate: SampleRate,
+        buffer_size: SupportedBufferSize,
+        sample_format: SampleFormat,
+    ) -> Self {
+        Self {
+            channels,
+            min_sample_rate,
+            max_sample_rate,
+            buffer_size,
+            sample_format,
+        }
+    }
+
     pub fn channels(&self) -> ChannelCount {
         self.channels
     }
@@ -570,6 +980,7 @@ impl SupportedStreamConfigRange {
             sample_rate,
             sample_format: self.sample_format,
             buffer_size: self.buffer_size,
+            channel_map: None,
         }
     }
 
@@ -581,6 +992,7 @@ impl SupportedStreamConfigRange {
             sample_rate: self.max_sample_rate,
             sample_format: self.sample_format,
             buffer_size: self.buffer_size,
+            channel_map: None,
         }
     }
 
@@ -657,7 +1069,7 @@ impl SupportedStreamConfigRange {
 
 #[test]
 fn test_cmp_default_heuristics() {
-    let mut formats = vec![
+    let mut formats = [
         SupportedStreamConfigRange {
             buffer_size: SupportedBufferSize::Range { min: 256, max: 512 },
             channels: 2,
@@ -755,8 +1167,8 @@ const COMMON_SAMPLE_RATES: &'static [SampleRate] = &[
 fn test_stream_instant() {
     let a = StreamInstant::new(2, 0);
     let b = StreamInstant::new(-2, 0);
-    let min = StreamInstant::new(std::i64::MIN, 0);
-    let max = StreamInstant::new(std::i64::MAX, 0);
+    let min = StreamInstant::new(i64::MIN, 0);
+    let max = StreamInstant::new(i64::MAX, 0);
     assert_eq!(
         a.sub(Duration::from_secs(1)),
         Some(StreamInstant::new(1, 0))
@@ -784,3 +1196,39 @@ fn test_stream_instant() {
     );
     assert_eq!(max.add(Duration::from_secs(1)), None);
 }
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_serde_round_trip() {
+    let config = StreamConfig {
+        channels: 6,
+        sample_rate: SampleRate(48_000),
+        buffer_size: BufferSize::Latency(Duration::from_millis(20)),
+        channel_map: ChannelPosition::default_layout(6),
+    };
+    let json = serde_json::to_string(&config).unwrap();
+    assert_eq!(serde_json::from_str::<StreamConfig>(&json).unwrap(), config);
+
+    let range = SupportedStreamConfigRange::new(
+        2,
+        SampleRate(8_000),
+        SampleRate(96_000),
+        SupportedBufferSize::Range { min: 64, max: 8192 },
+        SampleFormat::I24,
+    );
+    let json = serde_json::to_string(&range).unwrap();
+    assert_eq!(
+        serde_json::from_str::<SupportedStreamConfigRange>(&json).unwrap(),
+        range
+    );
+
+    let descriptor = DeviceDescriptor {
+        host: ALL_HOSTS[0],
+        id: "hw:CARD=PCH,DEV=0".to_owned(),
+    };
+    let json = serde_json::to_string(&descriptor).unwrap();
+    assert_eq!(
+        serde_json::from_str::<DeviceDescriptor>(&json).unwrap(),
+        descriptor
+    );
+}
@@ -0,0 +1,277 @@
+//! Choosing the supported config that best matches a partial description of the desired stream.
+
+use crate::{
+    BufferSize, ChannelCount, FrameCount, MatchConfigError, SampleFormat, SampleRate, StreamConfig,
+    SupportedBufferSize, SupportedStreamConfig, SupportedStreamConfigRange,
+};
+use std::cmp::Ordering;
+
+/// The rate preferred when a `ConfigRequest` does not name one, as by
+/// `SupportedStreamConfigRange::cmp_default_heuristics`.
+const DEFAULT_SAMPLE_RATE: SampleRate = SampleRate(44_100);
+
+/// A partial description of a stream config, matched against the configs a device supports by
+/// `DeviceTrait::match_input_config` and `match_output_config` or by `best_match`.
+///
+/// The channel count and sample formats are requirements that a config must meet to match. The
+/// sample rate and buffer size are preferences: configs that cannot meet them still match, with a
+/// lower score the further they are off.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ConfigRequest {
+    /// The preferred sample rate. `None` does not affect the score: configs that support 44.1 kHz
+    /// are matched at 44.1 kHz and others at their highest rate, and 44.1 kHz only decides between
+    /// configs of equal score after the channel count and sample format, as by
+    /// `SupportedStreamConfigRange::cmp_default_heuristics`.
+    pub sample_rate: Option<SampleRate>,
+    /// The minimum number of channels, with configs closest to it preferred. `None` accepts any
+    /// channel count, preferring stereo and then mono.
+    pub min_channels: Option<ChannelCount>,
+    /// The acceptable sample formats, in order of preference. Empty to accept any format.
+    pub sample_formats: Vec<SampleFormat>,
+    /// The preferred buffer size in frames. `None` leaves the buffer size to the host.
+    pub buffer_size: Option<FrameCount>,
+}
+
+/// The supported config that best matches a `ConfigRequest`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConfigMatch {
+    /// The matching config, at the supported sample rate closest to the requested one.
+    pub config: SupportedStreamConfig,
+    /// The buffer size to request: the requested size clamped to the supported range, or
+    /// `BufferSize::Default` if the request did not name one.
+    pub buffer_size: BufferSize,
+    /// How closely the config matches the request, above `0.0` and up to `1.0` for a config that
+    /// meets every preference exactly.
+    ///
+    /// The score is the product of a score per preference: the ratio of the supported sample rate
+    /// to the requested one, or the inverse if larger, and likewise for the channel count and the
+    /// buffer size, and `(n - i) / n` for the `i`th of `n` acceptable sample formats.
+    pub score: f32,
+}
+
+impl ConfigMatch {
+    /// The config to build a stream with, with the matched buffer size.
+    pub fn stream_config(&self) -> StreamConfig {
+        StreamConfig {
+            buffer_size: self.buffer_size.clone(),
+            ..self.config.config()
+        }
+    }
+}
+
+impl ConfigRequest {
+    /// The config among `supported` that best matches the request.
+    ///
+    /// Configs with equal scores are ranked by `SupportedStreamConfigRange::cmp_default_heuristics`.
+    /// Fails with the reason no config matched if none meets the requirements of the request.
+    pub fn best_match<I>(&self, supported: I) -> Result<ConfigMatch, MatchConfigError>
+    where
+        I: IntoIterator<Item = SupportedStreamConfigRange>,
+    {
+        let supported: Vec<_> = supported.into_iter().collect();
+        let best = supported
+            .iter()
+            .filter_map(|range| self.score(range).map(|score| (score, range)))
+            .max_by(|(a_score, a), (b_score, b)| {
+                a_score
+                    .partial_cmp(b_score)
+                    .unwrap_or(Ordering::Equal)
+                    .then_with(|| a.cmp_default_heuristics(b))
+            });
+        let (score, range) = match best {
+            Some(best) => best,
+            None => return Err(self.mismatch(&supported)),
+        };
+        let buffer_size = match self.buffer_size {
+            Some(frames) => BufferSize::Fixed(clamp_buffer_size(frames, &range.buffer_size)),
+            None => BufferSize::Default,
+        };
+        Ok(ConfigMatch {
+            config: range.clone().with_sample_rate(self.sample_rate_in(range)),
+            buffer_size,
+            score,
+        })
+    }
+
+    // The score of `range`, if it meets the requirements of the request.
+    fn score(&self, range: &SupportedStreamConfigRange) -> Option<f32> {
+        let mut score = 1.0;
+        if let Some(min_channels) = self.min_channels {
+            if range.channels < min_channels {
+                return None;
+            }
+            score *= ratio(range.channels as u32, min_channels as u32);
+        }
+        if !self.sample_formats.is_empty() {
+            let n = self.sample_formats.len();
+            let i = self
+                .sample_formats
+                .iter()
+                .position(|&format| format == range.sample_format)?;
+            score *= (n - i) as f32 / n as f32;
+        }
+        if let Some(sample_rate) = self.sample_rate {
+            score *= ratio(self.sample_rate_in(range).0, sample_rate.0);
+        }
+        if let Some(frames) = self.buffer_size {
+            score *= ratio(clamp_buffer_size(frames, &range.buffer_size), frames);
+        }
+        Some(score)
+    }
+
+    // The rate within `range` closest to the requested one.
+    fn sample_rate_in(&self, range: &SupportedStreamConfigRange) -> SampleRate {
+        let in_range = |rate| range.min_sample_rate <= rate && rate <= range.max_sample_rate;
+        match self.sample_rate {
+            Some(rate) => rate.max(range.min_sample_rate).min(range.max_sample_rate),
+            None if in_range(DEFAULT_SAMPLE_RATE) => DEFAULT_SAMPLE_RATE,
+            None => range.max_sample_rate,
+        }
+    }
+
+    // Why none of `supported` meets the requirements of the request.
+    fn mismatch(&self, supported: &[SupportedStreamConfigRange]) -> MatchConfigError {
+        if supported.is_empty() {
+            return MatchConfigError::NoSupportedConfigs;
+        }
+        let max_channels = supported.iter().map(|range| range.channels).max();
+        match (self.min_channels, max_channels) {
+            (Some(min_channels), Some(max_channels)) if max_channels < min_channels => {
+                return MatchConfigError::TooFewChannels {
+                    min_channels,
+                    max_channels,
+                };
+            }
+            _ => (),
+        }
+        let acceptable =
+            |range: &SupportedStreamConfigRange| self.sample_formats.contains(&range.sample_format);
+        if !self.sample_formats.is_empty() && !supported.iter().any(acceptable) {
+            let mut formats = Vec::new();
+            for range in supported {
+                if !formats.contains(&range.sample_format) {
+                    formats.push(range.sample_format);
+                }
+            }
+            return MatchConfigError::NoAcceptableFormat { supported: formats };
+        }
+        MatchConfigError::NoMatchingConfig
+    }
+}
+
+// The smaller of `a` and `b` relative to the larger.
+fn ratio(a: u32, b: u32) -> f32 {
+    if a == b {
+        1.0
+    } else {
+        a.min(b) as f32 / a.max(b) as f32
+    }
+}
+
+fn clamp_buffer_size(frames: FrameCount, supported: &SupportedBufferSize) -> FrameCount {
+    match *supported {
+        SupportedBufferSize::Range { min, max } => frames.max(min).min(max),
+        SupportedBufferSize::Unknown => frames,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ConfigRequest;
+    use {
+        BufferSize, MatchConfigError, SampleFormat, SampleRate, SupportedBufferSize,
+        SupportedStreamConfigRange,
+    };
+
+    fn supported() -> Vec<SupportedStreamConfigRange> {
+        let range = |channels, min, max, sample_format| {
+            SupportedStreamConfigRange::new(
+                channels,
+                SampleRate(min),
+                SampleRate(max),
+                SupportedBufferSize::Range { min: 64, max: 4096 },
+                sample_format,
+            )
+        };
+        vec![
+            range(2, 44_100, 48_000, SampleFormat::I16),
+            range(2, 48_000, 48_000, SampleFormat::F32),
+            range(8, 8_000, 192_000, SampleFormat::I32),
+        ]
+    }
+
+    #[test]
+    fn test_best_match() {
+        // Without preferences, the default heuristics decide.
+        let best = ConfigRequest::default().best_match(supported()).unwrap();
+        assert_eq!(best.config.sample_format(), SampleFormat::F32);
+        assert_eq!(best.config.sample_rate(), SampleRate(48_000));
+        assert_eq!(best.buffer_size, BufferSize::Default);
+        assert_eq!(best.score, 1.0);
+        // Between configs that differ only in their rates, the one supporting 44.1 kHz wins.
+        let mut i16_only = supported();
+        i16_only[1].sample_format = SampleFormat::I16;
+        let best = ConfigRequest::default().best_match(i16_only).unwrap();
+        assert_eq!(best.config.sample_rate(), SampleRate(44_100));
+        assert_eq!(best.score, 1.0);
+
+        let request = ConfigRequest {
+            sample_rate: Some(SampleRate(44_100)),
+            buffer_size: Some(8192),
+            ..Default::default()
+        };
+        let best = request.best_match(supported()).unwrap();
+        assert_eq!(best.config.sample_format(), SampleFormat::I16);
+        assert_eq!(best.config.sample_rate(), SampleRate(44_100));
+        assert_eq!(best.stream_config().buffer_size, BufferSize::Fixed(4096));
+        assert_eq!(best.score, 0.5);
+
+        let request = ConfigRequest {
+            min_channels: Some(4),
+            sample_formats: vec![SampleFormat::F32, SampleFormat::I32],
+            ..Default::default()
+        };
+        let best = request.best_match(supported()).unwrap();
+        assert_eq!(best.config.channels(), 8);
+        assert_eq!(best.score, 0.25);
+    }
+
+    #[test]
+    fn test_match_failures() {
+        let request = ConfigRequest {
+            min_channels: Some(16),
+            ..Default::default()
+        };
+        match request.best_match(supported()) {
+            Err(MatchConfigError::TooFewChannels {
+                min_channels: 16,
+                max_channels: 8,
+            }) => (),
+            result => panic!("unexpected result {:?}", result),
+        }
+        let request = ConfigRequest {
+            sample_formats: vec![SampleFormat::U8],
+            ..Default::default()
+        };
+        match request.best_match(supported()) {
+            Err(MatchConfigError::NoAcceptableFormat { supported }) => assert_eq!(
+                supported,
+                vec![SampleFormat::I16, SampleFormat::F32, SampleFormat::I32]
+            ),
+            result => panic!("unexpected result {:?}", result),
+        }
+        let request = ConfigRequest {
+            min_channels: Some(4),
+            sample_formats: vec![SampleFormat::F32],
+            ..Default::default()
+        };
+        match request.best_match(supported()) {
+            Err(MatchConfigError::NoMatchingConfig) => (),
+            result => panic!("unexpected result {:?}", result),
+        }
+        match ConfigRequest::default().best_match(Vec::new()) {
+            Err(MatchConfigError::NoSupportedConfigs) => (),
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+}
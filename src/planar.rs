@@ -0,0 +1,246 @@
+//! Planar streams, whose data callbacks receive a separate buffer for each channel rather than
+//! interleaved samples.
+
+use crate::{
+    BuildStreamError, ChannelCount, Data, InputCallbackInfo, OutputCallbackInfo, SampleFormat,
+    StreamConfig, StreamError,
+};
+use convert::max_buffer_frames;
+use std::{mem, slice};
+use traits::DeviceTrait;
+
+/// Holds the buffers of the channels of a planar stream that is layered on top of an interleaved
+/// one, converting between both layouts.
+struct PlanarBuffer {
+    channels: usize,
+    sample_format: SampleFormat,
+    frames: usize,
+    // `u64` so that the buffers are aligned for every sample format.
+    storage: Vec<u64>,
+    planes: Vec<*mut ()>,
+}
+
+// The pointers in `planes` only ever point into `storage`, which is owned by the buffer.
+unsafe impl Send for PlanarBuffer {}
+
+impl PlanarBuffer {
+    // A buffer with room for `max_frames` frames, so that it never allocates on the audio thread
+    // for the buffers the host passes.
+    fn new(channels: usize, sample_format: SampleFormat, max_frames: usize) -> Self {
+        let mut buffer = PlanarBuffer {
+            channels,
+            sample_format,
+            frames: 0,
+            storage: Vec::new(),
+            planes: Vec::with_capacity(channels),
+        };
+        buffer.reserve(max_frames);
+        buffer
+    }
+
+    fn frame_size(&self) -> usize {
+        self.channels * self.sample_format.sample_size()
+    }
+
+    fn plane_size(&self) -> usize {
+        self.frames * self.sample_format.sample_size()
+    }
+
+    fn reserve(&mut self, frames: usize) {
+        let len = frames * self.frame_size();
+        let words = len.div_ceil(mem::size_of::<u64>());
+        if self.storage.len() < words {
+            self.storage.resize(words, 0);
+        }
+    }
+
+    // Makes room for as many frames as `interleaved` holds. Only allocates if the host passes more
+    // frames than the buffer was created for.
+    fn resize_for(&mut self, interleaved: &[u8]) {
+        let frames = match self.frame_size() {
+            0 => 0,
+            frame_size => interleaved.len() / frame_size,
+        };
+        if frames == self.frames && self.planes.len() == self.channels {
+            return;
+        }
+        self.frames = frames;
+        self.reserve(frames);
+        let plane_size = self.plane_size();
+        let base = self.storage.as_mut_ptr() as *mut u8;
+        self.planes.clear();
+        for channel in 0..self.channels {
+            // Each plane starts at a multiple of the sample size, keeping samples aligned.
+            self.planes
+                .push(unsafe { base.add(channel * plane_size) } as *mut ());
+        }
+    }
+
+    fn bytes_mut(&mut self) -> &mut [u8] {
+        let len = self.plane_size() * self.channels;
+        unsafe { slice::from_raw_parts_mut(self.storage.as_mut_ptr() as *mut u8, len) }
+    }
+
+    fn deinterleave(&mut self, interleaved: &[u8]) {
+        self.resize_for(interleaved);
+        let (frame_size, plane_size) = (self.frame_size(), self.plane_size());
+        if frame_size == 0 {
+            return;
+        }
+        let sample_size = self.sample_format.sample_size();
+        let planar = self.bytes_mut();
+        for (frame_index, frame) in interleaved.chunks_exact(frame_size).enumerate() {
+            for (channel, sample) in frame.chunks_exact(sample_size).enumerate() {
+                let offset = channel * plane_size + frame_index * sample_size;
+                planar[offset..offset + sample_size].copy_from_slice(sample);
+            }
+        }
+    }
+
+    fn interleave(&mut self, interleaved: &mut [u8]) {
+        let (frame_size, plane_size) = (self.frame_size(), self.plane_size());
+        if frame_size == 0 {
+            return;
+        }
+        let sample_size = self.sample_format.sample_size();
+        let planar = self.bytes_mut();
+        for (frame_index, frame) in interleaved.chunks_exact_mut(frame_size).enumerate() {
+            for (channel, sample) in frame.chunks_exact_mut(sample_size).enumerate() {
+                let offset = channel * plane_size + frame_index * sample_size;
+                sample.copy_from_slice(&planar[offset..offset + sample_size]);
+            }
+        }
+    }
+
+    fn data(&mut self) -> Data {
+        // The planes point to `channels` non-overlapping buffers of `frames` samples each.
+        unsafe {
+            Data::from_planar_parts(
+                self.planes.as_ptr(),
+                self.channels,
+                self.frames,
+                self.sample_format,
+            )
+        }
+    }
+}
+
+/// Wraps the data callback of a planar input stream into one for an interleaved stream, which
+/// de-interleaves the data into buffers with room for `max_frames` frames.
+pub(crate) fn deinterleave_input<D>(
+    channels: ChannelCount,
+    sample_format: SampleFormat,
+    max_frames: usize,
+    mut data_callback: D,
+) -> impl FnMut(&Data, &InputCallbackInfo) + Send + 'static
+where
+    D: FnMut(&Data, &InputCallbackInfo) + Send + 'static,
+{
+    let mut planar = PlanarBuffer::new(channels as usize, sample_format, max_frames);
+    move |data, info| {
+        planar.deinterleave(data.bytes());
+        data_callback(&planar.data(), info);
+    }
+}
+
+/// Wraps the data callback of a planar output stream into one for an interleaved stream, which
+/// interleaves the data written to buffers with room for `max_frames` frames.
+pub(crate) fn interleave_output<D>(
+    channels: ChannelCount,
+    sample_format: SampleFormat,
+    max_frames: usize,
+    mut data_callback: D,
+) -> impl FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static
+where
+    D: FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static,
+{
+    let mut planar = PlanarBuffer::new(channels as usize, sample_format, max_frames);
+    move |data, info| {
+        let interleaved = data.bytes_mut();
+        planar.resize_for(interleaved);
+        data_callback(&mut planar.data(), info);
+        planar.interleave(interleaved);
+    }
+}
+
+/// The default implementation of `DeviceTrait::build_input_stream_planar_raw`, which de-interleaves
+/// the data of a stream created via `build_input_stream_raw`.
+pub(crate) fn build_input_stream_planar_raw<Dev, D, E>(
+    device: &Dev,
+    config: &StreamConfig,
+    sample_format: SampleFormat,
+    data_callback: D,
+    error_callback: E,
+) -> Result<Dev::Stream, BuildStreamError>
+where
+    Dev: DeviceTrait + ?Sized,
+    D: FnMut(&Data, &InputCallbackInfo) + Send + 'static,
+    E: FnMut(StreamError) + Send + 'static,
+{
+    let frames = max_buffer_frames(device.supported_input_configs(), config);
+    device.build_input_stream_raw(
+        config,
+        sample_format,
+        deinterleave_input(config.channels, sample_format, frames, data_callback),
+        error_callback,
+    )
+}
+
+/// The default implementation of `DeviceTrait::build_output_stream_planar_raw`, which interleaves
+/// the data written by the callback into the buffer of a stream created via
+/// `build_output_stream_raw`.
+pub(crate) fn build_output_stream_planar_raw<Dev, D, E>(
+    device: &Dev,
+    config: &StreamConfig,
+    sample_format: SampleFormat,
+    data_callback: D,
+    error_callback: E,
+) -> Result<Dev::Stream, BuildStreamError>
+where
+    Dev: DeviceTrait + ?Sized,
+    D: FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static,
+    E: FnMut(StreamError) + Send + 'static,
+{
+    let frames = max_buffer_frames(device.supported_output_configs(), config);
+    device.build_output_stream_raw(
+        config,
+        sample_format,
+        interleave_output(config.channels, sample_format, frames, data_callback),
+        error_callback,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PlanarBuffer;
+    use SampleFormat;
+
+    #[test]
+    fn test_planar_buffer_round_trip() {
+        let interleaved: Vec<i16> = vec![1, -1, 2, -2, 3, -3];
+        let bytes: Vec<u8> = interleaved.iter().flat_map(|s| s.to_ne_bytes()).collect();
+        let mut planar = PlanarBuffer::new(2, SampleFormat::I16, 3);
+        let storage = planar.storage.as_ptr();
+        planar.deinterleave(&bytes);
+        {
+            let data = planar.data();
+            assert_eq!(data.planes(), Some(2));
+            assert_eq!(data.len(), 6);
+            assert_eq!(data.channel::<i16>(0), Some(&[1i16, 2, 3][..]));
+            assert_eq!(data.channel::<i16>(1), Some(&[-1i16, -2, -3][..]));
+            assert_eq!(data.channel::<i16>(2), None);
+            assert_eq!(data.channel::<f32>(0), None);
+            assert_eq!(data.as_slice::<i16>(), None);
+        }
+        planar.data().channel_mut::<i16>(1).unwrap()[1] = 7;
+        let mut out = vec![0u8; bytes.len()];
+        planar.interleave(&mut out);
+        let out: Vec<i16> = out
+            .chunks_exact(2)
+            .map(|b| i16::from_ne_bytes([b[0], b[1]]))
+            .collect();
+        assert_eq!(out, vec![1, -1, 2, 7, 3, -3]);
+        // The storage was sized when the buffer was created.
+        assert_eq!(planar.storage.as_ptr(), storage);
+    }
+}
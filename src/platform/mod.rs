@@ -7,6 +7,16 @@
 #[doc(inline)]
 pub use self::platform_impl::*;
 
+/// The test host, available on all platforms with the `test-host` feature.
+///
+/// It is not part of the dynamically dispatched `Host`, as its streams must be driven via the
+/// `TestStreamHandle` retrieved from the concrete `TestStream` type.
+#[cfg(feature = "test-host")]
+pub use crate::host::test::{
+    Device as TestDevice, Devices as TestDevices, Host as TestHost, Stream as TestStream,
+    StreamHandle as TestStreamHandle,
+};
+
 // A macro to assist with implementing a platform's dynamically dispatched `Host` type.
 //
 // These dynamically dispatched types are necessary to allow for users to switch between hosts at
@@ -77,13 +87,25 @@ macro_rules! impl_platform_host {
 
         /// Unique identifier for available hosts on the platform.
         #[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+        #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
         pub enum HostId {
             $(
                 $HostVariant,
             )*
         }
 
-        enum DeviceInner {
+        /// A reference to a device, by its host and `DeviceTrait::id`, that can be stored or sent
+        /// elsewhere and resolved back into the device later.
+        #[derive(Clone, Debug, Eq, Hash, PartialEq)]
+        #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+        pub struct DeviceDescriptor {
+            pub host: HostId,
+            pub id: String,
+        }
+
+        /// The host-specific **Device** wrapped by the platform's dynamically dispatched **Device**
+        /// type, giving access to host-specific functionality.
+        pub enum DeviceInner {
             $(
                 $HostVariant(crate::host::$host_mod::Device),
             )*
@@ -95,13 +117,17 @@ macro_rules! impl_platform_host {
             )*
         }
 
-        enum HostInner {
+        /// The host-specific **Host** wrapped by the platform's dynamically dispatched **Host**
+        /// type, giving access to host-specific functionality.
+        pub enum HostInner {
             $(
                 $HostVariant(crate::host::$host_mod::Host),
             )*
         }
 
-        enum StreamInner {
+        /// The host-specific **Stream** wrapped by the platform's dynamically dispatched **Stream**
+        /// type, giving access to host-specific functionality.
+        pub enum StreamInner {
             $(
                 $HostVariant(crate::host::$host_mod::Stream),
             )*
@@ -138,6 +164,76 @@ macro_rules! impl_platform_host {
                     )*
                 }
             }
+
+            /// A reference to the host-specific `HostInner` of this host.
+            pub fn as_inner(&self) -> &HostInner {
+                &self.0
+            }
+
+            /// A mutable reference to the host-specific `HostInner` of this host.
+            pub fn as_inner_mut(&mut self) -> &mut HostInner {
+                &mut self.0
+            }
+
+            /// Unwraps the host-specific `HostInner` of this host.
+            pub fn into_inner(self) -> HostInner {
+                self.0
+            }
+        }
+
+        impl Device {
+            /// A reference to the host-specific `DeviceInner` of this device.
+            pub fn as_inner(&self) -> &DeviceInner {
+                &self.0
+            }
+
+            /// A mutable reference to the host-specific `DeviceInner` of this device.
+            pub fn as_inner_mut(&mut self) -> &mut DeviceInner {
+                &mut self.0
+            }
+
+            /// Unwraps the host-specific `DeviceInner` of this device.
+            pub fn into_inner(self) -> DeviceInner {
+                self.0
+            }
+
+            /// A descriptor of this device, to find it again via `DeviceDescriptor::resolve`.
+            pub fn descriptor(&self) -> Result<DeviceDescriptor, crate::DeviceNameError> {
+                let host = match self.0 {
+                    $(
+                        DeviceInner::$HostVariant(_) => HostId::$HostVariant,
+                    )*
+                };
+                let id = crate::traits::DeviceTrait::id(self)?;
+                Ok(DeviceDescriptor { host, id })
+            }
+        }
+
+        impl DeviceDescriptor {
+            /// The device this descriptor refers to, via `host_from_id` and
+            /// `HostTrait::device_by_id`.
+            pub fn resolve(&self) -> Result<Device, crate::ResolveDeviceError> {
+                let host = host_from_id(self.host)?;
+                crate::traits::HostTrait::device_by_id(&host, &self.id)
+                    .ok_or(crate::ResolveDeviceError::DeviceNotFound)
+            }
+        }
+
+        impl Stream {
+            /// A reference to the host-specific `StreamInner` of this stream.
+            pub fn as_inner(&self) -> &StreamInner {
+                &self.0
+            }
+
+            /// A mutable reference to the host-specific `StreamInner` of this stream.
+            pub fn as_inner_mut(&mut self) -> &mut StreamInner {
+                &mut self.0
+            }
+
+            /// Unwraps the host-specific `StreamInner` of this stream.
+            pub fn into_inner(self) -> StreamInner {
+                self.0
+            }
         }
 
         impl Iterator for Devices {
@@ -215,6 +311,15 @@ macro_rules! impl_platform_host {
                 }
             }
 
+            fn id(&self) -> Result<String, crate::DeviceNameError> {
+                match self.0 {
+                    $(
+                        // Hosts may have an inherent `id` of another type.
+                        DeviceInner::$HostVariant(ref d) => crate::traits::DeviceTrait::id(d),
+                    )*
+                }
+            }
+
             fn supported_input_configs(&self) -> Result<Self::SupportedInputConfigs, crate::SupportedStreamConfigsError> {
                 match self.0 {
                     $(
@@ -306,6 +411,112 @@ macro_rules! impl_platform_host {
                     )*
                 }
             }
+
+            fn build_duplex_stream_raw<D, E>(
+                &self,
+                input_config: &crate::StreamConfig,
+                output_config: &crate::StreamConfig,
+                sample_format: crate::SampleFormat,
+                data_callback: D,
+                error_callback: E,
+            ) -> Result<Self::Stream, crate::BuildStreamError>
+            where
+                D: FnMut(&crate::Data, &mut crate::Data, &crate::DuplexCallbackInfo) + Send + 'static,
+                E: FnMut(crate::StreamError) + Send + 'static,
+            {
+                match self.0 {
+                    $(
+                        DeviceInner::$HostVariant(ref d) => d
+                            .build_duplex_stream_raw(
+                                input_config,
+                                output_config,
+                                sample_format,
+                                data_callback,
+                                error_callback,
+                            )
+                            .map(StreamInner::$HostVariant)
+                            .map(Stream::from),
+                    )*
+                }
+            }
+
+            fn build_input_stream_planar_raw<D, E>(
+                &self,
+                config: &crate::StreamConfig,
+                sample_format: crate::SampleFormat,
+                data_callback: D,
+                error_callback: E,
+            ) -> Result<Self::Stream, crate::BuildStreamError>
+            where
+                D: FnMut(&crate::Data, &crate::InputCallbackInfo) + Send + 'static,
+                E: FnMut(crate::StreamError) + Send + 'static,
+            {
+                match self.0 {
+                    $(
+                        DeviceInner::$HostVariant(ref d) => d
+                            .build_input_stream_planar_raw(
+                                config,
+                                sample_format,
+                                data_callback,
+                                error_callback,
+                            )
+                            .map(StreamInner::$HostVariant)
+                            .map(Stream::from),
+                    )*
+                }
+            }
+
+            fn build_output_stream_planar_raw<D, E>(
+                &self,
+                config: &crate::StreamConfig,
+                sample_format: crate::SampleFormat,
+                data_callback: D,
+                error_callback: E,
+            ) -> Result<Self::Stream, crate::BuildStreamError>
+            where
+                D: FnMut(&mut crate::Data, &crate::OutputCallbackInfo) + Send + 'static,
+                E: FnMut(crate::StreamError) + Send + 'static,
+            {
+                match self.0 {
+                    $(
+                        DeviceInner::$HostVariant(ref d) => d
+                            .build_output_stream_planar_raw(
+                                config,
+                                sample_format,
+                                data_callback,
+                                error_callback,
+                            )
+                            .map(StreamInner::$HostVariant)
+                            .map(Stream::from),
+                    )*
+                }
+            }
+
+            fn build_input_stream_blocking(
+                &self,
+                config: &crate::StreamConfig,
+                sample_format: crate::SampleFormat,
+            ) -> Result<crate::InputStreamReader, crate::BuildStreamError> {
+                match self.0 {
+                    $(
+                        DeviceInner::$HostVariant(ref d) => d
+                            .build_input_stream_blocking(config, sample_format),
+                    )*
+                }
+            }
+
+            fn build_output_stream_blocking(
+                &self,
+                config: &crate::StreamConfig,
+                sample_format: crate::SampleFormat,
+            ) -> Result<crate::OutputStreamWriter, crate::BuildStreamError> {
+                match self.0 {
+                    $(
+                        DeviceInner::$HostVariant(ref d) => d
+                            .build_output_stream_blocking(config, sample_format),
+                    )*
+                }
+            }
         }
 
         impl crate::traits::HostTrait for Host {
@@ -326,6 +537,16 @@ macro_rules! impl_platform_host {
                 }
             }
 
+            fn device_by_id(&self, id: &str) -> Option<Self::Device> {
+                match self.0 {
+                    $(
+                        HostInner::$HostVariant(ref h) => {
+                            h.device_by_id(id).map(DeviceInner::$HostVariant).map(Device::from)
+                        }
+                    )*
+                }
+            }
+
             fn default_input_device(&self) -> Option<Self::Device> {
                 match self.0 {
                     $(
@@ -345,6 +566,20 @@ macro_rules! impl_platform_host {
                     )*
                 }
             }
+
+            fn watch_devices<F>(
+                &self,
+                callback: F,
+            ) -> Result<crate::DeviceWatcher, crate::WatchDevicesError>
+            where
+                F: FnMut(crate::DeviceEvent) + Send + 'static,
+            {
+                match self.0 {
+                    $(
+                        HostInner::$HostVariant(ref h) => h.watch_devices(callback),
+                    )*
+                }
+            }
         }
 
         impl crate::traits::StreamTrait for Stream {
@@ -367,6 +602,96 @@ macro_rules! impl_platform_host {
                     )*
                 }
             }
+
+            fn config(&self) -> Option<crate::StreamConfig> {
+                match self.0 {
+                    $(
+                        StreamInner::$HostVariant(ref s) => {
+                            s.config()
+                        }
+                    )*
+                }
+            }
+
+            fn buffer_size(&self) -> Option<crate::FrameCount> {
+                match self.0 {
+                    $(
+                        StreamInner::$HostVariant(ref s) => {
+                            s.buffer_size()
+                        }
+                    )*
+                }
+            }
+
+            fn period_size(&self) -> Option<crate::FrameCount> {
+                match self.0 {
+                    $(
+                        StreamInner::$HostVariant(ref s) => {
+                            s.period_size()
+                        }
+                    )*
+                }
+            }
+
+            fn latency(&self) -> Option<std::time::Duration> {
+                match self.0 {
+                    $(
+                        StreamInner::$HostVariant(ref s) => {
+                            s.latency()
+                        }
+                    )*
+                }
+            }
+
+            fn volume_range(&self) -> Option<crate::VolumeRange> {
+                match self.0 {
+                    $(
+                        StreamInner::$HostVariant(ref s) => {
+                            s.volume_range()
+                        }
+                    )*
+                }
+            }
+
+            fn volume(&self) -> Result<f32, crate::VolumeError> {
+                match self.0 {
+                    $(
+                        StreamInner::$HostVariant(ref s) => {
+                            s.volume()
+                        }
+                    )*
+                }
+            }
+
+            fn set_volume(&self, volume_db: f32) -> Result<(), crate::VolumeError> {
+                match self.0 {
+                    $(
+                        StreamInner::$HostVariant(ref s) => {
+                            s.set_volume(volume_db)
+                        }
+                    )*
+                }
+            }
+
+            fn is_muted(&self) -> Result<bool, crate::VolumeError> {
+                match self.0 {
+                    $(
+                        StreamInner::$HostVariant(ref s) => {
+                            s.is_muted()
+                        }
+                    )*
+                }
+            }
+
+            fn set_muted(&self, muted: bool) -> Result<(), crate::VolumeError> {
+                match self.0 {
+                    $(
+                        StreamInner::$HostVariant(ref s) => {
+                            s.set_muted(muted)
+                        }
+                    )*
+                }
+            }
         }
 
         impl From<DeviceInner> for Device {
@@ -445,26 +770,79 @@ macro_rules! impl_platform_host {
     };
 }
 
-// TODO: Add pulseaudio and jack here eventually.
 #[cfg(any(target_os = "linux", target_os = "dragonfly", target_os = "freebsd"))]
 mod platform_impl {
     pub use crate::host::alsa::{
-        Device as AlsaDevice, Devices as AlsaDevices, Host as AlsaHost, Stream as AlsaStream,
+        AccessMode as AlsaAccessMode, CardInfo as AlsaCardInfo, Device as AlsaDevice,
+        DeviceInfo as AlsaDeviceInfo, Devices as AlsaDevices, Host as AlsaHost,
+        PcmKind as AlsaPcmKind, Stream as AlsaStream,
         SupportedInputConfigs as AlsaSupportedInputConfigs,
         SupportedOutputConfigs as AlsaSupportedOutputConfigs,
     };
     #[cfg(feature = "jack")]
     pub use crate::host::jack::{
-        Device as JackDevice, Devices as JackDevices, Host as JackHost, Stream as JackStream,
+        CallbackInfoExt as JackCallbackInfoExt, Device as JackDevice, Devices as JackDevices,
+        Host as JackHost, Midi as JackMidi, MidiEvent as JackMidiEvent,
+        MidiWriteError as JackMidiWriteError, PortDirection as JackPortDirection,
+        PortEvent as JackPortEvent, Stream as JackStream,
         SupportedInputConfigs as JackSupportedInputConfigs,
-        SupportedOutputConfigs as JackSupportedOutputConfigs,
+        SupportedOutputConfigs as JackSupportedOutputConfigs, TransportBbt as JackTransportBbt,
+        TransportInfo as JackTransportInfo, TransportState as JackTransportState,
+    };
+    #[cfg(feature = "pipewire")]
+    pub use crate::host::pipewire::{
+        Device as PipeWireDevice, Devices as PipeWireDevices, Host as PipeWireHost,
+        Stream as PipeWireStream, SupportedInputConfigs as PipeWireSupportedInputConfigs,
+        SupportedOutputConfigs as PipeWireSupportedOutputConfigs,
+    };
+    #[cfg(feature = "pulseaudio")]
+    pub use crate::host::pulseaudio::{
+        Device as PulseAudioDevice, Devices as PulseAudioDevices, Host as PulseAudioHost,
+        Stream as PulseAudioStream, SupportedInputConfigs as PulseAudioSupportedInputConfigs,
+        SupportedOutputConfigs as PulseAudioSupportedOutputConfigs,
     };
 
-    #[cfg(feature = "jack")]
-    impl_platform_host!(Jack jack "JACK", Alsa alsa "ALSA");
+    pub use crate::host::null::{
+        Device as NullDevice, Devices as NullDevices, Host as NullHost, Stream as NullStream,
+        SupportedInputConfigs as NullSupportedInputConfigs,
+        SupportedOutputConfigs as NullSupportedOutputConfigs,
+    };
+
+    #[cfg(all(feature = "jack", feature = "pipewire", feature = "pulseaudio"))]
+    impl_platform_host!(Jack jack "JACK", PipeWire pipewire "PipeWire", PulseAudio pulseaudio "PulseAudio", Alsa alsa "ALSA", Null null "Null");
+
+    #[cfg(all(feature = "jack", feature = "pipewire", not(feature = "pulseaudio")))]
+    impl_platform_host!(Jack jack "JACK", PipeWire pipewire "PipeWire", Alsa alsa "ALSA", Null null "Null");
+
+    #[cfg(all(feature = "jack", not(feature = "pipewire"), feature = "pulseaudio"))]
+    impl_platform_host!(Jack jack "JACK", PulseAudio pulseaudio "PulseAudio", Alsa alsa "ALSA", Null null "Null");
+
+    #[cfg(all(
+        feature = "jack",
+        not(feature = "pipewire"),
+        not(feature = "pulseaudio")
+    ))]
+    impl_platform_host!(Jack jack "JACK", Alsa alsa "ALSA", Null null "Null");
+
+    #[cfg(all(not(feature = "jack"), feature = "pipewire", feature = "pulseaudio"))]
+    impl_platform_host!(PipeWire pipewire "PipeWire", PulseAudio pulseaudio "PulseAudio", Alsa alsa "ALSA", Null null "Null");
+
+    #[cfg(all(
+        not(feature = "jack"),
+        feature = "pipewire",
+        not(feature = "pulseaudio")
+    ))]
+    impl_platform_host!(PipeWire pipewire "PipeWire", Alsa alsa "ALSA", Null null "Null");
+
+    #[cfg(all(
+        not(feature = "jack"),
+        not(feature = "pipewire"),
+        feature = "pulseaudio"
+    ))]
+    impl_platform_host!(PulseAudio pulseaudio "PulseAudio", Alsa alsa "ALSA", Null null "Null");
 
-    #[cfg(not(feature = "jack"))]
-    impl_platform_host!(Alsa alsa "ALSA");
+    #[cfg(not(any(feature = "jack", feature = "pipewire", feature = "pulseaudio")))]
+    impl_platform_host!(Alsa alsa "ALSA", Null null "Null");
 
     /// The default host for the current compilation target platform.
     pub fn default_host() -> Host {
@@ -587,7 +965,7 @@ mod platform_impl {
 )))]
 mod platform_impl {
     pub use crate::host::null::{
-        Device as NullDevice, Devices as NullDevices, Host as NullHost,
+        Device as NullDevice, Devices as NullDevices, Host as NullHost, Stream as NullStream,
         SupportedInputConfigs as NullSupportedInputConfigs,
         SupportedOutputConfigs as NullSupportedOutputConfigs,
     };
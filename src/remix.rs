@@ -0,0 +1,480 @@
+//! Streams whose data callbacks see a channel count the device may not support, opened with a
+//! channel count the device does support and mixed between the two.
+
+use crate::{
+    BuildStreamError, ChannelCount, ChannelPosition, DefaultStreamConfigError, InputCallbackInfo,
+    OutputCallbackInfo, Sample, StreamConfig, StreamError, SupportedStreamConfig,
+    SupportedStreamConfigRange, SupportedStreamConfigsError,
+};
+use convert::{build_stream_error, max_buffer_frames};
+use std::f32::consts::FRAC_1_SQRT_2;
+use traits::DeviceTrait;
+
+/// How the channels of a remixed stream are mixed into those of the device, or the other way
+/// around for input streams.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum ChannelMix {
+    /// Open the device with the requested channel count if it supports it, and otherwise with the
+    /// smallest larger count it supports, or failing that the largest one.
+    ///
+    /// Channels are mixed by position, as given by the channel maps of the stream config and the
+    /// device or the default layouts of their channel counts. Channels missing on the other side
+    /// are folded into their nearest neighbours: mono is duplicated to both front channels, stereo
+    /// is averaged to mono, and 5.1 and 7.1 are downmixed with the centre and surround channels
+    /// at -3 dB and the LFE channel dropped. Mixes that could clip are scaled down to unity gain.
+    /// Channels without a known position are mixed by index instead.
+    #[default]
+    Auto,
+    /// A mix matrix with a row of gains per channel the mix produces and a column per channel it
+    /// mixes from: a row per channel of the device and a column per channel of the stream for
+    /// output streams, and the other way around for input streams.
+    ///
+    /// The device is opened with as many channels as the matrix has rows or columns respectively.
+    Matrix(Vec<Vec<f32>>),
+}
+
+/// A mix from `inputs` channels into `outputs` channels.
+#[derive(Debug, PartialEq)]
+struct Remix {
+    inputs: usize,
+    outputs: usize,
+    // The gains of the inputs, row by row for every output.
+    gains: Vec<f32>,
+}
+
+impl Remix {
+    fn from_rows(rows: Vec<Vec<f32>>) -> Result<Self, BuildStreamError> {
+        let inputs = rows.first().map_or(0, Vec::len);
+        if inputs == 0
+            || rows.len() > ChannelCount::MAX as usize
+            || inputs > ChannelCount::MAX as usize
+            || rows.iter().any(|row| row.len() != inputs)
+        {
+            return Err(BuildStreamError::InvalidArgument);
+        }
+        Ok(Remix {
+            inputs,
+            outputs: rows.len(),
+            gains: rows.into_iter().flatten().collect(),
+        })
+    }
+
+    /// The mix between two layouts of `inputs` and `outputs` channels, described by
+    /// `ChannelMix::Auto`.
+    fn auto(
+        input_layout: Option<Vec<ChannelPosition>>,
+        output_layout: Option<Vec<ChannelPosition>>,
+        inputs: usize,
+        outputs: usize,
+    ) -> Self {
+        let mut gains = vec![0.0; inputs * outputs];
+        fn known(layout: &Option<Vec<ChannelPosition>>, len: usize) -> Option<&[ChannelPosition]> {
+            layout
+                .as_deref()
+                .filter(|layout| layout.len() == len && !layout.contains(&ChannelPosition::Unknown))
+        }
+
+        match (known(&input_layout, inputs), known(&output_layout, outputs)) {
+            (Some(input_layout), Some(output_layout)) => {
+                let mut column = vec![0.0; outputs];
+                for (input, &position) in input_layout.iter().enumerate() {
+                    column.iter_mut().for_each(|gain| *gain = 0.0);
+                    fold(position, 1.0, output_layout, &mut column);
+                    for (output, gain) in column.iter().enumerate() {
+                        gains[output * inputs + input] = *gain;
+                    }
+                }
+                for row in gains.chunks_mut(inputs) {
+                    let sum: f32 = row.iter().sum();
+                    if sum > 1.0 {
+                        row.iter_mut().for_each(|gain| *gain /= sum);
+                    }
+                }
+            }
+            _ if inputs == 1 => {
+                for gain in gains.iter_mut().take(2) {
+                    *gain = 1.0;
+                }
+            }
+            _ if outputs == 1 => {
+                for gain in &mut gains {
+                    *gain = 1.0 / inputs as f32;
+                }
+            }
+            _ => {
+                for channel in 0..inputs.min(outputs) {
+                    gains[channel * inputs + channel] = 1.0;
+                }
+            }
+        }
+        Remix {
+            inputs,
+            outputs,
+            gains,
+        }
+    }
+
+    /// Mix the frames of `input` into those of `output`.
+    fn apply<S, T>(&self, input: &[S], output: &mut [T])
+    where
+        S: Sample,
+        T: Sample,
+    {
+        let frames = input
+            .chunks_exact(self.inputs)
+            .zip(output.chunks_exact_mut(self.outputs));
+        for (input, output) in frames {
+            for (sample, gains) in output.iter_mut().zip(self.gains.chunks(self.inputs)) {
+                let mixed: f32 = gains
+                    .iter()
+                    .zip(input)
+                    .map(|(gain, sample)| gain * sample.to_f32())
+                    .sum();
+                *sample = T::from(&mixed);
+            }
+        }
+    }
+}
+
+// Add a channel at `position` with `gain` to the `gains` of the channels of `layout`, folding it
+// into its nearest neighbours if `layout` lacks its position.
+fn fold(position: ChannelPosition, gain: f32, layout: &[ChannelPosition], gains: &mut [f32]) {
+    use self::ChannelPosition::*;
+    if position != Unknown {
+        if let Some(index) = layout.iter().position(|&p| p == position) {
+            gains[index] += gain;
+            return;
+        }
+    }
+    let has = |position| layout.contains(&position);
+    match position {
+        Mono => {
+            if has(FrontLeft) && has(FrontRight) {
+                fold(FrontLeft, gain, layout, gains);
+                fold(FrontRight, gain, layout, gains);
+            } else if has(FrontCenter) {
+                fold(FrontCenter, gain, layout, gains);
+            }
+        }
+        FrontLeft | FrontRight => {
+            if has(Mono) {
+                fold(Mono, gain * 0.5, layout, gains);
+            } else if has(FrontCenter) {
+                fold(FrontCenter, gain * 0.5, layout, gains);
+            }
+        }
+        FrontCenter => {
+            fold(FrontLeft, gain * FRAC_1_SQRT_2, layout, gains);
+            fold(FrontRight, gain * FRAC_1_SQRT_2, layout, gains);
+        }
+        FrontLeftOfCenter => fold(FrontLeft, gain, layout, gains),
+        FrontRightOfCenter => fold(FrontRight, gain, layout, gains),
+        RearLeft if has(SideLeft) => fold(SideLeft, gain, layout, gains),
+        RearRight if has(SideRight) => fold(SideRight, gain, layout, gains),
+        SideLeft if has(RearLeft) => fold(RearLeft, gain, layout, gains),
+        SideRight if has(RearRight) => fold(RearRight, gain, layout, gains),
+        RearLeft | SideLeft => fold(FrontLeft, gain * FRAC_1_SQRT_2, layout, gains),
+        RearRight | SideRight => fold(FrontRight, gain * FRAC_1_SQRT_2, layout, gains),
+        RearCenter => {
+            fold(RearLeft, gain * FRAC_1_SQRT_2, layout, gains);
+            fold(RearRight, gain * FRAC_1_SQRT_2, layout, gains);
+        }
+        TopFrontLeft => fold(FrontLeft, gain * FRAC_1_SQRT_2, layout, gains),
+        TopFrontRight => fold(FrontRight, gain * FRAC_1_SQRT_2, layout, gains),
+        TopFrontCenter | TopCenter => fold(FrontCenter, gain * FRAC_1_SQRT_2, layout, gains),
+        TopRearLeft => fold(RearLeft, gain * FRAC_1_SQRT_2, layout, gains),
+        TopRearRight => fold(RearRight, gain * FRAC_1_SQRT_2, layout, gains),
+        TopRearCenter => fold(RearCenter, gain * FRAC_1_SQRT_2, layout, gains),
+        LowFrequency | Unknown => (),
+    }
+}
+
+// The channel count to open a stream with `config` with under `ChannelMix::Auto`.
+fn device_channels<I>(
+    supported: Result<I, SupportedStreamConfigsError>,
+    config: &StreamConfig,
+) -> Result<ChannelCount, BuildStreamError>
+where
+    I: Iterator<Item = SupportedStreamConfigRange>,
+{
+    let counts: Vec<ChannelCount> = supported
+        .map_err(build_stream_error)?
+        .filter(|range| {
+            range.channels > 0
+                && range.min_sample_rate <= config.sample_rate
+                && config.sample_rate <= range.max_sample_rate
+        })
+        .map(|range| range.channels)
+        .collect();
+    let larger = counts
+        .iter()
+        .filter(|&&channels| channels >= config.channels);
+    larger
+        .min()
+        .or_else(|| counts.iter().max())
+        .cloned()
+        .ok_or(BuildStreamError::StreamConfigNotSupported)
+}
+
+// The positions of the channels of the stream.
+fn stream_layout(config: &StreamConfig) -> Option<Vec<ChannelPosition>> {
+    config
+        .channel_map
+        .clone()
+        .filter(|map| map.len() == config.channels as usize)
+        .or_else(|| ChannelPosition::default_layout(config.channels))
+}
+
+// The positions of the channels of the device opened with `channels` channels.
+fn device_layout(
+    default_config: Result<SupportedStreamConfig, DefaultStreamConfigError>,
+    channels: ChannelCount,
+) -> Option<Vec<ChannelPosition>> {
+    default_config
+        .ok()
+        .filter(|config| config.channels() == channels)
+        .and_then(|config| config.channel_map().map(<[_]>::to_vec))
+        .or_else(|| ChannelPosition::default_layout(channels))
+}
+
+fn device_config(config: &StreamConfig, channels: ChannelCount) -> StreamConfig {
+    StreamConfig {
+        channels,
+        channel_map: None,
+        ..config.clone()
+    }
+}
+
+/// The default implementation of `DeviceTrait::build_input_stream_remixed`.
+pub(crate) fn build_input_stream_remixed<Dev, T, D, E>(
+    device: &Dev,
+    config: &StreamConfig,
+    mix: ChannelMix,
+    mut data_callback: D,
+    error_callback: E,
+) -> Result<Dev::Stream, BuildStreamError>
+where
+    Dev: DeviceTrait + ?Sized,
+    T: Sample + Send + 'static,
+    D: FnMut(&[T], &InputCallbackInfo) + Send + 'static,
+    E: FnMut(StreamError) + Send + 'static,
+{
+    let remix = match mix {
+        ChannelMix::Auto => {
+            let channels = device_channels(device.supported_input_configs(), config)?;
+            if channels == config.channels {
+                return device.build_input_stream_converted(config, data_callback, error_callback);
+            }
+            Remix::auto(
+                device_layout(device.default_input_config(), channels),
+                stream_layout(config),
+                channels as usize,
+                config.channels as usize,
+            )
+        }
+        ChannelMix::Matrix(rows) => Remix::from_rows(rows)?,
+    };
+    if remix.outputs != config.channels as usize {
+        return Err(BuildStreamError::InvalidArgument);
+    }
+    let device_config = device_config(config, remix.inputs as ChannelCount);
+    let frames = max_buffer_frames(device.supported_input_configs(), &device_config);
+    let mut scratch: Vec<T> = Vec::with_capacity(frames * remix.outputs);
+    device.build_input_stream_converted(
+        &device_config,
+        move |data: &[f32], info: &InputCallbackInfo| {
+            let frames = data.len() / remix.inputs;
+            scratch.clear();
+            scratch.resize(frames * remix.outputs, T::from(&0.0f32));
+            remix.apply(data, &mut scratch);
+            data_callback(&scratch, info);
+        },
+        error_callback,
+    )
+}
+
+/// The default implementation of `DeviceTrait::build_output_stream_remixed`.
+pub(crate) fn build_output_stream_remixed<Dev, T, D, E>(
+    device: &Dev,
+    config: &StreamConfig,
+    mix: ChannelMix,
+    mut data_callback: D,
+    error_callback: E,
+) -> Result<Dev::Stream, BuildStreamError>
+where
+    Dev: DeviceTrait + ?Sized,
+    T: Sample + Send + 'static,
+    D: FnMut(&mut [T], &OutputCallbackInfo) + Send + 'static,
+    E: FnMut(StreamError) + Send + 'static,
+{
+    let remix = match mix {
+        ChannelMix::Auto => {
+            let channels = device_channels(device.supported_output_configs(), config)?;
+            if channels == config.channels {
+                return device.build_output_stream_converted(config, data_callback, error_callback);
+            }
+            Remix::auto(
+                stream_layout(config),
+                device_layout(device.default_output_config(), channels),
+                config.channels as usize,
+                channels as usize,
+            )
+        }
+        ChannelMix::Matrix(rows) => Remix::from_rows(rows)?,
+    };
+    if remix.inputs != config.channels as usize {
+        return Err(BuildStreamError::InvalidArgument);
+    }
+    let device_config = device_config(config, remix.outputs as ChannelCount);
+    let frames = max_buffer_frames(device.supported_output_configs(), &device_config);
+    let mut scratch: Vec<T> = Vec::with_capacity(frames * remix.inputs);
+    device.build_output_stream_converted(
+        &device_config,
+        move |data: &mut [f32], info: &OutputCallbackInfo| {
+            let frames = data.len() / remix.outputs;
+            scratch.clear();
+            scratch.resize(frames * remix.inputs, T::from(&0.0f32));
+            data_callback(&mut scratch, info);
+            remix.apply(&scratch, data);
+        },
+        error_callback,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Remix, FRAC_1_SQRT_2};
+    use {BuildStreamError, ChannelPosition};
+
+    fn auto(inputs: usize, outputs: usize) -> Remix {
+        Remix::auto(
+            ChannelPosition::default_layout(inputs as u16),
+            ChannelPosition::default_layout(outputs as u16),
+            inputs,
+            outputs,
+        )
+    }
+
+    #[test]
+    fn test_auto_remix() {
+        assert_eq!(auto(1, 2).gains, vec![1.0, 1.0]);
+        assert_eq!(auto(2, 1).gains, vec![0.5, 0.5]);
+        // Stereo goes to the front of 7.1, leaving the other channels silent.
+        let mut upmix = vec![0.0; 16];
+        upmix[0] = 1.0;
+        upmix[3] = 1.0;
+        assert_eq!(auto(2, 8).gains, upmix);
+
+        // FL FR FC LFE RL RR into stereo, scaled down to unity gain.
+        let downmix = auto(6, 2);
+        let sum = 1.0 + 2.0 * FRAC_1_SQRT_2;
+        let left = [
+            1.0 / sum,
+            0.0,
+            FRAC_1_SQRT_2 / sum,
+            0.0,
+            FRAC_1_SQRT_2 / sum,
+            0.0,
+        ];
+        for (gain, expected) in downmix.gains[..6].iter().zip(&left) {
+            assert!((gain - expected).abs() < 1e-6);
+        }
+        let mut frame = [0.0f32; 2];
+        downmix.apply(&[0.5f32, 0.5, 0.5, 0.5, 0.5, 0.5], &mut frame);
+        assert!((frame[0] - 0.5).abs() < 1e-6 && (frame[1] - 0.5).abs() < 1e-6);
+
+        // Without known positions, channels are mixed by index.
+        let unknown = Remix::auto(None, None, 3, 10);
+        assert_eq!(unknown.gains[0], 1.0);
+        assert_eq!(unknown.gains[4], 1.0);
+        assert_eq!(unknown.gains[8], 1.0);
+        assert_eq!(unknown.gains.iter().sum::<f32>(), 3.0);
+    }
+
+    #[test]
+    fn test_matrix_remix() {
+        let remix = Remix::from_rows(vec![vec![1.0, 0.0], vec![0.0, 0.5], vec![0.5, 0.5]]).unwrap();
+        assert_eq!((remix.inputs, remix.outputs), (2, 3));
+        let mut output = [0i16; 3];
+        remix.apply(&[1.0f32, -1.0], &mut output);
+        assert_eq!(output, [i16::MAX, -(i16::MAX / 2) - 1, 0]);
+        match Remix::from_rows(vec![vec![1.0, 0.0], vec![1.0]]) {
+            Err(BuildStreamError::InvalidArgument) => (),
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "test-host")]
+    fn test_remixed_streams() {
+        use host::null::single_config;
+        use host::test::Device;
+        use std::sync::mpsc;
+        use traits::{DeviceTrait, StreamTrait};
+        use {BufferSize, ChannelMix, SampleFormat, SampleRate, StreamConfig};
+
+        let device = Device::new(
+            "remixer",
+            vec![single_config(2, SampleRate(48_000), SampleFormat::F32)],
+            vec![single_config(1, SampleRate(48_000), SampleFormat::F32)],
+        );
+        let config = |channels| StreamConfig {
+            channels,
+            sample_rate: SampleRate(48_000),
+            buffer_size: BufferSize::Fixed(64),
+            channel_map: None,
+        };
+
+        // Stereo captured by the device is averaged into mono.
+        let (tx, rx) = mpsc::channel();
+        let input = device
+            .build_input_stream_remixed(
+                &config(1),
+                ChannelMix::Auto,
+                move |data: &[f32], _: &_| tx.send(data.to_vec()).unwrap(),
+                |_| panic!(),
+            )
+            .unwrap();
+        input.play().unwrap();
+        input
+            .handle()
+            .push_input(&[0.5f32, -0.25, 1.0, 0.0, -1.0, -0.5]);
+        assert!(input.handle().advance(3));
+        assert_eq!(rx.try_recv().unwrap(), vec![0.125, 0.5, -0.75]);
+
+        // Stereo played through the mono device is averaged as well.
+        let output = device
+            .build_output_stream_remixed(
+                &config(2),
+                ChannelMix::Auto,
+                |data: &mut [f32], _: &_| {
+                    for frame in data.chunks_mut(2) {
+                        frame.copy_from_slice(&[0.5, -0.25]);
+                    }
+                },
+                |_| panic!(),
+            )
+            .unwrap();
+        output.play().unwrap();
+        assert!(output.handle().advance(4));
+        assert_eq!(output.handle().take_output::<f32>(), vec![0.125; 4]);
+
+        // A matrix picks the channels to mix.
+        let left = ChannelMix::Matrix(vec![vec![1.0, 0.0]]);
+        let output = device
+            .build_output_stream_remixed(
+                &config(2),
+                left,
+                |data: &mut [f32], _: &_| {
+                    for frame in data.chunks_mut(2) {
+                        frame.copy_from_slice(&[0.5, -0.25]);
+                    }
+                },
+                |_| panic!(),
+            )
+            .unwrap();
+        output.play().unwrap();
+        assert!(output.handle().advance(4));
+        assert_eq!(output.handle().take_output::<f32>(), vec![0.5; 4]);
+    }
+}
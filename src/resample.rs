@@ -0,0 +1,485 @@
+//! Streams whose data callbacks run at a sample rate the device may not support, opened at the
+//! nearest rate the device does support and resampled with a windowed sinc interpolator.
+
+use crate::{
+    BuildStreamError, InputCallbackInfo, OutputCallbackInfo, Sample, SampleRate, StreamConfig,
+    StreamError, StreamInstant, SupportedStreamConfigRange, SupportedStreamConfigsError,
+};
+use convert::{build_stream_error, max_buffer_frames};
+use std::f64::consts::PI;
+use std::time::Duration;
+use traits::DeviceTrait;
+
+/// The number of entries of the kernel table per input frame. The kernel is linearly interpolated
+/// between them.
+const TABLE_RESOLUTION: usize = 256;
+
+/// The trade-off between the quality and the cost of the resampler of a resampled stream.
+///
+/// Higher qualities use longer filters, which pass more of the audible band and reject more of
+/// the aliasing, at a higher CPU cost and latency.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum ResampleQuality {
+    /// A short filter for low latency and CPU use.
+    Fast,
+    /// A filter transparent for most content.
+    #[default]
+    Balanced,
+    /// A long filter for mastering quality.
+    Best,
+}
+
+impl ResampleQuality {
+    // The number of zero crossings of the kernel on either side of its center, the cutoff relative
+    // to the Nyquist frequency of the lower of the two rates, and the beta of the Kaiser window.
+    fn parameters(self) -> (usize, f64, f64) {
+        match self {
+            ResampleQuality::Fast => (8, 0.85, 6.0),
+            ResampleQuality::Balanced => (16, 0.91, 8.0),
+            ResampleQuality::Best => (32, 0.95, 10.0),
+        }
+    }
+}
+
+/// A bandlimited resampler of interleaved `f32` frames.
+///
+/// Every output frame is interpolated from the input frames within half the kernel width of its
+/// position. Input is buffered until enough of it has been pushed for the next output frames.
+struct Resampler {
+    channels: usize,
+    // The number of input frames per output frame.
+    step: f64,
+    // Half the width of the kernel, in input frames.
+    half_width: f64,
+    // The kernel from its center outwards, sampled `TABLE_RESOLUTION` times per input frame.
+    table: Vec<f32>,
+    // The buffered input frames.
+    input: Vec<f32>,
+    // The position of the next output frame, in input frames from the first buffered one.
+    position: f64,
+}
+
+impl Resampler {
+    fn new(
+        channels: usize,
+        input_rate: SampleRate,
+        output_rate: SampleRate,
+        quality: ResampleQuality,
+    ) -> Self {
+        let (zero_crossings, rolloff, beta) = quality.parameters();
+        let step = input_rate.0 as f64 / output_rate.0 as f64;
+        // The cutoff relative to the Nyquist frequency of the input, lowered when downsampling so
+        // that the output does not alias.
+        let cutoff = rolloff * (1.0 / step).min(1.0);
+        let half_width = zero_crossings as f64 / cutoff;
+        let len = (half_width * TABLE_RESOLUTION as f64).ceil() as usize + 2;
+        let table = (0..len)
+            .map(|i| {
+                let x = i as f64 / TABLE_RESOLUTION as f64;
+                if x >= half_width {
+                    0.0
+                } else {
+                    (cutoff * sinc(cutoff * x) * kaiser(x / half_width, beta)) as f32
+                }
+            })
+            .collect();
+        // Start with half a kernel of silence, so that the first output frame lines up with the
+        // first input frame.
+        let history = half_width.ceil() as usize;
+        Resampler {
+            channels,
+            step,
+            half_width,
+            table,
+            input: vec![0.0; history * channels],
+            position: history as f64,
+        }
+    }
+
+    fn reserve(&mut self, output_frames: usize) {
+        let frames = self.input_frames_needed(output_frames) + self.buffered_frames();
+        self.input.reserve(frames * self.channels);
+    }
+
+    fn buffered_frames(&self) -> usize {
+        self.input.len() / self.channels
+    }
+
+    /// The number of input frames to push before `frames` output frames can be produced.
+    fn input_frames_needed(&self, frames: usize) -> usize {
+        if frames == 0 {
+            return 0;
+        }
+        let last = self.position + (frames - 1) as f64 * self.step + self.half_width;
+        (last.floor() as usize + 1).saturating_sub(self.buffered_frames())
+    }
+
+    /// The number of output frames that can be produced from the buffered input.
+    fn output_frames_available(&self) -> usize {
+        let span = self.buffered_frames() as f64 - self.position - self.half_width;
+        if span <= 0.0 {
+            0
+        } else {
+            (span / self.step).ceil() as usize
+        }
+    }
+
+    fn push<I>(&mut self, samples: I)
+    where
+        I: IntoIterator<Item = f32>,
+    {
+        self.input.extend(samples);
+    }
+
+    /// Fill `output` with the next frames, which must not be more than are available, and drop the
+    /// input frames no longer needed.
+    fn process(&mut self, output: &mut [f32]) {
+        let channels = self.channels;
+        let frames = output.len() / channels;
+        for (i, frame) in output.chunks_exact_mut(channels).enumerate() {
+            let t = self.position + i as f64 * self.step;
+            let first = (t - self.half_width).ceil().max(0.0) as usize;
+            let last = ((t + self.half_width).floor() as usize).min(self.buffered_frames() - 1);
+            for sample in frame.iter_mut() {
+                *sample = 0.0;
+            }
+            for k in first..=last {
+                let weight = self.kernel(t - k as f64);
+                let input = &self.input[k * channels..(k + 1) * channels];
+                for (sample, input) in frame.iter_mut().zip(input) {
+                    *sample += weight * input;
+                }
+            }
+        }
+        self.position += frames as f64 * self.step;
+        let consumed = ((self.position - self.half_width).floor().max(0.0) as usize)
+            .min(self.buffered_frames());
+        self.input.drain(..consumed * channels);
+        self.position -= consumed as f64;
+    }
+
+    fn kernel(&self, x: f64) -> f32 {
+        let x = x.abs() * TABLE_RESOLUTION as f64;
+        let index = x as usize;
+        if index + 1 >= self.table.len() {
+            return 0.0;
+        }
+        let fraction = (x - index as f64) as f32;
+        self.table[index] + (self.table[index + 1] - self.table[index]) * fraction
+    }
+}
+
+fn sinc(x: f64) -> f64 {
+    if x == 0.0 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+// The Kaiser window at `x` in `-1..=1`.
+fn kaiser(x: f64, beta: f64) -> f64 {
+    bessel_i0(beta * (1.0 - x * x).max(0.0).sqrt()) / bessel_i0(beta)
+}
+
+// The zeroth order modified Bessel function of the first kind.
+fn bessel_i0(x: f64) -> f64 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    let mut k = 1.0;
+    while term > sum * 1e-12 {
+        term *= (x / (2.0 * k)) * (x / (2.0 * k));
+        sum += term;
+        k += 1.0;
+    }
+    sum
+}
+
+// `instant` moved by `seconds`, which may be negative.
+fn offset(instant: StreamInstant, seconds: f64) -> StreamInstant {
+    let duration = Duration::from_secs_f64(seconds.abs());
+    let moved = if seconds < 0.0 {
+        instant.sub(duration)
+    } else {
+        instant.add(duration)
+    };
+    moved.unwrap_or(instant)
+}
+
+// The rate to open a stream with `config` at: the requested rate if the device supports it, and
+// otherwise the closest supported rate with the requested channel count, the higher one on a tie.
+fn nearest_rate<I>(
+    supported: Result<I, SupportedStreamConfigsError>,
+    config: &StreamConfig,
+) -> Result<SampleRate, BuildStreamError>
+where
+    I: Iterator<Item = SupportedStreamConfigRange>,
+{
+    let requested = config.sample_rate;
+    supported
+        .map_err(build_stream_error)?
+        .filter(|range| range.channels == config.channels)
+        .map(|range| {
+            requested
+                .max(range.min_sample_rate)
+                .min(range.max_sample_rate)
+        })
+        .min_by_key(|rate| {
+            let distance = (rate.0 as i64 - requested.0 as i64).abs();
+            (distance, std::cmp::Reverse(*rate))
+        })
+        .ok_or(BuildStreamError::StreamConfigNotSupported)
+}
+
+/// The default implementation of `DeviceTrait::build_input_stream_resampled`.
+pub(crate) fn build_input_stream_resampled<Dev, T, D, E>(
+    device: &Dev,
+    config: &StreamConfig,
+    quality: ResampleQuality,
+    mut data_callback: D,
+    error_callback: E,
+) -> Result<Dev::Stream, BuildStreamError>
+where
+    Dev: DeviceTrait + ?Sized,
+    T: Sample + Send + 'static,
+    D: FnMut(&[T], &InputCallbackInfo) + Send + 'static,
+    E: FnMut(StreamError) + Send + 'static,
+{
+    let device_rate = nearest_rate(device.supported_input_configs(), config)?;
+    if device_rate == config.sample_rate {
+        return device.build_input_stream_converted(config, data_callback, error_callback);
+    }
+    let device_config = StreamConfig {
+        sample_rate: device_rate,
+        ..config.clone()
+    };
+    let channels = config.channels as usize;
+    let mut resampler = Resampler::new(channels, device_rate, config.sample_rate, quality);
+    // The output frames available after pushing the largest buffer of the device.
+    let device_frames = max_buffer_frames(device.supported_input_configs(), &device_config);
+    let frames = (device_frames as f64 / resampler.step).ceil() as usize + 1;
+    let mut output: Vec<f32> = Vec::with_capacity(frames * channels);
+    let mut scratch: Vec<T> = Vec::with_capacity(frames * channels);
+    resampler.reserve(frames);
+    device.build_input_stream_converted(
+        &device_config,
+        move |data: &[f32], info: &InputCallbackInfo| {
+            let first = resampler.buffered_frames();
+            resampler.push(data.iter().cloned());
+            let frames = resampler.output_frames_available();
+            if frames == 0 {
+                return;
+            }
+            // The first output frame lies at its position relative to the first frame of `data`.
+            let delay = (resampler.position - first as f64) / device_rate.0 as f64;
+            output.clear();
+            output.resize(frames * channels, 0.0);
+            resampler.process(&mut output);
+            scratch.clear();
+            scratch.extend(output.iter().map(|sample| T::from(sample)));
+            let mut info = info.clone();
+            info.timestamp.capture = offset(info.timestamp.capture, delay);
+            data_callback(&scratch, &info);
+        },
+        error_callback,
+    )
+}
+
+/// The default implementation of `DeviceTrait::build_output_stream_resampled`.
+pub(crate) fn build_output_stream_resampled<Dev, T, D, E>(
+    device: &Dev,
+    config: &StreamConfig,
+    quality: ResampleQuality,
+    mut data_callback: D,
+    error_callback: E,
+) -> Result<Dev::Stream, BuildStreamError>
+where
+    Dev: DeviceTrait + ?Sized,
+    T: Sample + Send + 'static,
+    D: FnMut(&mut [T], &OutputCallbackInfo) + Send + 'static,
+    E: FnMut(StreamError) + Send + 'static,
+{
+    let device_rate = nearest_rate(device.supported_output_configs(), config)?;
+    if device_rate == config.sample_rate {
+        return device.build_output_stream_converted(config, data_callback, error_callback);
+    }
+    let device_config = StreamConfig {
+        sample_rate: device_rate,
+        ..config.clone()
+    };
+    let channels = config.channels as usize;
+    let input_rate = config.sample_rate.0 as f64;
+    let mut resampler = Resampler::new(channels, config.sample_rate, device_rate, quality);
+    // The input frames needed to fill the largest buffer of the device.
+    let device_frames = max_buffer_frames(device.supported_output_configs(), &device_config);
+    let frames = resampler.input_frames_needed(device_frames) + 1;
+    let mut scratch: Vec<T> = Vec::with_capacity(frames * channels);
+    resampler.reserve(device_frames);
+    device.build_output_stream_converted(
+        &device_config,
+        move |data: &mut [f32], info: &OutputCallbackInfo| {
+            let frames = resampler.input_frames_needed(data.len() / channels);
+            if frames > 0 {
+                // The first frame written now is played after the frames buffered before it.
+                let delay = (resampler.buffered_frames() as f64 - resampler.position) / input_rate;
+                scratch.clear();
+                scratch.resize(frames * channels, T::from(&0.0f32));
+                let mut info = info.clone();
+                info.timestamp.playback = offset(info.timestamp.playback, delay);
+                data_callback(&mut scratch, &info);
+                resampler.push(scratch.iter().map(Sample::to_f32));
+            }
+            resampler.process(data);
+        },
+        error_callback,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{nearest_rate, ResampleQuality, Resampler};
+    use std::f64::consts::PI;
+    use {
+        BufferSize, BuildStreamError, SampleFormat, SampleRate, StreamConfig, SupportedBufferSize,
+        SupportedStreamConfigRange,
+    };
+
+    #[test]
+    fn test_nearest_rate() {
+        let range = |channels, min, max| {
+            SupportedStreamConfigRange::new(
+                channels,
+                SampleRate(min),
+                SampleRate(max),
+                SupportedBufferSize::Unknown,
+                SampleFormat::F32,
+            )
+        };
+        let supported = || Ok(vec![range(2, 48_000, 48_000), range(2, 8_000, 32_000)].into_iter());
+        let config = |rate| StreamConfig {
+            channels: 2,
+            sample_rate: SampleRate(rate),
+            buffer_size: BufferSize::Default,
+            channel_map: None,
+        };
+        let nearest = |rate| nearest_rate(supported(), &config(rate)).unwrap();
+        assert_eq!(nearest(22_050), SampleRate(22_050));
+        assert_eq!(nearest(44_100), SampleRate(48_000));
+        assert_eq!(nearest(40_000), SampleRate(48_000));
+        assert_eq!(nearest(96_000), SampleRate(48_000));
+        let mono = StreamConfig {
+            channels: 1,
+            ..config(44_100)
+        };
+        match nearest_rate(supported(), &mono) {
+            Err(BuildStreamError::StreamConfigNotSupported) => (),
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_resampler_sine() {
+        let (input_rate, output_rate) = (44_100.0, 48_000.0);
+        let mut resampler = Resampler::new(
+            1,
+            SampleRate(44_100),
+            SampleRate(48_000),
+            ResampleQuality::default(),
+        );
+        let sine = |t: f64| (2.0 * PI * 1_000.0 * t).sin();
+        let mut pushed = 0;
+        let mut output = Vec::new();
+        for _ in 0..40 {
+            // Pull 256 frames at a time, as an output stream would.
+            let frames = resampler.input_frames_needed(256);
+            resampler.push((pushed..pushed + frames).map(|i| sine(i as f64 / input_rate) as f32));
+            pushed += frames;
+            let mut block = vec![0.0; 256];
+            resampler.process(&mut block);
+            output.extend(block);
+        }
+        // The input frames consumed track the ratio of the rates.
+        let expected = output.len() as f64 * input_rate / output_rate;
+        assert!((pushed as f64 - expected).abs() < 2.0 * resampler.half_width + 2.0);
+        // Past the silence the resampler starts with, the output follows the sine.
+        for (i, &sample) in output.iter().enumerate().skip(64) {
+            let error = (sample as f64 - sine(i as f64 / output_rate)).abs();
+            assert!(error < 1e-3, "frame {} off by {}", i, error);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "test-host")]
+    fn test_resampled_streams() {
+        use host::null::single_config;
+        use host::test::Device;
+        use std::sync::{Arc, Mutex};
+        use traits::{DeviceTrait, StreamTrait};
+
+        let device = Device::new(
+            "resampler",
+            vec![single_config(2, SampleRate(48_000), SampleFormat::F32)],
+            vec![single_config(2, SampleRate(48_000), SampleFormat::F32)],
+        );
+        let config = StreamConfig {
+            channels: 2,
+            sample_rate: SampleRate(44_100),
+            buffer_size: BufferSize::Fixed(64),
+            channel_map: None,
+        };
+        let quality = ResampleQuality::Fast;
+        let half_width =
+            Resampler::new(2, SampleRate(48_000), config.sample_rate, quality).half_width;
+        // 200 periods of 64 frames at 48 kHz make up 11760 frames at 44.1 kHz.
+        let (periods, expected) = (200, 11_760.0);
+
+        let frames = Arc::new(Mutex::new(Vec::new()));
+        let frames2 = frames.clone();
+        let output = device
+            .build_output_stream_resampled(
+                &config,
+                quality,
+                move |data: &mut [f32], _: &_| frames2.lock().unwrap().push(data.len() / 2),
+                |_| panic!(),
+            )
+            .unwrap();
+        output.play().unwrap();
+        for _ in 0..periods {
+            assert!(output.handle().advance(64));
+        }
+        assert_eq!(output.handle().take_output::<f32>().len(), periods * 64 * 2);
+        let frames = frames.lock().unwrap().clone();
+        // Past the first callback, which fills the resampler, every period of 64 frames takes
+        // 58.8 frames on average.
+        assert!(frames[1..]
+            .iter()
+            .all(|&frames| frames == 58 || frames == 59));
+        let total = frames.iter().sum::<usize>() as f64;
+        assert!(
+            (total - expected).abs() < 2.0 * half_width + 2.0,
+            "{} frames",
+            total
+        );
+
+        let frames = Arc::new(Mutex::new(0));
+        let frames2 = frames.clone();
+        let input = device
+            .build_input_stream_resampled(
+                &config,
+                quality,
+                move |data: &[f32], _: &_| *frames2.lock().unwrap() += data.len() / 2,
+                |_| panic!(),
+            )
+            .unwrap();
+        input.play().unwrap();
+        for _ in 0..periods {
+            assert!(input.handle().advance(64));
+        }
+        let total = *frames.lock().unwrap() as f64;
+        assert!(
+            (total - expected).abs() < 2.0 * half_width + 2.0,
+            "{} frames",
+            total
+        );
+    }
+}
@@ -2,13 +2,29 @@ use std::mem;
 
 /// Format that each sample has.
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum SampleFormat {
+    /// The value 0 corresponds to 0.
+    I8,
     /// The value 0 corresponds to 0.
     I16,
+    /// 24-bit samples stored in the least significant bytes of a 4-byte container. The value 0
+    /// corresponds to 0.
+    I24,
+    /// 24-bit samples packed into 3 bytes. The value 0 corresponds to 0.
+    I24Packed,
+    /// The value 0 corresponds to 0.
+    I32,
+    /// The value 0 corresponds to 128.
+    U8,
     /// The value 0 corresponds to 32768.
     U16,
+    /// The value 0 corresponds to 2147483648.
+    U32,
     /// The boundaries are (-1.0, 1.0).
     F32,
+    /// The boundaries are (-1.0, 1.0).
+    F64,
 }
 
 impl SampleFormat {
@@ -16,11 +32,107 @@ impl SampleFormat {
     #[inline]
     pub fn sample_size(&self) -> usize {
         match *self {
+            SampleFormat::I8 => mem::size_of::<i8>(),
             SampleFormat::I16 => mem::size_of::<i16>(),
+            SampleFormat::I24 => mem::size_of::<I24>(),
+            SampleFormat::I24Packed => mem::size_of::<I24Packed>(),
+            SampleFormat::I32 => mem::size_of::<i32>(),
+            SampleFormat::U8 => mem::size_of::<u8>(),
             SampleFormat::U16 => mem::size_of::<u16>(),
+            SampleFormat::U32 => mem::size_of::<u32>(),
             SampleFormat::F32 => mem::size_of::<f32>(),
+            SampleFormat::F64 => mem::size_of::<f64>(),
+        }
+    }
+}
+
+/// A signed 24-bit sample stored in the least significant bytes of a native-endian `i32`.
+///
+/// This is the layout used by ALSA's `S24_LE`/`S24_BE` formats.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(transparent)]
+pub struct I24(i32);
+
+/// A signed 24-bit sample packed into 3 native-endian bytes.
+///
+/// This is the layout used by ALSA's `S24_3LE`/`S24_3BE` formats.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[repr(transparent)]
+pub struct I24Packed([u8; 3]);
+
+impl I24 {
+    /// The smallest value representable by a 24-bit sample.
+    pub const MIN: i32 = -(1 << 23);
+    /// The largest value representable by a 24-bit sample.
+    pub const MAX: i32 = (1 << 23) - 1;
+
+    /// Create a 24-bit sample from the given value.
+    ///
+    /// Returns `None` if the value lies outside of the range `I24::MIN..=I24::MAX`.
+    pub fn new(value: i32) -> Option<Self> {
+        if !(Self::MIN..=Self::MAX).contains(&value) {
+            None
+        } else {
+            Some(I24(value))
+        }
+    }
+
+    /// The value of the sample as an `i32` in the range `I24::MIN..=I24::MAX`.
+    #[inline]
+    pub fn inner(&self) -> i32 {
+        self.0
+    }
+}
+
+impl I24Packed {
+    /// Create a packed 24-bit sample from the given value.
+    ///
+    /// Returns `None` if the value lies outside of the range `I24::MIN..=I24::MAX`.
+    pub fn new(value: i32) -> Option<Self> {
+        I24::new(value).map(I24Packed::pack)
+    }
+
+    /// The value of the sample as an `i32` in the range `I24::MIN..=I24::MAX`.
+    #[inline]
+    pub fn inner(&self) -> i32 {
+        self.unpack().inner()
+    }
+
+    #[inline]
+    fn pack(sample: I24) -> Self {
+        let bytes = sample.0.to_ne_bytes();
+        if cfg!(target_endian = "big") {
+            I24Packed([bytes[1], bytes[2], bytes[3]])
+        } else {
+            I24Packed([bytes[0], bytes[1], bytes[2]])
         }
     }
+
+    #[inline]
+    fn unpack(self) -> I24 {
+        let b = self.0;
+        // Place the bytes in the most significant positions and sign-extend via the shift.
+        let value = if cfg!(target_endian = "big") {
+            i32::from_ne_bytes([b[0], b[1], b[2], 0])
+        } else {
+            i32::from_ne_bytes([0, b[0], b[1], b[2]])
+        };
+        I24(value >> 8)
+    }
+}
+
+impl From<I24> for I24Packed {
+    #[inline]
+    fn from(sample: I24) -> Self {
+        I24Packed::pack(sample)
+    }
+}
+
+impl From<I24Packed> for I24 {
+    #[inline]
+    fn from(sample: I24Packed) -> Self {
+        sample.unpack()
+    }
 }
 
 /// Trait for containers that contain PCM data.
@@ -30,17 +142,106 @@ pub unsafe trait Sample: Copy + Clone {
 
     /// Turns the sample into its equivalent as a floating-point.
     fn to_f32(&self) -> f32;
+    /// Turns the sample into its equivalent as a double precision floating-point.
+    ///
+    /// Defaults to widening `to_f32`, which loses precision for samples wider than 24 bits.
+    #[inline]
+    fn to_f64(&self) -> f64 {
+        self.to_f32() as f64
+    }
     /// Converts this sample into a standard i16 sample.
     fn to_i16(&self) -> i16;
+    /// Converts this sample into a standard i32 sample.
+    ///
+    /// Defaults to widening `to_i16`, which loses precision for samples wider than 16 bits.
+    #[inline]
+    fn to_i32(&self) -> i32 {
+        (self.to_i16() as i32) << 16
+    }
     /// Converts this sample into a standard u16 sample.
     fn to_u16(&self) -> u16;
 
-    /// Converts any sample type to this one by calling `to_i16`, `to_u16` or `to_f32`.
+    /// Converts any sample type to this one by calling `to_i16`, `to_i32`, `to_u16`, `to_f32` or
+    /// `to_f64`.
     fn from<S>(&S) -> Self
     where
         S: Sample;
 }
 
+unsafe impl Sample for i8 {
+    const FORMAT: SampleFormat = SampleFormat::I8;
+
+    #[inline]
+    fn to_f32(&self) -> f32 {
+        self.to_i16().to_f32()
+    }
+
+    #[inline]
+    fn to_f64(&self) -> f64 {
+        self.to_i16().to_f64()
+    }
+
+    #[inline]
+    fn to_i16(&self) -> i16 {
+        (*self as i16) << 8
+    }
+
+    #[inline]
+    fn to_i32(&self) -> i32 {
+        (*self as i32) << 24
+    }
+
+    #[inline]
+    fn to_u16(&self) -> u16 {
+        self.to_i16().to_u16()
+    }
+
+    #[inline]
+    fn from<S>(sample: &S) -> Self
+    where
+        S: Sample,
+    {
+        (sample.to_i16() >> 8) as i8
+    }
+}
+
+unsafe impl Sample for u8 {
+    const FORMAT: SampleFormat = SampleFormat::U8;
+
+    #[inline]
+    fn to_f32(&self) -> f32 {
+        self.to_i16().to_f32()
+    }
+
+    #[inline]
+    fn to_f64(&self) -> f64 {
+        self.to_i16().to_f64()
+    }
+
+    #[inline]
+    fn to_i16(&self) -> i16 {
+        self.to_u16().to_i16()
+    }
+
+    #[inline]
+    fn to_i32(&self) -> i32 {
+        self.to_u16().to_i32()
+    }
+
+    #[inline]
+    fn to_u16(&self) -> u16 {
+        (*self as u16) << 8
+    }
+
+    #[inline]
+    fn from<S>(sample: &S) -> Self
+    where
+        S: Sample,
+    {
+        (sample.to_u16() >> 8) as u8
+    }
+}
+
 unsafe impl Sample for u16 {
     const FORMAT: SampleFormat = SampleFormat::U16;
 
@@ -49,6 +250,11 @@ unsafe impl Sample for u16 {
         self.to_i16().to_f32()
     }
 
+    #[inline]
+    fn to_f64(&self) -> f64 {
+        self.to_i16().to_f64()
+    }
+
     #[inline]
     fn to_i16(&self) -> i16 {
         if *self >= 32768 {
@@ -58,6 +264,11 @@ unsafe impl Sample for u16 {
         }
     }
 
+    #[inline]
+    fn to_i32(&self) -> i32 {
+        self.to_i16().to_i32()
+    }
+
     #[inline]
     fn to_u16(&self) -> u16 {
         *self
@@ -84,11 +295,25 @@ unsafe impl Sample for i16 {
         }
     }
 
+    #[inline]
+    fn to_f64(&self) -> f64 {
+        if *self < 0 {
+            *self as f64 / -(i16::MIN as f64)
+        } else {
+            *self as f64 / i16::MAX as f64
+        }
+    }
+
     #[inline]
     fn to_i16(&self) -> i16 {
         *self
     }
 
+    #[inline]
+    fn to_i32(&self) -> i32 {
+        (*self as i32) << 16
+    }
+
     #[inline]
     fn to_u16(&self) -> u16 {
         if *self < 0 {
@@ -107,6 +332,162 @@ unsafe impl Sample for i16 {
     }
 }
 
+unsafe impl Sample for I24 {
+    const FORMAT: SampleFormat = SampleFormat::I24;
+
+    #[inline]
+    fn to_f32(&self) -> f32 {
+        self.to_f64() as f32
+    }
+
+    #[inline]
+    fn to_f64(&self) -> f64 {
+        if self.0 < 0 {
+            self.0 as f64 / -(I24::MIN as f64)
+        } else {
+            self.0 as f64 / I24::MAX as f64
+        }
+    }
+
+    #[inline]
+    fn to_i16(&self) -> i16 {
+        (self.0 >> 8) as i16
+    }
+
+    #[inline]
+    fn to_i32(&self) -> i32 {
+        self.0 << 8
+    }
+
+    #[inline]
+    fn to_u16(&self) -> u16 {
+        self.to_i16().to_u16()
+    }
+
+    #[inline]
+    fn from<S>(sample: &S) -> Self
+    where
+        S: Sample,
+    {
+        I24(sample.to_i32() >> 8)
+    }
+}
+
+unsafe impl Sample for I24Packed {
+    const FORMAT: SampleFormat = SampleFormat::I24Packed;
+
+    #[inline]
+    fn to_f32(&self) -> f32 {
+        self.unpack().to_f32()
+    }
+
+    #[inline]
+    fn to_f64(&self) -> f64 {
+        self.unpack().to_f64()
+    }
+
+    #[inline]
+    fn to_i16(&self) -> i16 {
+        self.unpack().to_i16()
+    }
+
+    #[inline]
+    fn to_i32(&self) -> i32 {
+        self.unpack().to_i32()
+    }
+
+    #[inline]
+    fn to_u16(&self) -> u16 {
+        self.unpack().to_u16()
+    }
+
+    #[inline]
+    fn from<S>(sample: &S) -> Self
+    where
+        S: Sample,
+    {
+        I24Packed::pack(<I24 as Sample>::from(sample))
+    }
+}
+
+unsafe impl Sample for i32 {
+    const FORMAT: SampleFormat = SampleFormat::I32;
+
+    #[inline]
+    fn to_f32(&self) -> f32 {
+        self.to_f64() as f32
+    }
+
+    #[inline]
+    fn to_f64(&self) -> f64 {
+        if *self < 0 {
+            *self as f64 / -(i32::MIN as f64)
+        } else {
+            *self as f64 / i32::MAX as f64
+        }
+    }
+
+    #[inline]
+    fn to_i16(&self) -> i16 {
+        (*self >> 16) as i16
+    }
+
+    #[inline]
+    fn to_i32(&self) -> i32 {
+        *self
+    }
+
+    #[inline]
+    fn to_u16(&self) -> u16 {
+        self.to_i16().to_u16()
+    }
+
+    #[inline]
+    fn from<S>(sample: &S) -> Self
+    where
+        S: Sample,
+    {
+        sample.to_i32()
+    }
+}
+
+unsafe impl Sample for u32 {
+    const FORMAT: SampleFormat = SampleFormat::U32;
+
+    #[inline]
+    fn to_f32(&self) -> f32 {
+        self.to_i32().to_f32()
+    }
+
+    #[inline]
+    fn to_f64(&self) -> f64 {
+        self.to_i32().to_f64()
+    }
+
+    #[inline]
+    fn to_i16(&self) -> i16 {
+        self.to_i32().to_i16()
+    }
+
+    #[inline]
+    fn to_i32(&self) -> i32 {
+        (*self ^ 0x8000_0000) as i32
+    }
+
+    #[inline]
+    fn to_u16(&self) -> u16 {
+        (*self >> 16) as u16
+    }
+
+    #[inline]
+    fn from<S>(sample: &S) -> Self
+    where
+        S: Sample,
+    {
+        sample.to_i32() as u32 ^ 0x8000_0000
+    }
+}
+
 unsafe impl Sample for f32 {
     const FORMAT: SampleFormat = SampleFormat::F32;
 
@@ -115,6 +496,11 @@ unsafe impl Sample for f32 {
         *self
     }
 
+    #[inline]
+    fn to_f64(&self) -> f64 {
+        *self as f64
+    }
+
     #[inline]
     fn to_i16(&self) -> i16 {
         if *self >= 0.0 {
@@ -124,6 +510,11 @@ unsafe impl Sample for f32 {
         }
     }
 
+    #[inline]
+    fn to_i32(&self) -> i32 {
+        self.to_f64().to_i32()
+    }
+
     #[inline]
     fn to_u16(&self) -> u16 {
         (((*self + 1.0) * 0.5) * ::std::u16::MAX as f32).round() as u16
@@ -138,9 +529,68 @@ unsafe impl Sample for f32 {
     }
 }
 
+unsafe impl Sample for f64 {
+    const FORMAT: SampleFormat = SampleFormat::F64;
+
+    #[inline]
+    fn to_f32(&self) -> f32 {
+        *self as f32
+    }
+
+    #[inline]
+    fn to_f64(&self) -> f64 {
+        *self
+    }
+
+    #[inline]
+    fn to_i16(&self) -> i16 {
+        if *self >= 0.0 {
+            (*self * i16::MAX as f64) as i16
+        } else {
+            (-*self * i16::MIN as f64) as i16
+        }
+    }
+
+    #[inline]
+    fn to_i32(&self) -> i32 {
+        if *self >= 0.0 {
+            (*self * i32::MAX as f64) as i32
+        } else {
+            (-*self * i32::MIN as f64) as i32
+        }
+    }
+
+    #[inline]
+    fn to_u16(&self) -> u16 {
+        (((*self + 1.0) * 0.5) * u16::MAX as f64).round() as u16
+    }
+
+    #[inline]
+    fn from<S>(sample: &S) -> Self
+    where
+        S: Sample,
+    {
+        sample.to_f64()
+    }
+}
+
+/// Fill the given buffer of raw `sample_format` samples with silence.
+pub(crate) fn fill_with_silence(buffer: &mut [u8], sample_format: SampleFormat) {
+    let silence = match sample_format {
+        SampleFormat::U8 => vec![0x80],
+        SampleFormat::U16 => 0x8000u16.to_ne_bytes().to_vec(),
+        SampleFormat::U32 => 0x8000_0000u32.to_ne_bytes().to_vec(),
+        _ => vec![0x00],
+    };
+    let silence = silence.iter().cycle();
+    for (byte, &value) in buffer.iter_mut().zip(silence) {
+        *byte = value;
+    }
+}
+
 #[cfg(test)]
 mod test {
-    use super::Sample;
+    use super::{I24Packed, Sample, I24};
 
     #[test]
     fn i16_to_i16() {
@@ -192,9 +642,9 @@ mod test {
     #[test]
     fn f32_to_i16() {
         assert_eq!(0.0f32.to_i16(), 0);
-        assert_eq!((-0.5f32).to_i16(), ::std::i16::MIN / 2);
-        assert_eq!(1.0f32.to_i16(), ::std::i16::MAX);
-        assert_eq!((-1.0f32).to_i16(), ::std::i16::MIN);
+        assert_eq!((-0.5f32).to_i16(), i16::MIN / 2);
+        assert_eq!(1.0f32.to_i16(), i16::MAX);
+        assert_eq!((-1.0f32).to_i16(), i16::MIN);
     }
 
     #[test]
@@ -210,4 +660,109 @@ mod test {
         assert_eq!((-0.7f32).to_f32(), -0.7);
         assert_eq!(1.0f32.to_f32(), 1.0);
     }
+
+    #[test]
+    fn i8_to_i16() {
+        assert_eq!(0i8.to_i16(), 0);
+        assert_eq!(127i8.to_i16(), 32512);
+        assert_eq!((-128i8).to_i16(), -32768);
+    }
+
+    #[test]
+    fn u8_to_i16() {
+        assert_eq!(128u8.to_i16(), 0);
+        assert_eq!(0u8.to_i16(), -32768);
+        assert_eq!(255u8.to_i16(), 32512);
+    }
+
+    #[test]
+    fn i16_to_i8_round_trip() {
+        for &s in &[-128i8, -64, -1, 0, 1, 64, 127] {
+            assert_eq!(<i8 as Sample>::from(&s.to_i16()), s);
+        }
+    }
+
+    #[test]
+    fn u16_to_u8_round_trip() {
+        for &s in &[0u8, 1, 127, 128, 129, 255] {
+            assert_eq!(<u8 as Sample>::from(&s.to_u16()), s);
+        }
+    }
+
+    #[test]
+    fn i24_to_i32() {
+        assert_eq!(I24::new(0).unwrap().to_i32(), 0);
+        assert_eq!(I24::new(I24::MAX).unwrap().to_i32(), i32::MAX - 0xff);
+        assert_eq!(I24::new(I24::MIN).unwrap().to_i32(), i32::MIN);
+        assert_eq!(I24::new(I24::MAX + 1), None);
+        assert_eq!(I24::new(I24::MIN - 1), None);
+    }
+
+    #[test]
+    fn i24_to_f32() {
+        assert_eq!(I24::new(0).unwrap().to_f32(), 0.0);
+        assert_eq!(I24::new(I24::MAX).unwrap().to_f32(), 1.0);
+        assert_eq!(I24::new(I24::MIN).unwrap().to_f32(), -1.0);
+    }
+
+    #[test]
+    fn i16_to_i24_round_trip() {
+        for &s in &[i16::MIN, -467, 0, 467, i16::MAX] {
+            assert_eq!(<I24 as Sample>::from(&s).to_i16(), s);
+        }
+    }
+
+    #[test]
+    fn i24_packed_round_trip() {
+        for &v in &[I24::MIN, -467, -1, 0, 1, 467, I24::MAX] {
+            let packed = I24Packed::new(v).unwrap();
+            assert_eq!(packed.inner(), v);
+            assert_eq!(packed.to_i32(), v << 8);
+        }
+    }
+
+    #[test]
+    fn i32_to_i16() {
+        assert_eq!(0i32.to_i16(), 0);
+        assert_eq!(i32::MAX.to_i16(), i16::MAX);
+        assert_eq!(i32::MIN.to_i16(), i16::MIN);
+    }
+
+    #[test]
+    fn i24_to_i32_round_trip() {
+        for &v in &[I24::MIN, -467, 0, 467, I24::MAX] {
+            let s = I24::new(v).unwrap();
+            assert_eq!(<I24 as Sample>::from(&s.to_i32()), s);
+        }
+    }
+
+    #[test]
+    fn u32_to_i32() {
+        assert_eq!(0x8000_0000u32.to_i32(), 0);
+        assert_eq!(0u32.to_i32(), i32::MIN);
+        assert_eq!(u32::MAX.to_i32(), i32::MAX);
+        assert_eq!(<u32 as Sample>::from(&0i32), 0x8000_0000);
+    }
+
+    #[test]
+    fn f32_to_i32() {
+        assert_eq!(0.0f32.to_i32(), 0);
+        assert_eq!(1.0f32.to_i32(), i32::MAX);
+        assert_eq!((-1.0f32).to_i32(), i32::MIN);
+    }
+
+    #[test]
+    fn f64_to_f32() {
+        assert_eq!(0.5f64.to_f32(), 0.5);
+        assert_eq!((-1.0f64).to_f32(), -1.0);
+        assert_eq!(<f64 as Sample>::from(&0.25f32), 0.25);
+    }
+
+    #[test]
+    fn i32_to_f64() {
+        assert_eq!(0i32.to_f64(), 0.0);
+        assert_eq!(i32::MAX.to_f64(), 1.0);
+        assert_eq!(i32::MIN.to_f64(), -1.0);
+        assert_eq!(<i32 as Sample>::from(&1.0f64), i32::MAX);
+    }
 }
@@ -1,10 +1,16 @@
 //! The suite of traits allowing CPAL to abstract over hosts, devices, event loops and stream IDs.
 
+use convert::{self, report_incorrect_sample_format, ErrorCallback};
+use remix;
+use resample;
+use std::time::Duration;
 use {
-    BuildStreamError, Data, DefaultStreamConfigError, DeviceNameError, DevicesError,
-    InputCallbackInfo, InputDevices, OutputCallbackInfo, OutputDevices, PauseStreamError,
-    PlayStreamError, Sample, SampleFormat, StreamConfig, StreamError, SupportedStreamConfig,
-    SupportedStreamConfigRange, SupportedStreamConfigsError,
+    BuildStreamError, ChannelMix, ConfigMatch, ConfigRequest, Data, DefaultStreamConfigError,
+    DeviceEvent, DeviceNameError, DeviceWatcher, DevicesError, DuplexCallbackInfo, FrameCount,
+    InputCallbackInfo, InputDevices, InputStreamReader, MatchConfigError, OutputCallbackInfo,
+    OutputDevices, OutputStreamWriter, PauseStreamError, PlayStreamError, ResampleQuality, Sample,
+    SampleFormat, StreamConfig, StreamError, SupportedStreamConfig, SupportedStreamConfigRange,
+    SupportedStreamConfigsError, VolumeError, VolumeRange, WatchDevicesError,
 };
 
 /// A **Host** provides access to the available audio devices on the system.
@@ -50,6 +56,13 @@ pub trait HostTrait {
     /// Returns `None` if no output device is available.
     fn default_output_device(&self) -> Option<Self::Device>;
 
+    /// The device whose `DeviceTrait::id` is `id`, if it is currently available.
+    fn device_by_id(&self, id: &str) -> Option<Self::Device> {
+        self.devices()
+            .ok()?
+            .find(|device| device.id().ok().as_deref() == Some(id))
+    }
+
     /// An iterator yielding all `Device`s currently available to the system that support one or more
     /// input stream formats.
     ///
@@ -77,6 +90,19 @@ pub trait HostTrait {
         }
         Ok(self.devices()?.filter(supports_output::<Self::Device>))
     }
+
+    /// Subscribe to device hot-plug and default device change events.
+    ///
+    /// The `callback` is invoked from a host-specific thread each time a `DeviceEvent` occurs,
+    /// until the returned `DeviceWatcher` is dropped.
+    ///
+    /// Returns `WatchDevicesError::NotSupported` if the host cannot report device events.
+    fn watch_devices<F>(&self, _callback: F) -> Result<DeviceWatcher, WatchDevicesError>
+    where
+        F: FnMut(DeviceEvent) + Send + 'static,
+    {
+        Err(WatchDevicesError::NotSupported)
+    }
 }
 
 /// A device that is capable of audio input and/or output.
@@ -88,12 +114,21 @@ pub trait DeviceTrait {
     type SupportedInputConfigs: Iterator<Item = SupportedStreamConfigRange>;
     /// The iterator type yielding supported output stream formats.
     type SupportedOutputConfigs: Iterator<Item = SupportedStreamConfigRange>;
-    /// The stream type created by `build_input_stream_raw` and `build_output_stream_raw`.
+    /// The stream type created by `build_input_stream_raw`, `build_output_stream_raw` and
+    /// `build_duplex_stream_raw`.
     type Stream: StreamTrait;
 
     /// The human-readable name of the device.
     fn name(&self) -> Result<String, DeviceNameError>;
 
+    /// An identifier of the device that stays the same across enumerations and restarts, to find
+    /// the device again via `HostTrait::device_by_id`.
+    ///
+    /// Defaults to the name of the device, for hosts without a more stable identifier.
+    fn id(&self) -> Result<String, DeviceNameError> {
+        self.name()
+    }
+
     /// An iterator yielding formats that are supported by the backend.
     ///
     /// Can return an error if the device is no longer valid (eg. it has been disconnected).
@@ -114,6 +149,21 @@ pub trait DeviceTrait {
     /// The default output stream format for the device.
     fn default_output_config(&self) -> Result<SupportedStreamConfig, DefaultStreamConfigError>;
 
+    /// The supported input stream config that best matches `request`, as by
+    /// `ConfigRequest::best_match`.
+    fn match_input_config(&self, request: &ConfigRequest) -> Result<ConfigMatch, MatchConfigError> {
+        request.best_match(self.supported_input_configs()?)
+    }
+
+    /// The supported output stream config that best matches `request`, as by
+    /// `ConfigRequest::best_match`.
+    fn match_output_config(
+        &self,
+        request: &ConfigRequest,
+    ) -> Result<ConfigMatch, MatchConfigError> {
+        request.best_match(self.supported_output_configs()?)
+    }
+
     /// Create an input stream.
     fn build_input_stream<T, D, E>(
         &self,
@@ -126,17 +176,22 @@ pub trait DeviceTrait {
         D: FnMut(&[T], &InputCallbackInfo) + Send + 'static,
         E: FnMut(StreamError) + Send + 'static,
     {
+        let error_callback = ErrorCallback::new(error_callback);
+        let data_error_callback = error_callback.clone();
+        let mut reported = false;
         self.build_input_stream_raw(
             config,
             T::FORMAT,
-            move |data, info| {
-                data_callback(
-                    data.as_slice()
-                        .expect("host supplied incorrect sample type"),
-                    info,
-                )
+            move |data, info| match data.as_slice() {
+                Some(samples) => data_callback(samples, info),
+                None => report_incorrect_sample_format(
+                    &data_error_callback,
+                    &mut reported,
+                    T::FORMAT,
+                    data,
+                ),
             },
-            error_callback,
+            move |err| error_callback.report(err),
         )
     }
 
@@ -152,20 +207,163 @@ pub trait DeviceTrait {
         D: FnMut(&mut [T], &OutputCallbackInfo) + Send + 'static,
         E: FnMut(StreamError) + Send + 'static,
     {
+        let error_callback = ErrorCallback::new(error_callback);
+        let data_error_callback = error_callback.clone();
+        let mut reported = false;
         self.build_output_stream_raw(
             config,
             T::FORMAT,
             move |data, info| {
-                data_callback(
-                    data.as_slice_mut()
-                        .expect("host supplied incorrect sample type"),
-                    info,
-                )
+                if let Some(samples) = data.as_slice_mut() {
+                    return data_callback(samples, info);
+                }
+                report_incorrect_sample_format(
+                    &data_error_callback,
+                    &mut reported,
+                    T::FORMAT,
+                    data,
+                );
+                let sample_format = data.sample_format();
+                crate::samples_formats::fill_with_silence(data.bytes_mut(), sample_format);
             },
+            move |err| error_callback.report(err),
+        )
+    }
+
+    /// Create an input stream whose data callback receives samples of type `T`, whether or not the
+    /// device supports `T::FORMAT`.
+    ///
+    /// The stream is opened in `T::FORMAT` if the device supports it for `config`, and otherwise
+    /// in the sample format the device supports that ranks highest by
+    /// `SupportedStreamConfigRange::cmp_default_heuristics`. Samples are converted with `Sample`
+    /// via a buffer allocated along with the stream.
+    fn build_input_stream_converted<T, D, E>(
+        &self,
+        config: &StreamConfig,
+        data_callback: D,
+        error_callback: E,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        T: Sample + Send + 'static,
+        D: FnMut(&[T], &InputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        convert::build_input_stream_converted(self, config, data_callback, error_callback)
+    }
+
+    /// Create an output stream whose data callback writes samples of type `T`, whether or not the
+    /// device supports `T::FORMAT`.
+    ///
+    /// See `build_input_stream_converted` for how the sample format of the stream is chosen.
+    fn build_output_stream_converted<T, D, E>(
+        &self,
+        config: &StreamConfig,
+        data_callback: D,
+        error_callback: E,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        T: Sample + Send + 'static,
+        D: FnMut(&mut [T], &OutputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        convert::build_output_stream_converted(self, config, data_callback, error_callback)
+    }
+
+    /// Create an input stream whose data callback receives samples of type `T` at
+    /// `config.sample_rate`, whether or not the device supports that rate.
+    ///
+    /// If the device does not support the requested rate with `config.channels` channels, the
+    /// stream is opened at the closest rate it does support and resampled with a bandlimited
+    /// interpolator of the given `quality`. The data callback then receives however many frames
+    /// are ready, which varies from callback to callback, and `buffer_size` counts frames at the
+    /// rate of the device. Capture timestamps refer to the first frame the callback receives.
+    ///
+    /// Samples are converted as by `build_input_stream_converted`.
+    fn build_input_stream_resampled<T, D, E>(
+        &self,
+        config: &StreamConfig,
+        quality: ResampleQuality,
+        data_callback: D,
+        error_callback: E,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        T: Sample + Send + 'static,
+        D: FnMut(&[T], &InputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        resample::build_input_stream_resampled(self, config, quality, data_callback, error_callback)
+    }
+
+    /// Create an output stream whose data callback writes samples of type `T` at
+    /// `config.sample_rate`, whether or not the device supports that rate.
+    ///
+    /// See `build_input_stream_resampled` for how the rate of the device is chosen. The data
+    /// callback is asked for however many frames the resampler needs to fill the buffer of the
+    /// device, which varies from callback to callback. Playback timestamps refer to the first
+    /// frame the callback writes.
+    fn build_output_stream_resampled<T, D, E>(
+        &self,
+        config: &StreamConfig,
+        quality: ResampleQuality,
+        data_callback: D,
+        error_callback: E,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        T: Sample + Send + 'static,
+        D: FnMut(&mut [T], &OutputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        resample::build_output_stream_resampled(
+            self,
+            config,
+            quality,
+            data_callback,
             error_callback,
         )
     }
 
+    /// Create an input stream whose data callback receives `config.channels` channels of samples
+    /// of type `T`, whether or not the device supports that channel count.
+    ///
+    /// The device is opened with the channel count chosen by `mix`, and its channels are mixed
+    /// into those of the stream as described by `ChannelMix`. `config.channel_map` describes the
+    /// channels of the stream rather than those of the device. Samples are converted as by
+    /// `build_input_stream_converted`.
+    fn build_input_stream_remixed<T, D, E>(
+        &self,
+        config: &StreamConfig,
+        mix: ChannelMix,
+        data_callback: D,
+        error_callback: E,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        T: Sample + Send + 'static,
+        D: FnMut(&[T], &InputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        remix::build_input_stream_remixed(self, config, mix, data_callback, error_callback)
+    }
+
+    /// Create an output stream whose data callback writes `config.channels` channels of samples
+    /// of type `T`, whether or not the device supports that channel count.
+    ///
+    /// See `build_input_stream_remixed`; the channels of the stream are mixed into those of the
+    /// device.
+    fn build_output_stream_remixed<T, D, E>(
+        &self,
+        config: &StreamConfig,
+        mix: ChannelMix,
+        data_callback: D,
+        error_callback: E,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        T: Sample + Send + 'static,
+        D: FnMut(&mut [T], &OutputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        remix::build_output_stream_remixed(self, config, mix, data_callback, error_callback)
+    }
+
     /// Create a dynamically typed input stream.
     fn build_input_stream_raw<D, E>(
         &self,
@@ -189,6 +387,156 @@ pub trait DeviceTrait {
     where
         D: FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static,
         E: FnMut(StreamError) + Send + 'static;
+
+    /// Create a dynamically typed input stream whose data callback receives a separate buffer for
+    /// each channel, accessed via `Data::channel`.
+    ///
+    /// Hosts that cannot deliver planar buffers directly de-interleave the data of a stream created
+    /// via `build_input_stream_raw`.
+    fn build_input_stream_planar_raw<D, E>(
+        &self,
+        config: &StreamConfig,
+        sample_format: SampleFormat,
+        data_callback: D,
+        error_callback: E,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        D: FnMut(&Data, &InputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        crate::planar::build_input_stream_planar_raw(
+            self,
+            config,
+            sample_format,
+            data_callback,
+            error_callback,
+        )
+    }
+
+    /// Create a dynamically typed output stream whose data callback fills a separate buffer for
+    /// each channel, accessed via `Data::channel_mut`.
+    ///
+    /// Hosts that cannot take planar buffers directly interleave them into the buffer of a stream
+    /// created via `build_output_stream_raw`.
+    fn build_output_stream_planar_raw<D, E>(
+        &self,
+        config: &StreamConfig,
+        sample_format: SampleFormat,
+        data_callback: D,
+        error_callback: E,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        D: FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        crate::planar::build_output_stream_planar_raw(
+            self,
+            config,
+            sample_format,
+            data_callback,
+            error_callback,
+        )
+    }
+
+    /// Create a duplex stream.
+    ///
+    /// The data callback receives the captured input and the output buffer to fill in the same
+    /// call. Both buffers always describe the same number of frames.
+    fn build_duplex_stream<T, D, E>(
+        &self,
+        input_config: &StreamConfig,
+        output_config: &StreamConfig,
+        mut data_callback: D,
+        error_callback: E,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        T: Sample,
+        D: FnMut(&[T], &mut [T], &DuplexCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        let error_callback = ErrorCallback::new(error_callback);
+        let data_error_callback = error_callback.clone();
+        let mut reported = false;
+        self.build_duplex_stream_raw(
+            input_config,
+            output_config,
+            T::FORMAT,
+            move |input, output, info| {
+                if let (Some(input), Some(output)) = (input.as_slice(), output.as_slice_mut()) {
+                    return data_callback(input, output, info);
+                }
+                let data = if input.sample_format() != T::FORMAT {
+                    input
+                } else {
+                    &*output
+                };
+                report_incorrect_sample_format(
+                    &data_error_callback,
+                    &mut reported,
+                    T::FORMAT,
+                    data,
+                );
+                let sample_format = output.sample_format();
+                crate::samples_formats::fill_with_silence(output.bytes_mut(), sample_format);
+            },
+            move |err| error_callback.report(err),
+        )
+    }
+
+    /// Create a dynamically typed duplex stream.
+    ///
+    /// The input and output configurations must share the same sample rate.
+    ///
+    /// Hosts that cannot deliver input and output within the same callback return
+    /// `BuildStreamError::StreamConfigNotSupported`.
+    fn build_duplex_stream_raw<D, E>(
+        &self,
+        _input_config: &StreamConfig,
+        _output_config: &StreamConfig,
+        _sample_format: SampleFormat,
+        _data_callback: D,
+        _error_callback: E,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        D: FnMut(&Data, &mut Data, &DuplexCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        Err(BuildStreamError::StreamConfigNotSupported)
+    }
+
+    /// Create an input stream whose captured audio is read by the calling thread via
+    /// `InputStreamReader::read`, rather than delivered to a callback.
+    ///
+    /// By default the audio is passed from a stream created via `build_input_stream_raw` to the
+    /// reader through a ring buffer. The stream is then built and run on a thread of its own, so
+    /// that the reader can be sent to other threads.
+    fn build_input_stream_blocking(
+        &self,
+        config: &StreamConfig,
+        sample_format: SampleFormat,
+    ) -> Result<InputStreamReader, BuildStreamError>
+    where
+        Self: Sync + 'static,
+    {
+        crate::blocking::build_input_stream_blocking(self, config, sample_format)
+    }
+
+    /// Create an output stream that plays the audio written by the calling thread via
+    /// `OutputStreamWriter::write`, rather than requested from a callback.
+    ///
+    /// By default the audio is passed from the writer to a stream created via
+    /// `build_output_stream_raw` through a ring buffer. The stream is then built and run on a
+    /// thread of its own, so that the writer can be sent to other threads.
+    fn build_output_stream_blocking(
+        &self,
+        config: &StreamConfig,
+        sample_format: SampleFormat,
+    ) -> Result<OutputStreamWriter, BuildStreamError>
+    where
+        Self: Sync + 'static,
+    {
+        crate::blocking::build_output_stream_blocking(self, config, sample_format)
+    }
 }
 
 /// A stream created from `Device`, with methods to control playback.
@@ -205,4 +553,72 @@ pub trait StreamTrait {
     /// Note: Not all devices support suspending the stream at the hardware level. This method may
     /// fail in these cases.
     fn pause(&self) -> Result<(), PauseStreamError>;
+
+    /// The configuration negotiated with the device, which may differ from the one requested when
+    /// the stream was built. The `buffer_size` of the returned config is always `Fixed`.
+    ///
+    /// For duplex streams this is the configuration of the output side.
+    ///
+    /// Returns `None` if the host does not report it.
+    fn config(&self) -> Option<StreamConfig> {
+        None
+    }
+
+    /// The size of the buffer between the application and the device, in frames.
+    ///
+    /// Returns `None` if the host does not report it.
+    fn buffer_size(&self) -> Option<FrameCount> {
+        None
+    }
+
+    /// The number of frames processed by each call to the data callback.
+    ///
+    /// Returns `None` if the host does not report it.
+    fn period_size(&self) -> Option<FrameCount> {
+        None
+    }
+
+    /// The nominal latency of the stream: the time between a sample being written by the data
+    /// callback and it being played for output streams, and between a sample being captured and
+    /// it being passed to the data callback for input streams. For duplex streams this is the sum
+    /// of both.
+    ///
+    /// Returns `None` if the host does not report it.
+    fn latency(&self) -> Option<Duration> {
+        None
+    }
+
+    /// The range `set_volume` accepts, in decibels relative to full scale.
+    ///
+    /// Hosts control the volume through the mixer of the device where it has one, in which case
+    /// the volume applies to everything played or captured through the device. Otherwise the
+    /// samples of the stream are scaled before reaching the device or the data callback.
+    ///
+    /// For duplex streams this is the volume of the output side.
+    ///
+    /// Returns `None` if the volume of the stream cannot be controlled.
+    fn volume_range(&self) -> Option<VolumeRange> {
+        None
+    }
+
+    /// The current volume, in decibels relative to full scale.
+    fn volume(&self) -> Result<f32, VolumeError> {
+        Err(VolumeError::NotSupported)
+    }
+
+    /// Set the volume, in decibels relative to full scale. Volumes outside of `volume_range` are
+    /// clamped to it.
+    fn set_volume(&self, _volume_db: f32) -> Result<(), VolumeError> {
+        Err(VolumeError::NotSupported)
+    }
+
+    /// Whether the stream is muted.
+    fn is_muted(&self) -> Result<bool, VolumeError> {
+        Err(VolumeError::NotSupported)
+    }
+
+    /// Mute or unmute the stream, leaving its volume as it is.
+    fn set_muted(&self, _muted: bool) -> Result<(), VolumeError> {
+        Err(VolumeError::NotSupported)
+    }
 }
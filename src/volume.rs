@@ -0,0 +1,156 @@
+//! A gain stage applied to the data of a stream, for hosts and devices without a volume control of
+//! their own.
+
+use crate::{Data, Sample, SampleFormat, VolumeRange};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+/// The volume and mute state of a stream, shared between the stream and its audio thread.
+pub(crate) struct SoftwareVolume {
+    // The bits of the `f32` volume in decibels.
+    volume_db: AtomicU32,
+    muted: AtomicBool,
+}
+
+impl SoftwareVolume {
+    /// The range of the gain stage, which only ever attenuates so that no sample can clip.
+    pub(crate) const RANGE: VolumeRange = VolumeRange {
+        min_db: -96.0,
+        max_db: 0.0,
+    };
+
+    pub(crate) fn new() -> Self {
+        SoftwareVolume {
+            volume_db: AtomicU32::new(0f32.to_bits()),
+            muted: AtomicBool::new(false),
+        }
+    }
+
+    pub(crate) fn volume(&self) -> f32 {
+        f32::from_bits(self.volume_db.load(Ordering::Relaxed))
+    }
+
+    pub(crate) fn set_volume(&self, volume_db: f32) {
+        let volume_db = Self::RANGE.clamp(volume_db);
+        self.volume_db.store(volume_db.to_bits(), Ordering::Relaxed);
+    }
+
+    pub(crate) fn is_muted(&self) -> bool {
+        self.muted.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn set_muted(&self, muted: bool) {
+        self.muted.store(muted, Ordering::Relaxed);
+    }
+
+    /// Scale the interleaved samples of `data` by the current volume, or silence them if muted.
+    ///
+    /// Leaves the data untouched at 0 dB, so that unity gain costs nothing.
+    pub(crate) fn apply(&self, data: &mut Data) {
+        let sample_format = data.sample_format();
+        let gain = if self.is_muted() {
+            if data.planes().is_none() {
+                crate::samples_formats::fill_with_silence(data.bytes_mut(), sample_format);
+                return;
+            }
+            // Planar data is not contiguous, but a gain of 0 silences samples of any format.
+            0.0
+        } else {
+            let volume_db = self.volume();
+            if volume_db >= 0.0 {
+                return;
+            }
+            10f64.powf(volume_db as f64 / 20.0)
+        };
+        match sample_format {
+            SampleFormat::I8 => scale::<i8>(data, gain),
+            SampleFormat::I16 => scale::<i16>(data, gain),
+            SampleFormat::I24 => scale::<crate::I24>(data, gain),
+            SampleFormat::I24Packed => scale::<crate::I24Packed>(data, gain),
+            SampleFormat::I32 => scale::<i32>(data, gain),
+            SampleFormat::U8 => scale::<u8>(data, gain),
+            SampleFormat::U16 => scale::<u16>(data, gain),
+            SampleFormat::U32 => scale::<u32>(data, gain),
+            SampleFormat::F32 => scale::<f32>(data, gain),
+            SampleFormat::F64 => scale::<f64>(data, gain),
+        }
+    }
+}
+
+fn scale<T>(data: &mut Data, gain: f64)
+where
+    T: Sample,
+{
+    let scale_samples = |samples: &mut [T]| {
+        for sample in samples {
+            *sample = T::from(&(sample.to_f64() * gain));
+        }
+    };
+    match data.planes() {
+        Some(channels) => {
+            for channel in 0..channels {
+                if let Some(samples) = data.channel_mut::<T>(channel) {
+                    scale_samples(samples);
+                }
+            }
+        }
+        None => {
+            if let Some(samples) = data.as_slice_mut::<T>() {
+                scale_samples(samples);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SoftwareVolume;
+    use {Data, SampleFormat};
+
+    fn apply(volume: &SoftwareVolume, samples: &mut [f32]) {
+        let mut data = unsafe {
+            Data::from_parts(
+                samples.as_mut_ptr() as *mut (),
+                samples.len(),
+                SampleFormat::F32,
+            )
+        };
+        volume.apply(&mut data);
+    }
+
+    #[test]
+    fn test_software_volume() {
+        let volume = SoftwareVolume::new();
+        let mut samples = [0.5f32, -0.5];
+        apply(&volume, &mut samples);
+        assert_eq!(samples, [0.5, -0.5]);
+
+        volume.set_volume(-20.0);
+        assert_eq!(volume.volume(), -20.0);
+        apply(&volume, &mut samples);
+        assert!((samples[0] - 0.05).abs() < 1e-6);
+        assert!((samples[1] + 0.05).abs() < 1e-6);
+
+        volume.set_muted(true);
+        apply(&volume, &mut samples);
+        assert_eq!(samples, [0.0, 0.0]);
+
+        // Out of range volumes are clamped.
+        volume.set_volume(12.0);
+        assert_eq!(volume.volume(), 0.0);
+        volume.set_volume(f32::NAN);
+        assert_eq!(volume.volume(), SoftwareVolume::RANGE.min_db);
+    }
+
+    #[test]
+    fn test_software_volume_planar() {
+        let volume = SoftwareVolume::new();
+        let mut left = [0u16, 65535];
+        let mut right = [16384u16, 49152];
+        let planes = [left.as_mut_ptr() as *mut (), right.as_mut_ptr() as *mut ()];
+        let mut data = unsafe { Data::from_planar_parts(planes.as_ptr(), 2, 2, SampleFormat::U16) };
+        volume.set_muted(true);
+        volume.apply(&mut data);
+        assert_eq!(data.channel::<u16>(0), Some(&[32768u16, 32768][..]));
+        assert_eq!(data.channel::<u16>(1), Some(&[32768u16, 32768][..]));
+    }
+}